@@ -41,7 +41,7 @@ impl std::fmt::Debug for Color {
       self.r,
       self.g,
       self.b,
-      (self.a / 255)
+      self.a as f32 / 255.0
     )
   }
 }
@@ -1,29 +1,374 @@
 use super::colors::Color;
 use crate::layout::css::Value;
 use crate::layout::geometry::*;
+use crate::layout::image::Image;
+use crate::layout::style::PositionStyle;
+use std::rc::Rc;
 
 pub type DisplayList = Vec<DisplayListCommand>;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub enum DisplayListCommand {
   Rect(Color, Rect),
+  // Fill a convex polygon, like a side of a border with mitred corners.
+  Polygon(Color, Polygon),
+  // Fill a rect with rounded corners, like the background of a box with `border-radius`.
+  RoundedRect(Color, RoundedRect),
+  // Fill the part of a convex polygon that is inside the first rounded rect and outside the
+  // second, like a side of a border with rounded corners.
+  RoundedBorder(Color, Polygon, RoundedRect, RoundedRect),
+  // Paint a shadow of a box, blurred and outside or inside the edge of the box.
+  BoxShadow(BoxShadow),
+  // Draw an image scaled to fill a rect.
+  Image(Rc<Image>, Rect),
+  // Clip the following commands to a rect, within the clip that is already active.
+  PushClip(Rect),
+  // Clip the following commands to a rect with rounded corners, like `PushClip`.
+  PushRoundedClip(RoundedRect),
+  PopClip,
+  // Draw the following commands into a group, which is composited with an opacity when it is
+  // popped.
+  PushOpacity(f32),
+  PopOpacity,
 }
 
-pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
-  let mut list = Vec::new();
-  render_layout_box(&mut list, layout_root);
-  return list;
+pub fn build_display_list(
+  layout_root: &LayoutBox,
+  viewport: Rect,
+  scroll_offsets: &ScrollOffsets,
+) -> DisplayList {
+  // The root box scrolls the viewport, which moves its content but not its own background.
+  let mut painter = Painter::new(scroll_offsets, true);
+  painter.paint_stacking_context(&PaintedBox::root(layout_root, (0.0, 0.0), viewport), true);
+  painter.finish()
 }
 
-fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
-  render_background(list, layout_box);
+/// Build a display list for each page of a paged layout, with the slice of the content that the
+/// page shows moved into its page area and clipped to it.
+pub fn build_page_display_lists(layout_root: &LayoutBox, pages: &[Page]) -> Vec<DisplayList> {
+  let scroll_offsets = ScrollOffsets::new();
+  pages
+    .iter()
+    .map(|page| {
+      let clip = page.content_rect();
+      let mut painter = Painter::new(&scroll_offsets, false);
+      let translation = (page.area.x, page.area.y - page.start);
+      let root = PaintedBox::root(layout_root, translation, clip);
+      painter.paint_stacking_context(&root, true);
+      let mut list = vec![DisplayListCommand::PushClip(clip)];
+      list.extend(painter.finish());
+      list.push(DisplayListCommand::PopClip);
 
-  for child in &layout_box.children {
-    render_layout_box(list, child);
+      // Leave out what the other pages show.
+      list.retain(|command| match command {
+        DisplayListCommand::Rect(_, rect) | DisplayListCommand::Image(_, rect) => {
+          let visible = rect.intersection(clip);
+          visible.width > 0.0 && visible.height > 0.0
+        }
+        DisplayListCommand::Polygon(_, points)
+        | DisplayListCommand::RoundedBorder(_, points, _, _) => {
+          let visible = bounding_rect(points).intersection(clip);
+          visible.width > 0.0 && visible.height > 0.0
+        }
+        DisplayListCommand::RoundedRect(_, rounded) => {
+          let visible = rounded.rect.intersection(clip);
+          visible.width > 0.0 && visible.height > 0.0
+        }
+        DisplayListCommand::BoxShadow(shadow) => {
+          let visible = shadow.bounds().intersection(clip);
+          visible.width > 0.0 && visible.height > 0.0
+        }
+        _ => true,
+      });
+      list
+    })
+    .collect()
+}
+
+/// A box to paint, with what its ancestors paint it with: the translation of the scroll
+/// containers and sticky boxes it is in, the clips of the boxes that clip it, and the text
+/// decorations that reach its text.
+#[derive(Clone)]
+struct PaintedBox<'l, 'a> {
+  layout_box: &'l LayoutBox<'a>,
+  parent: Option<&'l LayoutBox<'a>>,
+  path: ScrollId,
+  translation: (f32, f32),
+  // The scrollport of the nearest scroll container, or the viewport, which sticky boxes stick
+  // to.
+  scrollport: Rect,
+  // The commands that push the clips of the ancestors, each with the path of its box.
+  clips: Vec<(ScrollId, DisplayListCommand)>,
+  decorations: Vec<TextDecoration>,
+}
+
+impl<'l, 'a> PaintedBox<'l, 'a> {
+  fn root(layout_box: &'l LayoutBox<'a>, translation: (f32, f32), viewport: Rect) -> Self {
+    PaintedBox {
+      layout_box,
+      parent: None,
+      path: Vec::new(),
+      translation,
+      scrollport: viewport,
+      clips: Vec::new(),
+      decorations: Vec::new(),
+    }
+  }
+}
+
+/// The descendants of a stacking context, in the layers that they are painted in.
+#[derive(Default)]
+struct Layers<'l, 'a> {
+  // The stacking contexts with a negative `z-index`.
+  negative: Vec<(i32, PaintedBox<'l, 'a>)>,
+  // The block-level and inline-level boxes in flow, in tree order.
+  in_flow: Vec<PaintedBox<'l, 'a>>,
+  // The positioned boxes without a `z-index` and the stacking contexts with `z-index: 0`, in
+  // tree order, with whether they are stacking contexts.
+  positioned: Vec<(bool, PaintedBox<'l, 'a>)>,
+  // The stacking contexts with a positive `z-index`.
+  positive: Vec<(i32, PaintedBox<'l, 'a>)>,
+}
+
+/// Paints boxes into a display list in the painting order of their stacking contexts, with the
+/// clips of their ancestors pushed around them.
+///
+/// https://www.w3.org/TR/CSS22/zindex.html
+struct Painter<'s> {
+  list: DisplayList,
+  scroll_offsets: &'s ScrollOffsets,
+  // Whether the root box scrolls the viewport, which moves its descendants without a clip.
+  viewport_scrolls: bool,
+  // The paths of the boxes whose clips are pushed in the list.
+  open_clips: Vec<ScrollId>,
+}
+
+impl<'s> Painter<'s> {
+  fn new(scroll_offsets: &'s ScrollOffsets, viewport_scrolls: bool) -> Self {
+    Painter {
+      list: Vec::new(),
+      scroll_offsets,
+      viewport_scrolls,
+      open_clips: Vec::new(),
+    }
+  }
+
+  fn finish(mut self) -> DisplayList {
+    self.set_clips(&[]);
+    self.list
+  }
+
+  /// Pop the clips that are pushed in the list and don't apply to the next commands, and push
+  /// the ones that do.
+  fn set_clips(&mut self, clips: &[(ScrollId, DisplayListCommand)]) {
+    let common = self
+      .open_clips
+      .iter()
+      .zip(clips.iter())
+      .take_while(|(open, (path, _))| *open == path)
+      .count();
+    while self.open_clips.len() > common {
+      self.open_clips.pop();
+      self.list.push(DisplayListCommand::PopClip);
+    }
+    for (path, command) in &clips[common..] {
+      self.open_clips.push(path.clone());
+      self.list.push(command.clone());
+    }
+  }
+
+  /// The children of a box, with the clip and the scroll offset of the box, and the text
+  /// decorations that reach into it and that it draws. Sticky children are shifted within the
+  /// scrollport they stick to.
+  fn children<'l, 'a>(&self, parent: &PaintedBox<'l, 'a>) -> Vec<PaintedBox<'l, 'a>> {
+    let layout_box = parent.layout_box;
+    let mut decorations = if layout_box.propagates_text_decorations() {
+      parent.decorations.clone()
+    } else {
+      Vec::new()
+    };
+    decorations.extend(layout_box.text_decoration());
+
+    let mut clips = parent.clips.clone();
+    let mut translation = parent.translation;
+    let mut scrollport = parent.scrollport;
+    let scroll = |path: &ScrollId, (x, y): (f32, f32)| {
+      let (scroll_x, scroll_y) = self.scroll_offsets.get(path).cloned().unwrap_or_default();
+      (x - scroll_x, y - scroll_y)
+    };
+    if parent.parent.is_none() && self.viewport_scrolls {
+      translation = scroll(&parent.path, translation);
+    } else if let Some(clip) = layout_box.overflow_clip_rect() {
+      clips.push((
+        parent.path.clone(),
+        match layout_box.overflow_clip_rounded_rect() {
+          Some(rounded) => {
+            DisplayListCommand::PushRoundedClip(translate_rounded(rounded, translation))
+          }
+          None => DisplayListCommand::PushClip(translate(clip, translation)),
+        },
+      ));
+      if layout_box.is_scroll_container() {
+        scrollport = translate(layout_box.dimensions.padding_box(), translation);
+        translation = scroll(&parent.path, translation);
+      }
+    }
+
+    layout_box
+      .children
+      .iter()
+      .enumerate()
+      .map(|(index, child)| {
+        let mut path = parent.path.clone();
+        path.push(index);
+        let mut translation = translation;
+        if child.position() == PositionStyle::Sticky {
+          // The content of a scroll container reaches as far as it can be scrolled.
+          let containing_block = if layout_box.is_scroll_container() {
+            layout_box.scrollable_overflow()
+          } else {
+            layout_box.dimensions.content_box()
+          };
+          let (dx, dy) = child.sticky_offset(
+            translate(scrollport, (-translation.0, -translation.1)),
+            containing_block,
+          );
+          translation = (translation.0 + dx, translation.1 + dy);
+        }
+        PaintedBox {
+          layout_box: child,
+          parent: Some(layout_box),
+          path,
+          translation,
+          scrollport,
+          clips: clips.clone(),
+          decorations: decorations.clone(),
+        }
+      })
+      .collect()
+  }
+
+  /// Sort the descendants of a box into the layers of a stacking context. Stacking contexts and
+  /// positioned descendants are only collected for a box that is a stacking context, and
+  /// descendants in flow only when `in_flow` is set.
+  fn collect<'l, 'a>(
+    &self,
+    parent: &PaintedBox<'l, 'a>,
+    layers: &mut Layers<'l, 'a>,
+    contexts: bool,
+    in_flow: bool,
+  ) {
+    for child in self.children(parent) {
+      if child.layout_box.is_truncated() {
+        continue;
+      }
+      match child.layout_box.stack_level(Some(parent.layout_box)) {
+        StackLevel::Context(z_index) if contexts => {
+          if z_index < 0 {
+            layers.negative.push((z_index, child));
+          } else if z_index == 0 {
+            layers.positioned.push((true, child));
+          } else {
+            layers.positive.push((z_index, child));
+          }
+        }
+        StackLevel::Context(_) => {}
+        // The positioned descendants and stacking contexts in a positioned box belong to the
+        // parent context, and are painted after it.
+        StackLevel::Positioned if contexts => {
+          layers.positioned.push((false, child.clone()));
+          self.collect(&child, layers, true, false);
+        }
+        StackLevel::Positioned => {}
+        StackLevel::InFlow if !in_flow => self.collect(&child, layers, contexts, false),
+        StackLevel::InFlow if child.layout_box.is_painted_atomically() => {
+          if contexts {
+            self.collect(&child, layers, true, false);
+          }
+          layers.in_flow.push(child);
+        }
+        StackLevel::InFlow => {
+          layers.in_flow.push(child.clone());
+          self.collect(&child, layers, contexts, true);
+        }
+      }
+    }
+  }
+
+  /// Paint a stacking context: its background and borders, the stacking contexts with a negative
+  /// `z-index`, the backgrounds of the blocks in flow, the inline content, the positioned
+  /// descendants and the stacking contexts with `z-index: 0`, and then those with a positive
+  /// `z-index`. Positioned boxes and atomic inlines are painted the same way, but without the
+  /// stacking contexts and positioned boxes in them, unless `context` is set.
+  fn paint_stacking_context(&mut self, painted: &PaintedBox, context: bool) {
+    // The box and its descendants are composited together at their opacity.
+    let opacity = opacity(painted.layout_box);
+    if opacity <= 0.0 {
+      return;
+    }
+    if opacity < 1.0 {
+      self.list.push(DisplayListCommand::PushOpacity(opacity));
+    }
+
+    let mut layers = Layers::default();
+    self.collect(painted, &mut layers, context, true);
+    layers.negative.sort_by_key(|(z_index, _)| *z_index);
+    layers.positive.sort_by_key(|(z_index, _)| *z_index);
+
+    self.paint_background(painted);
+    for (_, descendant) in &layers.negative {
+      self.paint_stacking_context(descendant, true);
+    }
+    for descendant in &layers.in_flow {
+      if !descendant.layout_box.is_inline_level() {
+        self.paint_background(descendant);
+      }
+    }
+    self.paint_foreground(painted);
+    for descendant in &layers.in_flow {
+      if descendant.layout_box.is_painted_atomically() {
+        self.paint_stacking_context(descendant, false);
+      } else {
+        if descendant.layout_box.is_inline_level() {
+          self.paint_background(descendant);
+        }
+        self.paint_foreground(descendant);
+      }
+    }
+    for (context, descendant) in &layers.positioned {
+      self.paint_stacking_context(descendant, *context);
+    }
+    for (_, descendant) in &layers.positive {
+      self.paint_stacking_context(descendant, true);
+    }
+
+    if opacity < 1.0 {
+      self.list.push(DisplayListCommand::PopOpacity);
+    }
+  }
+
+  /// Paint the shadows, the background, the borders and the column rules of a box.
+  fn paint_background(&mut self, painted: &PaintedBox) {
+    self.set_clips(&painted.clips);
+    let (list, layout_box, translation) = (&mut self.list, painted.layout_box, painted.translation);
+    render_box_shadows(list, layout_box, translation, false);
+    render_background(list, layout_box, translation);
+    render_box_shadows(list, layout_box, translation, true);
+    render_borders(list, layout_box, translation);
+    render_column_rules(list, layout_box, translation);
+  }
+
+  /// Paint the content of a box: its image, its marker and the decorations of its text.
+  fn paint_foreground(&mut self, painted: &PaintedBox) {
+    self.set_clips(&painted.clips);
+    let (list, layout_box, translation) = (&mut self.list, painted.layout_box, painted.translation);
+    render_image(list, layout_box, translation);
+    render_marker(list, layout_box, translation);
+    render_text_decorations(list, layout_box, &painted.decorations, translation);
   }
 }
 
-fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
+fn render_background(list: &mut DisplayList, layout_box: &LayoutBox, translation: (f32, f32)) {
   if let Some(node) = layout_box.get_style_node_option() {
     let color = match node.lookup(
       "background-color",
@@ -34,14 +379,279 @@ fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
       _ => Color::transparent(),
     };
 
-    // TODO: fix transparency issues?
-    if color.a < 255 {
+    if color.a == 0 {
       return;
     }
 
+    // The background follows the inner curves of the border.
+    let rounded = layout_box.rounded_padding_box();
+    if rounded.is_rounded() {
+      list.push(DisplayListCommand::RoundedRect(
+        color,
+        translate_rounded(rounded, translation),
+      ));
+      return;
+    }
     list.push(DisplayListCommand::Rect(
       color,
-      layout_box.dimensions.padding_box(),
+      translate(layout_box.dimensions.padding_box(), translation),
     ));
   }
 }
+
+// Paint the outer shadows of a box under its background, or its inset shadows over it. The
+// first shadow is on top, so they are painted from the last one.
+fn render_box_shadows(
+  list: &mut DisplayList,
+  layout_box: &LayoutBox,
+  translation: (f32, f32),
+  inset: bool,
+) {
+  for shadow in layout_box.box_shadows().into_iter().rev() {
+    if shadow.inset != inset || shadow.color.a == 0 {
+      continue;
+    }
+    list.push(DisplayListCommand::BoxShadow(BoxShadow {
+      shape: translate_rounded(shadow.shape, translation),
+      edge: translate_rounded(shadow.edge, translation),
+      ..shadow
+    }));
+  }
+}
+
+// Paint the sides of the border of a box, over its background.
+fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox, translation: (f32, f32)) {
+  for piece in layout_box.border_pieces() {
+    let (dx, dy) = translation;
+    let points = piece
+      .polygon
+      .iter()
+      .map(|(x, y)| (x + dx, y + dy))
+      .collect();
+    list.push(match piece.curves {
+      Some((outer, inner)) => DisplayListCommand::RoundedBorder(
+        piece.color,
+        points,
+        translate_rounded(outer, translation),
+        translate_rounded(inner, translation),
+      ),
+      None => DisplayListCommand::Polygon(piece.color, points),
+    });
+  }
+}
+
+fn render_image(list: &mut DisplayList, layout_box: &LayoutBox, translation: (f32, f32)) {
+  let image = match layout_box
+    .get_style_node_option()
+    .and_then(|node| node.image())
+  {
+    Some(image) => image,
+    None => return,
+  };
+  let rect = match layout_box.object_rect() {
+    Some(rect) => rect,
+    None => return,
+  };
+
+  // The image is clipped to the content box when `object-fit` makes it overflow.
+  let content_box = layout_box.dimensions.content_box();
+  let overflows = rect.x < content_box.x
+    || rect.y < content_box.y
+    || rect.x + rect.width > content_box.x + content_box.width
+    || rect.y + rect.height > content_box.y + content_box.height;
+  if overflows {
+    list.push(DisplayListCommand::PushClip(translate(
+      content_box,
+      translation,
+    )));
+  }
+  list.push(DisplayListCommand::Image(
+    image.clone(),
+    translate(rect, translation),
+  ));
+  if overflows {
+    list.push(DisplayListCommand::PopClip);
+  }
+}
+
+// Paint the rules between the columns of a multi-column container, in the `column-rule-color`
+// or the color of the container.
+fn render_column_rules(list: &mut DisplayList, layout_box: &LayoutBox, translation: (f32, f32)) {
+  let rules = layout_box.column_rule_rects();
+  if rules.is_empty() {
+    return;
+  }
+  let node = layout_box.get_style_node();
+  let shorthand_color = match node.value("column-rule") {
+    Some(Value::List(values)) => values.into_iter().find_map(|value| match value {
+      Value::ColorValue(color) => Some(color),
+      _ => None,
+    }),
+    Some(Value::ColorValue(color)) => Some(color),
+    _ => None,
+  };
+  let color = match node.value("column-rule-color") {
+    Some(Value::ColorValue(color)) => color,
+    _ => match (shorthand_color, node.value("color")) {
+      (Some(color), _) | (None, Some(Value::ColorValue(color))) => color,
+      _ => Color::black(),
+    },
+  };
+  for rule in rules {
+    list.push(DisplayListCommand::Rect(
+      color,
+      translate(rule, translation),
+    ));
+  }
+}
+
+// Paint the bullet of a list marker. Markers with text aren't painted, like other text.
+fn render_marker(list: &mut DisplayList, layout_box: &LayoutBox, translation: (f32, f32)) {
+  let marker = match layout_box.marker() {
+    Some(marker) => marker,
+    None => return,
+  };
+  let content = layout_box.dimensions.content_box();
+  let bullet = match marker.bullet_rect(content, layout_box.viewport()) {
+    Some(bullet) => translate(bullet, translation),
+    None => return,
+  };
+  let color = match marker.list_item.value("color") {
+    Some(Value::ColorValue(color)) => color,
+    _ => Color::black(),
+  };
+
+  // A `circle` is drawn as the outline of the bullet, and a `disc` like a `square`.
+  if marker.style_type != "circle" {
+    list.push(DisplayListCommand::Rect(color, bullet));
+    return;
+  }
+  let line = (bullet.width / 5.0).max(1.0);
+  let edges = [
+    Rect {
+      height: line,
+      ..bullet
+    },
+    Rect {
+      y: bullet.y + bullet.height - line,
+      height: line,
+      ..bullet
+    },
+    Rect {
+      width: line,
+      ..bullet
+    },
+    Rect {
+      x: bullet.x + bullet.width - line,
+      width: line,
+      ..bullet
+    },
+  ];
+  for edge in edges.iter() {
+    list.push(DisplayListCommand::Rect(color, *edge));
+  }
+}
+
+// Paint the lines of the text decorations that reach a box across its text, outermost first.
+fn render_text_decorations(
+  list: &mut DisplayList,
+  layout_box: &LayoutBox,
+  decorations: &[TextDecoration],
+  translation: (f32, f32),
+) {
+  if !layout_box.propagates_text_decorations() {
+    return;
+  }
+  for decoration in decorations {
+    for rect in layout_box.text_decoration_rects(decoration) {
+      list.push(DisplayListCommand::Rect(
+        decoration.color,
+        translate(rect, translation),
+      ));
+    }
+  }
+}
+
+// The opacity of the group of a box, anonymous boxes are opaque.
+fn opacity(layout_box: &LayoutBox) -> f32 {
+  layout_box
+    .get_style_node_option()
+    .map_or(1.0, |node| node.opacity())
+}
+
+fn translate(rect: Rect, (dx, dy): (f32, f32)) -> Rect {
+  Rect {
+    x: rect.x + dx,
+    y: rect.y + dy,
+    ..rect
+  }
+}
+
+fn translate_rounded(rounded: RoundedRect, translation: (f32, f32)) -> RoundedRect {
+  RoundedRect {
+    rect: translate(rounded.rect, translation),
+    ..rounded
+  }
+}
+
+// The smallest rect that contains the points of a polygon.
+pub(super) fn bounding_rect(points: &[(f32, f32)]) -> Rect {
+  let (mut left, mut top) = (f32::INFINITY, f32::INFINITY);
+  let (mut right, mut bottom) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+  for &(x, y) in points {
+    left = left.min(x);
+    top = top.min(y);
+    right = right.max(x);
+    bottom = bottom.max(y);
+  }
+  Rect {
+    x: left,
+    y: top,
+    width: (right - left).max(0.0),
+    height: (bottom - top).max(0.0),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::layout::geometry::testing::{body, div, layout};
+
+  /// The commands that paint a document, with the fills written as their color and the opacity
+  /// groups as `push` and `pop`.
+  fn commands(document: &crate::layout::dom::Node, stylesheet: &str) -> Vec<String> {
+    layout(document, stylesheet, |root| {
+      let viewport = root.dimensions.margin_box();
+      build_display_list(root, viewport, &ScrollOffsets::new())
+        .iter()
+        .map(|command| match command {
+          DisplayListCommand::Rect(color, _) => format!("{}", color.r),
+          DisplayListCommand::PushOpacity(opacity) => format!("push {}", opacity),
+          DisplayListCommand::PopOpacity => "pop".to_string(),
+          command => format!("{:?}", command),
+        })
+        .collect()
+    })
+  }
+
+  const CSS: &str = "div { height: 20px; } \
+    .a { background: rgb(1, 0, 0); } .b { background: rgb(2, 0, 0); } \
+    .c { background: rgb(3, 0, 0); }";
+
+  #[test]
+  fn opacity_groups_nest_with_the_boxes() {
+    let document = body(vec![div("a", vec![div("b", vec![div("c", vec![])])])]);
+    let stylesheet = format!("{} .a {{ opacity: 0.5; }} .c {{ opacity: 0.25; }}", CSS);
+    assert_eq!(
+      commands(&document, &stylesheet),
+      vec!["push 0.5", "1", "2", "push 0.25", "3", "pop", "pop"]
+    );
+  }
+
+  #[test]
+  fn opaque_boxes_have_no_group_and_transparent_boxes_are_not_painted() {
+    let document = body(vec![div("a", vec![div("b", vec![])]), div("c", vec![])]);
+    let stylesheet = format!("{} .a {{ opacity: 0; }} .c {{ opacity: 1; }}", CSS);
+    assert_eq!(commands(&document, &stylesheet), vec!["3"]);
+  }
+}
@@ -1,23 +1,201 @@
 pub mod colors;
 pub mod display_list;
+pub mod raster;
 pub mod utils;
 pub mod vertex;
-pub use vertex::Vertex;
+pub use vertex::{TexturedVertex, Vertex};
 
 use crate::glium::Surface;
 use colors::Color;
-use utils::gl_to_pos;
+use crate::layout::geometry::{BoxShadow, Rect, RoundedRect};
+use crate::layout::image::Image;
+use utils::{gl_to_pos, point_to_gl, rect_to_scissor};
 
-static FRAGMENT_SHADER_SRC: &str = r#"
+// The coverage of a pixel by a rect with rounded corners, given as its rect and the horizontal
+// and vertical radii of its corners clockwise from the top left. Pixels on the edge are covered
+// in part, by how far their center is inside it, which anti-aliases the curves.
+macro_rules! rounded_rect_coverage_src {
+    () => {
+        r#"
+    float rounded_rect_coverage(vec2 p, vec4 rect, vec4 radii_x, vec4 radii_y) {
+        vec2 top_left = rect.xy;
+        vec2 bottom_right = rect.xy + rect.zw;
+        float distance = max(
+            max(top_left.x - p.x, p.x - bottom_right.x),
+            max(top_left.y - p.y, p.y - bottom_right.y)
+        );
+
+        vec2 radii;
+        vec2 center;
+        if (p.x < top_left.x + radii_x.x && p.y < top_left.y + radii_y.x) {
+            radii = vec2(radii_x.x, radii_y.x);
+            center = top_left + radii;
+        } else if (p.x > bottom_right.x - radii_x.y && p.y < top_left.y + radii_y.y) {
+            radii = vec2(radii_x.y, radii_y.y);
+            center = vec2(bottom_right.x - radii.x, top_left.y + radii.y);
+        } else if (p.x > bottom_right.x - radii_x.z && p.y > bottom_right.y - radii_y.z) {
+            radii = vec2(radii_x.z, radii_y.z);
+            center = bottom_right - radii;
+        } else if (p.x < top_left.x + radii_x.w && p.y > bottom_right.y - radii_y.w) {
+            radii = vec2(radii_x.w, radii_y.w);
+            center = vec2(top_left.x + radii.x, bottom_right.y - radii.y);
+        } else {
+            return clamp(0.5 - distance, 0.0, 1.0);
+        }
+
+        // An estimate of the distance to the ellipse of the corner.
+        vec2 q = (p - center) / radii;
+        float k0 = length(q);
+        float k1 = length(q / radii);
+        if (k1 == 0.0) {
+            return 1.0;
+        }
+        return clamp(0.5 - k0 * (k0 - 1.0) / k1, 0.0, 1.0);
+    }
+
+    // The coverage of a pixel by the rounded clip, if there is one.
+    float clip_coverage(vec2 p) {
+        if (!has_clip) {
+            return 1.0;
+        }
+        return rounded_rect_coverage(p, clip_rect, clip_radii_x, clip_radii_y);
+    }
+"#
+    };
+}
+
+static FRAGMENT_SHADER_SRC: &str = concat!(
+    r#"
     #version 140
 
     uniform vec4 in_color;
+    uniform float viewport_height;
+    // The shape is filled inside the outer rounded rect and outside the inner one, when they are
+    // given.
+    uniform bool has_outer;
+    uniform vec4 outer_rect;
+    uniform vec4 outer_radii_x;
+    uniform vec4 outer_radii_y;
+    uniform bool has_inner;
+    uniform vec4 inner_rect;
+    uniform vec4 inner_radii_x;
+    uniform vec4 inner_radii_y;
+    uniform bool has_clip;
+    uniform vec4 clip_rect;
+    uniform vec4 clip_radii_x;
+    uniform vec4 clip_radii_y;
     out vec4 color;
+"#,
+    rounded_rect_coverage_src!(),
+    r#"
+    void main() {
+        vec2 p = vec2(gl_FragCoord.x, viewport_height - gl_FragCoord.y);
+        float coverage = clip_coverage(p);
+        if (has_outer) {
+            coverage *= rounded_rect_coverage(p, outer_rect, outer_radii_x, outer_radii_y);
+        }
+        if (has_inner) {
+            coverage *= 1.0 - rounded_rect_coverage(p, inner_rect, inner_radii_x, inner_radii_y);
+        }
+        // Colors are written with premultiplied alpha.
+        color = vec4(in_color.rgb, 1.0) * (in_color.a * coverage);
+    }
+"#
+);
+
+// The shadow of a rounded rect, blurred by a Gaussian, is the integral of the Gaussian over the
+// rect. Across each row the rect is a segment, so the integral along it is a difference of error
+// functions, and down the rows it is summed from a few samples.
+//
+// https://madebyevan.com/shaders/fast-rounded-rectangle-shadows/
+static SHADOW_FRAGMENT_SHADER_SRC: &str = concat!(
+    r#"
+    #version 140
+
+    uniform vec4 in_color;
+    uniform float viewport_height;
+    uniform float sigma;
+    uniform bool inset;
+    uniform vec4 shadow_rect;
+    uniform vec4 shadow_radii_x;
+    uniform vec4 shadow_radii_y;
+    uniform vec4 edge_rect;
+    uniform vec4 edge_radii_x;
+    uniform vec4 edge_radii_y;
+    uniform bool has_clip;
+    uniform vec4 clip_rect;
+    uniform vec4 clip_radii_x;
+    uniform vec4 clip_radii_y;
+    out vec4 color;
+"#,
+    rounded_rect_coverage_src!(),
+    r#"
+    // An approximation of the error function.
+    vec2 erf(vec2 x) {
+        vec2 s = sign(x);
+        vec2 a = abs(x);
+        x = 1.0 + (0.278393 + (0.230389 + 0.078108 * (a * a)) * a) * a;
+        x *= x;
+        return s - s / (x * x);
+    }
+
+    float gaussian(float x) {
+        return exp(-(x * x) / (2.0 * sigma * sigma)) / (2.5066283 * sigma);
+    }
+
+    // The blurred shadow along the row at a distance y from the center, where the corner with
+    // the given radii narrows the row.
+    float shadow_row(float x, float y, vec2 radii, vec2 half_size) {
+        float delta = min(half_size.y - radii.y - abs(y), 0.0) / max(radii.y, 0.0001);
+        float curved = half_size.x - radii.x + radii.x * sqrt(max(0.0, 1.0 - delta * delta));
+        vec2 integral = 0.5 + 0.5 * erf((x + vec2(-curved, curved)) * (0.7071068 / sigma));
+        return integral.y - integral.x;
+    }
+
+    float blurred_coverage(vec2 p) {
+        vec2 half_size = shadow_rect.zw * 0.5;
+        vec2 point = p - (shadow_rect.xy + half_size);
+
+        // The corner of the quarter of the rect that the pixel is in.
+        vec2 radii;
+        if (point.y < 0.0) {
+            radii = point.x < 0.0
+                ? vec2(shadow_radii_x.x, shadow_radii_y.x)
+                : vec2(shadow_radii_x.y, shadow_radii_y.y);
+        } else {
+            radii = point.x < 0.0
+                ? vec2(shadow_radii_x.w, shadow_radii_y.w)
+                : vec2(shadow_radii_x.z, shadow_radii_y.z);
+        }
+        radii = min(radii, half_size);
+
+        // The Gaussian is negligible past three standard deviations.
+        float start = clamp(-3.0 * sigma, point.y - half_size.y, point.y + half_size.y);
+        float end = clamp(3.0 * sigma, point.y - half_size.y, point.y + half_size.y);
+        float step = (end - start) / 4.0;
+        float y = start + step * 0.5;
+        float value = 0.0;
+        for (int i = 0; i < 4; i++) {
+            value += shadow_row(point.x, point.y - y, radii, half_size) * gaussian(y) * step;
+            y += step;
+        }
+        return value;
+    }
 
     void main() {
-        color = in_color;
+        vec2 p = vec2(gl_FragCoord.x, viewport_height - gl_FragCoord.y);
+        float shadow = sigma > 0.0
+            ? blurred_coverage(p)
+            : rounded_rect_coverage(p, shadow_rect, shadow_radii_x, shadow_radii_y);
+        float edge = rounded_rect_coverage(p, edge_rect, edge_radii_x, edge_radii_y);
+        // An outer shadow shows outside the edge of the box, and an inset one inside it, where
+        // the shape of the shadow doesn't cover it.
+        float coverage = inset ? (1.0 - shadow) * edge : shadow * (1.0 - edge);
+        coverage *= clip_coverage(p);
+        color = vec4(in_color.rgb, 1.0) * (in_color.a * coverage);
     }
-"#;
+"#
+);
 
 static VERTEX_SHADER_SRC: &str = r#"
     #version 140
@@ -29,6 +207,58 @@ static VERTEX_SHADER_SRC: &str = r#"
     }
 "#;
 
+static IMAGE_FRAGMENT_SHADER_SRC: &str = concat!(
+    r#"
+    #version 140
+
+    in vec2 v_tex_coords;
+    out vec4 color;
+
+    uniform sampler2D tex;
+    uniform float viewport_height;
+    uniform bool has_clip;
+    uniform vec4 clip_rect;
+    uniform vec4 clip_radii_x;
+    uniform vec4 clip_radii_y;
+"#,
+    rounded_rect_coverage_src!(),
+    r#"
+    void main() {
+        vec2 p = vec2(gl_FragCoord.x, viewport_height - gl_FragCoord.y);
+        vec4 texel = texture(tex, v_tex_coords);
+        color = vec4(texel.rgb * texel.a, texel.a) * clip_coverage(p);
+    }
+"#
+);
+
+// Composite a group, which is drawn with premultiplied alpha, at an opacity.
+static GROUP_FRAGMENT_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 v_tex_coords;
+    out vec4 color;
+
+    uniform sampler2D tex;
+    uniform float opacity;
+
+    void main() {
+        color = texture(tex, v_tex_coords) * opacity;
+    }
+"#;
+
+static IMAGE_VERTEX_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 position;
+    in vec2 tex_coords;
+    out vec2 v_tex_coords;
+
+    void main() {
+        v_tex_coords = tex_coords;
+        gl_Position = vec4(position, 0.0, 1.0);
+    }
+"#;
+
 fn opaque_shader(display: glium::Display) -> glium::Program {
     let program =
         glium::Program::from_source(&display, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC, None)
@@ -36,68 +266,459 @@ fn opaque_shader(display: glium::Display) -> glium::Program {
     program
 }
 
-pub fn draw_rect(
+/// Source-over compositing of colors with premultiplied alpha.
+///
+/// https://www.w3.org/TR/compositing-1/#porterduffcompositingoperators_srcover
+fn premultiplied_blending() -> glium::Blend {
+    let source_over = glium::BlendingFunction::Addition {
+        source: glium::LinearBlendingFactor::One,
+        destination: glium::LinearBlendingFactor::OneMinusSourceAlpha,
+    };
+    glium::Blend {
+        color: source_over,
+        alpha: source_over,
+        constant_value: (0.0, 0.0, 0.0, 0.0),
+    }
+}
+
+/// The clip that drawing is cut to: its rect with the scissor test, and its rounded rect, if it
+/// has one, in the fragment shaders.
+#[derive(Copy, Clone)]
+pub struct Clip {
+    pub rect: Rect,
+    pub rounded: Option<RoundedRect>,
+}
+
+/// The uniforms of a rounded rect in the fragment shaders: whether it is given, its rect, and the
+/// horizontal and the vertical radii of its corners.
+fn rounded_rect_uniforms(rounded: Option<&RoundedRect>) -> (bool, [f32; 4], [f32; 4], [f32; 4]) {
+    match rounded {
+        Some(rounded) => {
+            let rect = rounded.rect;
+            let radii = rounded.radii;
+            (
+                true,
+                [rect.x, rect.y, rect.width, rect.height],
+                [radii[0].0, radii[1].0, radii[2].0, radii[3].0],
+                [radii[0].1, radii[1].1, radii[2].1, radii[3].1],
+            )
+        }
+        None => (false, [0.0; 4], [0.0; 4], [0.0; 4]),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn draw_rect<S: Surface>(
     display: glium::Display,
-    target: &mut glium::Frame,
+    target: &mut S,
     x: i32,
     y: i32,
     w: i32,
     h: i32,
     color: Option<Color>,
+    clip: Option<Clip>,
+) {
+    let (x, y, w, h) = (x as f32, y as f32, w as f32, h as f32);
+    draw_shape(
+        display,
+        target,
+        &[(x, y), (x, y + h), (x + w, y + h), (x + w, y)],
+        color.unwrap_or(Color::black()),
+        None,
+        None,
+        clip,
+    );
+}
+
+pub fn draw_polygon<S: Surface>(
+    display: glium::Display,
+    target: &mut S,
+    points: &[(f32, f32)],
+    color: Color,
+    clip: Option<Clip>,
+) {
+    draw_shape(display, target, points, color, None, None, clip);
+}
+
+pub fn draw_rounded_rect<S: Surface>(
+    display: glium::Display,
+    target: &mut S,
+    rounded: &RoundedRect,
+    color: Color,
+    clip: Option<Clip>,
+) {
+    // The quad reaches past the rect for the pixels its edges cover in part.
+    let rect = rounded.rect;
+    let (left, top) = (rect.x - 1.0, rect.y - 1.0);
+    let (right, bottom) = (rect.x + rect.width + 1.0, rect.y + rect.height + 1.0);
+    draw_shape(
+        display,
+        target,
+        &[(left, top), (left, bottom), (right, bottom), (right, top)],
+        color,
+        Some(rounded),
+        None,
+        clip,
+    );
+}
+
+/// Fill a convex polygon, within an outer rounded rect and outside an inner one when they are
+/// given. The edges of the rounded rects are anti-aliased, and the pixels they cover in part are
+/// blended with what is drawn already.
+#[allow(clippy::too_many_arguments)]
+fn draw_shape<S: Surface>(
+    display: glium::Display,
+    target: &mut S,
+    points: &[(f32, f32)],
+    color: Color,
+    outer: Option<&RoundedRect>,
+    inner: Option<&RoundedRect>,
+    clip: Option<Clip>,
 ) {
-    let color = color.unwrap_or(Color::black());
+    if points.len() < 3 {
+        return;
+    }
     let program = opaque_shader(display.clone());
 
     let dimensions = display.get_framebuffer_dimensions();
 
-    let vertex1 = Vertex {
-        position: gl_to_pos((x, y), dimensions),
+    // The polygon is convex, so a fan from its first point covers it.
+    let shape: Vec<Vertex> = points
+        .iter()
+        .map(|&point| Vertex {
+            position: point_to_gl(point, dimensions),
+        })
+        .collect();
+    let vertex_buffer = glium::VertexBuffer::new(&display, &shape).unwrap();
+    let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleFan);
+
+    let (has_outer, outer_rect, outer_radii_x, outer_radii_y) = rounded_rect_uniforms(outer);
+    let (has_inner, inner_rect, inner_radii_x, inner_radii_y) = rounded_rect_uniforms(inner);
+    let (has_clip, clip_rect, clip_radii_x, clip_radii_y) =
+        rounded_rect_uniforms(clip.as_ref().and_then(|clip| clip.rounded.as_ref()));
+    let uniforms = uniform! {
+      in_color: color,
+      viewport_height: dimensions.1 as f32,
+      has_outer: has_outer,
+      outer_rect: outer_rect,
+      outer_radii_x: outer_radii_x,
+      outer_radii_y: outer_radii_y,
+      has_inner: has_inner,
+      inner_rect: inner_rect,
+      inner_radii_x: inner_radii_x,
+      inner_radii_y: inner_radii_y,
+      has_clip: has_clip,
+      clip_rect: clip_rect,
+      clip_radii_x: clip_radii_x,
+      clip_radii_y: clip_radii_y,
     };
-    let vertex2 = Vertex {
-        position: gl_to_pos((x, y + h), dimensions),
+
+    let params = glium::DrawParameters {
+        scissor: clip.map(|clip| rect_to_scissor(&clip.rect, dimensions)),
+        blend: premultiplied_blending(),
+        ..Default::default()
     };
-    let vertex3 = Vertex {
-        position: gl_to_pos((x + w, y + h), dimensions),
+
+    target
+        .draw(&vertex_buffer, indices, &program, &uniforms, &params)
+        .unwrap();
+}
+
+/// Paint a box shadow, blurred and cut to the outside of the edge of its box, or to the inside
+/// for an inset shadow.
+pub fn draw_box_shadow<S: Surface>(
+    display: glium::Display,
+    target: &mut S,
+    shadow: &BoxShadow,
+    clip: Option<Clip>,
+) {
+    let program = glium::Program::from_source(
+        &display,
+        VERTEX_SHADER_SRC,
+        SHADOW_FRAGMENT_SHADER_SRC,
+        None,
+    )
+    .unwrap();
+
+    let dimensions = display.get_framebuffer_dimensions();
+    // The quad reaches past the area of the shadow for the pixels its edges cover in part.
+    let rect = shadow.bounds();
+    let (left, top) = (rect.x - 1.0, rect.y - 1.0);
+    let (right, bottom) = (rect.x + rect.width + 1.0, rect.y + rect.height + 1.0);
+    let shape: Vec<Vertex> = [(left, top), (left, bottom), (right, bottom), (right, top)]
+        .iter()
+        .map(|&point| Vertex {
+            position: point_to_gl(point, dimensions),
+        })
+        .collect();
+    let vertex_buffer = glium::VertexBuffer::new(&display, &shape).unwrap();
+    let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleFan);
+
+    let (_, shadow_rect, shadow_radii_x, shadow_radii_y) =
+        rounded_rect_uniforms(Some(&shadow.shape));
+    let (_, edge_rect, edge_radii_x, edge_radii_y) = rounded_rect_uniforms(Some(&shadow.edge));
+    let (has_clip, clip_rect, clip_radii_x, clip_radii_y) =
+        rounded_rect_uniforms(clip.as_ref().and_then(|clip| clip.rounded.as_ref()));
+    let uniforms = uniform! {
+      in_color: shadow.color,
+      viewport_height: dimensions.1 as f32,
+      sigma: shadow.sigma,
+      inset: shadow.inset,
+      shadow_rect: shadow_rect,
+      shadow_radii_x: shadow_radii_x,
+      shadow_radii_y: shadow_radii_y,
+      edge_rect: edge_rect,
+      edge_radii_x: edge_radii_x,
+      edge_radii_y: edge_radii_y,
+      has_clip: has_clip,
+      clip_rect: clip_rect,
+      clip_radii_x: clip_radii_x,
+      clip_radii_y: clip_radii_y,
     };
-    let vertex4 = Vertex {
-        position: gl_to_pos((x + w, y), dimensions),
+
+    let params = glium::DrawParameters {
+        scissor: clip.map(|clip| rect_to_scissor(&clip.rect, dimensions)),
+        blend: premultiplied_blending(),
+        ..Default::default()
     };
 
-    let shape = vec![vertex1, vertex2, vertex3, vertex4];
+    target
+        .draw(&vertex_buffer, indices, &program, &uniforms, &params)
+        .unwrap();
+}
+
+pub fn draw_image<S: Surface>(
+    display: glium::Display,
+    target: &mut S,
+    image: &Image,
+    rect: &Rect,
+    clip: Option<Clip>,
+) {
+    let program = glium::Program::from_source(
+        &display,
+        IMAGE_VERTEX_SHADER_SRC,
+        IMAGE_FRAGMENT_SHADER_SRC,
+        None,
+    )
+    .unwrap();
+
+    let dimensions = display.get_framebuffer_dimensions();
+    let (x, y) = (rect.x as i32, rect.y as i32);
+    let (w, h) = (rect.width as i32, rect.height as i32);
+
+    // The rows of the image start at the top, which is where textures start as well.
+    let shape = vec![
+        TexturedVertex {
+            position: gl_to_pos((x, y), dimensions),
+            tex_coords: [0.0, 0.0],
+        },
+        TexturedVertex {
+            position: gl_to_pos((x, y + h), dimensions),
+            tex_coords: [0.0, 1.0],
+        },
+        TexturedVertex {
+            position: gl_to_pos((x + w, y + h), dimensions),
+            tex_coords: [1.0, 1.0],
+        },
+        TexturedVertex {
+            position: gl_to_pos((x + w, y), dimensions),
+            tex_coords: [1.0, 0.0],
+        },
+    ];
+    let vertex_buffer = glium::VertexBuffer::new(&display, &shape).unwrap();
+    let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleFan);
+
+    let raw_image = glium::texture::RawImage2d::from_raw_rgba(
+        image.pixels.clone(),
+        (image.width, image.height),
+    );
+    let texture = glium::texture::SrgbTexture2d::new(&display, raw_image).unwrap();
+    let (has_clip, clip_rect, clip_radii_x, clip_radii_y) =
+        rounded_rect_uniforms(clip.as_ref().and_then(|clip| clip.rounded.as_ref()));
+    let uniforms = uniform! {
+      tex: &texture,
+      viewport_height: dimensions.1 as f32,
+      has_clip: has_clip,
+      clip_rect: clip_rect,
+      clip_radii_x: clip_radii_x,
+      clip_radii_y: clip_radii_y,
+    };
+
+    let params = glium::DrawParameters {
+        scissor: clip.map(|clip| rect_to_scissor(&clip.rect, dimensions)),
+        blend: premultiplied_blending(),
+        ..Default::default()
+    };
+
+    target
+        .draw(&vertex_buffer, indices, &program, &uniforms, &params)
+        .unwrap();
+}
+
+/// Composite a group that was drawn into a texture the size of the framebuffer at an opacity.
+pub fn draw_group<S: Surface>(
+    display: glium::Display,
+    target: &mut S,
+    texture: &glium::texture::Texture2d,
+    opacity: f32,
+) {
+    let program = glium::Program::from_source(
+        &display,
+        IMAGE_VERTEX_SHADER_SRC,
+        GROUP_FRAGMENT_SHADER_SRC,
+        None,
+    )
+    .unwrap();
+
+    // The group covers the whole framebuffer, with its rows in the same order.
+    let shape = vec![
+        TexturedVertex {
+            position: [-1.0, -1.0],
+            tex_coords: [0.0, 0.0],
+        },
+        TexturedVertex {
+            position: [1.0, -1.0],
+            tex_coords: [1.0, 0.0],
+        },
+        TexturedVertex {
+            position: [1.0, 1.0],
+            tex_coords: [1.0, 1.0],
+        },
+        TexturedVertex {
+            position: [-1.0, 1.0],
+            tex_coords: [0.0, 1.0],
+        },
+    ];
     let vertex_buffer = glium::VertexBuffer::new(&display, &shape).unwrap();
     let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleFan);
 
     let uniforms = uniform! {
-      in_color: color
+      tex: texture,
+      opacity: opacity,
+    };
+
+    let params = glium::DrawParameters {
+        blend: premultiplied_blending(),
+        ..Default::default()
     };
 
     target
-        .draw(
-            &vertex_buffer,
-            &indices,
-            &program,
-            &uniforms,
-            &Default::default(),
-        )
+        .draw(&vertex_buffer, indices, &program, &uniforms, &params)
         .unwrap();
 }
 
-pub fn render_list(
+/// Draw a command that paints something, within a clip.
+fn draw_command<S: Surface>(
+    display: glium::Display,
+    target: &mut S,
+    command: &display_list::DisplayListCommand,
+    clip: Option<Clip>,
+) {
+    match command {
+        display_list::DisplayListCommand::Rect(color, rect) => draw_rect(
+            display,
+            target,
+            rect.x as i32,
+            rect.y as i32,
+            rect.width as i32,
+            rect.height as i32,
+            Some(*color),
+            clip,
+        ),
+        display_list::DisplayListCommand::Polygon(color, points) => {
+            draw_polygon(display, target, points, *color, clip)
+        }
+        display_list::DisplayListCommand::RoundedRect(color, rounded) => {
+            draw_rounded_rect(display, target, rounded, *color, clip)
+        }
+        display_list::DisplayListCommand::RoundedBorder(color, points, outer, inner) => {
+            draw_shape(
+                display,
+                target,
+                points,
+                *color,
+                Some(outer),
+                Some(inner),
+                clip,
+            )
+        }
+        display_list::DisplayListCommand::BoxShadow(shadow) => {
+            draw_box_shadow(display, target, shadow, clip)
+        }
+        display_list::DisplayListCommand::Image(image, rect) => {
+            draw_image(display, target, image, rect, clip)
+        }
+        _ => {}
+    }
+}
+
+pub fn render_list<S: Surface>(
     list: &display_list::DisplayList,
     display: glium::Display,
-    target: &mut glium::Frame,
+    target: &mut S,
 ) {
+    // The intersection of the clips that were pushed, the last one applies. Only the innermost
+    // rounded clip cuts the corners.
+    let mut clips: Vec<Clip> = Vec::new();
+    // The groups that are drawn into instead of the target, each with its opacity, the last one
+    // applies.
+    let mut groups: Vec<(glium::texture::Texture2d, f32)> = Vec::new();
+
     for command in list.iter() {
         match command {
-            display_list::DisplayListCommand::Rect(color, rect) => draw_rect(
-                display.clone(),
-                target,
-                rect.x as i32,
-                rect.y as i32,
-                rect.width as i32,
-                rect.height as i32,
-                Some(*color),
-            ),
+            display_list::DisplayListCommand::PushClip(rect) => {
+                let clip = match clips.last() {
+                    Some(outer) => Clip {
+                        rect: outer.rect.intersection(*rect),
+                        ..*outer
+                    },
+                    None => Clip {
+                        rect: *rect,
+                        rounded: None,
+                    },
+                };
+                clips.push(clip);
+            }
+            display_list::DisplayListCommand::PushRoundedClip(rounded) => {
+                let rect = match clips.last() {
+                    Some(outer) => outer.rect.intersection(rounded.rect),
+                    None => rounded.rect,
+                };
+                clips.push(Clip {
+                    rect,
+                    rounded: Some(*rounded),
+                });
+            }
+            display_list::DisplayListCommand::PopClip => {
+                clips.pop();
+            }
+            display_list::DisplayListCommand::PushOpacity(opacity) => {
+                let (width, height) = display.get_framebuffer_dimensions();
+                let texture =
+                    glium::texture::Texture2d::empty(&display, width.max(1), height.max(1))
+                        .unwrap();
+                texture.as_surface().clear_color(0.0, 0.0, 0.0, 0.0);
+                groups.push((texture, *opacity));
+            }
+            display_list::DisplayListCommand::PopOpacity => {
+                if let Some((texture, opacity)) = groups.pop() {
+                    match groups.last() {
+                        Some((parent, _)) => {
+                            draw_group(display.clone(), &mut parent.as_surface(), &texture, opacity)
+                        }
+                        None => draw_group(display.clone(), target, &texture, opacity),
+                    }
+                }
+            }
+            command => match groups.last() {
+                Some((group, _)) => draw_command(
+                    display.clone(),
+                    &mut group.as_surface(),
+                    command,
+                    clips.last().cloned(),
+                ),
+                None => draw_command(display.clone(), target, command, clips.last().cloned()),
+            },
         }
     }
 }
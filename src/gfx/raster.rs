@@ -0,0 +1,506 @@
+//! A CPU backend, which rasterizes a display list into pixels in memory the way `render_list`
+//! draws it with OpenGL, for rendering without a window, like into an image file.
+//!
+//! Colors are blended with premultiplied alpha, like the glium renderer does. Rects, polygons and
+//! images cover the pixels whose center they contain, and the curves of rounded rects and the
+//! blur of box shadows cover pixels in part, with the same functions as the fragment shaders.
+
+use super::colors::Color;
+use super::display_list::{bounding_rect, DisplayList, DisplayListCommand};
+use super::Clip;
+use crate::layout::geometry::{BoxShadow, Polygon, Rect, RoundedRect};
+use crate::layout::image::Image;
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/// A pixel with premultiplied alpha, with its channels from 0 to 1.
+type Pixel = [f32; 4];
+
+/// Pixels in rows from the top left.
+pub struct Canvas {
+  pub width: u32,
+  pub height: u32,
+  pixels: Vec<Pixel>,
+}
+
+impl Canvas {
+  /// A canvas filled with a color.
+  pub fn new(width: u32, height: u32, background: Color) -> Self {
+    Canvas {
+      width,
+      height,
+      pixels: vec![premultiply(background, 1.0); (width * height) as usize],
+    }
+  }
+
+  /// The pixels as RGBA bytes without premultiplied alpha, the way image files store them.
+  pub fn to_rgba8(&self) -> Vec<u8> {
+    self
+      .pixels
+      .iter()
+      .flat_map(|&[r, g, b, a]| {
+        let unpremultiply = |channel: f32| if a > 0.0 { channel / a } else { 0.0 };
+        [unpremultiply(r), unpremultiply(g), unpremultiply(b), a]
+      })
+      .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+      .collect()
+  }
+
+  /// Blend a source over the pixels whose center is in an area and in a clip, given the color of
+  /// the source at the center of each pixel.
+  fn blend(&mut self, area: Rect, clip: Option<&Clip>, source: impl Fn((f32, f32)) -> Pixel) {
+    let area = match clip {
+      Some(clip) => area.intersection(clip.rect),
+      None => area,
+    };
+    // The pixels whose center is at or after the start of the area, and before its end.
+    let range = |start: f32, length: f32, size: u32| {
+      let first = (start - 0.5).ceil().clamp(0.0, size as f32) as u32;
+      let end = (start + length - 0.5).ceil().clamp(0.0, size as f32) as u32;
+      first..end.max(first)
+    };
+    for y in range(area.y, area.height, self.height) {
+      for x in range(area.x, area.width, self.width) {
+        let point = (x as f32 + 0.5, y as f32 + 0.5);
+        let coverage = clip_coverage(point, clip);
+        let source = source(point).map(|channel| channel * coverage);
+        let pixel = &mut self.pixels[(y * self.width + x) as usize];
+        *pixel = source_over(source, *pixel);
+      }
+    }
+  }
+
+  /// Blend a color over the pixels in an area, by how much a shape covers each of them.
+  fn fill(
+    &mut self,
+    color: Color,
+    area: Rect,
+    clip: Option<&Clip>,
+    coverage: impl Fn((f32, f32)) -> f32,
+  ) {
+    self.blend(area, clip, |point| premultiply(color, coverage(point)));
+  }
+
+  /// Paint a command that paints something, within a clip.
+  fn draw_command(&mut self, command: &DisplayListCommand, clip: Option<&Clip>) {
+    match command {
+      DisplayListCommand::Rect(color, rect) => self.fill(*color, *rect, clip, |_| 1.0),
+      DisplayListCommand::Polygon(color, points) => {
+        self.fill(*color, bounding_rect(points), clip, |point| {
+          polygon_coverage(point, points)
+        })
+      }
+      // The areas reach past the shapes for the pixels their edges cover in part.
+      DisplayListCommand::RoundedRect(color, rounded) => {
+        self.fill(*color, grow(rounded.rect, 1.0), clip, |point| {
+          rounded_rect_coverage(point, rounded)
+        })
+      }
+      DisplayListCommand::RoundedBorder(color, points, outer, inner) => {
+        self.fill(*color, bounding_rect(points), clip, |point| {
+          polygon_coverage(point, points)
+            * rounded_rect_coverage(point, outer)
+            * (1.0 - rounded_rect_coverage(point, inner))
+        })
+      }
+      DisplayListCommand::BoxShadow(shadow) => {
+        self.fill(shadow.color, grow(shadow.bounds(), 1.0), clip, |point| {
+          shadow_coverage(point, shadow)
+        })
+      }
+      DisplayListCommand::Image(image, rect) => {
+        self.blend(*rect, clip, |point| image_pixel(image, rect, point))
+      }
+      _ => {}
+    }
+  }
+
+  /// Composite a group that was painted into a canvas of the same size at an opacity.
+  fn draw_group(&mut self, group: &Canvas, opacity: f32) {
+    for (pixel, source) in self.pixels.iter_mut().zip(&group.pixels) {
+      *pixel = source_over(source.map(|channel| channel * opacity), *pixel);
+    }
+  }
+}
+
+/// Paint a display list onto a canvas.
+pub fn render_list(list: &DisplayList, canvas: &mut Canvas) {
+  // The intersection of the clips that were pushed, the last one applies. Only the innermost
+  // rounded clip cuts the corners.
+  let mut clips: Vec<Clip> = Vec::new();
+  // The groups that are painted instead of the canvas, each with its opacity, the last one
+  // applies.
+  let mut groups: Vec<(Canvas, f32)> = Vec::new();
+
+  for command in list.iter() {
+    match command {
+      DisplayListCommand::PushClip(rect) => {
+        let clip = match clips.last() {
+          Some(outer) => Clip {
+            rect: outer.rect.intersection(*rect),
+            ..*outer
+          },
+          None => Clip {
+            rect: *rect,
+            rounded: None,
+          },
+        };
+        clips.push(clip);
+      }
+      DisplayListCommand::PushRoundedClip(rounded) => {
+        let rect = match clips.last() {
+          Some(outer) => outer.rect.intersection(rounded.rect),
+          None => rounded.rect,
+        };
+        clips.push(Clip {
+          rect,
+          rounded: Some(*rounded),
+        });
+      }
+      DisplayListCommand::PopClip => {
+        clips.pop();
+      }
+      DisplayListCommand::PushOpacity(opacity) => {
+        let transparent = Color::new_alpha(0, 0, 0, 0);
+        let group = Canvas::new(canvas.width, canvas.height, transparent);
+        groups.push((group, *opacity));
+      }
+      DisplayListCommand::PopOpacity => {
+        if let Some((group, opacity)) = groups.pop() {
+          match groups.last_mut() {
+            Some((parent, _)) => parent.draw_group(&group, opacity),
+            None => canvas.draw_group(&group, opacity),
+          }
+        }
+      }
+      command => match groups.last_mut() {
+        Some((group, _)) => group.draw_command(command, clips.last()),
+        None => canvas.draw_command(command, clips.last()),
+      },
+    }
+  }
+}
+
+/// A color with premultiplied alpha, at a coverage.
+fn premultiply(color: Color, coverage: f32) -> Pixel {
+  let alpha = color.a as f32 / 255.0 * coverage;
+  [
+    color.r as f32 / 255.0 * alpha,
+    color.g as f32 / 255.0 * alpha,
+    color.b as f32 / 255.0 * alpha,
+    alpha,
+  ]
+}
+
+/// A pixel with premultiplied alpha over another, like `premultiplied_blending`.
+fn source_over(source: Pixel, destination: Pixel) -> Pixel {
+  let remaining = 1.0 - source[3];
+  [0, 1, 2, 3].map(|channel| source[channel] + destination[channel] * remaining)
+}
+
+/// The texel of an image scaled to fill a rect that is nearest to a point, with premultiplied
+/// alpha.
+fn image_pixel(image: &Image, rect: &Rect, (x, y): (f32, f32)) -> Pixel {
+  let texel = |offset: f32, length: f32, size: u32| {
+    ((offset / length * size as f32) as u32).min(size.saturating_sub(1)) as usize
+  };
+  let column = texel(x - rect.x, rect.width, image.width);
+  let row = texel(y - rect.y, rect.height, image.height);
+  match image
+    .pixels
+    .get((row * image.width as usize + column) * 4..)
+    .and_then(|pixel| pixel.get(..4))
+  {
+    Some(&[r, g, b, a]) => premultiply(Color::new_alpha(r, g, b, a), 1.0),
+    _ => [0.0; 4],
+  }
+}
+
+/// The coverage of a pixel by the rounded clip, if there is one.
+fn clip_coverage(point: (f32, f32), clip: Option<&Clip>) -> f32 {
+  match clip.and_then(|clip| clip.rounded.as_ref()) {
+    Some(rounded) => rounded_rect_coverage(point, rounded),
+    None => 1.0,
+  }
+}
+
+/// A rect grown by a distance on every side.
+fn grow(rect: Rect, distance: f32) -> Rect {
+  Rect {
+    x: rect.x - distance,
+    y: rect.y - distance,
+    width: rect.width + 2.0 * distance,
+    height: rect.height + 2.0 * distance,
+  }
+}
+
+/// Whether a convex polygon, in either winding, contains a point: the point is on the same side
+/// of each of its edges.
+fn polygon_coverage((x, y): (f32, f32), points: &Polygon) -> f32 {
+  let (mut before, mut after) = (false, false);
+  for (index, &(x0, y0)) in points.iter().enumerate() {
+    let (x1, y1) = points[(index + 1) % points.len()];
+    let side = (x1 - x0) * (y - y0) - (y1 - y0) * (x - x0);
+    before |= side < 0.0;
+    after |= side > 0.0;
+  }
+  if points.len() >= 3 && !(before && after) {
+    1.0
+  } else {
+    0.0
+  }
+}
+
+/// The coverage of a pixel by a rect with rounded corners, like `rounded_rect_coverage` in the
+/// fragment shaders.
+fn rounded_rect_coverage((x, y): (f32, f32), rounded: &RoundedRect) -> f32 {
+  let rect = rounded.rect;
+  let (left, top) = (rect.x, rect.y);
+  let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+  let distance = (left - x).max(x - right).max((top - y).max(y - bottom));
+
+  let [top_left, top_right, bottom_right, bottom_left] = rounded.radii;
+  let ((radius_x, radius_y), center) = if x < left + top_left.0 && y < top + top_left.1 {
+    (top_left, (left + top_left.0, top + top_left.1))
+  } else if x > right - top_right.0 && y < top + top_right.1 {
+    (top_right, (right - top_right.0, top + top_right.1))
+  } else if x > right - bottom_right.0 && y > bottom - bottom_right.1 {
+    (
+      bottom_right,
+      (right - bottom_right.0, bottom - bottom_right.1),
+    )
+  } else if x < left + bottom_left.0 && y > bottom - bottom_left.1 {
+    (bottom_left, (left + bottom_left.0, bottom - bottom_left.1))
+  } else {
+    return (0.5 - distance).clamp(0.0, 1.0);
+  };
+  if radius_x <= 0.0 || radius_y <= 0.0 {
+    return (0.5 - distance).clamp(0.0, 1.0);
+  }
+
+  // An estimate of the distance to the ellipse of the corner.
+  let q = ((x - center.0) / radius_x, (y - center.1) / radius_y);
+  let k0 = q.0.hypot(q.1);
+  let k1 = (q.0 / radius_x).hypot(q.1 / radius_y);
+  if k1 == 0.0 {
+    return 1.0;
+  }
+  (0.5 - k0 * (k0 - 1.0) / k1).clamp(0.0, 1.0)
+}
+
+/// The coverage of a pixel by a box shadow, like the shadow fragment shader.
+fn shadow_coverage(point: (f32, f32), shadow: &BoxShadow) -> f32 {
+  let covered = if shadow.sigma > 0.0 {
+    blurred_coverage(point, &shadow.shape, shadow.sigma)
+  } else {
+    rounded_rect_coverage(point, &shadow.shape)
+  };
+  let edge = rounded_rect_coverage(point, &shadow.edge);
+  // An outer shadow shows outside the edge of the box, and an inset one inside it, where the
+  // shape of the shadow doesn't cover it.
+  if shadow.inset {
+    (1.0 - covered) * edge
+  } else {
+    covered * (1.0 - edge)
+  }
+}
+
+/// An approximation of the error function.
+fn erf(x: f32) -> f32 {
+  let (s, a) = (x.signum(), x.abs());
+  let x = 1.0 + (0.278393 + (0.230389 + 0.078108 * (a * a)) * a) * a;
+  let x = x * x;
+  s - s / (x * x)
+}
+
+fn gaussian(x: f32, sigma: f32) -> f32 {
+  (-(x * x) / (2.0 * sigma * sigma)).exp() / (2.5066283 * sigma)
+}
+
+/// The blurred shadow along the row at a distance y from the center, where the corner with the
+/// given radii narrows the row.
+fn shadow_row(x: f32, y: f32, radii: (f32, f32), half_size: (f32, f32), sigma: f32) -> f32 {
+  let delta = (half_size.1 - radii.1 - y.abs()).min(0.0) / radii.1.max(0.0001);
+  let curved = half_size.0 - radii.0 + radii.0 * (1.0 - delta * delta).max(0.0).sqrt();
+  let integral = |x: f32| 0.5 + 0.5 * erf(x * (FRAC_1_SQRT_2 / sigma));
+  integral(x + curved) - integral(x - curved)
+}
+
+/// The coverage of a pixel by a rounded rect blurred by a Gaussian, integrated across each row
+/// and summed down the rows from a few samples.
+fn blurred_coverage((x, y): (f32, f32), shape: &RoundedRect, sigma: f32) -> f32 {
+  let rect = shape.rect;
+  let half_size = (rect.width * 0.5, rect.height * 0.5);
+  let point = (x - (rect.x + half_size.0), y - (rect.y + half_size.1));
+
+  // The corner of the quarter of the rect that the pixel is in.
+  let [top_left, top_right, bottom_right, bottom_left] = shape.radii;
+  let radii = match (point.0 < 0.0, point.1 < 0.0) {
+    (true, true) => top_left,
+    (false, true) => top_right,
+    (false, false) => bottom_right,
+    (true, false) => bottom_left,
+  };
+  let radii = (radii.0.min(half_size.0), radii.1.min(half_size.1));
+
+  // The Gaussian is negligible past three standard deviations.
+  let (low, high) = (point.1 - half_size.1, point.1 + half_size.1);
+  let start = (-3.0 * sigma).max(low).min(high);
+  let end = (3.0 * sigma).max(low).min(high);
+  let step = (end - start) / 4.0;
+  (0..4)
+    .map(|sample| {
+      let y = start + step * (sample as f32 + 0.5);
+      shadow_row(point.0, point.1 - y, radii, half_size, sigma) * gaussian(y, sigma) * step
+    })
+    .sum()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{render_list, Canvas, Pixel};
+  use crate::gfx::colors::Color;
+  use crate::gfx::display_list::DisplayListCommand;
+  use crate::layout::geometry::{BoxShadow, Rect, RoundedRect};
+
+  fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect {
+    Rect {
+      x,
+      y,
+      width,
+      height,
+    }
+  }
+
+  fn pixel(canvas: &Canvas, x: u32, y: u32) -> Pixel {
+    canvas.pixels[(y * canvas.width + x) as usize]
+  }
+
+  fn render(commands: Vec<DisplayListCommand>) -> Canvas {
+    let mut canvas = Canvas::new(60, 40, Color::new_alpha(255, 255, 255, 255));
+    render_list(&commands, &mut canvas);
+    canvas
+  }
+
+  /// The alpha of a shadow painted in black on a transparent canvas.
+  fn shadow_alpha(shadow: BoxShadow) -> impl Fn(u32, u32) -> f32 {
+    let mut canvas = Canvas::new(60, 40, Color::new_alpha(0, 0, 0, 0));
+    render_list(&vec![DisplayListCommand::BoxShadow(shadow)], &mut canvas);
+    move |x, y| pixel(&canvas, x, y)[3]
+  }
+
+  fn assert_close(actual: f32, expected: f32) {
+    assert!(
+      (actual - expected).abs() < 0.02,
+      "{} isn't close to {}",
+      actual,
+      expected
+    );
+  }
+
+  #[test]
+  fn fills_the_pixels_a_rect_covers() {
+    let red = Color::new_alpha(255, 0, 0, 255);
+    let canvas = render(vec![DisplayListCommand::Rect(
+      red,
+      rect(10.0, 10.0, 5.0, 5.0),
+    )]);
+    assert_eq!(pixel(&canvas, 10, 10), [1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(pixel(&canvas, 14, 14), [1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(pixel(&canvas, 15, 15), [1.0; 4]);
+    assert_eq!(pixel(&canvas, 9, 10), [1.0; 4]);
+
+    let rgba = canvas.to_rgba8();
+    let offset = (10 * canvas.width as usize + 10) * 4;
+    assert_eq!(rgba[offset..offset + 4], [255, 0, 0, 255]);
+    assert_eq!(rgba[..4], [255, 255, 255, 255]);
+  }
+
+  #[test]
+  fn blurs_outer_shadows_outside_the_box() {
+    let edge = RoundedRect {
+      rect: rect(5.0, 10.0, 20.0, 20.0),
+      radii: [(0.0, 0.0); 4],
+    };
+    // The shadow is moved 20px right, and blurred by a standard deviation of 2px.
+    let alpha = shadow_alpha(BoxShadow {
+      color: Color::new_alpha(0, 0, 0, 255),
+      shape: RoundedRect {
+        rect: rect(25.0, 10.0, 20.0, 20.0),
+        radii: [(0.0, 0.0); 4],
+      },
+      sigma: 2.0,
+      edge,
+      inset: false,
+    });
+
+    // Dark inside the shape, half covered at its edge, and faded past three deviations.
+    assert_close(alpha(35, 20), 1.0);
+    assert_close(alpha(44, 20) + alpha(45, 20), 1.0);
+    assert!(alpha(43, 20) > alpha(44, 20) && alpha(45, 20) > alpha(46, 20));
+    assert_close(alpha(52, 20), 0.0);
+    // Cut to the outside of the box.
+    assert_eq!(alpha(15, 20), 0.0);
+  }
+
+  #[test]
+  fn paints_inset_shadows_inside_the_box() {
+    let edge = RoundedRect {
+      rect: rect(10.0, 10.0, 30.0, 20.0),
+      radii: [(0.0, 0.0); 4],
+    };
+    // The shadow is shrunk by a spread of 5px, and isn't blurred.
+    let alpha = shadow_alpha(BoxShadow {
+      color: Color::new_alpha(0, 0, 0, 255),
+      shape: RoundedRect {
+        rect: rect(15.0, 15.0, 20.0, 10.0),
+        radii: [(0.0, 0.0); 4],
+      },
+      sigma: 0.0,
+      edge,
+      inset: true,
+    });
+    assert_eq!(alpha(12, 20), 1.0);
+    assert_eq!(alpha(25, 20), 0.0);
+    assert_eq!(alpha(5, 20), 0.0);
+  }
+
+  #[test]
+  fn clips_to_rounded_rects_and_composites_groups() {
+    let black = Color::new_alpha(0, 0, 0, 255);
+    let canvas = render(vec![
+      DisplayListCommand::PushRoundedClip(RoundedRect {
+        rect: rect(0.0, 0.0, 20.0, 20.0),
+        radii: [(10.0, 10.0); 4],
+      }),
+      DisplayListCommand::PushOpacity(0.5),
+      DisplayListCommand::Rect(black, rect(0.0, 0.0, 60.0, 40.0)),
+      DisplayListCommand::PopOpacity,
+      DisplayListCommand::PopClip,
+    ]);
+    // Half of the black group shows over white inside the clip, and none of it past the curve
+    // of the corners or outside the rect.
+    assert_eq!(pixel(&canvas, 10, 10), [0.5, 0.5, 0.5, 1.0]);
+    assert_eq!(pixel(&canvas, 0, 0), [1.0; 4]);
+    assert_eq!(pixel(&canvas, 30, 10), [1.0; 4]);
+  }
+
+  #[test]
+  fn composites_nested_groups_once_each() {
+    let black = Color::new_alpha(0, 0, 0, 255);
+    let canvas = render(vec![
+      DisplayListCommand::PushOpacity(0.5),
+      // Overlapping fills in a group cover each other before the group is composited.
+      DisplayListCommand::Rect(black, rect(0.0, 0.0, 20.0, 20.0)),
+      DisplayListCommand::Rect(black, rect(10.0, 0.0, 20.0, 20.0)),
+      DisplayListCommand::PushOpacity(0.5),
+      DisplayListCommand::Rect(black, rect(40.0, 0.0, 10.0, 10.0)),
+      DisplayListCommand::PopOpacity,
+      DisplayListCommand::PopOpacity,
+    ]);
+    assert_eq!(pixel(&canvas, 5, 5), [0.5, 0.5, 0.5, 1.0]);
+    assert_eq!(pixel(&canvas, 15, 5), [0.5, 0.5, 0.5, 1.0]);
+    // The inner group is composited into the outer one, which halves it again.
+    assert_eq!(pixel(&canvas, 45, 5), [0.75, 0.75, 0.75, 1.0]);
+    assert_eq!(pixel(&canvas, 45, 15), [1.0; 4]);
+  }
+}
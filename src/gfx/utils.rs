@@ -1,3 +1,5 @@
+use crate::layout::geometry::Rect;
+
 pub fn gl_to_pos(position: (i32, i32), dimensions: (u32, u32)) -> [f32; 2] {
   let (pixel_x, pixel_y) = position;
   let (d_width, d_height) = dimensions;
@@ -8,3 +10,30 @@ pub fn gl_to_pos(position: (i32, i32), dimensions: (u32, u32)) -> [f32; 2] {
 
   [x, y]
 }
+
+/// The position of a point in pixels from the top left of the framebuffer, in OpenGL
+/// coordinates, like `gl_to_pos` for points between pixels.
+pub fn point_to_gl(point: (f32, f32), dimensions: (u32, u32)) -> [f32; 2] {
+  let (d_width, d_height) = (dimensions.0 as f32, dimensions.1 as f32);
+  [
+    -1.0 + 2.0 * point.0 / d_width,
+    1.0 - 2.0 * point.1 / d_height,
+  ]
+}
+
+/// The scissor rect that clips drawing to a rect in pixels from the top left of the
+/// framebuffer, which counts from the bottom left instead.
+pub fn rect_to_scissor(rect: &Rect, dimensions: (u32, u32)) -> glium::Rect {
+  let (d_width, d_height) = (dimensions.0 as f32, dimensions.1 as f32);
+  let left = rect.x.max(0.0).min(d_width);
+  let top = rect.y.max(0.0).min(d_height);
+  let right = (rect.x + rect.width).max(left).min(d_width);
+  let bottom = (rect.y + rect.height).max(top).min(d_height);
+
+  glium::Rect {
+    left: left as u32,
+    bottom: (d_height - bottom) as u32,
+    width: (right - left) as u32,
+    height: (bottom - top) as u32,
+  }
+}
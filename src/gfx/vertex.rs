@@ -4,3 +4,11 @@ pub struct Vertex {
 }
 
 implement_vertex!(Vertex, position);
+
+#[derive(Copy, Clone)]
+pub struct TexturedVertex {
+    pub position: [f32; 2],
+    pub tex_coords: [f32; 2],
+}
+
+implement_vertex!(TexturedVertex, position, tex_coords);
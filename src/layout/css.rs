@@ -6,26 +6,50 @@
 // Data structures:
 
 use super::dom::ElementData;
+use super::font::FontFace;
 use crate::gfx::colors::Color;
 use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 #[derive(Debug)]
 pub struct Stylesheet {
   pub rules: Vec<Rule>,
+  pub font_faces: Vec<FontFaceRule>,
+  pub page_rules: Vec<PageRule>,
 }
 
 impl Stylesheet {
-  fn find_matching_rules(&self, data: &ElementData) -> Vec<MatchedRule> {
+  fn find_matching_rules(
+    &self,
+    data: &ElementData,
+    pseudo_element: Option<PseudoElement>,
+  ) -> Vec<MatchedRule> {
     self
       .rules
       .iter()
-      .filter_map(|rule| find_rule_matches(rule, data))
+      .filter_map(|rule| find_rule_matches(rule, data, pseudo_element))
       .collect()
   }
 
   pub fn specified_values_for_element(&self, data: &ElementData) -> super::style::StylePropertyMap {
+    self.specified_values(self.find_matching_rules(data, None))
+  }
+
+  /// The specified values of a pseudo-element of an element, or `None` if no rule applies to it.
+  pub fn specified_values_for_pseudo_element(
+    &self,
+    data: &ElementData,
+    pseudo_element: PseudoElement,
+  ) -> Option<super::style::StylePropertyMap> {
+    let rules = self.find_matching_rules(data, Some(pseudo_element));
+    if rules.is_empty() {
+      return None;
+    }
+    Some(self.specified_values(rules))
+  }
+
+  fn specified_values(&self, mut rules: Vec<MatchedRule>) -> super::style::StylePropertyMap {
     let mut values = HashMap::new();
-    let mut rules = self.find_matching_rules(data);
 
     // Go through the rules from lowest to highest specificity.
     rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
@@ -45,14 +69,40 @@ pub struct Rule {
   pub declarations: Vec<Declaration>,
 }
 
+/// An `@font-face` rule, which describes a font face that text can select by its family name.
+///
+/// https://www.w3.org/TR/css-fonts-4/#font-face-rule
+#[derive(Debug)]
+pub struct FontFaceRule {
+  pub declarations: Vec<Declaration>,
+  // The font face loaded from the `src` of the rule, see `font::load_font_faces`.
+  pub face: Option<Rc<FontFace>>,
+}
+
+/// An `@page` rule, which sets the size and margins of the pages of a paged layout.
+///
+/// https://www.w3.org/TR/css-page-3/#at-page-rule
+#[derive(Debug)]
+pub struct PageRule {
+  // The page selector, like `:first`, empty when the rule applies to every page.
+  pub selector: String,
+  pub declarations: Vec<Declaration>,
+}
+
 type MatchedRule<'a> = (Specificity, &'a Rule);
 
 // NOTE: this is not an impl to prevent a lifetime rabbit hole that bubbles up to `Stylesheet`
-fn find_rule_matches<'a>(rule: &'a Rule, data: &ElementData) -> Option<MatchedRule<'a>> {
+fn find_rule_matches<'a>(
+  rule: &'a Rule,
+  data: &ElementData,
+  pseudo_element: Option<PseudoElement>,
+) -> Option<MatchedRule<'a>> {
   let x = rule
     .selectors
     .iter()
-    .find(|selector| selector.matches_with_element_data(data))
+    .find(|selector| {
+      selector.pseudo_element() == pseudo_element && selector.matches_with_element_data(data)
+    })
     .map(|selector| (selector.specificity(), rule));
 
   x
@@ -69,6 +119,13 @@ impl Selector {
       Selector::Simple(selector) => return selector.matches_with_element_data(data),
     }
   }
+
+  /// The pseudo-element the selector matches, rather than the element itself.
+  fn pseudo_element(&self) -> Option<PseudoElement> {
+    match self {
+      Selector::Simple(selector) => selector.pseudo_element,
+    }
+  }
 }
 
 #[derive(Debug)]
@@ -76,10 +133,27 @@ pub struct SimpleSelector {
   pub tag_name: Option<String>,
   pub id: Option<String>,
   pub class: Vec<String>,
+  pub pseudo_element: Option<PseudoElement>,
+  // Pseudo-classes and pseudo-elements that aren't supported, which keep the selector from
+  // matching anything.
+  pub unsupported: Vec<String>,
+}
+
+/// The pseudo-elements that generate content, selected with `::before` and `::after`.
+///
+/// https://www.w3.org/TR/css-pseudo-4/#generated-content
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PseudoElement {
+  Before,
+  After,
 }
 
 impl SimpleSelector {
   fn matches_with_element_data(&self, data: &ElementData) -> bool {
+    if !self.unsupported.is_empty() {
+      return false;
+    }
+
     // Check type selector
     if self.tag_name.iter().any(|name| data.tag_name != *name) {
       return false;
@@ -116,13 +190,35 @@ pub struct Declaration {
 pub enum Value {
   Keyword(String),
   Length(f32, Unit),
+  Number(f32),
   ColorValue(Color),
+  Str(String),
+  Function(String, Vec<Value>),
+  List(Vec<Value>),
+  // The `/` separating parts of a value, e.g. `grid-column: 1 / 3`.
+  Slash,
+  // The `,` separating the items of a comma-separated value, e.g. `font-family: Inter, serif`.
+  Comma,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Unit {
   Px,
   Percentage,
+  Fr,
+  // Lengths relative to the font size, which the style tree computes to px, see `style::inherit`.
+  Em,
+  Rem,
+  Ex,
+  Ch,
+  // Lengths relative to the size of the viewport, resolved in layout.
+  Vw,
+  Vh,
+  Vmin,
+  Vmax,
+  // Angles, in degrees, and times, in seconds.
+  Deg,
+  S,
 }
 
 pub type Specificity = (usize, usize, usize);
@@ -133,50 +229,110 @@ impl Selector {
     let Selector::Simple(ref simple) = *self;
     let a = simple.id.iter().count();
     let b = simple.class.len();
-    let c = simple.tag_name.iter().count();
+    let c = simple.tag_name.iter().count() + simple.pseudo_element.iter().count();
     (a, b, c)
   }
 }
 
 impl Value {
-  /// Return the size of a length in px, or zero for non-lengths.
-  pub fn to_px(&self, reference: f32) -> f32 {
+  /// Return the size of a length in px, or zero for non-lengths. Percentages are relative to
+  /// `reference`, and viewport-percentage lengths to the viewport.
+  pub fn to_px(&self, reference: f32, viewport: Viewport) -> f32 {
+    let Viewport { width, height } = viewport;
     match *self {
       Value::Length(f, Unit::Percentage) => (f / 100.0) * reference,
       Value::Length(f, Unit::Px) => f,
+      Value::Length(f, Unit::Vw) => f / 100.0 * width,
+      Value::Length(f, Unit::Vh) => f / 100.0 * height,
+      Value::Length(f, Unit::Vmin) => f / 100.0 * width.min(height),
+      Value::Length(f, Unit::Vmax) => f / 100.0 * width.max(height),
       _ => 0.0,
     }
   }
 }
 
+/// The size of the viewport that the viewport-percentage lengths are resolved against, the
+/// window or the page area.
+///
+/// https://www.w3.org/TR/css-values-4/#viewport-relative-lengths
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Viewport {
+  pub width: f32,
+  pub height: f32,
+}
+
 /// Parse a whole CSS stylesheet.
 pub fn parse(source: String) -> Stylesheet {
   let mut parser = Parser {
     pos: 0,
     input: source,
+    invalid: false,
   };
+  let mut rules = Vec::new();
+  let mut font_faces = Vec::new();
+  let mut page_rules = Vec::new();
+  loop {
+    parser.consume_whitespace();
+    if parser.eof() {
+      break;
+    }
+    if parser.next_char() != '@' {
+      rules.push(parser.parse_rule());
+      continue;
+    }
+    match &*parser.parse_at_keyword() {
+      "font-face" => font_faces.push(FontFaceRule {
+        declarations: parser.parse_declarations(),
+        face: None,
+      }),
+      "page" => page_rules.push(PageRule {
+        selector: parser.consume_while(|c| c != '{').trim().to_string(),
+        declarations: parser.parse_declarations(),
+      }),
+      _ => parser.skip_at_rule(),
+    }
+  }
   Stylesheet {
-    rules: parser.parse_rules(),
+    rules,
+    font_faces,
+    page_rules,
   }
 }
 
 struct Parser {
   pos: usize,
   input: String,
+  // Whether the declaration that is parsed has a value that isn't valid, like a length with an
+  // unknown unit, so that it is dropped.
+  invalid: bool,
 }
 
 impl Parser {
-  /// Parse a list of rule sets, separated by optional whitespace.
-  fn parse_rules(&mut self) -> Vec<Rule> {
-    let mut rules = Vec::new();
-    loop {
-      self.consume_whitespace();
-      if self.eof() {
-        break;
+  /// Parse the name of an at-rule, like `font-face` in `@font-face`.
+  fn parse_at_keyword(&mut self) -> String {
+    assert_eq!(self.consume_char(), '@');
+    let name = self.parse_identifier().to_ascii_lowercase();
+    self.consume_whitespace();
+    name
+  }
+
+  /// Skip the rest of an unsupported at-rule, up to the end of its block or the `;` that ends
+  /// it.
+  fn skip_at_rule(&mut self) {
+    let mut depth = 0;
+    while !self.eof() {
+      match self.consume_char() {
+        ';' if depth == 0 => break,
+        '{' => depth += 1,
+        '}' => {
+          depth -= 1;
+          if depth == 0 {
+            break;
+          }
+        }
+        _ => {}
       }
-      rules.push(self.parse_rule());
     }
-    rules
   }
 
   /// Parse a rule set: `<selectors> { <declarations> }`.
@@ -213,6 +369,8 @@ impl Parser {
       tag_name: None,
       id: None,
       class: Vec::new(),
+      pseudo_element: None,
+      unsupported: Vec::new(),
     };
     while !self.eof() {
       match self.next_char() {
@@ -228,6 +386,19 @@ impl Parser {
           // universal selector
           self.consume_char();
         }
+        ':' => {
+          // Pseudo-elements start with `::`, except for the legacy `:before` and `:after`.
+          self.consume_char();
+          if self.next_char() == ':' {
+            self.consume_char();
+          }
+          let name = self.parse_identifier();
+          match &*name.to_ascii_lowercase() {
+            "before" => selector.pseudo_element = Some(PseudoElement::Before),
+            "after" => selector.pseudo_element = Some(PseudoElement::After),
+            _ => selector.unsupported.push(name),
+          }
+        }
         c if valid_identifier_char(c) => {
           selector.tag_name = Some(self.parse_identifier());
         }
@@ -247,77 +418,232 @@ impl Parser {
         self.consume_char();
         break;
       }
-      declarations.push(self.parse_declaration());
+      if let Some(declaration) = self.parse_declaration() {
+        declarations.push(declaration);
+      }
     }
     declarations
   }
 
-  /// Parse one `<property>: <value>;` declaration.
-  fn parse_declaration(&mut self) -> Declaration {
+  /// Parse one `<property>: <value>;` declaration. The items of a comma-separated value are
+  /// put in one list, separated by `Value::Comma`. A declaration with a value that isn't valid
+  /// is `None`.
+  fn parse_declaration(&mut self) -> Option<Declaration> {
+    self.invalid = false;
     let property_name = self.parse_identifier();
     self.consume_whitespace();
     assert_eq!(self.consume_char(), ':');
     self.consume_whitespace();
-    let value = self.parse_value();
-    self.consume_whitespace();
+    let mut value = self.parse_value_list();
+    if self.next_char() == ',' {
+      let mut values = Vec::new();
+      loop {
+        match value {
+          Value::List(items) => values.extend(items),
+          value => values.push(value),
+        }
+        if self.next_char() != ',' {
+          break;
+        }
+        self.consume_char();
+        self.consume_whitespace();
+        values.push(Value::Comma);
+        value = self.parse_value_list();
+      }
+      value = Value::List(values);
+    }
     assert_eq!(self.consume_char(), ';');
 
-    Declaration {
+    if self.invalid {
+      return None;
+    }
+    Some(Declaration {
       name: property_name,
       value: value,
-    }
+    })
   }
 
   // Methods for parsing values:
 
+  /// Parse a space-separated list of component values, e.g. `1 1 0px`, up to the end of the
+  /// declaration or function argument.
+  ///
+  /// A single component is returned as is, rather than as a list of one.
+  fn parse_value_list(&mut self) -> Value {
+    let mut values = Vec::new();
+    loop {
+      values.push(self.parse_value());
+      self.consume_whitespace();
+      match self.next_char() {
+        ';' | ',' | ')' => break,
+        _ => {}
+      }
+    }
+
+    if values.len() == 1 {
+      values.pop().unwrap()
+    } else {
+      Value::List(values)
+    }
+  }
+
   fn parse_value(&mut self) -> Value {
     match self.next_char() {
-      '0'..='9' => self.parse_length(),
+      '0'..='9' | '.' => self.parse_length(),
+      '-' if self.peek_at(1).is_ascii_digit() || self.peek_at(1) == '.' => self.parse_length(),
       '#' => self.parse_color(),
-      _ => Value::Keyword(self.parse_identifier()),
+      '"' | '\'' => Value::Str(self.parse_string()),
+      '/' => {
+        self.consume_char();
+        Value::Slash
+      }
+      c if valid_identifier_char(c) => {
+        let identifier = self.parse_identifier();
+        if !self.eof() && self.next_char() == '(' {
+          let function = self.parse_function(identifier);
+          if let Value::Function(ref name, ref arguments) = function {
+            if let Some(color) = rgb_color(name, arguments) {
+              return Value::ColorValue(color);
+            }
+          }
+          function
+        } else if identifier.eq_ignore_ascii_case("transparent") {
+          Value::ColorValue(Color::transparent())
+        } else {
+          Value::Keyword(identifier)
+        }
+      }
+      c => panic!("Unexpected character {} in value", c),
     }
   }
 
+  /// Parse the comma-separated arguments of a function like `minmax(100px, 1fr)`. The unquoted
+  /// argument of `url()` is a string.
+  fn parse_function(&mut self, name: String) -> Value {
+    assert_eq!(self.consume_char(), '(');
+    if name.eq_ignore_ascii_case("url") {
+      self.consume_whitespace();
+      if self.next_char() != '"' && self.next_char() != '\'' {
+        let url = self.consume_while(|c| c != ')');
+        self.consume_char();
+        return Value::Function(name, vec![Value::Str(url.trim_end().to_string())]);
+      }
+    }
+    let mut arguments = Vec::new();
+    loop {
+      self.consume_whitespace();
+      if self.next_char() == ')' {
+        self.consume_char();
+        break;
+      }
+      arguments.push(self.parse_value_list());
+      if self.next_char() == ',' {
+        self.consume_char();
+      }
+    }
+    Value::Function(name, arguments)
+  }
+
+  /// Parse a quoted string, without the quotes.
+  fn parse_string(&mut self) -> String {
+    let quote = self.consume_char();
+    let string = self.consume_while(|c| c != quote);
+    assert_eq!(self.consume_char(), quote);
+    string
+  }
+
+  /// Parse a length, or a plain number when no unit follows (e.g. `0` or `-1`).
   fn parse_length(&mut self) -> Value {
-    Value::Length(self.parse_float(), self.parse_unit())
+    let value = self.parse_float();
+    match self.next_char() {
+      '%' | 'a'..='z' | 'A'..='Z' => match self.parse_unit() {
+        Some((unit, scale)) => Value::Length(value * scale, unit),
+        None => {
+          self.invalid = true;
+          Value::Number(value)
+        }
+      },
+      _ => Value::Number(value),
+    }
   }
 
   fn parse_float(&mut self) -> f32 {
-    let s = self.consume_while(|c| match c {
+    let mut s = String::new();
+    if self.next_char() == '-' {
+      s.push(self.consume_char());
+    }
+    s.push_str(&self.consume_while(|c| match c {
       '0'..='9' | '.' => true,
       _ => false,
-    });
+    }));
     s.parse().unwrap()
   }
 
-  fn parse_unit(&mut self) -> Unit {
+  /// Parse a unit, with the factor that converts values to it, or `None` for an unknown unit.
+  /// Absolute lengths are converted to px, at 96px to the inch, angles to degrees and times to
+  /// seconds.
+  ///
+  /// https://www.w3.org/TR/css-values-4/#absolute-lengths
+  /// https://www.w3.org/TR/css-values-4/#other-units
+  fn parse_unit(&mut self) -> Option<(Unit, f32)> {
     if self.next_char() == '%' {
       self.consume_char();
-      return Unit::Percentage;
+      return Some((Unit::Percentage, 1.0));
     }
 
-    match &*self.parse_identifier().to_ascii_lowercase() {
-      "px" => Unit::Px,
-      _ => panic!("unrecognized unit"),
-    }
+    let unit = match &*self.parse_identifier().to_ascii_lowercase() {
+      "px" => (Unit::Px, 1.0),
+      "in" => (Unit::Px, 96.0),
+      "cm" => (Unit::Px, 96.0 / 2.54),
+      "mm" => (Unit::Px, 96.0 / 25.4),
+      "q" => (Unit::Px, 96.0 / 101.6),
+      "pt" => (Unit::Px, 96.0 / 72.0),
+      "pc" => (Unit::Px, 16.0),
+      "fr" => (Unit::Fr, 1.0),
+      "em" => (Unit::Em, 1.0),
+      "rem" => (Unit::Rem, 1.0),
+      "ex" => (Unit::Ex, 1.0),
+      "ch" => (Unit::Ch, 1.0),
+      "vw" => (Unit::Vw, 1.0),
+      "vh" => (Unit::Vh, 1.0),
+      "vmin" => (Unit::Vmin, 1.0),
+      "vmax" => (Unit::Vmax, 1.0),
+      "deg" => (Unit::Deg, 1.0),
+      "grad" => (Unit::Deg, 0.9),
+      "rad" => (Unit::Deg, 180.0 / std::f32::consts::PI),
+      "turn" => (Unit::Deg, 360.0),
+      "s" => (Unit::S, 1.0),
+      "ms" => (Unit::S, 0.001),
+      _ => return None,
+    };
+    Some(unit)
   }
 
+  /// Parse a hex color, with 3 or 6 digits, or 4 or 8 with the alpha channel.
   fn parse_color(&mut self) -> Value {
     assert_eq!(self.consume_char(), '#');
     let hex_value = self.peek_while(valid_hex_char);
 
     match hex_value.len() {
-      3 => Value::ColorValue(Color {
+      3 | 4 => Value::ColorValue(Color {
         r: self.parse_hex_single(),
         g: self.parse_hex_single(),
         b: self.parse_hex_single(),
-        a: 255,
+        a: if hex_value.len() == 4 {
+          self.parse_hex_single()
+        } else {
+          255
+        },
       }),
-      6 => Value::ColorValue(Color {
+      6 | 8 => Value::ColorValue(Color {
         r: self.parse_hex_pair(),
         g: self.parse_hex_pair(),
         b: self.parse_hex_pair(),
-        a: 255,
+        a: if hex_value.len() == 8 {
+          self.parse_hex_pair()
+        } else {
+          255
+        },
       }),
       _ => panic!("incorrect hex color definition"),
     }
@@ -398,6 +724,42 @@ impl Parser {
   }
 }
 
+/// The color of an `rgb()` or `rgba()` function, with its channels separated by commas, or by
+/// spaces with the alpha after a slash. Channels are numbers from 0 to 255 or percentages, and
+/// the alpha is a number from 0 to 1 or a percentage.
+///
+/// https://www.w3.org/TR/css-color-4/#rgb-functions
+fn rgb_color(name: &str, arguments: &[Value]) -> Option<Color> {
+  if !name.eq_ignore_ascii_case("rgb") && !name.eq_ignore_ascii_case("rgba") {
+    return None;
+  }
+  let values: Vec<&Value> = match arguments {
+    [Value::List(values)] => values
+      .iter()
+      .filter(|value| **value != Value::Slash)
+      .collect(),
+    arguments => arguments.iter().collect(),
+  };
+  let channel = |value: &Value, scale: f32| match *value {
+    Value::Number(number) => Some((number / scale).clamp(0.0, 1.0)),
+    Value::Length(percentage, Unit::Percentage) => Some((percentage / 100.0).clamp(0.0, 1.0)),
+    _ => None,
+  };
+  let byte = |fraction: f32| (fraction * 255.0).round() as u8;
+  match values.as_slice() {
+    [r, g, b] | [r, g, b, _] => Some(Color {
+      r: byte(channel(r, 255.0)?),
+      g: byte(channel(g, 255.0)?),
+      b: byte(channel(b, 255.0)?),
+      a: match values.get(3) {
+        Some(alpha) => byte(channel(alpha, 1.0)?),
+        None => 255,
+      },
+    }),
+    _ => None,
+  }
+}
+
 fn valid_identifier_char(c: char) -> bool {
   match c {
     'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => true, // TODO: Include U+00A0 and higher.
@@ -411,3 +773,67 @@ fn valid_hex_char(c: char) -> bool {
     _ => false,
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn declarations(source: &str) -> Vec<Declaration> {
+    parse(format!("a {{ {} }}", source))
+      .rules
+      .remove(0)
+      .declarations
+  }
+
+  #[test]
+  fn parses_relative_lengths_angles_and_times() {
+    let parsed =
+      declarations("text-indent: 2em; width: 50vw; rotate: 0.5turn; transition-duration: 250ms;");
+    let values: Vec<&Value> = parsed
+      .iter()
+      .map(|declaration| &declaration.value)
+      .collect();
+    assert_eq!(
+      values,
+      vec![
+        &Value::Length(2.0, Unit::Em),
+        &Value::Length(50.0, Unit::Vw),
+        &Value::Length(180.0, Unit::Deg),
+        &Value::Length(0.25, Unit::S),
+      ]
+    );
+  }
+
+  #[test]
+  fn resolves_percentages_and_viewport_percentages() {
+    let viewport = Viewport {
+      width: 800.0,
+      height: 600.0,
+    };
+    let px = |value: f32, unit: Unit| Value::Length(value, unit).to_px(200.0, viewport);
+    assert_eq!(px(10.0, Unit::Percentage), 20.0);
+    assert_eq!(px(10.0, Unit::Vw), 80.0);
+    assert_eq!(px(10.0, Unit::Vh), 60.0);
+    assert_eq!(px(10.0, Unit::Vmin), 60.0);
+    assert_eq!(px(10.0, Unit::Vmax), 80.0);
+  }
+
+  #[test]
+  fn drops_declarations_with_unknown_units() {
+    let parsed = declarations("width: 10furlongs; height: 10px;");
+    assert_eq!(parsed.len(), 1);
+    assert_eq!(parsed[0].name, "height");
+  }
+
+  #[test]
+  fn parses_functions_with_angles() {
+    let parsed = declarations("transform: rotate(45deg) scale(2);");
+    assert_eq!(
+      parsed[0].value,
+      Value::List(vec![
+        Value::Function("rotate".to_string(), vec![Value::Length(45.0, Unit::Deg)]),
+        Value::Function("scale".to_string(), vec![Value::Number(2.0)]),
+      ])
+    );
+  }
+}
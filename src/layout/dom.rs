@@ -1,5 +1,6 @@
 use std::collections::{HashMap, HashSet};
 
+use super::image::Image;
 use html5ever::rcdom;
 use std::rc::Rc;
 
@@ -10,6 +11,8 @@ pub struct ElementData {
   pub tag_name: String,
   pub attributes: AttrMap,
   pub is_debug_mode: bool,
+  // The decoded image of a replaced element like `<img>`, see `image::load_images`.
+  pub image: Option<Rc<Image>>,
 }
 
 impl ElementData {
@@ -85,6 +88,7 @@ pub fn elem(name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
       tag_name: name,
       attributes: attrs,
       is_debug_mode,
+      image: None,
     }),
     is_debug_mode,
   }
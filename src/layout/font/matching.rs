@@ -0,0 +1,100 @@
+//! Font style matching, which narrows the faces of a family down to those closest to the desired
+//! `font-stretch`, then `font-style`, then `font-weight`.
+//!
+//! https://www.w3.org/TR/css-fonts-4/#font-style-matching
+
+use super::FontFace;
+use crate::layout::style::FontStyle;
+
+/// The face that best matches a weight, style and stretch among some faces.
+pub(super) fn best_face<'f, I>(
+  faces: I,
+  weight: f32,
+  style: FontStyle,
+  stretch: f32,
+) -> Option<&'f FontFace>
+where
+  I: IntoIterator<Item = &'f FontFace>,
+{
+  let faces = faces.into_iter().collect();
+  let faces = narrow(faces, |face| stretch_distance(face.stretch, stretch));
+  let faces = narrow(faces, |face| style_distance(face.style, style));
+  let faces = narrow(faces, |face| weight_distance(face.weight, weight));
+  faces.into_iter().next()
+}
+
+/// The faces with the smallest key.
+fn narrow<K, F>(faces: Vec<&FontFace>, key: F) -> Vec<&FontFace>
+where
+  K: PartialOrd,
+  F: Fn(&FontFace) -> K,
+{
+  let keys: Vec<K> = faces.iter().map(|face| key(face)).collect();
+  let best = match keys
+    .iter()
+    .reduce(|best, key| if key < best { key } else { best })
+  {
+    Some(best) => best,
+    None => return faces,
+  };
+  faces
+    .iter()
+    .zip(keys.iter())
+    .filter(|(_, key)| *key == best)
+    .map(|(face, _)| *face)
+    .collect()
+}
+
+/// The value of a range closest to a desired one.
+fn closest((min, max): (f32, f32), desired: f32) -> f32 {
+  desired.max(min).min(max)
+}
+
+/// How far a face's widths are from a desired one: narrower widths are preferred for a desired
+/// width up to `normal`, and wider ones otherwise.
+fn stretch_distance(range: (f32, f32), desired: f32) -> (u8, f32) {
+  let value = closest(range, desired);
+  let preferred = if desired <= 100.0 {
+    value <= desired
+  } else {
+    value >= desired
+  };
+  (!preferred as u8, (value - desired).abs())
+}
+
+/// How far a face's style is from a desired one: italic falls back to oblique, oblique to italic,
+/// and normal to oblique, before the remaining style.
+fn style_distance(style: FontStyle, desired: FontStyle) -> usize {
+  let order = match desired {
+    FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+    FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal],
+    FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic],
+  };
+  order
+    .iter()
+    .position(|s| *s == style)
+    .unwrap_or(order.len())
+}
+
+/// How far a face's weights are from a desired one: a desired weight from 400 to 500 prefers
+/// heavier weights up to 500, then lighter weights, then heavier ones. Lighter weights are
+/// preferred below 400, and heavier ones above 500.
+fn weight_distance(range: (f32, f32), desired: f32) -> (u8, f32) {
+  let value = closest(range, desired);
+  let rank = if value == desired {
+    0
+  } else if (400.0..=500.0).contains(&desired) {
+    if value > desired && value <= 500.0 {
+      1
+    } else if value < desired {
+      2
+    } else {
+      3
+    }
+  } else if (desired < 400.0) == (value < desired) {
+    1
+  } else {
+    2
+  };
+  (rank, (value - desired).abs())
+}
@@ -0,0 +1,305 @@
+//! Fonts: the faces of `@font-face` rules, loaded from local TrueType, OpenType, WOFF and WOFF2
+//! files, font matching, which picks the face for each character of some text by its
+//! `font-family`, `font-weight`, `font-style` and `font-stretch`, and the shaping of the runs of
+//! text that each face renders.
+//!
+//! There is no database of installed fonts, so `local()` sources and generic families like
+//! `serif` don't match any face. Text without a face is measured by estimate (see
+//! `geometry::list`).
+//!
+//! https://www.w3.org/TR/css-fonts-4/#font-face-rule
+//! https://www.w3.org/TR/css-fonts-4/#font-matching-algorithm
+
+mod matching;
+mod sfnt;
+mod shaping;
+#[cfg(test)]
+mod testing;
+mod woff;
+mod woff2;
+
+pub use sfnt::{Font, FontError};
+pub use shaping::FontSettings;
+
+use super::css::{Declaration, Stylesheet, Value};
+use super::style::{font_stretch_value, font_weight_value, FontStyle, StyledNode};
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+/// The formats of `src` that can be loaded, by their `format()` hint.
+const SUPPORTED_FORMATS: &[&str] = &[
+  "truetype",
+  "opentype",
+  "woff",
+  "woff2",
+  "truetype-variations",
+  "opentype-variations",
+  "woff-variations",
+  "woff2-variations",
+];
+
+/// A font face from an `@font-face` rule, with the descriptors that font matching compares.
+pub struct FontFace {
+  pub family: String,
+  // The ranges of weights and widths the face covers.
+  pub weight: (f32, f32),
+  pub style: FontStyle,
+  pub stretch: (f32, f32),
+  pub font: Font,
+}
+
+impl FontFace {
+  /// The face described by the declarations of an `@font-face` rule, for the font loaded from
+  /// its `src`. Descriptors that aren't set, or are `auto`, take the properties of the font.
+  ///
+  /// https://www.w3.org/TR/css-fonts-4/#font-prop-desc
+  fn new(declarations: &[Declaration], font: Font) -> Self {
+    let descriptor = |name: &str| {
+      declarations
+        .iter()
+        .rev()
+        .find(|declaration| declaration.name == name)
+        .map(|declaration| declaration.value.clone())
+    };
+
+    let family = match descriptor("font-family") {
+      Some(Value::Str(family)) => family,
+      Some(Value::Keyword(family)) => family,
+      Some(Value::List(values)) => values
+        .iter()
+        .filter_map(|value| match value {
+          Value::Keyword(keyword) => Some(keyword.as_str()),
+          _ => None,
+        })
+        .collect::<Vec<&str>>()
+        .join(" "),
+      _ => font.family.clone(),
+    };
+    let weight = descriptor("font-weight")
+      .and_then(|value| range(&value, font_weight_value))
+      .unwrap_or((font.weight, font.weight));
+    let stretch = descriptor("font-stretch")
+      .and_then(|value| range(&value, font_stretch_value))
+      .unwrap_or((font.stretch, font.stretch));
+    let style = match descriptor("font-style") {
+      Some(Value::Keyword(keyword)) => FontStyle::from_keyword(&keyword),
+      // An oblique face can be followed by its range of angles.
+      Some(Value::List(values)) => match values.first() {
+        Some(Value::Keyword(keyword)) => FontStyle::from_keyword(keyword),
+        _ => None,
+      },
+      _ => None,
+    };
+    let style = style.unwrap_or(if font.italic {
+      FontStyle::Italic
+    } else if font.oblique {
+      FontStyle::Oblique
+    } else {
+      FontStyle::Normal
+    });
+
+    FontFace {
+      family,
+      weight,
+      style,
+      stretch,
+      font,
+    }
+  }
+}
+
+impl fmt::Debug for FontFace {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "FontFace {{ family: {:?}, weight: {:?}, style: {:?}, stretch: {:?} }}",
+      self.family, self.weight, self.style, self.stretch
+    )
+  }
+}
+
+/// The font faces that the `@font-face` rules of a stylesheet loaded.
+#[derive(Debug, Default)]
+pub struct FontCollection {
+  faces: Vec<Rc<FontFace>>,
+}
+
+impl FontCollection {
+  pub fn new(stylesheet: &Stylesheet) -> Self {
+    FontCollection {
+      faces: stylesheet
+        .font_faces
+        .iter()
+        .filter_map(|rule| rule.face.clone())
+        .collect(),
+    }
+  }
+
+  /// The faces that text with some style selects: the best match in each family of its
+  /// `font-family` that has any faces, in order.
+  pub fn select(&self, style: &StyledNode) -> FontSelection<'_> {
+    let (weight, font_style, stretch) = (
+      style.font_weight(),
+      style.font_style(),
+      style.font_stretch(),
+    );
+    let faces = style
+      .font_families()
+      .iter()
+      .filter_map(|family| {
+        let family_faces = self
+          .faces
+          .iter()
+          .map(Rc::as_ref)
+          .filter(|face| face.family.eq_ignore_ascii_case(family));
+        matching::best_face(family_faces, weight, font_style, stretch)
+      })
+      .collect();
+    FontSelection {
+      faces,
+      collection: self,
+      weight,
+      style: font_style,
+      stretch,
+    }
+  }
+}
+
+/// The faces that text with some style selects.
+pub struct FontSelection<'f> {
+  faces: Vec<&'f FontFace>,
+  collection: &'f FontCollection,
+  weight: f32,
+  style: FontStyle,
+  stretch: f32,
+}
+
+impl<'f> FontSelection<'f> {
+  /// The face that renders a character: the first selected face that has a glyph for it, or
+  /// else the best match among all the faces that have one.
+  ///
+  /// https://www.w3.org/TR/css-fonts-4/#cluster-matching
+  pub fn face_for_char(&self, c: char) -> Option<&'f FontFace> {
+    let has_glyph = |face: &&FontFace| face.font.glyph_index(c).is_some();
+    if let Some(face) = self.faces.iter().copied().find(has_glyph) {
+      return Some(face);
+    }
+    let fallback = self
+      .collection
+      .faces
+      .iter()
+      .map(Rc::as_ref)
+      .filter(has_glyph);
+    matching::best_face(fallback, self.weight, self.style, self.stretch)
+  }
+
+  /// Split text into runs of characters that the same face renders, as their byte ranges, with
+  /// `None` for the characters that no face has a glyph for. Combining marks and joiners stay in
+  /// the run of their base character when its face has a glyph for them.
+  pub fn runs(&self, text: &str) -> Vec<(Range<usize>, Option<&'f FontFace>)> {
+    let mut runs: Vec<(Range<usize>, Option<&'f FontFace>)> = Vec::new();
+    for (index, c) in text.char_indices() {
+      let end = index + c.len_utf8();
+      let next = self.face_for_char(c);
+      if let Some((range, face)) = runs.last_mut() {
+        let extends_cluster =
+          is_cluster_extender(c) && face.is_some_and(|face| face.font.glyph_index(c).is_some());
+        let same_face = match (*face, next) {
+          (Some(face), Some(next)) => std::ptr::eq(face, next),
+          (None, None) => true,
+          _ => false,
+        };
+        if extends_cluster || same_face {
+          range.end = end;
+          continue;
+        }
+      }
+      runs.push((index..end, next));
+    }
+    runs
+  }
+}
+
+/// Whether a character is a combining mark, a variation selector or a joiner, which belongs to
+/// the grapheme cluster of the character before it.
+///
+/// https://www.w3.org/TR/css-fonts-4/#cluster-matching
+fn is_cluster_extender(c: char) -> bool {
+  matches!(
+    c,
+    '\u{300}'..='\u{36f}'
+      | '\u{1ab0}'..='\u{1aff}'
+      | '\u{1dc0}'..='\u{1dff}'
+      | '\u{200c}'..='\u{200d}'
+      | '\u{20d0}'..='\u{20ff}'
+      | '\u{fe00}'..='\u{fe0f}'
+      | '\u{fe20}'..='\u{fe2f}'
+  )
+}
+
+/// Load the font face of every `@font-face` rule in a stylesheet, from the first source in its
+/// `src` that loads, with `url()`s relative to the directory of the stylesheet. Sources in
+/// formats that aren't supported are skipped, and rules without a source that loads are left
+/// out.
+pub fn load_font_faces(stylesheet: &mut Stylesheet, base_path: &Path) {
+  for rule in &mut stylesheet.font_faces {
+    let src = rule
+      .declarations
+      .iter()
+      .rev()
+      .find(|declaration| declaration.name == "src")
+      .map(|declaration| match declaration.value {
+        Value::List(ref values) => values.clone(),
+        ref value => vec![value.clone()],
+      })
+      .unwrap_or_default();
+
+    for source in src.split(|value| *value == Value::Comma) {
+      let mut url = None;
+      let mut supported = true;
+      for value in source {
+        if let Value::Function(name, arguments) = value {
+          match (name.as_str(), arguments.as_slice()) {
+            ("url", [Value::Str(url_string)]) => url = Some(url_string),
+            ("format", [Value::Str(format)]) | ("format", [Value::Keyword(format)]) => {
+              supported = SUPPORTED_FORMATS.contains(&format.to_ascii_lowercase().as_str())
+            }
+            _ => {}
+          }
+        }
+      }
+      let url = match url {
+        Some(url) if supported => url,
+        _ => continue,
+      };
+
+      let path = base_path.join(url.trim_start_matches("file://"));
+      match std::fs::read(&path)
+        .map_err(FontError::from)
+        .and_then(|data| Font::parse(&data))
+      {
+        Ok(font) => {
+          rule.face = Some(Rc::new(FontFace::new(&rule.declarations, font)));
+          break;
+        }
+        Err(error) => eprintln!("Failed to load font {}: {}", path.display(), error),
+      }
+    }
+  }
+}
+
+/// The range of a descriptor like `font-weight: 300 700`, or a single value as a range.
+fn range<F: Fn(&Value) -> Option<f32>>(value: &Value, parse: F) -> Option<(f32, f32)> {
+  match value {
+    Value::List(values) => match values.as_slice() {
+      [min, max] => {
+        let (min, max) = (parse(min)?, parse(max)?);
+        Some((min.min(max), min.max(max)))
+      }
+      _ => None,
+    },
+    value => parse(value).map(|value| (value, value)),
+  }
+}
@@ -0,0 +1,438 @@
+//! OpenType fonts, with TrueType or CFF outlines: the tables that describe a font face, and the
+//! one that maps characters to glyphs. The glyphs and their advances for some text come from
+//! shaping, see `shaping`.
+//!
+//! https://learn.microsoft.com/en-us/typography/opentype/spec/otff
+
+use super::shaping::{RunKey, ShapedRun};
+use super::{woff, woff2};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// The tag of an OpenType table, like `cmap`.
+pub type Tag = [u8; 4];
+
+#[derive(Debug)]
+pub enum FontError {
+  Io(std::io::Error),
+  // A font format that can't be decoded, like a TrueType collection.
+  UnsupportedFormat(&'static str),
+  // A missing table, or one that is too short for what it says it contains.
+  Malformed(&'static str),
+}
+
+impl fmt::Display for FontError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      FontError::Io(error) => write!(f, "{}", error),
+      FontError::UnsupportedFormat(format) => write!(f, "unsupported font format {}", format),
+      FontError::Malformed(table) => write!(f, "malformed {} table", table),
+    }
+  }
+}
+
+impl From<std::io::Error> for FontError {
+  fn from(error: std::io::Error) -> Self {
+    FontError::Io(error)
+  }
+}
+
+/// A parsed font, with the properties that font matching compares and the data that text is
+/// shaped with.
+pub struct Font {
+  // The font file, with a WOFF or WOFF2 file decoded to the OpenType font it wraps.
+  pub(super) data: Vec<u8>,
+  // Where each table is in the data.
+  tables: HashMap<Tag, Range<usize>>,
+  // The typographic family name, or the legacy one.
+  pub family: String,
+  pub weight: f32,
+  pub italic: bool,
+  pub oblique: bool,
+  // The width of the font as a percentage of normal, like `font-stretch`.
+  pub stretch: f32,
+  pub units_per_em: f32,
+  // The `cmap` subtable that maps characters to glyphs, as its offset and format.
+  cmap: Option<(usize, u16)>,
+  // The runs of text that were shaped with the font, see `Font::shape`.
+  pub(super) shaped_runs: RefCell<HashMap<RunKey, Rc<ShapedRun>>>,
+}
+
+impl Font {
+  /// Parse a TrueType or OpenType font, or one wrapped in a WOFF or WOFF2 file.
+  pub fn parse(data: &[u8]) -> Result<Self, FontError> {
+    let data = match data.get(0..4) {
+      Some(b"wOFF") => woff::decode(data)?,
+      Some(b"wOF2") => woff2::decode(data)?,
+      Some(b"ttcf") => return Err(FontError::UnsupportedFormat("TrueType collection")),
+      Some([0, 1, 0, 0]) | Some(b"OTTO") | Some(b"true") => data.to_vec(),
+      _ => return Err(FontError::UnsupportedFormat("unknown")),
+    };
+    let tables = sfnt_tables(&data)?;
+    Font::from_tables(data, tables)
+  }
+
+  fn from_tables(data: Vec<u8>, tables: HashMap<Tag, Range<usize>>) -> Result<Self, FontError> {
+    let table = |tag: &Tag| tables.get(tag).map(|range| &data[range.clone()]);
+    let head = table(b"head").ok_or(FontError::Malformed("head"))?;
+    let units_per_em = read_u16(head, 18, "head")? as f32;
+    let mac_style = read_u16(head, 44, "head")?;
+
+    // The weight, width and slope come from the `OS/2` table, or `head` without one.
+    let (weight, stretch, italic, oblique) = match table(b"OS/2") {
+      Some(os2) => {
+        let width_class = read_u16(os2, 6, "OS/2")?;
+        let selection = read_u16(os2, 62, "OS/2").unwrap_or(0);
+        (
+          read_u16(os2, 4, "OS/2")? as f32,
+          stretch_of_width_class(width_class),
+          selection & 1 != 0,
+          selection & (1 << 9) != 0,
+        )
+      }
+      None => (
+        if mac_style & 1 != 0 { 700.0 } else { 400.0 },
+        100.0,
+        mac_style & 2 != 0,
+        false,
+      ),
+    };
+
+    let mut font = Font {
+      family: String::new(),
+      weight,
+      italic,
+      oblique,
+      stretch,
+      units_per_em,
+      cmap: None,
+      data,
+      tables,
+      shaped_runs: RefCell::default(),
+    };
+    font.family = font.family_name().unwrap_or_default();
+    font.cmap = font.find_cmap_subtable()?;
+    Ok(font)
+  }
+
+  /// The data of a table, if the font has it.
+  pub fn table(&self, tag: &Tag) -> Option<&[u8]> {
+    self.tables.get(tag).map(|range| &self.data[range.clone()])
+  }
+
+  /// The glyph of a character, `None` if the font doesn't have one.
+  ///
+  /// https://learn.microsoft.com/en-us/typography/opentype/spec/cmap
+  pub fn glyph_index(&self, c: char) -> Option<u16> {
+    let (offset, format) = self.cmap?;
+    let cmap = self.table(b"cmap")?;
+    let c = c as u32;
+    let glyph = match format {
+      4 if c <= 0xffff => {
+        let seg_count = read_u16(cmap, offset + 6, "cmap").ok()? as usize / 2;
+        let end_codes = offset + 14;
+        let start_codes = end_codes + 2 * seg_count + 2;
+        let id_deltas = start_codes + 2 * seg_count;
+        let id_range_offsets = id_deltas + 2 * seg_count;
+
+        // The first segment that ends at or after the character.
+        let (mut low, mut high) = (0, seg_count);
+        while low < high {
+          let middle = (low + high) / 2;
+          if (read_u16(cmap, end_codes + 2 * middle, "cmap").ok()? as u32) < c {
+            low = middle + 1;
+          } else {
+            high = middle;
+          }
+        }
+        if low == seg_count {
+          return None;
+        }
+        let start = read_u16(cmap, start_codes + 2 * low, "cmap").ok()? as u32;
+        if c < start {
+          return None;
+        }
+        let delta = read_u16(cmap, id_deltas + 2 * low, "cmap").ok()?;
+        let range_offset_position = id_range_offsets + 2 * low;
+        let range_offset = read_u16(cmap, range_offset_position, "cmap").ok()? as usize;
+        if range_offset == 0 {
+          (c as u16).wrapping_add(delta)
+        } else {
+          let position = range_offset_position + range_offset + 2 * (c - start) as usize;
+          match read_u16(cmap, position, "cmap").ok()? {
+            0 => 0,
+            glyph => glyph.wrapping_add(delta),
+          }
+        }
+      }
+      12 => {
+        let group_count = read_u32(cmap, offset + 12, "cmap").ok()? as usize;
+        let groups = offset + 16;
+        let (mut low, mut high) = (0, group_count);
+        while low < high {
+          let middle = (low + high) / 2;
+          let group = groups + 12 * middle;
+          if read_u32(cmap, group + 4, "cmap").ok()? < c {
+            low = middle + 1;
+          } else {
+            high = middle;
+          }
+        }
+        let group = groups + 12 * low;
+        if low == group_count || read_u32(cmap, group, "cmap").ok()? > c {
+          return None;
+        }
+        let start = read_u32(cmap, group, "cmap").ok()?;
+        let start_glyph = read_u32(cmap, group + 8, "cmap").ok()?;
+        u16::try_from(start_glyph.checked_add(c - start)?).ok()?
+      }
+      _ => return None,
+    };
+    if glyph == 0 {
+      None
+    } else {
+      Some(glyph)
+    }
+  }
+
+  /// The typographic family name from the `name` table, or the legacy family name. Windows
+  /// names in US English are preferred.
+  ///
+  /// https://learn.microsoft.com/en-us/typography/opentype/spec/name
+  fn family_name(&self) -> Option<String> {
+    let name = self.table(b"name")?;
+    let count = read_u16(name, 2, "name").ok()? as usize;
+    let storage = read_u16(name, 4, "name").ok()? as usize;
+    let mut best: Option<(u32, String)> = None;
+    for index in 0..count {
+      let record = 6 + 12 * index;
+      let platform = read_u16(name, record, "name").ok()?;
+      let language = read_u16(name, record + 4, "name").ok()?;
+      let name_id = read_u16(name, record + 6, "name").ok()?;
+      let length = read_u16(name, record + 8, "name").ok()? as usize;
+      let offset = storage + read_u16(name, record + 10, "name").ok()? as usize;
+      let bytes = match name.get(offset..offset + length) {
+        Some(bytes) => bytes,
+        None => continue,
+      };
+      let string = match platform {
+        // Unicode and Windows names are UTF-16BE, Macintosh names are treated as Latin-1.
+        0 | 3 => {
+          let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+          String::from_utf16_lossy(&units)
+        }
+        1 => bytes.iter().map(|&byte| byte as char).collect(),
+        _ => continue,
+      };
+      let score = match name_id {
+        16 => 0,
+        1 => 4,
+        _ => continue,
+      } + match (platform, language) {
+        (3, 0x409) => 0,
+        (3, _) | (0, _) => 1,
+        _ => 2,
+      };
+      if best
+        .as_ref()
+        .is_none_or(|(best_score, _)| score < *best_score)
+      {
+        best = Some((score, string));
+      }
+    }
+    best.map(|(_, string)| string)
+  }
+
+  /// The best `cmap` subtable the font has for Unicode characters: one for all of Unicode in
+  /// format 12, or one for the Basic Multilingual Plane in format 4.
+  fn find_cmap_subtable(&self) -> Result<Option<(usize, u16)>, FontError> {
+    let cmap = match self.table(b"cmap") {
+      Some(cmap) => cmap,
+      None => return Ok(None),
+    };
+    let count = read_u16(cmap, 2, "cmap")? as usize;
+    let mut best = None;
+    for index in 0..count {
+      let record = 4 + 8 * index;
+      let platform = read_u16(cmap, record, "cmap")?;
+      let encoding = read_u16(cmap, record + 2, "cmap")?;
+      let offset = read_u32(cmap, record + 4, "cmap")? as usize;
+      let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+      if !unicode {
+        continue;
+      }
+      let format = read_u16(cmap, offset, "cmap")?;
+      let score = match format {
+        12 => 0,
+        4 => 1,
+        _ => continue,
+      };
+      if best.is_none_or(|(best_score, _, _)| score < best_score) {
+        best = Some((score, offset, format));
+      }
+    }
+    Ok(best.map(|(_, offset, format)| (offset, format)))
+  }
+}
+
+impl fmt::Debug for Font {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Font {{ family: {:?} }}", self.family)
+  }
+}
+
+/// Where the tables of an OpenType font file are, from its table directory.
+fn sfnt_tables(data: &[u8]) -> Result<HashMap<Tag, Range<usize>>, FontError> {
+  let count = read_u16(data, 4, "table directory")? as usize;
+  let mut tables = HashMap::new();
+  for index in 0..count {
+    let record = 12 + 16 * index;
+    let tag = read_tag(data, record)?;
+    let offset = read_u32(data, record + 8, "table directory")? as usize;
+    let length = read_u32(data, record + 12, "table directory")? as usize;
+    if offset
+      .checked_add(length)
+      .is_none_or(|end| end > data.len())
+    {
+      return Err(FontError::Malformed("table directory"));
+    }
+    tables.insert(tag, offset..offset + length);
+  }
+  Ok(tables)
+}
+
+/// The `font-stretch` percentage of a `usWidthClass` from 1 (ultra-condensed) to 9
+/// (ultra-expanded).
+fn stretch_of_width_class(width_class: u16) -> f32 {
+  match width_class {
+    1 => 50.0,
+    2 => 62.5,
+    3 => 75.0,
+    4 => 87.5,
+    6 => 112.5,
+    7 => 125.0,
+    8 => 150.0,
+    9 => 200.0,
+    _ => 100.0,
+  }
+}
+
+pub(super) fn read_tag(data: &[u8], offset: usize) -> Result<Tag, FontError> {
+  match data.get(offset..offset + 4) {
+    Some(&[a, b, c, d]) => Ok([a, b, c, d]),
+    _ => Err(FontError::Malformed("table directory")),
+  }
+}
+
+pub(super) fn read_u16(data: &[u8], offset: usize, table: &'static str) -> Result<u16, FontError> {
+  match data.get(offset..offset + 2) {
+    Some(&[a, b]) => Ok(u16::from_be_bytes([a, b])),
+    _ => Err(FontError::Malformed(table)),
+  }
+}
+
+pub(super) fn read_u32(data: &[u8], offset: usize, table: &'static str) -> Result<u32, FontError> {
+  match data.get(offset..offset + 4) {
+    Some(&[a, b, c, d]) => Ok(u32::from_be_bytes([a, b, c, d])),
+    _ => Err(FontError::Malformed(table)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{cmap, cmap_format_12, cmap_format_4, font_tables, sfnt, FAMILY};
+  use super::{Font, FontError};
+
+  #[test]
+  fn parses_the_properties_of_a_font() {
+    let font = Font::parse(&sfnt(&font_tables(cmap(&[]), vec![]))).unwrap();
+    assert_eq!(font.family, FAMILY);
+    assert_eq!(font.weight, 700.0);
+    assert_eq!(font.stretch, 100.0);
+    assert!(font.italic);
+    assert!(!font.oblique);
+    assert_eq!(font.units_per_em, 1000.0);
+    assert_eq!(font.glyph_index('A'), None);
+  }
+
+  #[test]
+  fn maps_characters_through_cmap_format_4() {
+    let tables = font_tables(cmap(&[(3, 1, cmap_format_4())]), vec![]);
+    let font = Font::parse(&sfnt(&tables)).unwrap();
+    let glyphs: Vec<Option<u16>> = "@ABCDabz\u{1f600}"
+      .chars()
+      .map(|c| font.glyph_index(c))
+      .collect();
+    assert_eq!(
+      glyphs,
+      vec![
+        None,
+        Some(1),
+        Some(2),
+        Some(3),
+        None,
+        Some(7),
+        None,
+        None,
+        None
+      ]
+    );
+  }
+
+  #[test]
+  fn maps_characters_through_cmap_format_12() {
+    // The subtable for all of Unicode is preferred over the one for the BMP.
+    let subtables = [(3, 1, cmap_format_4()), (3, 10, cmap_format_12())];
+    let font = Font::parse(&sfnt(&font_tables(cmap(&subtables), vec![]))).unwrap();
+    let glyphs: Vec<Option<u16>> = [
+      'A',
+      '\u{1f5ff}',
+      '\u{1f600}',
+      '\u{1f602}',
+      '\u{1f603}',
+      '\u{20000}',
+      '\u{20001}',
+      '\u{30000}',
+    ]
+    .iter()
+    .map(|&c| font.glyph_index(c))
+    .collect();
+    assert_eq!(
+      glyphs,
+      vec![None, None, Some(20), Some(22), None, None, None, None]
+    );
+  }
+
+  #[test]
+  fn rejects_malformed_fonts() {
+    let data = sfnt(&font_tables(cmap(&[]), vec![]));
+    assert!(matches!(
+      Font::parse(b"GIF89a"),
+      Err(FontError::UnsupportedFormat(_))
+    ));
+    assert!(matches!(
+      Font::parse(&data[..20]),
+      Err(FontError::Malformed("table directory"))
+    ));
+    assert!(matches!(
+      Font::parse(&data[..data.len() - 4]),
+      Err(FontError::Malformed("table directory"))
+    ));
+
+    // A `cmap` subtable past the end of the table.
+    let mut subtable_offset = cmap(&[(3, 1, cmap_format_4())]);
+    subtable_offset[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+    let data = sfnt(&font_tables(subtable_offset, vec![]));
+    assert!(matches!(
+      Font::parse(&data),
+      Err(FontError::Malformed("cmap"))
+    ));
+  }
+}
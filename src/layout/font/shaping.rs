@@ -0,0 +1,138 @@
+//! Text shaping with rustybuzz, a port of HarfBuzz: the glyphs that a run of text in one font
+//! is rendered with and their positions, after the substitutions of the font like ligatures and
+//! the positioning like kerning and mark attachment that complex scripts need. Runs are shaped
+//! with the OpenType features and variation axes that the font properties select, and cached by
+//! their font, size, text and settings.
+//!
+//! Text is not laid out or painted yet, so the shaped runs only size generated text: the width of
+//! a run is its advance along the line. The glyphs and their positions are not drawn.
+//!
+//! https://www.w3.org/TR/css-fonts-4/#font-feature-variation-resolution
+
+use super::sfnt::{Font, Tag};
+use rustybuzz::{Feature, UnicodeBuffer, Variation};
+use std::fmt;
+use std::rc::Rc;
+
+/// The OpenType features and variation axes that text is shaped with, each as its tag and its
+/// value. Later features override earlier ones with the same tag.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FontSettings {
+  pub features: Vec<(Tag, u32)>,
+  pub variations: Vec<(Tag, f32)>,
+}
+
+/// A glyph of a shaped run, with its advance and its offset from where it would be drawn
+/// otherwise, in pixels.
+pub struct ShapedGlyph {
+  pub glyph: u32,
+  // The byte offset in the text of the first character that the glyph renders.
+  pub cluster: usize,
+  pub advance: (f32, f32),
+  pub offset: (f32, f32),
+}
+
+/// The glyphs of a run of text, in visual order.
+#[derive(Default)]
+pub struct ShapedRun {
+  pub glyphs: Vec<ShapedGlyph>,
+}
+
+impl ShapedRun {
+  /// The advance of the run along the line.
+  pub fn width(&self) -> f32 {
+    self.glyphs.iter().map(|glyph| glyph.advance.0).sum()
+  }
+}
+
+impl fmt::Debug for ShapedRun {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_list()
+      .entries(self.glyphs.iter().map(|glyph| {
+        format!(
+          "{}@{} +{:?} {:?}",
+          glyph.glyph, glyph.cluster, glyph.advance, glyph.offset
+        )
+      }))
+      .finish()
+  }
+}
+
+/// What a shaped run is cached by, with the font size and the variation values as their bits.
+#[derive(PartialEq, Eq, Hash)]
+pub(super) struct RunKey {
+  text: String,
+  font_size: u32,
+  features: Vec<(Tag, u32)>,
+  variations: Vec<(Tag, u32)>,
+}
+
+impl Font {
+  /// Shape a run of text at a font size with some settings. Runs are shaped once and shared
+  /// after that.
+  pub fn shape(&self, text: &str, font_size: f32, settings: &FontSettings) -> Rc<ShapedRun> {
+    let key = RunKey {
+      text: text.to_string(),
+      font_size: font_size.to_bits(),
+      features: settings.features.clone(),
+      variations: settings
+        .variations
+        .iter()
+        .map(|(tag, value)| (*tag, value.to_bits()))
+        .collect(),
+    };
+    if let Some(run) = self.shaped_runs.borrow().get(&key) {
+      return run.clone();
+    }
+    let run = Rc::new(self.shape_uncached(text, font_size, settings));
+    self.shaped_runs.borrow_mut().insert(key, run.clone());
+    run
+  }
+
+  fn shape_uncached(&self, text: &str, font_size: f32, settings: &FontSettings) -> ShapedRun {
+    let mut face = match rustybuzz::Face::from_slice(&self.data, 0) {
+      Some(face) => face,
+      None => return ShapedRun::default(),
+    };
+    let variations: Vec<Variation> = settings
+      .variations
+      .iter()
+      .map(|(tag, value)| Variation {
+        tag: rustybuzz::ttf_parser::Tag::from_bytes(tag),
+        value: *value,
+      })
+      .collect();
+    face.set_variations(&variations);
+    let features: Vec<Feature> = settings
+      .features
+      .iter()
+      .map(|(tag, value)| Feature::new(rustybuzz::ttf_parser::Tag::from_bytes(tag), *value, ..))
+      .collect();
+
+    // The script, direction and language of the run are guessed from its characters.
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    let output = rustybuzz::shape(&face, &features, buffer);
+
+    let scale = font_size / self.units_per_em;
+    let glyphs = output
+      .glyph_infos()
+      .iter()
+      .zip(output.glyph_positions())
+      .map(|(info, position)| ShapedGlyph {
+        glyph: info.glyph_id,
+        cluster: info.cluster as usize,
+        advance: (
+          position.x_advance as f32 * scale,
+          position.y_advance as f32 * scale,
+        ),
+        offset: (
+          position.x_offset as f32 * scale,
+          position.y_offset as f32 * scale,
+        ),
+      })
+      .collect();
+    ShapedRun { glyphs }
+  }
+}
@@ -0,0 +1,118 @@
+//! Helpers for the tests of the font parsers: the tables of a small OpenType font, built byte by
+//! byte, and the font files that wrap them.
+
+use super::sfnt::Tag;
+use super::woff::{checksum, sfnt_data};
+
+/// The `flavor` of an OpenType font with TrueType outlines.
+pub const TRUETYPE: u32 = 0x0001_0000;
+
+/// The family name of the test font.
+pub const FAMILY: &str = "Test Sans";
+
+/// Append big-endian 16-bit values to some data.
+pub fn push_u16(data: &mut Vec<u8>, values: &[u16]) {
+  for value in values {
+    data.extend_from_slice(&value.to_be_bytes());
+  }
+}
+
+/// Append big-endian 32-bit values to some data.
+pub fn push_u32(data: &mut Vec<u8>, values: &[u32]) {
+  for value in values {
+    data.extend_from_slice(&value.to_be_bytes());
+  }
+}
+
+/// A `cmap` table with the subtables for some platform and encoding IDs.
+pub fn cmap(subtables: &[(u16, u16, Vec<u8>)]) -> Vec<u8> {
+  let mut data = Vec::new();
+  push_u16(&mut data, &[0, subtables.len() as u16]);
+  let mut offset = 4 + 8 * subtables.len();
+  for (platform, encoding, subtable) in subtables {
+    push_u16(&mut data, &[*platform, *encoding]);
+    push_u32(&mut data, &[offset as u32]);
+    offset += subtable.len();
+  }
+  for (_, _, subtable) in subtables {
+    data.extend_from_slice(subtable);
+  }
+  data
+}
+
+/// A format 4 `cmap` subtable that maps `A` to `C` to glyphs 1 to 3 by a delta, and `a` and `b`
+/// to glyphs 7 and none through the glyph array.
+pub fn cmap_format_4() -> Vec<u8> {
+  let seg_count = 3;
+  let mut data = Vec::new();
+  push_u16(&mut data, &[4, 0, 0, 2 * seg_count, 4, 1, 2]);
+  // The end codes, the reserved pad, the start codes, the deltas and the range offsets. The
+  // range offset of `a` to `b` points past the two remaining range offsets to the glyph array.
+  push_u16(&mut data, &[67, 98, 0xffff, 0]);
+  push_u16(&mut data, &[65, 97, 0xffff]);
+  push_u16(&mut data, &[(-64i16) as u16, 0, 1]);
+  push_u16(&mut data, &[0, 4, 0]);
+  push_u16(&mut data, &[7, 0]);
+  let length = data.len() as u16;
+  data[2..4].copy_from_slice(&length.to_be_bytes());
+  data
+}
+
+/// A format 12 `cmap` subtable that maps U+1F600 to U+1F602 to glyphs 20 to 22, and has groups
+/// whose glyphs overflow 16 and 32 bits.
+pub fn cmap_format_12() -> Vec<u8> {
+  let groups: [[u32; 3]; 3] = [
+    [0x1f600, 0x1f602, 20],
+    [0x20000, 0x20001, 0xffff_ffff],
+    [0x30000, 0x30000, 0x1_0000],
+  ];
+  let mut data = Vec::new();
+  push_u16(&mut data, &[12, 0]);
+  push_u32(
+    &mut data,
+    &[16 + 12 * groups.len() as u32, 0, groups.len() as u32],
+  );
+  for group in &groups {
+    push_u32(&mut data, group);
+  }
+  data
+}
+
+/// The tables of a bold italic font with 1000 units per em, named `FAMILY`, with some `cmap`
+/// table and some other tables.
+pub fn font_tables(cmap: Vec<u8>, other_tables: Vec<(Tag, Vec<u8>)>) -> Vec<(Tag, Vec<u8>)> {
+  let mut head = vec![0; 54];
+  head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+
+  let mut os2 = vec![0; 78];
+  os2[4..6].copy_from_slice(&700u16.to_be_bytes());
+  os2[6..8].copy_from_slice(&5u16.to_be_bytes());
+  os2[62..64].copy_from_slice(&1u16.to_be_bytes());
+
+  let family: Vec<u16> = FAMILY.encode_utf16().collect();
+  let mut name = Vec::new();
+  push_u16(
+    &mut name,
+    &[0, 1, 18, 3, 1, 0x409, 1, 2 * family.len() as u16, 0],
+  );
+  push_u16(&mut name, &family);
+
+  let mut tables = vec![
+    (*b"OS/2", os2),
+    (*b"cmap", cmap),
+    (*b"head", head),
+    (*b"name", name),
+  ];
+  tables.extend(other_tables);
+  tables.sort_by_key(|(tag, _)| *tag);
+  tables
+}
+
+/// The OpenType font file with some tables.
+pub fn sfnt(tables: &[(Tag, Vec<u8>)]) -> Vec<u8> {
+  let tables: Vec<(Tag, u32, Vec<u8>)> = tables
+    .iter()
+    .map(|(tag, table)| (*tag, checksum(table), table.clone()))
+    .collect();
+  sfnt_data(TRUETYPE, &tables)
+}
@@ -0,0 +1,206 @@
+//! WOFF 1.0 files: the tables of an OpenType font, each compressed with zlib.
+//!
+//! https://www.w3.org/TR/WOFF/
+
+use super::sfnt::{read_tag, read_u16, read_u32, FontError, Tag};
+use flate2::read::ZlibDecoder;
+use std::io::Read;
+
+/// The size of the WOFF header, which the table directory follows.
+const HEADER_SIZE: usize = 44;
+
+/// The most tables an OpenType font can have, so that the fields of its table directory, which
+/// count them in units of 16 bytes, fit in 16 bits.
+pub(super) const MAX_TABLES: usize = 4095;
+
+/// Decompress a WOFF file into the OpenType font it wraps.
+pub(super) fn decode(data: &[u8]) -> Result<Vec<u8>, FontError> {
+  let flavor = read_u32(data, 4, "WOFF header")?;
+  let count = read_u16(data, 12, "WOFF header")? as usize;
+  if count > MAX_TABLES {
+    return Err(FontError::Malformed("WOFF header"));
+  }
+  let mut tables: Vec<(Tag, u32, Vec<u8>)> = Vec::new();
+  for index in 0..count {
+    let entry = HEADER_SIZE + 20 * index;
+    let tag = read_tag(data, entry)?;
+    let offset = read_u32(data, entry + 4, "WOFF table directory")? as usize;
+    let compressed_length = read_u32(data, entry + 8, "WOFF table directory")? as usize;
+    let length = read_u32(data, entry + 12, "WOFF table directory")? as usize;
+    let checksum = read_u32(data, entry + 16, "WOFF table directory")?;
+    let compressed = offset
+      .checked_add(compressed_length)
+      .and_then(|end| data.get(offset..end))
+      .ok_or(FontError::Malformed("WOFF table directory"))?;
+
+    // Tables that don't get smaller are stored as they are. The length in the directory isn't
+    // trusted, so the table only grows as much as the data decompresses to, and one byte more
+    // shows that it decompresses to more than the length.
+    let table = if compressed_length < length {
+      let mut table = Vec::new();
+      ZlibDecoder::new(compressed)
+        .take(length as u64 + 1)
+        .read_to_end(&mut table)?;
+      if table.len() != length {
+        return Err(FontError::Malformed("WOFF table"));
+      }
+      table
+    } else {
+      compressed.to_vec()
+    };
+    tables.push((tag, checksum, table));
+  }
+  tables.sort_by_key(|(tag, _, _)| *tag);
+  Ok(sfnt_data(flavor, &tables))
+}
+
+/// An OpenType font file with some tables, in the table directory order, each padded to four
+/// bytes. There can be at most `MAX_TABLES` tables.
+///
+/// https://learn.microsoft.com/en-us/typography/opentype/spec/otff#table-directory
+pub(super) fn sfnt_data(flavor: u32, tables: &[(Tag, u32, Vec<u8>)]) -> Vec<u8> {
+  let count = tables.len();
+  debug_assert!(count <= MAX_TABLES);
+  let entry_selector = (usize::BITS - 1 - count.max(1).leading_zeros()) as usize;
+  let search_range = 16 << entry_selector;
+
+  let mut data = Vec::new();
+  data.extend_from_slice(&flavor.to_be_bytes());
+  for value in [
+    count,
+    search_range,
+    entry_selector,
+    (count * 16).saturating_sub(search_range),
+  ] {
+    data.extend_from_slice(&(value as u16).to_be_bytes());
+  }
+  let mut offset = 12 + 16 * tables.len();
+  for (tag, checksum, table) in tables {
+    data.extend_from_slice(tag);
+    data.extend_from_slice(&checksum.to_be_bytes());
+    data.extend_from_slice(&(offset as u32).to_be_bytes());
+    data.extend_from_slice(&(table.len() as u32).to_be_bytes());
+    offset += (table.len() + 3) & !3;
+  }
+  for (_, _, table) in tables {
+    data.extend_from_slice(table);
+    data.resize((data.len() + 3) & !3, 0);
+  }
+  data
+}
+
+/// The checksum of an OpenType table, the sum of its data as 32-bit integers, padded with zeros.
+///
+/// https://learn.microsoft.com/en-us/typography/opentype/spec/otff#calculating-checksums
+pub(super) fn checksum(table: &[u8]) -> u32 {
+  table.chunks(4).fold(0u32, |sum, chunk| {
+    let mut word = [0; 4];
+    word[..chunk.len()].copy_from_slice(chunk);
+    sum.wrapping_add(u32::from_be_bytes(word))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::sfnt::{Font, FontError, Tag};
+  use super::super::testing::{cmap, cmap_format_4, font_tables, push_u16, push_u32, sfnt};
+  use super::super::testing::{FAMILY, TRUETYPE};
+  use super::{checksum, decode, HEADER_SIZE};
+  use flate2::write::ZlibEncoder;
+  use flate2::Compression;
+  use std::io::Write;
+
+  /// A WOFF file with some tables, each given with the length its directory entry claims.
+  fn woff(tables: &[(Tag, Vec<u8>, usize)]) -> Vec<u8> {
+    let mut data = b"wOFF".to_vec();
+    push_u32(&mut data, &[TRUETYPE, 0]);
+    push_u16(&mut data, &[tables.len() as u16, 0]);
+    push_u32(&mut data, &[0]);
+    push_u16(&mut data, &[1, 0]);
+    push_u32(&mut data, &[0, 0, 0, 0, 0]);
+    assert_eq!(data.len(), HEADER_SIZE);
+
+    let mut offset = HEADER_SIZE + 20 * tables.len();
+    let mut table_data = Vec::new();
+    for (tag, table, length) in tables {
+      let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+      encoder.write_all(table).unwrap();
+      let compressed = encoder.finish().unwrap();
+      let stored = if compressed.len() < table.len() {
+        compressed
+      } else {
+        table.clone()
+      };
+      data.extend_from_slice(tag);
+      push_u32(
+        &mut data,
+        &[
+          offset as u32,
+          stored.len() as u32,
+          *length as u32,
+          checksum(table),
+        ],
+      );
+      offset += stored.len();
+      table_data.extend(stored);
+    }
+    data.extend(table_data);
+    data
+  }
+
+  fn test_tables() -> Vec<(Tag, Vec<u8>)> {
+    // A table of zeros that compresses well, next to the ones that don't.
+    font_tables(
+      cmap(&[(3, 1, cmap_format_4())]),
+      vec![(*b"prep", vec![0; 400])],
+    )
+  }
+
+  #[test]
+  fn decodes_to_the_font_it_wraps() {
+    let tables = test_tables();
+    let file = woff(
+      &tables
+        .iter()
+        .map(|(tag, table)| (*tag, table.clone(), table.len()))
+        .collect::<Vec<_>>(),
+    );
+    assert_eq!(decode(&file).unwrap(), sfnt(&tables));
+
+    let font = Font::parse(&file).unwrap();
+    assert_eq!(font.family, FAMILY);
+    assert_eq!(font.table(b"prep"), Some(&[0; 400][..]));
+    assert_eq!(font.glyph_index('B'), Some(2));
+  }
+
+  #[test]
+  fn rejects_tables_that_dont_decompress_to_their_length() {
+    for length in [399, 401, u32::MAX as usize] {
+      let file = woff(&[(*b"prep", vec![0; 400], length)]);
+      assert!(matches!(
+        decode(&file),
+        Err(FontError::Malformed("WOFF table"))
+      ));
+    }
+  }
+
+  #[test]
+  fn rejects_malformed_directories() {
+    let mut file = woff(&[(*b"prep", vec![0; 400], 400)]);
+    // More tables than a font can have.
+    let mut too_many = file.clone();
+    too_many[12..14].copy_from_slice(&u16::MAX.to_be_bytes());
+    assert!(matches!(
+      decode(&too_many),
+      Err(FontError::Malformed("WOFF header"))
+    ));
+
+    // A table that ends past the end of the file.
+    file[HEADER_SIZE + 8..HEADER_SIZE + 12].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!(matches!(
+      decode(&file),
+      Err(FontError::Malformed("WOFF table directory"))
+    ));
+    assert!(decode(&file[..HEADER_SIZE + 10]).is_err());
+  }
+}
@@ -0,0 +1,752 @@
+//! WOFF2 files: the tables of an OpenType font compressed together with Brotli, with the `glyf`
+//! and `loca` tables, and optionally `hmtx`, transformed to compress better.
+//!
+//! https://www.w3.org/TR/WOFF2/
+
+use super::sfnt::{read_u16, read_u32, FontError, Tag};
+use super::woff::{checksum, sfnt_data, MAX_TABLES};
+use brotli_decompressor::Decompressor;
+use std::convert::TryFrom;
+use std::io::Read;
+
+/// The size of the WOFF2 header, which the table directory follows.
+const HEADER_SIZE: usize = 48;
+
+/// The tags of the tables that the table directory refers to by their index.
+///
+/// https://www.w3.org/TR/WOFF2/#table_dir_format
+const KNOWN_TAGS: [&Tag; 63] = [
+  b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+  b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+  b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+  b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+  b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+  b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+  b"Gloc", b"Feat", b"Sill",
+];
+
+// The flags of the points of a simple glyph in the `glyf` table.
+// https://learn.microsoft.com/en-us/typography/opentype/spec/glyf#simple-glyph-description
+const ON_CURVE_POINT: u8 = 0x01;
+const X_SHORT_VECTOR: u8 = 0x02;
+const Y_SHORT_VECTOR: u8 = 0x04;
+const X_IS_SAME_OR_POSITIVE: u8 = 0x10;
+const Y_IS_SAME_OR_POSITIVE: u8 = 0x20;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+// The flags of the components of a composite glyph.
+// https://learn.microsoft.com/en-us/typography/opentype/spec/glyf#composite-glyph-description
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+/// A table in the table directory.
+struct TableEntry {
+  tag: Tag,
+  // Whether the table is stored transformed, and how long it is in the decompressed data.
+  transformed: bool,
+  stored_length: usize,
+  // The length of the table in the font. A reconstructed `glyf` table can have another length,
+  // as the glyphs can be encoded differently than they were.
+  length: usize,
+}
+
+/// Decompress a WOFF2 file into the OpenType font it wraps.
+pub(super) fn decode(data: &[u8]) -> Result<Vec<u8>, FontError> {
+  let flavor = read_u32(data, 4, "WOFF2 header")?;
+  if flavor.to_be_bytes() == *b"ttcf" {
+    return Err(FontError::UnsupportedFormat("WOFF2 font collection"));
+  }
+  let count = read_u16(data, 12, "WOFF2 header")? as usize;
+  if count > MAX_TABLES {
+    return Err(FontError::Malformed("WOFF2 header"));
+  }
+  let compressed_length = read_u32(data, 20, "WOFF2 header")? as usize;
+
+  let mut directory = Stream::new(data, "WOFF2 table directory");
+  directory.skip(HEADER_SIZE)?;
+  let mut entries = Vec::new();
+  for _ in 0..count {
+    let flags = directory.u8()?;
+    let tag = match flags & 0x3f {
+      0x3f => directory.tag()?,
+      index => *KNOWN_TAGS[index as usize],
+    };
+    let length = directory.base128()?;
+    // `glyf` and `loca` are transformed unless they have the null transform version 3, the
+    // other tables are only transformed with a version other than 0.
+    let version = flags >> 6;
+    let transformed = match &tag {
+      b"glyf" | b"loca" => version == 0,
+      _ => version != 0,
+    };
+    let stored_length = if transformed {
+      directory.base128()?
+    } else {
+      length
+    };
+    entries.push(TableEntry {
+      tag,
+      transformed,
+      stored_length,
+      length,
+    });
+  }
+
+  // The table data follows the directory as a single Brotli stream. The lengths in the
+  // directory aren't trusted, so the data only grows as much as the stream decompresses to.
+  let compressed = directory.bytes(compressed_length)?;
+  let total_length = entries
+    .iter()
+    .try_fold(0usize, |total, entry| {
+      total.checked_add(entry.stored_length)
+    })
+    .ok_or(FontError::Malformed("WOFF2 table directory"))?;
+  let mut stream = Vec::new();
+  Decompressor::new(compressed, 4096)
+    .take(total_length as u64 + 1)
+    .read_to_end(&mut stream)?;
+  if stream.len() != total_length {
+    return Err(FontError::Malformed("WOFF2 table data"));
+  }
+
+  let mut stored_tables = Vec::new();
+  let mut offset = 0;
+  for entry in &entries {
+    stored_tables.push((entry, &stream[offset..offset + entry.stored_length]));
+    offset += entry.stored_length;
+  }
+  let stored = |tag: &Tag| stored_tables.iter().find(|(entry, _)| entry.tag == *tag);
+
+  // The transformed `glyf` table holds the glyphs and `loca` too, and the left side bearings
+  // of the transformed `hmtx` table can be the left edges of the glyphs.
+  let glyphs = match (stored(b"glyf"), stored(b"loca")) {
+    (Some((glyf, data)), Some((loca, _))) if glyf.transformed => {
+      if !loca.transformed || loca.stored_length != 0 {
+        return Err(FontError::Malformed("loca"));
+      }
+      let glyphs = Glyphs::reconstruct(data)?;
+      if glyphs.loca.len() != loca.length {
+        return Err(FontError::Malformed("loca"));
+      }
+      Some(glyphs)
+    }
+    (Some((glyf, _)), _) | (_, Some((glyf, _))) if glyf.transformed => {
+      return Err(FontError::Malformed("glyf"));
+    }
+    _ => None,
+  };
+
+  let mut tables: Vec<(Tag, u32, Vec<u8>)> = Vec::new();
+  for (entry, data) in &stored_tables {
+    let table = match (&entry.tag, &glyphs) {
+      (b"glyf", Some(glyphs)) => glyphs.glyf.clone(),
+      (b"loca", Some(glyphs)) => glyphs.loca.clone(),
+      (b"hmtx", Some(glyphs)) if entry.transformed => {
+        let table = |tag| stored(tag).map(|(_, data)| *data);
+        let hhea = table(b"hhea").ok_or(FontError::Malformed("hhea"))?;
+        let maxp = table(b"maxp").ok_or(FontError::Malformed("maxp"))?;
+        let metrics_count = read_u16(hhea, 34, "hhea")? as usize;
+        let glyph_count = read_u16(maxp, 4, "maxp")? as usize;
+        reconstruct_hmtx(data, metrics_count, glyph_count, &glyphs.x_mins)?
+      }
+      _ if entry.transformed => return Err(FontError::Malformed("WOFF2 table directory")),
+      _ => data.to_vec(),
+    };
+    tables.push((entry.tag, checksum(&table), table));
+  }
+  tables.sort_by_key(|(tag, _, _)| *tag);
+  Ok(sfnt_data(flavor, &tables))
+}
+
+/// The `glyf` and `loca` tables reconstructed from a transformed `glyf` table, and the left
+/// edge of the bounding box of each glyph.
+///
+/// https://www.w3.org/TR/WOFF2/#glyf_table_format
+struct Glyphs {
+  glyf: Vec<u8>,
+  loca: Vec<u8>,
+  x_mins: Vec<i16>,
+}
+
+impl Glyphs {
+  fn reconstruct(data: &[u8]) -> Result<Self, FontError> {
+    let mut header = Stream::new(data, "glyf");
+    header.skip(2)?;
+    let option_flags = header.u16()?;
+    let glyph_count = header.u16()? as usize;
+    let index_format = header.u16()?;
+
+    // The glyphs are split into streams of the same kind of data, which follow the header.
+    let mut stream_lengths = [0; 7];
+    for length in &mut stream_lengths {
+      *length = header.u32()? as usize;
+    }
+    let mut next_stream = |index: usize| {
+      header
+        .bytes(stream_lengths[index])
+        .map(|data| Stream::new(data, "glyf"))
+    };
+    let mut contour_counts = next_stream(0)?;
+    let mut point_counts = next_stream(1)?;
+    let mut point_flags = next_stream(2)?;
+    let mut coordinates = next_stream(3)?;
+    let mut components = next_stream(4)?;
+    let mut boxes = next_stream(5)?;
+    let mut instructions = next_stream(6)?;
+
+    // Which glyphs have an explicit bounding box, and which simple glyphs have overlapping
+    // contours, one bit each from the most significant.
+    let bbox_bitmap = boxes.bytes(4 * glyph_count.div_ceil(32))?;
+    let overlap_bitmap = if option_flags & 1 != 0 {
+      Some(header.bytes(glyph_count.div_ceil(8))?)
+    } else {
+      None
+    };
+    let bit = |bitmap: &[u8], index: usize| bitmap[index / 8] & (0x80 >> (index % 8)) != 0;
+
+    let mut glyphs = Glyphs {
+      glyf: Vec::new(),
+      loca: Vec::new(),
+      x_mins: Vec::new(),
+    };
+    let mut offsets = vec![0];
+    for index in 0..glyph_count {
+      let glyf = &mut glyphs.glyf;
+      let has_bbox = bit(bbox_bitmap, index);
+      let x_min = match contour_counts.i16()? {
+        0 if has_bbox => return Err(FontError::Malformed("glyf")),
+        0 => 0,
+        // A composite glyph, whose bounding box is always given.
+        -1 => {
+          if !has_bbox {
+            return Err(FontError::Malformed("glyf"));
+          }
+          let bbox = boxes.bytes(8)?;
+          let start = components.position;
+          let mut has_instructions = false;
+          loop {
+            let flags = components.u16()?;
+            let arguments = if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+              4
+            } else {
+              2
+            };
+            let transform = if flags & WE_HAVE_A_SCALE != 0 {
+              2
+            } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+              4
+            } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+              8
+            } else {
+              0
+            };
+            components.skip(2 + arguments + transform)?;
+            has_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+            if flags & MORE_COMPONENTS == 0 {
+              break;
+            }
+          }
+          glyf.extend_from_slice(&(-1i16).to_be_bytes());
+          glyf.extend_from_slice(bbox);
+          glyf.extend_from_slice(&components.data[start..components.position]);
+          if has_instructions {
+            let length = coordinates.u255()?;
+            glyf.extend_from_slice(&length.to_be_bytes());
+            glyf.extend_from_slice(instructions.bytes(length as usize)?);
+          }
+          i16::from_be_bytes([bbox[0], bbox[1]])
+        }
+        contour_count if contour_count > 0 => {
+          let mut end_points = Vec::new();
+          let mut point_count = 0usize;
+          for _ in 0..contour_count {
+            point_count += point_counts.u255()? as usize;
+            let end_point = point_count
+              .checked_sub(1)
+              .and_then(|end_point| u16::try_from(end_point).ok())
+              .ok_or(FontError::Malformed("glyf"))?;
+            end_points.push(end_point);
+          }
+
+          // The points are stored as a flag and a triplet of coordinate bytes each.
+          let mut points: Vec<Point> = Vec::new();
+          for &flag in point_flags.bytes(point_count)? {
+            let (on_curve, dx, dy) = triplet(flag, &mut coordinates)?;
+            let (x, y) = points.last().map_or((0, 0), |point| (point.x, point.y));
+            points.push(Point {
+              on_curve,
+              dx,
+              dy,
+              x: x + dx,
+              y: y + dy,
+            });
+          }
+          let instruction_length = coordinates.u255()?;
+
+          let bbox = if has_bbox {
+            boxes.bytes(8)?.to_vec()
+          } else {
+            let bounds = |coordinate: fn(&Point) -> i32| {
+              let values = points.iter().map(coordinate);
+              let min = values.clone().min().unwrap_or(0) as i16;
+              let max = values.max().unwrap_or(0) as i16;
+              (min, max)
+            };
+            let (x_min, x_max) = bounds(|point| point.x);
+            let (y_min, y_max) = bounds(|point| point.y);
+            [x_min, y_min, x_max, y_max]
+              .iter()
+              .flat_map(|value| value.to_be_bytes())
+              .collect()
+          };
+
+          glyf.extend_from_slice(&contour_count.to_be_bytes());
+          glyf.extend_from_slice(&bbox);
+          for end_point in end_points {
+            glyf.extend_from_slice(&end_point.to_be_bytes());
+          }
+          glyf.extend_from_slice(&instruction_length.to_be_bytes());
+          glyf.extend_from_slice(instructions.bytes(instruction_length as usize)?);
+          let overlaps = overlap_bitmap.is_some_and(|bitmap| bit(bitmap, index));
+          glyf.extend(encode_points(&points, overlaps));
+          i16::from_be_bytes([bbox[0], bbox[1]])
+        }
+        _ => return Err(FontError::Malformed("glyf")),
+      };
+      glyphs.x_mins.push(x_min);
+
+      // Glyphs start at offsets that are a multiple of 4, which the short `loca` format needs
+      // to be even.
+      glyf.resize((glyf.len() + 3) & !3, 0);
+      offsets.push(glyf.len());
+    }
+
+    for offset in offsets {
+      if index_format == 0 {
+        let offset = u16::try_from(offset / 2).map_err(|_| FontError::Malformed("loca"))?;
+        glyphs.loca.extend_from_slice(&offset.to_be_bytes());
+      } else {
+        glyphs
+          .loca
+          .extend_from_slice(&(offset as u32).to_be_bytes());
+      }
+    }
+    Ok(glyphs)
+  }
+}
+
+/// A point of a simple glyph, with its offset from the point before it and its coordinates.
+struct Point {
+  on_curve: bool,
+  dx: i32,
+  dy: i32,
+  x: i32,
+  y: i32,
+}
+
+/// The flag of a point in a simple glyph of a transformed `glyf` table and the triplet of bytes
+/// from the glyph stream that it selects, decoded to whether the point is on the curve and its
+/// offset from the point before it.
+///
+/// https://www.w3.org/TR/WOFF2/#triplet_decoding
+fn triplet(flag: u8, stream: &mut Stream) -> Result<(bool, i32, i32), FontError> {
+  let on_curve = flag & 0x80 == 0;
+  let flag = (flag & 0x7f) as i32;
+  let with_sign = |flag: i32, value: i32| if flag & 1 != 0 { value } else { -value };
+  let mut byte = || stream.u8().map(i32::from);
+  let (dx, dy) = match flag {
+    0..=9 => (0, with_sign(flag, ((flag & 14) << 7) + byte()?)),
+    10..=19 => (with_sign(flag, (((flag - 10) & 14) << 7) + byte()?), 0),
+    20..=83 => {
+      let (b0, b1) = (flag - 20, byte()?);
+      (
+        with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+        with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)),
+      )
+    }
+    84..=119 => {
+      let (b0, b1, b2) = (flag - 84, byte()?, byte()?);
+      (
+        with_sign(flag, 1 + ((b0 / 12) << 8) + b1),
+        with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2),
+      )
+    }
+    120..=123 => {
+      let (b1, b2, b3) = (byte()?, byte()?, byte()?);
+      (
+        with_sign(flag, (b1 << 4) + (b2 >> 4)),
+        with_sign(flag >> 1, ((b2 & 0x0f) << 8) + b3),
+      )
+    }
+    _ => {
+      let (b1, b2, b3, b4) = (byte()?, byte()?, byte()?, byte()?);
+      (
+        with_sign(flag, (b1 << 8) + b2),
+        with_sign(flag >> 1, (b3 << 8) + b4),
+      )
+    }
+  };
+  Ok((on_curve, dx, dy))
+}
+
+/// The flags and the x and y coordinates of the points of a simple glyph in the `glyf` table,
+/// with the offsets that fit in a byte stored in one.
+fn encode_points(points: &[Point], overlaps: bool) -> Vec<u8> {
+  let mut flags = Vec::new();
+  let mut xs = Vec::new();
+  let mut ys = Vec::new();
+  for point in points {
+    let mut flag = if point.on_curve { ON_CURVE_POINT } else { 0 };
+    for (delta, short, same_or_positive, coordinates) in [
+      (point.dx, X_SHORT_VECTOR, X_IS_SAME_OR_POSITIVE, &mut xs),
+      (point.dy, Y_SHORT_VECTOR, Y_IS_SAME_OR_POSITIVE, &mut ys),
+    ] {
+      if delta == 0 {
+        flag |= same_or_positive;
+      } else if delta.abs() < 256 {
+        flag |= short;
+        if delta > 0 {
+          flag |= same_or_positive;
+        }
+        coordinates.push(delta.unsigned_abs() as u8);
+      } else {
+        coordinates.extend_from_slice(&(delta as i16).to_be_bytes());
+      }
+    }
+    flags.push(flag);
+  }
+  if let (true, Some(flag)) = (overlaps, flags.first_mut()) {
+    *flag |= OVERLAP_SIMPLE;
+  }
+  flags.extend(xs);
+  flags.extend(ys);
+  flags
+}
+
+/// The `hmtx` table reconstructed from a transformed one, which can leave out the left side
+/// bearings that are the left edges of the glyphs.
+///
+/// https://www.w3.org/TR/WOFF2/#hmtx_table_format
+fn reconstruct_hmtx(
+  data: &[u8],
+  metrics_count: usize,
+  glyph_count: usize,
+  x_mins: &[i16],
+) -> Result<Vec<u8>, FontError> {
+  if metrics_count == 0 || metrics_count > glyph_count || x_mins.len() < glyph_count {
+    return Err(FontError::Malformed("hmtx"));
+  }
+  let mut stream = Stream::new(data, "hmtx");
+  let flags = stream.u8()?;
+  let mut advances = Vec::new();
+  for _ in 0..metrics_count {
+    advances.push(stream.u16()?);
+  }
+  let mut bearings = Vec::new();
+  for (index, &x_min) in x_mins.iter().enumerate().take(glyph_count) {
+    // The bearings of the glyphs with an advance are left out with flag 1, and those of the
+    // glyphs after them, which share the last advance, with flag 2.
+    let left_out = if index < metrics_count { 1 } else { 2 };
+    bearings.push(if flags & left_out != 0 {
+      x_min
+    } else {
+      stream.i16()?
+    });
+  }
+
+  let mut table = Vec::new();
+  for (index, bearing) in bearings.into_iter().enumerate() {
+    if let Some(advance) = advances.get(index) {
+      table.extend_from_slice(&advance.to_be_bytes());
+    }
+    table.extend_from_slice(&bearing.to_be_bytes());
+  }
+  Ok(table)
+}
+
+/// Some data that is read from the start, with the name of the table it belongs to for errors.
+struct Stream<'d> {
+  data: &'d [u8],
+  position: usize,
+  table: &'static str,
+}
+
+impl<'d> Stream<'d> {
+  fn new(data: &'d [u8], table: &'static str) -> Self {
+    Stream {
+      data,
+      position: 0,
+      table,
+    }
+  }
+
+  fn bytes(&mut self, length: usize) -> Result<&'d [u8], FontError> {
+    let bytes = self
+      .position
+      .checked_add(length)
+      .and_then(|end| self.data.get(self.position..end))
+      .ok_or(FontError::Malformed(self.table))?;
+    self.position += length;
+    Ok(bytes)
+  }
+
+  fn skip(&mut self, length: usize) -> Result<(), FontError> {
+    self.bytes(length).map(|_| ())
+  }
+
+  fn u8(&mut self) -> Result<u8, FontError> {
+    Ok(self.bytes(1)?[0])
+  }
+
+  fn u16(&mut self) -> Result<u16, FontError> {
+    let bytes = self.bytes(2)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+  }
+
+  fn i16(&mut self) -> Result<i16, FontError> {
+    self.u16().map(|value| value as i16)
+  }
+
+  fn u32(&mut self) -> Result<u32, FontError> {
+    let bytes = self.bytes(4)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+  }
+
+  fn tag(&mut self) -> Result<Tag, FontError> {
+    let bytes = self.bytes(4)?;
+    Ok([bytes[0], bytes[1], bytes[2], bytes[3]])
+  }
+
+  /// A `255UInt16`: a byte, or a code for how to read a larger value.
+  ///
+  /// https://www.w3.org/TR/WOFF2/#255UInt16
+  fn u255(&mut self) -> Result<u16, FontError> {
+    Ok(match self.u8()? {
+      253 => self.u16()?,
+      254 => 506 + self.u8()? as u16,
+      255 => 253 + self.u8()? as u16,
+      code => code as u16,
+    })
+  }
+
+  /// A `UIntBase128`: a 32-bit value in up to five bytes of seven bits each, most significant
+  /// first.
+  ///
+  /// https://www.w3.org/TR/WOFF2/#UIntBase128
+  fn base128(&mut self) -> Result<usize, FontError> {
+    let mut value: u32 = 0;
+    for index in 0..5 {
+      let byte = self.u8()?;
+      // Leading zeros and values that don't fit in 32 bits aren't allowed.
+      if (index == 0 && byte == 0x80) || value & 0xfe00_0000 != 0 {
+        return Err(FontError::Malformed(self.table));
+      }
+      value = (value << 7) | (byte & 0x7f) as u32;
+      if byte & 0x80 == 0 {
+        return Ok(value as usize);
+      }
+    }
+    Err(FontError::Malformed(self.table))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::sfnt::{Font, FontError, Tag};
+  use super::super::testing::{cmap, cmap_format_4, font_tables, push_u16, push_u32, sfnt};
+  use super::super::testing::{FAMILY, TRUETYPE};
+  use super::{decode, KNOWN_TAGS};
+
+  /// A Brotli stream that stores some data uncompressed, in a single meta-block.
+  ///
+  /// https://www.rfc-editor.org/rfc/rfc7932#section-9.2
+  fn brotli(data: &[u8]) -> Vec<u8> {
+    assert!(!data.is_empty() && data.len() <= 1 << 16);
+    // A window of 16 bits, and a meta-block that isn't the last one, with four nibbles of
+    // length, that is uncompressed.
+    let header = ((data.len() as u32 - 1) << 4) | (1 << 20);
+    let mut stream = header.to_le_bytes()[..3].to_vec();
+    stream.extend_from_slice(data);
+    // The last meta-block, which is empty.
+    stream.push(0b11);
+    stream
+  }
+
+  fn base128(value: usize) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+      bytes.insert(0, (value & 0x7f) as u8 | 0x80);
+      value >>= 7;
+    }
+    bytes
+  }
+
+  /// A WOFF2 file with some tables, each given with its transform version, its length in the
+  /// font and the data that is stored for it.
+  fn woff2(flavor: u32, tables: &[(Tag, u8, usize, Vec<u8>)]) -> Vec<u8> {
+    let mut directory = Vec::new();
+    let mut stored = Vec::new();
+    for (tag, version, length, data) in tables {
+      match KNOWN_TAGS.iter().position(|known| *known == tag) {
+        Some(index) => directory.push(version << 6 | index as u8),
+        None => {
+          directory.push(version << 6 | 0x3f);
+          directory.extend_from_slice(tag);
+        }
+      }
+      directory.extend(base128(*length));
+      let transformed = match tag {
+        b"glyf" | b"loca" => *version == 0,
+        _ => *version != 0,
+      };
+      if transformed {
+        directory.extend(base128(data.len()));
+      }
+      stored.extend_from_slice(data);
+    }
+    let compressed = brotli(&stored);
+
+    let mut file = b"wOF2".to_vec();
+    push_u32(&mut file, &[flavor, 0]);
+    push_u16(&mut file, &[tables.len() as u16, 0]);
+    push_u32(&mut file, &[0, compressed.len() as u32]);
+    push_u16(&mut file, &[1, 0]);
+    push_u32(&mut file, &[0, 0, 0, 0, 0]);
+    file.extend(directory);
+    file.extend(compressed);
+    file
+  }
+
+  /// A WOFF2 file with tables that are stored as they are.
+  fn untransformed(tables: &[(Tag, Vec<u8>)]) -> Vec<u8> {
+    let tables: Vec<(Tag, u8, usize, Vec<u8>)> = tables
+      .iter()
+      .map(|(tag, table)| {
+        let version = if tag == b"glyf" || tag == b"loca" {
+          3
+        } else {
+          0
+        };
+        (*tag, version, table.len(), table.clone())
+      })
+      .collect();
+    woff2(TRUETYPE, &tables)
+  }
+
+  #[test]
+  fn decodes_untransformed_tables() {
+    let tables = font_tables(
+      cmap(&[(3, 1, cmap_format_4())]),
+      vec![(*b"glyf", vec![1; 12]), (*b"zzzz", vec![2; 5])],
+    );
+    let file = untransformed(&tables);
+    assert_eq!(decode(&file).unwrap(), sfnt(&tables));
+
+    let font = Font::parse(&file).unwrap();
+    assert_eq!(font.family, FAMILY);
+    assert_eq!(font.glyph_index('C'), Some(3));
+    assert_eq!(font.table(b"zzzz"), Some(&[2; 5][..]));
+  }
+
+  #[test]
+  fn reconstructs_transformed_glyf_loca_and_hmtx() {
+    // An empty glyph, a triangle through (5, 0), (105, 0) and (55, 200) that starts on the
+    // curve, and a composite of the triangle moved by (10, 20).
+    let mut glyf = Vec::new();
+    push_u16(&mut glyf, &[0, 0, 3, 0]);
+    push_u32(&mut glyf, &[6, 1, 3, 5, 6, 12, 0]);
+    push_u16(&mut glyf, &[0, 1, (-1i16) as u16]);
+    glyf.push(3);
+    glyf.extend_from_slice(&[11, 11, 86]);
+    glyf.extend_from_slice(&[5, 100, 49, 199, 0]);
+    push_u16(&mut glyf, &[0x0002, 1]);
+    glyf.extend_from_slice(&[10, 20]);
+    glyf.extend_from_slice(&[0x20, 0, 0, 0]);
+    push_u16(&mut glyf, &[15, 20, 115, 220]);
+
+    // Advances for the first two glyphs, whose left side bearings are the left edges of their
+    // boxes, and the left side bearing of the last one.
+    let mut hmtx = vec![1];
+    push_u16(&mut hmtx, &[500, 600, 7]);
+
+    let mut hhea = vec![0; 36];
+    hhea[34..36].copy_from_slice(&2u16.to_be_bytes());
+    let mut maxp = Vec::new();
+    push_u32(&mut maxp, &[0x5000]);
+    push_u16(&mut maxp, &[3]);
+
+    let other_tables = vec![(*b"hhea", hhea.clone()), (*b"maxp", maxp.clone())];
+    let mut tables: Vec<(Tag, u8, usize, Vec<u8>)> = font_tables(cmap(&[]), other_tables.clone())
+      .into_iter()
+      .map(|(tag, table)| (tag, 0, table.len(), table))
+      .collect();
+    tables.push((*b"glyf", 0, 40, glyf));
+    tables.push((*b"loca", 0, 8, Vec::new()));
+    tables.push((*b"hmtx", 1, 10, hmtx));
+    let file = woff2(TRUETYPE, &tables);
+
+    let mut expected_glyf = Vec::new();
+    push_u16(&mut expected_glyf, &[1, 5, 0, 105, 200, 2, 0]);
+    expected_glyf.extend_from_slice(&[0x33, 0x33, 0x27, 5, 100, 50, 200, 0, 0, 0]);
+    push_u16(
+      &mut expected_glyf,
+      &[(-1i16) as u16, 15, 20, 115, 220, 0x0002, 1],
+    );
+    expected_glyf.extend_from_slice(&[10, 20]);
+    let mut expected_loca = Vec::new();
+    push_u16(&mut expected_loca, &[0, 0, 12, 20]);
+    let mut expected_hmtx = Vec::new();
+    push_u16(&mut expected_hmtx, &[500, 0, 600, 5, 7]);
+
+    let mut other_tables = other_tables;
+    other_tables.push((*b"glyf", expected_glyf));
+    other_tables.push((*b"loca", expected_loca));
+    other_tables.push((*b"hmtx", expected_hmtx));
+    assert_eq!(
+      decode(&file).unwrap(),
+      sfnt(&font_tables(cmap(&[]), other_tables))
+    );
+  }
+
+  #[test]
+  fn rejects_malformed_files() {
+    let tables = font_tables(cmap(&[]), vec![]);
+    let file = untransformed(&tables);
+
+    let collection = woff2(u32::from_be_bytes(*b"ttcf"), &[(*b"head", 0, 1, vec![0])]);
+    assert!(matches!(
+      decode(&collection),
+      Err(FontError::UnsupportedFormat(_))
+    ));
+
+    // Data that ends in the middle of the Brotli stream.
+    assert!(decode(&file[..file.len() - 10]).is_err());
+
+    // A table that is longer than the data.
+    let too_long = woff2(TRUETYPE, &[(*b"head", 0, 2, vec![0])]);
+    assert!(matches!(
+      decode(&too_long),
+      Err(FontError::Malformed("WOFF2 table data"))
+    ));
+
+    // A transformed `glyf` table without `loca`.
+    let glyf_only = woff2(TRUETYPE, &[(*b"glyf", 0, 1, vec![0])]);
+    assert!(matches!(
+      decode(&glyf_only),
+      Err(FontError::Malformed("glyf"))
+    ));
+
+    // A length with a leading zero.
+    let mut leading_zero = woff2(TRUETYPE, &[(*b"head", 0, 1, vec![0])]);
+    leading_zero.insert(49, 0x80);
+    assert!(matches!(
+      decode(&leading_zero),
+      Err(FontError::Malformed("WOFF2 table directory"))
+    ));
+  }
+}
@@ -0,0 +1,51 @@
+//! Helpers for the box alignment properties shared by flex and grid layout.
+//!
+//! https://www.w3.org/TR/css-align-3/
+
+use crate::layout::css::{Unit, Value, Viewport};
+use crate::layout::style::StyledNode;
+
+/// Split free space over `count` boxes according to a `justify-content` or `align-content`
+/// keyword, returning the offset of the first box and the extra space between boxes.
+pub fn distribute(keyword: &str, free_space: f32, count: usize) -> (f32, f32) {
+  let count = count as f32;
+  match keyword {
+    "flex-end" | "end" | "right" => (free_space, 0.0),
+    "center" => (free_space / 2.0, 0.0),
+    "space-between" if free_space > 0.0 && count > 1.0 => (0.0, free_space / (count - 1.0)),
+    "space-around" if free_space > 0.0 => (free_space / count / 2.0, free_space / count),
+    "space-around" => (free_space / 2.0, 0.0),
+    "space-evenly" if free_space > 0.0 => (free_space / (count + 1.0), free_space / (count + 1.0)),
+    "space-evenly" => (free_space / 2.0, 0.0),
+    _ => (0.0, 0.0),
+  }
+}
+
+/// The offset of a box within its alignment container for a `justify-self` or `align-self`
+/// keyword, given the free space left around it.
+pub fn self_alignment_offset(keyword: &str, free_space: f32) -> f32 {
+  match keyword {
+    "end" | "flex-end" | "self-end" | "right" => free_space,
+    "center" => free_space / 2.0,
+    _ => 0.0,
+  }
+}
+
+/// The used `row-gap` and `column-gap`, from the longhands or the `gap` shorthand.
+///
+/// https://www.w3.org/TR/css-align-3/#gap-shorthand
+pub fn gaps(style: &StyledNode, width: f32, height: f32, viewport: Viewport) -> (f32, f32) {
+  let zero = Value::Length(0.0, Unit::Px);
+  let (row_gap, column_gap) = match style.value("gap") {
+    Some(Value::List(values)) => (values[0].clone(), values[values.len() - 1].clone()),
+    Some(value) => (value.clone(), value),
+    None => (zero.clone(), zero),
+  };
+
+  let row_gap = style.value("row-gap").unwrap_or(row_gap);
+  let column_gap = style.value("column-gap").unwrap_or(column_gap);
+  (
+    row_gap.to_px(height, viewport),
+    column_gap.to_px(width, viewport),
+  )
+}
@@ -0,0 +1,169 @@
+//! Preferred aspect ratios from the `aspect-ratio` property, which size a box with an `auto`
+//! width or height from its other dimension.
+//!
+//! The ratio applies to the content box, or to the border box with `box-sizing: border-box`.
+//! A height from the ratio still grows to fit the content, unless `min-height` is set or the
+//! content is clipped, and both dimensions stay within their `min-` and `max-` sizes.
+//!
+//! https://www.w3.org/TR/css-sizing-4/#aspect-ratio
+
+use super::replaced::edges;
+use super::LayoutBox;
+use crate::layout::css::{Unit, Value};
+use crate::layout::style::StyledNode;
+
+impl<'a> LayoutBox<'a> {
+  /// The ratio of the width to the height set by `aspect-ratio`, if any, and whether it is
+  /// `auto`, which prefers the natural ratio of replaced elements.
+  pub(super) fn aspect_ratio(&self) -> Option<(f32, bool)> {
+    let style = self.get_style_node_option()?;
+    let values = match style.value("aspect-ratio")? {
+      Value::List(values) => values,
+      value => vec![value],
+    };
+    let auto = values.contains(&Value::Keyword("auto".to_string()));
+    let numbers: Vec<f32> = values
+      .iter()
+      .filter_map(|value| match *value {
+        Value::Number(n) => Some(n),
+        _ => None,
+      })
+      .collect();
+    let ratio = match numbers[..] {
+      [width] => width,
+      [width, height] => width / height,
+      _ => return None,
+    };
+
+    // A degenerate ratio behaves like `auto`. The width is along the lines, which is the
+    // physical height in vertical writing modes.
+    if !ratio.is_finite() || ratio <= 0.0 {
+      None
+    } else if style.writing_mode.is_vertical() {
+      Some((1.0 / ratio, auto))
+    } else {
+      Some((ratio, auto))
+    }
+  }
+
+  /// The content height that follows from the content width through the preferred aspect
+  /// ratio of a non-replaced box, if it has one.
+  ///
+  /// `reference` is the width of the containing block for percentages in the edges.
+  pub(super) fn ratio_height(&self, width: f32, reference: f32) -> Option<f32> {
+    let (ratio, _) = self.aspect_ratio()?;
+    let style = self.get_style_node();
+    Some(match style.keyword("box-sizing").as_deref() {
+      Some("border-box") => {
+        let (horizontal_edges, vertical_edges) = edges(style, reference, self.viewport);
+        ((width + horizontal_edges) / ratio - vertical_edges).max(0.0)
+      }
+      _ => width / ratio,
+    })
+  }
+
+  /// The content width that follows from a definite `height` through the preferred aspect
+  /// ratio of a non-replaced box, if it has both. The height is clamped by `min-height` and
+  /// `max-height` first.
+  ///
+  /// Percentage heights need the height of the containing block, when it is known.
+  pub(super) fn ratio_width(&self, reference_height: Option<f32>, reference: f32) -> Option<f32> {
+    let viewport = self.viewport;
+    let style = self.get_style_node_option()?;
+    let (_, vertical_edges) = edges(style, reference, viewport);
+    let height = match (style.value("height"), reference_height) {
+      (Some(Value::Length(_, Unit::Percentage)), None) => return None,
+      (_, reference_height) => {
+        let reference_height = reference_height.unwrap_or(0.0);
+        let height = style.size("height", reference_height, vertical_edges, viewport)?;
+        style.clamp_size("height", height, reference_height, vertical_edges, viewport)
+      }
+    };
+    self.width_from_ratio(height, reference)
+  }
+
+  /// The content width that follows from a content height through the preferred aspect ratio
+  /// of a non-replaced box, if it has one.
+  pub(super) fn width_from_ratio(&self, height: f32, reference: f32) -> Option<f32> {
+    let (ratio, _) = self.aspect_ratio()?;
+    let style = self.get_style_node();
+    Some(match style.keyword("box-sizing").as_deref() {
+      Some("border-box") => {
+        let (horizontal_edges, vertical_edges) = edges(style, reference, self.viewport);
+        ((height + vertical_edges) * ratio - horizontal_edges).max(0.0)
+      }
+      _ => height * ratio,
+    })
+  }
+
+  /// Whether the box has a preferred aspect ratio, from `aspect-ratio` or the natural ratio of
+  /// a replaced element.
+  pub(super) fn has_preferred_aspect_ratio(&self) -> bool {
+    self.aspect_ratio().is_some() || self.intrinsic_size().is_some()
+  }
+}
+
+/// Whether a box whose height follows from its aspect ratio grows to fit its content, which it
+/// does with `min-height: auto` when the content isn't clipped.
+///
+/// https://www.w3.org/TR/css-sizing-4/#aspect-ratio-minimum
+pub(super) fn grows_to_fit_content(style: &StyledNode) -> bool {
+  let min_height_auto = match style.value("min-height") {
+    None => true,
+    Some(Value::Keyword(ref keyword)) => keyword == "auto",
+    Some(_) => false,
+  };
+  let visible = matches!(style.keyword("overflow").as_deref(), None | Some("visible"));
+  min_height_auto && visible
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, border_box, div, layout};
+
+  fn height(css: &str) -> f32 {
+    let document = body(vec![div("box", vec![])]);
+    layout(&document, css, |root| border_box(root, &[0]).3)
+  }
+
+  #[test]
+  fn auto_heights_follow_the_ratio() {
+    assert_eq!(height(".box { width: 200px; aspect-ratio: 2 / 1; }"), 100.0);
+    assert_eq!(
+      height(".box { width: 200px; padding: 10px; aspect-ratio: 2; box-sizing: border-box; }"),
+      100.0
+    );
+  }
+
+  #[test]
+  fn heights_from_the_ratio_stay_within_min_and_max_height() {
+    assert_eq!(
+      height(".box { width: 200px; aspect-ratio: 2; max-height: 60px; }"),
+      60.0
+    );
+    assert_eq!(
+      height(".box { width: 200px; aspect-ratio: 2; min-height: 150px; }"),
+      150.0
+    );
+  }
+
+  #[test]
+  fn auto_widths_follow_the_ratio_within_min_and_max_width() {
+    let document = body(vec![div("box", vec![])]);
+    let width = |css: &str| layout(&document, css, |root| border_box(root, &[0]).2);
+    assert_eq!(width(".box { height: 50px; aspect-ratio: 2; }"), 100.0);
+    assert_eq!(
+      width(".box { height: 50px; aspect-ratio: 2; max-width: 80px; }"),
+      80.0
+    );
+  }
+
+  #[test]
+  fn heights_from_the_ratio_grow_to_fit_the_content() {
+    let document = body(vec![div("box", vec![div("content", vec![])])]);
+    let height = |css: &str| layout(&document, css, |root| border_box(root, &[0]).3);
+    let css = ".box { width: 200px; aspect-ratio: 2; } .content { height: 150px; }";
+    assert_eq!(height(css), 150.0);
+    assert_eq!(height(&format!("{} .box {{ min-height: 0; }}", css)), 100.0);
+  }
+}
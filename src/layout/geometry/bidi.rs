@@ -0,0 +1,157 @@
+//! Bidirectional reordering of the boxes on a line.
+//!
+//! The Unicode Bidirectional Algorithm resolves an embedding level for the inline content of a
+//! block container, which is one paragraph whose base direction is the `direction` of the block.
+//! Atomic inlines take part as U+FFFC OBJECT REPLACEMENT CHARACTER, and inline boxes with
+//! `unicode-bidi` wrap their content in the matching bidi control characters. The boxes on each
+//! line are then reordered by their levels.
+//!
+//! Text is not laid out yet, so only atomic inlines are reordered. The text of text nodes takes
+//! part in resolving their levels, but isn't split into runs or placed on the line itself.
+//!
+//! https://www.w3.org/TR/css-writing-modes-3/#text-direction
+//! https://www.unicode.org/reports/tr9/
+
+use super::{BoxType, LayoutBox};
+use crate::layout::dom::NodeType;
+use crate::layout::style::{Direction, UnicodeBidi};
+use unicode_bidi::{BidiInfo, Level, ParagraphBidiInfo};
+
+const OBJECT_REPLACEMENT: char = '\u{fffc}';
+
+/// Resolve the embedding levels of the atomic inlines among some inline-level boxes and their
+/// descendants, in the order they are collected in for layout.
+pub(super) fn atomic_inline_levels(boxes: &[LayoutBox], direction: Direction) -> Vec<Level> {
+  let mut text = String::new();
+  let mut starts = Vec::new();
+  collect_paragraph(boxes, &mut text, &mut starts);
+  if starts.is_empty() {
+    return Vec::new();
+  }
+
+  let base_level = match direction {
+    Direction::Ltr => Level::ltr(),
+    Direction::Rtl => Level::rtl(),
+  };
+  let info = ParagraphBidiInfo::new(&text, Some(base_level));
+  starts.into_iter().map(|start| info.levels[start]).collect()
+}
+
+/// The order to place boxes with some levels in, from left to right, as indices into `levels`.
+pub(super) fn visual_order(levels: &[Level]) -> Vec<usize> {
+  BidiInfo::reorder_visual(levels)
+}
+
+/// Append the text of inline-level boxes to a paragraph, and the index where each atomic inline
+/// starts to `starts`.
+fn collect_paragraph(boxes: &[LayoutBox], text: &mut String, starts: &mut Vec<usize>) {
+  for inline_box in boxes {
+    if inline_box.is_atomic_inline() {
+      starts.push(text.len());
+      match inline_box.box_type {
+        BoxType::GeneratedText(_, ref generated) if !generated.is_empty() => {
+          push_collapsed(text, generated)
+        }
+        _ => text.push(OBJECT_REPLACEMENT),
+      }
+    } else if let BoxType::InlineNode(node) = inline_box.box_type {
+      if let NodeType::Text(ref content) = node.node.node_type {
+        push_collapsed(text, content);
+      }
+      let (open, close) = control_characters(node.unicode_bidi(), node.direction());
+      text.push_str(open);
+      collect_paragraph(&inline_box.children, text, starts);
+      text.push_str(close);
+    }
+  }
+}
+
+/// Append text with its segment breaks and tabs as spaces, which they are rendered as, so they
+/// don't separate paragraphs or segments.
+fn push_collapsed(text: &mut String, content: &str) {
+  text.extend(content.chars().map(|c| match c {
+    '\n' | '\r' | '\t' => ' ',
+    c => c,
+  }));
+}
+
+/// The bidi control characters that start and end the content of an inline box.
+///
+/// https://www.w3.org/TR/css-writing-modes-3/#bidi-control-codes-injection-table
+fn control_characters(
+  unicode_bidi: UnicodeBidi,
+  direction: Direction,
+) -> (&'static str, &'static str) {
+  let rtl = direction == Direction::Rtl;
+  match unicode_bidi {
+    UnicodeBidi::Normal => ("", ""),
+    UnicodeBidi::Embed if rtl => ("\u{202b}", "\u{202c}"),
+    UnicodeBidi::Embed => ("\u{202a}", "\u{202c}"),
+    UnicodeBidi::Isolate if rtl => ("\u{2067}", "\u{2069}"),
+    UnicodeBidi::Isolate => ("\u{2066}", "\u{2069}"),
+    UnicodeBidi::BidiOverride if rtl => ("\u{202e}", "\u{202c}"),
+    UnicodeBidi::BidiOverride => ("\u{202d}", "\u{202c}"),
+    UnicodeBidi::IsolateOverride if rtl => ("\u{2067}\u{202e}", "\u{202c}\u{2069}"),
+    UnicodeBidi::IsolateOverride => ("\u{2066}\u{202d}", "\u{202c}\u{2069}"),
+    UnicodeBidi::Plaintext => ("\u{2068}", "\u{2069}"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{border_box, div, layout};
+  use crate::layout::dom::{elem, text, Node};
+  use std::collections::HashMap;
+
+  fn body_with_dir(dir: &str, children: Vec<Node>) -> Node {
+    let mut attributes = HashMap::new();
+    attributes.insert("dir".to_string(), dir.to_string());
+    elem("body".to_string(), attributes, children)
+  }
+
+  const CSS: &str = ".a, .b { display: inline-block; height: 10px; } \
+                     .a { width: 100px; } .b { width: 50px; } \
+                     .span { display: inline; }";
+
+  #[test]
+  fn rtl_paragraphs_place_boxes_from_the_right() {
+    let document = body_with_dir("rtl", vec![div("a", vec![]), div("b", vec![])]);
+    layout(&document, CSS, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (700.0, 0.0, 100.0, 10.0));
+      assert_eq!(border_box(root, &[0, 1]), (650.0, 0.0, 50.0, 10.0));
+    });
+  }
+
+  #[test]
+  fn isolated_rtl_spans_are_reversed_in_ltr_paragraphs() {
+    let span = div("span", vec![div("a", vec![]), div("b", vec![])]);
+    let document = body_with_dir("ltr", vec![div("b", vec![]), span]);
+    let css = format!("{} .span {{ unicode-bidi: isolate; direction: rtl; }}", CSS);
+    layout(&document, &css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (0.0, 0.0, 50.0, 10.0));
+      // The `.a` and `.b` in the span follow the first `.b` from right to left.
+      assert_eq!(border_box(root, &[0, 1, 0]), (100.0, 0.0, 100.0, 10.0));
+      assert_eq!(border_box(root, &[0, 1, 1]), (50.0, 0.0, 50.0, 10.0));
+    });
+  }
+
+  #[test]
+  fn text_takes_part_in_resolving_levels() {
+    // Between right-to-left letters, the boxes are right-to-left in a left-to-right paragraph.
+    let document = body_with_dir(
+      "ltr",
+      vec![
+        text("\u{5d0}".to_string()),
+        div("a", vec![]),
+        text("\u{5d1}".to_string()),
+        div("b", vec![]),
+        text("\u{5d2}".to_string()),
+      ],
+    );
+    layout(&document, CSS, |root| {
+      let a = border_box(root, &[0, 1]);
+      let b = border_box(root, &[0, 3]);
+      assert_eq!((b.0, a.0), (0.0, 50.0));
+    });
+  }
+}
@@ -0,0 +1,339 @@
+//! Borders: the style and color of each side of a box, and the shapes that paint them.
+//!
+//! Each side is painted as the trapezoid between the edge of the border box and the edge of the
+//! padding box, so adjacent sides meet along the line between the outer and the inner corner and
+//! sides of another color or width are mitred. The widths come from layout, after they are
+//! mapped to the page, so the styles and colors are looked up by their physical side too.
+//!
+//! The sides of a box with rounded corners reach around the curves of their corners up to the
+//! mitres, and are filled between the rounded rects of the outer and the inner border edge.
+//!
+//! https://www.w3.org/TR/css-backgrounds-3/#borders
+
+use super::radius::RoundedRect;
+use super::{EdgeSizes, LayoutBox, Rect};
+use crate::gfx::colors::Color;
+use crate::layout::css::Value;
+use crate::layout::style::{BorderStyle, StyledNode};
+
+/// A polygon, as its points in order around it.
+pub type Polygon = Vec<(f32, f32)>;
+
+/// The physical sides of a box, in the order of the box shorthands.
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+/// One side of the border of a box.
+#[derive(Debug, Copy, Clone)]
+struct BorderSide {
+  width: f32,
+  style: BorderStyle,
+  color: Color,
+  // The corners of the side on the border box and on the padding box, in the same order, from
+  // the start of the side to its end going clockwise.
+  outer: [(f32, f32); 2],
+  inner: [(f32, f32); 2],
+}
+
+impl BorderSide {
+  /// The band of the side between two fractions of its width, from the outer edge at 0 to the
+  /// inner edge at 1, mitred like the whole side.
+  fn band(&self, from: f32, to: f32) -> Polygon {
+    let at = |corner: usize, fraction: f32| {
+      let (outer, inner) = (self.outer[corner], self.inner[corner]);
+      (
+        outer.0 + (inner.0 - outer.0) * fraction,
+        outer.1 + (inner.1 - outer.1) * fraction,
+      )
+    };
+    vec![at(0, from), at(1, from), at(1, to), at(0, to)]
+  }
+
+  /// Whether the side runs along the x axis.
+  fn is_horizontal(&self) -> bool {
+    self.outer[0].1 == self.outer[1].1
+  }
+
+  /// The part of the box on the side of its mitres, from the outer edge to a fraction of the
+  /// width of the side deep, or to where the mitres meet if that is closer.
+  fn wedge(&self, to: f32) -> Polygon {
+    let axis = |point: (f32, f32)| {
+      if self.is_horizontal() {
+        point.0
+      } else {
+        point.1
+      }
+    };
+    // The length along the side changes linearly from the outer to the inner edge.
+    let outer_length = axis(self.outer[1]) - axis(self.outer[0]);
+    let inner_length = axis(self.inner[1]) - axis(self.inner[0]);
+    if outer_length != inner_length {
+      let meet = outer_length / (outer_length - inner_length);
+      if meet > 0.0 && meet < to {
+        let mut triangle = self.band(0.0, meet);
+        triangle.pop();
+        return triangle;
+      }
+    }
+    self.band(0.0, to)
+  }
+
+  /// The strips across the side that its dashes or dots cover, as their start and end along it.
+  /// Dots are as long as the side is wide and dashes three times as long, with gaps of the same
+  /// length, adjusted so that the side starts and ends with one.
+  fn dashes(&self, dash: f32) -> Vec<(f32, f32)> {
+    let horizontal = self.is_horizontal();
+    let axis = |point: (f32, f32)| if horizontal { point.0 } else { point.1 };
+    let (start, end) = (axis(self.outer[0]), axis(self.outer[1]));
+    let length = (end - start).abs();
+    if dash <= 0.0 || length <= 0.0 {
+      return Vec::new();
+    }
+    let count = ((length + dash) / (2.0 * dash)).round().max(1.0);
+    let dash = length / (2.0 * count - 1.0);
+    let direction = (end - start).signum();
+    (0..count as usize)
+      .map(|index| {
+        let from = start + direction * dash * 2.0 * index as f32;
+        let to = from + direction * dash;
+        (from.min(to), from.max(to))
+      })
+      .collect()
+  }
+}
+
+/// A piece of the border of a box, a convex polygon filled with a color. When the box has
+/// rounded corners, only the part of the polygon between the outer and the inner rounded rect of
+/// its band is filled, which follows the curves of the corners.
+#[derive(Debug, Clone)]
+pub struct BorderPiece {
+  pub color: Color,
+  pub polygon: Polygon,
+  pub curves: Option<(RoundedRect, RoundedRect)>,
+}
+
+impl<'a> LayoutBox<'a> {
+  /// The pieces that paint the border of the box.
+  ///
+  /// https://www.w3.org/TR/css-backgrounds-3/#border-style
+  pub fn border_pieces(&self) -> Vec<BorderPiece> {
+    let style = match self.get_style_node_option() {
+      Some(style) => style,
+      None => return Vec::new(),
+    };
+    let border = self.dimensions.border;
+    let rounded = self.rounded_border_box();
+    let corners = |rect: Rect| {
+      let (left, top) = (rect.x, rect.y);
+      let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+      [(left, top), (right, top), (right, bottom), (left, bottom)]
+    };
+    let outer = corners(self.dimensions.border_box());
+    let inner = corners(self.dimensions.padding_box());
+    let widths = [border.top, border.right, border.bottom, border.left];
+
+    let mut pieces = Vec::new();
+    for (index, side_name) in SIDES.iter().enumerate() {
+      let side = BorderSide {
+        width: widths[index],
+        style: style.border_style(side_name),
+        color: border_color(style, side_name),
+        outer: [outer[index], outer[(index + 1) % 4]],
+        inner: [inner[index], inner[(index + 1) % 4]],
+      };
+      if side.width <= 0.0 || side.color.a == 0 {
+        continue;
+      }
+
+      // With rounded corners, a side covers the curves of its corners as far as the mitres
+      // reach, and each band is cut to the rounded rects at its edges.
+      let depth = if rounded.is_rounded() {
+        let (start, end) = (rounded.radii[index], rounded.radii[(index + 1) % 4]);
+        let radius = if side.is_horizontal() {
+          start.1.max(end.1)
+        } else {
+          start.0.max(end.0)
+        };
+        Some(radius.max(side.width) / side.width)
+      } else {
+        None
+      };
+      let at = |fraction: f32| {
+        rounded.inset_by(EdgeSizes {
+          left: border.left * fraction,
+          right: border.right * fraction,
+          top: border.top * fraction,
+          bottom: border.bottom * fraction,
+        })
+      };
+      let mut piece = |color: Color, from: f32, to: f32, strip: Option<(f32, f32)>| {
+        let (polygon, curves) = match depth {
+          Some(depth) => (side.wedge(depth), Some((at(from), at(to)))),
+          None => (side.band(from, to), None),
+        };
+        let polygon = match strip {
+          Some((start, end)) => clip_to_strip(&polygon, side.is_horizontal(), start, end),
+          None => polygon,
+        };
+        if polygon.len() >= 3 {
+          pieces.push(BorderPiece {
+            color,
+            polygon,
+            curves,
+          });
+        }
+      };
+
+      // The top and left sides are in shadow for `inset`, and the other two for `outset`.
+      let top_left = index == 0 || index == 3;
+      let (dark, light) = shades(side.color);
+      let (near, far) = if top_left {
+        (dark, light)
+      } else {
+        (light, dark)
+      };
+
+      match side.style {
+        BorderStyle::None | BorderStyle::Hidden => {}
+        BorderStyle::Solid => piece(side.color, 0.0, 1.0, None),
+        // Lines thinner than 3px don't leave room for a gap.
+        BorderStyle::Double if side.width < 3.0 => piece(side.color, 0.0, 1.0, None),
+        BorderStyle::Double => {
+          piece(side.color, 0.0, 1.0 / 3.0, None);
+          piece(side.color, 2.0 / 3.0, 1.0, None);
+        }
+        BorderStyle::Dashed | BorderStyle::Dotted => {
+          let dash = if side.style == BorderStyle::Dashed {
+            side.width * 3.0
+          } else {
+            side.width
+          };
+          for strip in side.dashes(dash) {
+            piece(side.color, 0.0, 1.0, Some(strip));
+          }
+        }
+        BorderStyle::Inset => piece(near, 0.0, 1.0, None),
+        BorderStyle::Outset => piece(far, 0.0, 1.0, None),
+        BorderStyle::Groove => {
+          piece(near, 0.0, 0.5, None);
+          piece(far, 0.5, 1.0, None);
+        }
+        BorderStyle::Ridge => {
+          piece(far, 0.0, 0.5, None);
+          piece(near, 0.5, 1.0, None);
+        }
+      }
+    }
+    pieces
+  }
+}
+
+/// The color of a side, from `border-<side>-color` or one of the border shorthands, or else the
+/// `color` of the box.
+fn border_color(style: &StyledNode, side: &str) -> Color {
+  match (style.border_value(side, "color"), style.value("color")) {
+    (Some(Value::ColorValue(color)), _) | (_, Some(Value::ColorValue(color))) => color,
+    _ => Color::black(),
+  }
+}
+
+/// The darker and the lighter shade of a color, for the sides of the 3D border styles. Black
+/// has no darker shade, so it is shaded like a dark gray.
+fn shades(color: Color) -> (Color, Color) {
+  let light = if color.r == 0 && color.g == 0 && color.b == 0 {
+    Color::new_alpha(0x60, 0x60, 0x60, color.a)
+  } else {
+    color
+  };
+  let darken = |channel: u8| (channel as f32 * 2.0 / 3.0) as u8;
+  let dark = Color::new_alpha(darken(light.r), darken(light.g), darken(light.b), light.a);
+  (dark, light)
+}
+
+/// The part of a convex polygon between two lines across an axis, at `from` and `to` along x
+/// for a horizontal strip or along y otherwise.
+fn clip_to_strip(polygon: &[(f32, f32)], horizontal: bool, from: f32, to: f32) -> Polygon {
+  let axis = move |point: (f32, f32)| if horizontal { point.0 } else { point.1 };
+  let clip = |points: Vec<(f32, f32)>, inside: &dyn Fn(f32) -> bool, edge: f32| {
+    let mut clipped = Vec::new();
+    for (index, &point) in points.iter().enumerate() {
+      let next = points[(index + 1) % points.len()];
+      if inside(axis(point)) {
+        clipped.push(point);
+      }
+      // Where the edge of the polygon crosses the line.
+      if inside(axis(point)) != inside(axis(next)) {
+        let t = (edge - axis(point)) / (axis(next) - axis(point));
+        clipped.push((
+          point.0 + (next.0 - point.0) * t,
+          point.1 + (next.1 - point.1) * t,
+        ));
+      }
+    }
+    clipped
+  };
+  let clipped = clip(polygon.to_vec(), &|value| value >= from, from);
+  if clipped.is_empty() {
+    return clipped;
+  }
+  clip(clipped, &|value| value <= to, to)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, div, layout};
+  use super::super::LayoutBox;
+
+  /// The border widths of the first box in the body, as top, right, bottom and left.
+  fn widths(root: &LayoutBox) -> [f32; 4] {
+    let border = root.children[0].dimensions.border;
+    [border.top, border.right, border.bottom, border.left]
+  }
+
+  fn border_widths(css: &str) -> [f32; 4] {
+    layout(&body(vec![div("box", vec![])]), css, widths)
+  }
+
+  #[test]
+  fn the_border_shorthand_sets_all_sides() {
+    let document = body(vec![div("box", vec![])]);
+    let css = ".box { width: 100px; height: 50px; border: 10px solid #ff0000; }";
+    layout(&document, css, |root| {
+      assert_eq!(widths(root), [10.0; 4]);
+      let pieces = root.children[0].border_pieces();
+      assert_eq!(pieces.len(), 4);
+      assert!(pieces
+        .iter()
+        .all(|piece| (piece.color.r, piece.color.g, piece.color.b) == (255, 0, 0)));
+    });
+  }
+
+  #[test]
+  fn side_shorthands_set_one_side() {
+    assert_eq!(
+      border_widths(".box { border-top: 4px dashed; }"),
+      [4.0, 0.0, 0.0, 0.0]
+    );
+    // The longhands win over the side shorthands, which win over the other shorthands.
+    let css = ".box { border: 2px solid; border-left: 6px double; border-left-width: 8px; }";
+    assert_eq!(border_widths(css), [2.0, 2.0, 2.0, 8.0]);
+    let css = ".box { border-width: 1px 2px; border-style: solid; border-right: 4px dotted; }";
+    assert_eq!(border_widths(css), [1.0, 4.0, 1.0, 2.0]);
+  }
+
+  #[test]
+  fn sides_without_a_style_have_no_border() {
+    assert_eq!(border_widths(".box { border-width: 5px; }"), [0.0; 4]);
+    assert_eq!(border_widths(".box { border: 5px #000000; }"), [0.0; 4]);
+    let css = ".box { border: 5px solid; border-style: solid hidden; border-bottom-style: none; }";
+    assert_eq!(border_widths(css), [5.0, 0.0, 0.0, 0.0]);
+    let css = ".box { border: 5px solid; border-top-style: hidden; border-left: none; }";
+    assert_eq!(border_widths(css), [0.0, 5.0, 5.0, 0.0]);
+  }
+
+  #[test]
+  fn width_keywords() {
+    assert_eq!(border_widths(".box { border: solid; }"), [3.0; 4]);
+    let css = ".box { border-style: solid; border-width: thin medium thick; }";
+    assert_eq!(border_widths(css), [1.0, 3.0, 5.0, 3.0]);
+  }
+}
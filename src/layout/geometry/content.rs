@@ -0,0 +1,429 @@
+//! Generated content: the text of `::before` and `::after` pseudo-elements from the `content`
+//! property, and the counters and quotes it refers to.
+//!
+//! Counters and the nesting level of quotes depend on everything before an element in the
+//! document, so they are kept in a `ContentState` while the box tree is built.
+//!
+//! https://www.w3.org/TR/css-content-3/
+//! https://www.w3.org/TR/css-lists-3/#auto-numbering
+
+use super::list::{estimate_text_size, format_counter, DEFAULT_FONT_SIZE};
+use super::{BoxType, LayoutBox, Rect};
+use crate::layout::css::Value;
+use crate::layout::dom::NodeType;
+use crate::layout::style::{DisplayStyle, StyledNode, TextTransform};
+use std::collections::HashMap;
+
+/// The counter that numbers list items.
+const LIST_ITEM: &str = "list-item";
+
+#[derive(Debug, Copy, Clone)]
+struct Counter {
+  value: i32,
+  // Whether list items count down, which is the case for `reversed(list-item)`.
+  reversed: bool,
+}
+
+/// The counters in scope and the nesting level of quotes at some point in the tree walk.
+pub(super) struct ContentState {
+  // The nested counters with each name, innermost last.
+  counters: HashMap<String, Vec<Counter>>,
+  // The names of the counters created among the children of each element that is being built,
+  // which go out of scope with that element.
+  scopes: Vec<Vec<String>>,
+  quote_depth: usize,
+}
+
+impl ContentState {
+  pub fn new() -> Self {
+    ContentState {
+      counters: HashMap::new(),
+      scopes: vec![Vec::new()],
+      quote_depth: 0,
+    }
+  }
+
+  /// Start building the children of an element. Counters they create are visible to their
+  /// following siblings and the descendants of those, until `leave_element`.
+  pub fn enter_element(&mut self) {
+    self.scopes.push(Vec::new());
+  }
+
+  pub fn leave_element(&mut self) {
+    for name in self.scopes.pop().unwrap_or_default() {
+      if let Some(counters) = self.counters.get_mut(&name) {
+        counters.pop();
+      }
+    }
+  }
+
+  /// Apply `counter-reset`, `counter-increment` and `counter-set` of an element or
+  /// pseudo-element, in that order, including the `list-item` counter that lists maintain
+  /// without any styles.
+  ///
+  /// https://www.w3.org/TR/css-lists-3/#counter-properties
+  pub fn update_counters(&mut self, node: &StyledNode, display: DisplayStyle) {
+    let element = node.element();
+    let tag_name = element.map(|data| data.tag_name.as_str());
+
+    // Lists reset the `list-item` counter unless a rule sets `counter-reset`, starting at the
+    // `start` attribute of an `<ol>`, and counting down if it is `reversed`.
+    match node.value("counter-reset") {
+      Some(value) => {
+        for (name, value, reversed) in counter_list(&value) {
+          // A reversed counter starts above the number of list items, which count down.
+          let default = if reversed {
+            list_item_count(node) + 1
+          } else {
+            0
+          };
+          self.reset(&name, value.unwrap_or(default), reversed);
+        }
+      }
+      None if matches!(tag_name, Some("ol") | Some("ul") | Some("menu")) => {
+        let start = node
+          .attribute("start")
+          .and_then(|start| start.trim().parse::<i32>().ok());
+        if node.attribute("reversed").is_some() {
+          let start = start.unwrap_or_else(|| list_item_count(node));
+          self.reset(LIST_ITEM, start + 1, true);
+        } else {
+          self.reset(LIST_ITEM, start.unwrap_or(1) - 1, false);
+        }
+      }
+      None => {}
+    }
+
+    // List items increment the `list-item` counter, unless a rule increments it already.
+    let increments = node
+      .value("counter-increment")
+      .map(|value| counter_list(&value))
+      .unwrap_or_default();
+    for (name, value, _) in &increments {
+      self.increment(name, value.unwrap_or(1));
+    }
+    if display == DisplayStyle::ListItem && increments.iter().all(|(name, ..)| name != LIST_ITEM) {
+      let reversed = self.innermost(LIST_ITEM).map(|counter| counter.reversed);
+      self.increment(LIST_ITEM, if reversed == Some(true) { -1 } else { 1 });
+    }
+
+    // The `value` attribute of a list item sets its number.
+    match node.value("counter-set") {
+      Some(value) => {
+        for (name, value, _) in counter_list(&value) {
+          self.set(&name, value.unwrap_or(0));
+        }
+      }
+      None if display == DisplayStyle::ListItem => {
+        if let Some(value) = node
+          .attribute("value")
+          .and_then(|value| value.trim().parse().ok())
+        {
+          self.set(LIST_ITEM, value);
+        }
+      }
+      None => {}
+    }
+  }
+
+  /// The value of the innermost counter with a name, zero if there is none.
+  pub fn counter(&self, name: &str) -> i32 {
+    self
+      .innermost(name)
+      .map(|counter| counter.value)
+      .unwrap_or(0)
+  }
+
+  /// The text generated by the `content` of a pseudo-element, or `None` if it doesn't generate
+  /// a box, with `content: none` or `normal`. Quotes update the nesting level, and the text is
+  /// transformed by `text-transform`.
+  ///
+  /// https://www.w3.org/TR/css-content-3/#content-property
+  pub fn generated_text(&mut self, pseudo_element: &StyledNode) -> Option<String> {
+    if !generates_box(pseudo_element) {
+      return None;
+    }
+    let values = match pseudo_element.value("content") {
+      Some(Value::List(values)) => values,
+      Some(value) => vec![value],
+      None => Vec::new(),
+    };
+
+    let quotes = quotes(pseudo_element);
+    let mut text = String::new();
+    for value in values {
+      match value {
+        Value::Str(string) => text.push_str(&string),
+        Value::Keyword(keyword) => match keyword.as_str() {
+          // Quotes nested deeper than the pairs given use the last pair.
+          "open-quote" => {
+            if let Some((open, _)) = quotes.get(self.quote_depth).or_else(|| quotes.last()) {
+              text.push_str(open);
+            }
+            self.quote_depth += 1;
+          }
+          "close-quote" if self.quote_depth > 0 => {
+            self.quote_depth -= 1;
+            if let Some((_, close)) = quotes.get(self.quote_depth).or_else(|| quotes.last()) {
+              text.push_str(close);
+            }
+          }
+          "no-open-quote" => self.quote_depth += 1,
+          "no-close-quote" => self.quote_depth = self.quote_depth.saturating_sub(1),
+          _ => {}
+        },
+        Value::Function(name, arguments) => match (name.as_str(), arguments.as_slice()) {
+          // The attribute of the element that generates the pseudo-element.
+          ("attr", [Value::Keyword(attribute)]) => {
+            if let NodeType::Element(ref data) = pseudo_element.node.node_type {
+              if let Some(value) = data.attributes.get(attribute) {
+                text.push_str(value);
+              }
+            }
+          }
+          ("counter", [Value::Keyword(counter), rest @ ..]) => {
+            let style_type = counter_style(rest.first());
+            text.push_str(&format_counter(self.counter(counter), &style_type));
+          }
+          ("counters", [Value::Keyword(counter), Value::Str(separator), rest @ ..]) => {
+            let style_type = counter_style(rest.first());
+            let counters = self.counters.get(counter).cloned().unwrap_or_default();
+            let values: Vec<String> = if counters.is_empty() {
+              vec![format_counter(0, &style_type)]
+            } else {
+              counters
+                .iter()
+                .map(|counter| format_counter(counter.value, &style_type))
+                .collect()
+            };
+            text.push_str(&values.join(separator));
+          }
+          _ => {}
+        },
+        _ => {}
+      }
+    }
+    Some(transform_text(&text, pseudo_element.text_transform()))
+  }
+
+  fn innermost(&self, name: &str) -> Option<&Counter> {
+    self.counters.get(name).and_then(|counters| counters.last())
+  }
+
+  /// Create a counter among the children of the element being built. It replaces a counter with
+  /// the same name that was created by a previous sibling.
+  fn reset(&mut self, name: &str, value: i32, reversed: bool) {
+    let counter = Counter { value, reversed };
+    let scope = self.scopes.last_mut().unwrap();
+    let counters = self.counters.entry(name.to_string()).or_default();
+    if scope.iter().any(|created| created == name) {
+      if let Some(last) = counters.last_mut() {
+        *last = counter;
+        return;
+      }
+    }
+    scope.push(name.to_string());
+    counters.push(counter);
+  }
+
+  fn increment(&mut self, name: &str, by: i32) {
+    match self
+      .counters
+      .get_mut(name)
+      .and_then(|counters| counters.last_mut())
+    {
+      Some(counter) => counter.value += by,
+      // A counter that is not in scope is created by the element that uses it.
+      None => self.reset(name, by, false),
+    }
+  }
+
+  fn set(&mut self, name: &str, value: i32) {
+    match self
+      .counters
+      .get_mut(name)
+      .and_then(|counters| counters.last_mut())
+    {
+      Some(counter) => counter.value = value,
+      None => self.reset(name, value, false),
+    }
+  }
+}
+
+impl<'a> LayoutBox<'a> {
+  /// Size a generated text box at the origin, with the size of its text estimated like that of
+  /// markers.
+  pub(super) fn layout_generated_text(&mut self) {
+    let (width, height) = self.generated_text_size();
+    self.dimensions.content = Rect {
+      x: 0.0,
+      y: 0.0,
+      width,
+      height,
+    };
+  }
+
+  /// The estimated size of the text of a generated text box, zero for other boxes.
+  pub(super) fn generated_text_size(&self) -> (f32, f32) {
+    let viewport = self.viewport;
+    match self.box_type {
+      BoxType::GeneratedText(pseudo_element, ref text) => {
+        let font_size = pseudo_element
+          .length("font-size", DEFAULT_FONT_SIZE, viewport)
+          .unwrap_or(DEFAULT_FONT_SIZE);
+        estimate_text_size(text, font_size, pseudo_element, viewport)
+      }
+      _ => (0.0, 0.0),
+    }
+  }
+}
+
+/// Whether a pseudo-element generates a box, which it doesn't with `content: none` or `normal`.
+pub(super) fn generates_box(pseudo_element: &StyledNode) -> bool {
+  match pseudo_element.value("content") {
+    None => false,
+    Some(Value::Keyword(ref keyword)) => keyword != "none" && keyword != "normal",
+    Some(_) => true,
+  }
+}
+
+/// Apply `text-transform` to some text with the full Unicode case mappings, which can change its
+/// length, like `ß` to `SS`. `capitalize` uppercases the first letter of each word, skipping
+/// punctuation before it, while words that start with a digit are left alone. Only generated
+/// text is transformed, since the text of the document is not laid out yet, and the transform
+/// only shows in the estimated size of the text, which isn't painted.
+///
+/// https://www.w3.org/TR/css-text-3/#text-transform-property
+fn transform_text(text: &str, transform: TextTransform) -> String {
+  match transform {
+    TextTransform::None => text.to_string(),
+    TextTransform::Uppercase => text.to_uppercase(),
+    TextTransform::Lowercase => text.to_lowercase(),
+    TextTransform::Capitalize => {
+      let mut transformed = String::with_capacity(text.len());
+      let mut word_start = true;
+      for c in text.chars() {
+        if c.is_whitespace() {
+          word_start = true;
+        } else if word_start && c.is_alphabetic() {
+          transformed.extend(c.to_uppercase());
+          word_start = false;
+          continue;
+        } else if c.is_numeric() {
+          word_start = false;
+        }
+        transformed.push(c);
+      }
+      transformed
+    }
+  }
+}
+
+/// Parse the value of `counter-reset`, `counter-increment` or `counter-set` into the names of
+/// counters with their value if it is given, and whether a reset counter is `reversed()`.
+fn counter_list(value: &Value) -> Vec<(String, Option<i32>, bool)> {
+  let values = match value {
+    Value::List(values) => values.clone(),
+    value => vec![value.clone()],
+  };
+
+  let mut counters: Vec<(String, Option<i32>, bool)> = Vec::new();
+  for value in values {
+    match value {
+      Value::Keyword(ref keyword) if keyword == "none" => {}
+      Value::Keyword(name) => counters.push((name, None, false)),
+      Value::Function(ref function, ref arguments) if function == "reversed" => {
+        if let Some(Value::Keyword(name)) = arguments.first() {
+          counters.push((name.clone(), None, true));
+        }
+      }
+      Value::Number(number) => {
+        if let Some(counter) = counters.last_mut() {
+          counter.1 = Some(number as i32);
+        }
+      }
+      _ => {}
+    }
+  }
+  counters
+}
+
+/// The counter style given to `counter()` or `counters()`, `decimal` by default.
+fn counter_style(value: Option<&Value>) -> String {
+  match value {
+    Some(Value::Keyword(style_type)) => style_type.clone(),
+    _ => "decimal".to_string(),
+  }
+}
+
+/// The pairs of open and close quotes from `quotes`, for each nesting level.
+///
+/// https://www.w3.org/TR/css-content-3/#quotes-property
+fn quotes(node: &StyledNode) -> Vec<(String, String)> {
+  match node.value("quotes") {
+    Some(Value::Keyword(ref keyword)) if keyword == "none" => Vec::new(),
+    Some(Value::List(values)) => values
+      .chunks(2)
+      .filter_map(|pair| match pair {
+        [Value::Str(open), Value::Str(close)] => Some((open.clone(), close.clone())),
+        _ => None,
+      })
+      .collect(),
+    _ => vec![
+      ("\u{201c}".to_string(), "\u{201d}".to_string()),
+      ("\u{2018}".to_string(), "\u{2019}".to_string()),
+    ],
+  }
+}
+
+/// The number of list items among the children of an element, where a reversed list starts.
+fn list_item_count(node: &StyledNode) -> i32 {
+  node
+    .children
+    .iter()
+    .filter(|child| child.display() == DisplayStyle::ListItem)
+    .count() as i32
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, div, layout};
+  use super::super::{BoxType, LayoutBox};
+
+  /// The generated text among a box and its descendants, with its size, in tree order.
+  fn generated_text(layout_box: &LayoutBox) -> Vec<(String, (f32, f32))> {
+    let mut found = Vec::new();
+    if let BoxType::GeneratedText(_, ref text) = layout_box.box_type {
+      found.push((text.clone(), layout_box.generated_text_size()));
+    }
+    for child in &layout_box.children {
+      found.extend(generated_text(child));
+    }
+    found
+  }
+
+  #[test]
+  fn generated_text_is_sized_by_the_inherited_font_size() {
+    let document = body(vec![div("big", vec![])]);
+    let css = ".big { font-size: 40px; } .big::before { content: \"x\"; }";
+    layout(&document, css, |root| {
+      let text = generated_text(root);
+      assert_eq!(text.len(), 1);
+      assert_eq!((text[0].1).1, 40.0);
+    });
+  }
+
+  #[test]
+  fn quotes_are_inherited() {
+    let document = body(vec![div("outer", vec![div("quote", vec![])])]);
+    let css = ".outer { quotes: \"<\" \">\"; } \
+               .quote::before { content: open-quote; } .quote::after { content: close-quote; }";
+    layout(&document, css, |root| {
+      let text: Vec<String> = generated_text(root)
+        .into_iter()
+        .map(|(text, _)| text)
+        .collect();
+      assert_eq!(text, vec!["<", ">"]);
+    });
+  }
+}
@@ -0,0 +1,251 @@
+//! Text decoration lines: the underlines, overlines and line-throughs of `text-decoration`.
+//!
+//! The decorations of a box are drawn across all the text of its in-flow content, but not inside
+//! atomic inlines or out-of-flow boxes, so they are passed down while the display list is built.
+//! Text is not laid out yet, so only generated text is decorated, with the baseline and the
+//! thickness of the lines estimated from the font size. The text of the document gets no
+//! decorations, and generated text isn't painted itself, so its lines are drawn on their own.
+//!
+//! https://www.w3.org/TR/css-text-decor-3/#line-decoration
+
+use super::list::DEFAULT_FONT_SIZE;
+use super::{BoxType, LayoutBox, Rect};
+use crate::gfx::colors::Color;
+use crate::layout::css::{Value, Viewport};
+use crate::layout::style::StyledNode;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DecorationLine {
+  Underline,
+  Overline,
+  LineThrough,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DecorationStyle {
+  Solid,
+  Double,
+  Dotted,
+  Dashed,
+  Wavy,
+}
+
+/// The lines drawn by the `text-decoration` of a box, in the color of that box.
+#[derive(Debug, Clone)]
+pub struct TextDecoration {
+  pub lines: Vec<DecorationLine>,
+  pub style: DecorationStyle,
+  pub color: Color,
+  // The thickness of the lines, `None` for `auto` and `from-font`. Percentages refer to the font
+  // size of the decorated text.
+  thickness: Option<Value>,
+}
+
+impl<'a> LayoutBox<'a> {
+  /// The decoration set by the `text-decoration` shorthand and longhands of the box, if it draws
+  /// any lines.
+  ///
+  /// https://www.w3.org/TR/css-text-decor-3/#text-decoration-property
+  pub fn text_decoration(&self) -> Option<TextDecoration> {
+    let style = self.get_style_node_option()?;
+    let mut decoration = TextDecoration {
+      lines: Vec::new(),
+      style: DecorationStyle::Solid,
+      color: match style.value("color") {
+        Some(Value::ColorValue(color)) => color,
+        _ => Color::black(),
+      },
+      thickness: None,
+    };
+
+    let shorthand = match style.value("text-decoration") {
+      Some(Value::List(values)) => values,
+      Some(value) => vec![value],
+      None => Vec::new(),
+    };
+    for value in shorthand {
+      match value {
+        Value::Keyword(ref keyword) => {
+          if let Some(line) = decoration_line(keyword) {
+            decoration.lines.push(line);
+          } else if let Some(style) = decoration_style(keyword) {
+            decoration.style = style;
+          }
+        }
+        Value::ColorValue(color) => decoration.color = color,
+        Value::Length(..) => decoration.thickness = Some(value),
+        _ => {}
+      }
+    }
+
+    match style.value("text-decoration-line") {
+      Some(Value::List(values)) => {
+        decoration.lines = values
+          .iter()
+          .filter_map(|value| match value {
+            Value::Keyword(keyword) => decoration_line(keyword),
+            _ => None,
+          })
+          .collect()
+      }
+      Some(Value::Keyword(keyword)) => {
+        decoration.lines = decoration_line(&keyword).into_iter().collect()
+      }
+      _ => {}
+    }
+    if let Some(style) = style
+      .keyword("text-decoration-style")
+      .and_then(|keyword| decoration_style(&keyword))
+    {
+      decoration.style = style;
+    }
+    if let Some(Value::ColorValue(color)) = style.value("text-decoration-color") {
+      decoration.color = color;
+    }
+    match style.value("text-decoration-thickness") {
+      Some(value @ Value::Length(..)) => decoration.thickness = Some(value),
+      Some(Value::Keyword(_)) => decoration.thickness = None,
+      _ => {}
+    }
+
+    if decoration.lines.is_empty() {
+      None
+    } else {
+      Some(decoration)
+    }
+  }
+
+  /// Whether the decorations of the ancestors of the box are drawn across its text, which they
+  /// aren't inside atomic inlines and out-of-flow boxes.
+  ///
+  /// https://www.w3.org/TR/css-text-decor-3/#line-decoration
+  pub fn propagates_text_decorations(&self) -> bool {
+    match self.box_type {
+      BoxType::GeneratedText(..) => true,
+      _ => !self.is_atomic_inline() && !self.position().is_out_of_flow(),
+    }
+  }
+
+  /// The rects that draw the lines of a decoration across the text of a generated text box,
+  /// none for other boxes. The lines run along the text, which is top to bottom in vertical
+  /// writing modes, where the line-over side is the right.
+  pub fn text_decoration_rects(&self, decoration: &TextDecoration) -> Vec<Rect> {
+    let viewport = self.viewport;
+    let style = match self.box_type {
+      BoxType::GeneratedText(style, _) => style,
+      _ => return Vec::new(),
+    };
+    let content = self.dimensions.content;
+    let vertical = style.writing_mode.is_vertical();
+    let length = if vertical {
+      content.height
+    } else {
+      content.width
+    };
+    let font_size = font_size(style, viewport);
+    let thickness = match decoration.thickness {
+      Some(ref thickness) => thickness.to_px(font_size, viewport),
+      None => (font_size / 16.0).max(1.0),
+    };
+    if thickness <= 0.0 || length <= 0.0 {
+      return Vec::new();
+    }
+
+    // Strokes along the text as (start, length, offset from the line-over edge), with the
+    // baseline a fifth of the font size above the bottom of the text.
+    let baseline = font_size * 0.8;
+    let mut strokes = Vec::new();
+    for line in &decoration.lines {
+      let offset = match line {
+        DecorationLine::Underline => baseline + thickness,
+        DecorationLine::Overline => 0.0,
+        DecorationLine::LineThrough => baseline - font_size * 0.3 - thickness / 2.0,
+      };
+      strokes.extend(line_strokes(decoration.style, length, offset, thickness));
+    }
+
+    strokes
+      .into_iter()
+      .map(|(start, stroke_length, offset)| {
+        let stroke_length = stroke_length.min(length - start);
+        if vertical {
+          Rect {
+            x: content.x + content.width - offset - thickness,
+            y: content.y + start,
+            width: thickness,
+            height: stroke_length,
+          }
+        } else {
+          Rect {
+            x: content.x + start,
+            y: content.y + offset,
+            width: stroke_length,
+            height: thickness,
+          }
+        }
+      })
+      .collect()
+  }
+}
+
+fn decoration_line(keyword: &str) -> Option<DecorationLine> {
+  match keyword {
+    "underline" => Some(DecorationLine::Underline),
+    "overline" => Some(DecorationLine::Overline),
+    "line-through" => Some(DecorationLine::LineThrough),
+    _ => None,
+  }
+}
+
+fn decoration_style(keyword: &str) -> Option<DecorationStyle> {
+  match keyword {
+    "solid" => Some(DecorationStyle::Solid),
+    "double" => Some(DecorationStyle::Double),
+    "dotted" => Some(DecorationStyle::Dotted),
+    "dashed" => Some(DecorationStyle::Dashed),
+    "wavy" => Some(DecorationStyle::Wavy),
+    _ => None,
+  }
+}
+
+fn font_size(style: &StyledNode, viewport: Viewport) -> f32 {
+  style
+    .length("font-size", DEFAULT_FONT_SIZE, viewport)
+    .unwrap_or(DEFAULT_FONT_SIZE)
+}
+
+/// Break a decoration line of some length into strokes of its thickness, as (start, length,
+/// offset) along and across the text. A double line adds a second line below the first, dots
+/// are square and a wavy line zigzags between strokes above and below the line.
+fn line_strokes(
+  style: DecorationStyle,
+  length: f32,
+  offset: f32,
+  thickness: f32,
+) -> Vec<(f32, f32, f32)> {
+  let repeat = |stroke: f32, period: f32, offsets: &[f32]| {
+    let mut strokes = Vec::new();
+    let mut start = 0.0;
+    let mut index = 0;
+    while start < length {
+      strokes.push((start, stroke, offsets[index % offsets.len()]));
+      start += period;
+      index += 1;
+    }
+    strokes
+  };
+  match style {
+    DecorationStyle::Solid => vec![(0.0, length, offset)],
+    DecorationStyle::Double => vec![
+      (0.0, length, offset),
+      (0.0, length, offset + thickness * 2.0),
+    ],
+    DecorationStyle::Dotted => repeat(thickness, thickness * 2.0, &[offset]),
+    DecorationStyle::Dashed => repeat(thickness * 3.0, thickness * 5.0, &[offset]),
+    DecorationStyle::Wavy => repeat(
+      thickness * 2.0,
+      thickness * 2.0,
+      &[offset - thickness, offset + thickness],
+    ),
+  }
+}
@@ -0,0 +1,900 @@
+//! Flex layout: the formatting context established by `display: flex | inline-flex`.
+//!
+//! https://www.w3.org/TR/css-flexbox-1/#layout-algorithm
+
+use super::alignment::{distribute, gaps};
+use super::intrinsic::IntrinsicSizes;
+use super::{LayoutBox, SimpleDimensions};
+use crate::layout::css::{Unit, Value};
+use crate::layout::style::StyledNode;
+
+#[derive(Copy, Clone, PartialEq)]
+enum FlexDirection {
+  Row,
+  RowReverse,
+  Column,
+  ColumnReverse,
+}
+
+impl FlexDirection {
+  fn is_row(self) -> bool {
+    self == FlexDirection::Row || self == FlexDirection::RowReverse
+  }
+
+  fn is_reverse(self) -> bool {
+    self == FlexDirection::RowReverse || self == FlexDirection::ColumnReverse
+  }
+}
+
+/// The state of one flex item while running the layout algorithm, all sizes are in the main
+/// and cross axis of the container rather than width and height.
+struct FlexItem {
+  // Index of the item in the children of the container.
+  index: usize,
+
+  grow: f32,
+  shrink: f32,
+  base_size: f32,
+  hypothetical_main_size: f32,
+  target_main_size: f32,
+  min_main_size: f32,
+  max_main_size: f32,
+  frozen: bool,
+
+  // Border and padding along each axis.
+  main_edges: f32,
+  cross_edges: f32,
+
+  // Margins along each axis, `None` for `auto`.
+  main_margins: (Option<f32>, Option<f32>),
+  cross_margins: (Option<f32>, Option<f32>),
+
+  cross_size: f32,
+  align_self: String,
+  stretched: bool,
+}
+
+impl FlexItem {
+  fn outer_main_size(&self, main_size: f32) -> f32 {
+    main_size
+      + self.main_edges
+      + self.main_margins.0.unwrap_or(0.0)
+      + self.main_margins.1.unwrap_or(0.0)
+  }
+
+  fn outer_cross_size(&self) -> f32 {
+    self.cross_size
+      + self.cross_edges
+      + self.cross_margins.0.unwrap_or(0.0)
+      + self.cross_margins.1.unwrap_or(0.0)
+  }
+
+  fn flex_factor(&self, growing: bool) -> f32 {
+    if growing {
+      self.grow
+    } else {
+      self.shrink
+    }
+  }
+
+  fn clamp(&self, main_size: f32) -> f32 {
+    main_size.min(self.max_main_size).max(self.min_main_size)
+  }
+}
+
+struct FlexLine {
+  // Indices into the list of flex items.
+  items: Vec<usize>,
+  cross_size: f32,
+  cross_position: f32,
+}
+
+impl<'a> LayoutBox<'a> {
+  /// Lay out the flex items of a flex container within its content area.
+  ///
+  /// Sets `self.dimensions.height` to the height of the flex lines (for rows) or items (for
+  /// columns), which `calculate_block_height` overrides if the container has a definite height.
+  pub(super) fn layout_flex_children(&mut self, original_containing_block: &SimpleDimensions) {
+    let viewport = self.viewport;
+    let style = self.get_style_node();
+    let container = self.dimensions.content;
+    let d = self.dimensions;
+    let vertical_edges = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+
+    let direction = flex_direction(style);
+    let wrap = flex_wrap(style);
+    let is_row = direction.is_row();
+
+    let definite_height = style
+      .size(
+        "height",
+        original_containing_block.height,
+        vertical_edges,
+        viewport,
+      )
+      .map(|height| {
+        style.clamp_size(
+          "height",
+          height,
+          original_containing_block.height,
+          vertical_edges,
+          viewport,
+        )
+      });
+    let main_available = if is_row {
+      Some(container.width)
+    } else {
+      definite_height
+    };
+    let cross_available = if is_row {
+      definite_height
+    } else {
+      Some(container.width)
+    };
+
+    let (row_gap, column_gap) = gaps(
+      style,
+      container.width,
+      definite_height.unwrap_or(0.0),
+      viewport,
+    );
+    let (main_gap, cross_gap) = if is_row {
+      (column_gap, row_gap)
+    } else {
+      (row_gap, column_gap)
+    };
+
+    let align_items = style
+      .keyword("align-items")
+      .unwrap_or_else(|| "normal".to_string());
+    let reference = SimpleDimensions::from_dimension(container, style.direction());
+
+    // Collect the in-flow children as flex items, in `order` order.
+    let mut order = Vec::new();
+    for (index, child) in self.children.iter_mut().enumerate() {
+      if child.position().is_out_of_flow() {
+        child.dimensions.content.x = container.x;
+        child.dimensions.content.y = container.y;
+        continue;
+      }
+      let order_value = match child.get_style_node_option().and_then(|s| s.value("order")) {
+        Some(Value::Number(n)) => n as i32,
+        _ => 0,
+      };
+      order.push((order_value, index));
+    }
+    order.sort_by_key(|&(order_value, index)| (order_value, index));
+
+    // Determine the flex base size and hypothetical main size of each item.
+    let mut items = Vec::new();
+    for (_, index) in order {
+      let child = &mut self.children[index];
+      child.calculate_edges(container.width);
+      let item_style = child.get_style_node_option();
+      let d = child.dimensions;
+
+      let (grow, shrink, basis) = match item_style {
+        Some(item_style) => flex_factors(item_style),
+        None => (0.0, 1.0, Value::Keyword("auto".to_string())),
+      };
+      let auto_margin = |name: &str| match item_style {
+        Some(item_style) => {
+          item_style.lookup(name, "margin", &Value::Number(0.0)) == Value::Keyword("auto".into())
+        }
+        None => false,
+      };
+      let margin = |name: &str, used: f32| if auto_margin(name) { None } else { Some(used) };
+
+      let (main_margins, cross_margins, main_edges, cross_edges) = if is_row {
+        (
+          (
+            margin("margin-left", d.margin.left),
+            margin("margin-right", d.margin.right),
+          ),
+          (
+            margin("margin-top", d.margin.top),
+            margin("margin-bottom", d.margin.bottom),
+          ),
+          d.border.left + d.border.right + d.padding.left + d.padding.right,
+          d.border.top + d.border.bottom + d.padding.top + d.padding.bottom,
+        )
+      } else {
+        (
+          (
+            margin("margin-top", d.margin.top),
+            margin("margin-bottom", d.margin.bottom),
+          ),
+          (
+            margin("margin-left", d.margin.left),
+            margin("margin-right", d.margin.right),
+          ),
+          d.border.top + d.border.bottom + d.padding.top + d.padding.bottom,
+          d.border.left + d.border.right + d.padding.left + d.padding.right,
+        )
+      };
+
+      let main_size_property = if is_row { "width" } else { "height" };
+      let cross_size_property = if is_row { "height" } else { "width" };
+      let min_main_size_property = format!("min-{}", main_size_property);
+      let max_main_size_property = format!("max-{}", main_size_property);
+      let main_reference = main_available.unwrap_or(0.0);
+
+      let align_self = match item_style.and_then(|s| s.keyword("align-self")) {
+        Some(ref keyword) if keyword != "auto" => keyword.clone(),
+        _ => align_items.clone(),
+      };
+      let cross_size_definite = item_style
+        .and_then(|s| {
+          s.size(
+            cross_size_property,
+            cross_available.unwrap_or(0.0),
+            cross_edges,
+            viewport,
+          )
+        })
+        .is_some();
+      let stretched = (align_self == "stretch" || align_self == "normal")
+        && !cross_size_definite
+        && cross_margins.0.is_some()
+        && cross_margins.1.is_some();
+
+      // A column item has to be laid out at its cross size to know its content height.
+      let mut cross_size = 0.0;
+      if !is_row {
+        let available = container.width - cross_edges - d.margin.left - d.margin.right;
+        cross_size = match child.width_property("width", container.width, cross_edges, available) {
+          Some(width) => width,
+          None => child.shrink_to_fit_width(available),
+        };
+        cross_size = child.clamp_width(cross_size, container.width, cross_edges, available);
+        child.layout_block_contents(container.x, container.y, cross_size, &reference);
+      }
+
+      let main_free_space =
+        main_reference - main_edges - main_margins.0.unwrap_or(0.0) - main_margins.1.unwrap_or(0.0);
+      let main_size =
+        |name: &str| child.flex_item_size(name, main_reference, main_edges, main_free_space);
+      let specified_main_size = main_size(main_size_property);
+      // A stretched item in a single line with a definite cross size has a definite cross size
+      // too, which its aspect ratio turns into a main size.
+      //
+      // https://www.w3.org/TR/css-flexbox-1/#definite-sizes
+      let stretched_height = match (is_row && stretched && wrap.is_none(), cross_available) {
+        (true, Some(height)) => Some(
+          height - cross_edges - cross_margins.0.unwrap_or(0.0) - cross_margins.1.unwrap_or(0.0),
+        ),
+        _ => None,
+      };
+
+      let base_size = match basis {
+        Value::Keyword(ref keyword) if keyword == "auto" => match specified_main_size {
+          Some(size) => size,
+          None => {
+            child.flex_content_main_size(is_row, cross_available, stretched_height, container.width)
+          }
+        },
+        Value::Length(_, Unit::Percentage) if main_available.is_none() => {
+          child.flex_content_main_size(is_row, cross_available, stretched_height, container.width)
+        }
+        // Like the main size property, a length includes the padding and border with
+        // `box-sizing: border-box`.
+        ref basis => {
+          let size = basis.to_px(main_available.unwrap_or(0.0), viewport);
+          match item_style.and_then(|s| s.keyword("box-sizing")).as_deref() {
+            Some("border-box") => (size - main_edges).max(0.0),
+            _ => size,
+          }
+        }
+      };
+
+      // Items in a row can't shrink below their min-content width with `min-width: auto`,
+      // unless they clip their content.
+      //
+      // https://www.w3.org/TR/css-flexbox-1/#min-size-auto
+      let max_main_size = main_size(&max_main_size_property).unwrap_or(f32::INFINITY);
+      let min_main_size = match main_size(&min_main_size_property) {
+        Some(size) => size,
+        None if is_row && item_style.is_some_and(is_overflow_visible) => {
+          let content_size = child.intrinsic_sizes().min_content.min(max_main_size);
+          specified_main_size.map_or(content_size, |size| size.min(content_size))
+        }
+        None => 0.0,
+      };
+
+      let mut item = FlexItem {
+        index,
+        grow,
+        shrink,
+        base_size,
+        hypothetical_main_size: 0.0,
+        target_main_size: 0.0,
+        min_main_size,
+        max_main_size,
+        frozen: false,
+        main_edges,
+        cross_edges,
+        main_margins,
+        cross_margins,
+        cross_size,
+        align_self,
+        stretched,
+      };
+      item.hypothetical_main_size = item.clamp(base_size);
+      items.push(item);
+    }
+
+    // Collect the items into flex lines.
+    let mut lines: Vec<FlexLine> = Vec::new();
+    let mut line_main_size = 0.0;
+    for (position, item) in items.iter().enumerate() {
+      let outer = item.outer_main_size(item.hypothetical_main_size);
+      let breaks = match (wrap, main_available, lines.last()) {
+        (Some(_), Some(available), Some(line)) => {
+          !line.items.is_empty() && line_main_size + main_gap + outer > available
+        }
+        (_, _, None) => true,
+        _ => false,
+      };
+
+      if breaks {
+        lines.push(FlexLine {
+          items: Vec::new(),
+          cross_size: 0.0,
+          cross_position: 0.0,
+        });
+        line_main_size = outer;
+      } else {
+        line_main_size += main_gap + outer;
+      }
+      lines.last_mut().unwrap().items.push(position);
+    }
+
+    // The main size of the container is only unknown for columns with an auto height, in which
+    // case every line is as long as its items.
+    let main_size = match main_available {
+      Some(available) => available,
+      None => lines
+        .iter()
+        .map(|line| {
+          let outer: f32 = line
+            .items
+            .iter()
+            .map(|&i| items[i].outer_main_size(items[i].hypothetical_main_size))
+            .sum();
+          outer + main_gap * (line.items.len() as f32 - 1.0)
+        })
+        .fold(0.0, f32::max),
+    };
+
+    for line in &lines {
+      resolve_flexible_lengths(&mut items, &line.items, main_size, main_gap);
+    }
+
+    // Determine the hypothetical cross size of each item by laying it out at its used main size.
+    for item in &mut items {
+      let child = &mut self.children[item.index];
+      if is_row {
+        child.layout_block_contents(container.x, container.y, item.target_main_size, &reference);
+        item.cross_size = child.dimensions.content.height;
+      } else {
+        child.dimensions.content.height = item.target_main_size;
+      }
+    }
+
+    // Determine the cross size of each flex line.
+    let single_line = wrap.is_none();
+    for line in &mut lines {
+      line.cross_size = match cross_available {
+        Some(available) if single_line => available,
+        _ => line
+          .items
+          .iter()
+          .map(|&i| items[i].outer_cross_size())
+          .fold(0.0, f32::max),
+      };
+    }
+
+    let lines_cross_size = lines.iter().map(|line| line.cross_size).sum::<f32>()
+      + cross_gap * (lines.len().max(1) as f32 - 1.0);
+    let cross_size = cross_available.unwrap_or(lines_cross_size);
+
+    // Distribute the remaining cross space between the lines with `align-content`.
+    let align_content = style
+      .keyword("align-content")
+      .unwrap_or_else(|| "normal".to_string());
+    let free_cross_space = cross_size - lines_cross_size;
+    if (align_content == "stretch" || align_content == "normal") && free_cross_space > 0.0 {
+      let extra = free_cross_space / lines.len() as f32;
+      for line in &mut lines {
+        line.cross_size += extra;
+      }
+    }
+    let (mut cross_position, cross_spacing) = distribute(
+      &align_content,
+      if align_content == "stretch" || align_content == "normal" {
+        0.0
+      } else {
+        free_cross_space
+      },
+      lines.len(),
+    );
+    for line in &mut lines {
+      line.cross_position = cross_position;
+      cross_position += line.cross_size + cross_gap + cross_spacing;
+    }
+    if wrap == Some(true) {
+      for line in &mut lines {
+        line.cross_position = cross_size - line.cross_position - line.cross_size;
+      }
+    }
+
+    // Stretch items to their line, and lay them out again if their width changed.
+    for line in &lines {
+      for &i in &line.items {
+        let item = &mut items[i];
+        if !item.stretched {
+          continue;
+        }
+
+        let cross_margins =
+          item.cross_margins.0.unwrap_or(0.0) + item.cross_margins.1.unwrap_or(0.0);
+        let mut stretched_size = (line.cross_size - cross_margins - item.cross_edges).max(0.0);
+        if let Some(item_style) = self.children[item.index].get_style_node_option() {
+          stretched_size = item_style.clamp_size(
+            if is_row { "height" } else { "width" },
+            stretched_size,
+            cross_available.unwrap_or(0.0),
+            item.cross_edges,
+            viewport,
+          );
+        }
+        if stretched_size == item.cross_size {
+          continue;
+        }
+
+        item.cross_size = stretched_size;
+        let child = &mut self.children[item.index];
+        if is_row {
+          child.dimensions.content.height = stretched_size;
+        } else {
+          child.layout_block_contents(container.x, container.y, stretched_size, &reference);
+          child.dimensions.content.height = item.target_main_size;
+        }
+      }
+    }
+
+    // Align the items along both axes, and move them into place.
+    let justify_content = style
+      .keyword("justify-content")
+      .unwrap_or_else(|| "normal".to_string());
+    for line in &lines {
+      let used_main_size: f32 = line
+        .items
+        .iter()
+        .map(|&i| items[i].outer_main_size(items[i].target_main_size))
+        .sum::<f32>()
+        + main_gap * (line.items.len() as f32 - 1.0);
+      let mut free_main_space = main_size - used_main_size;
+
+      // Auto margins absorb positive free space before `justify-content` gets to it.
+      let auto_margins = line
+        .items
+        .iter()
+        .map(|&i| {
+          let margins = items[i].main_margins;
+          margins.0.is_none() as usize + margins.1.is_none() as usize
+        })
+        .sum::<usize>();
+      let auto_margin_size = if auto_margins > 0 && free_main_space > 0.0 {
+        let size = free_main_space / auto_margins as f32;
+        free_main_space = 0.0;
+        size
+      } else {
+        0.0
+      };
+
+      let (mut main_position, main_spacing) =
+        distribute(&justify_content, free_main_space, line.items.len());
+
+      for &i in &line.items {
+        let item = &items[i];
+        let margin_start = item.main_margins.0.unwrap_or(auto_margin_size);
+        let margin_end = item.main_margins.1.unwrap_or(auto_margin_size);
+        let outer_main_size = item.target_main_size + item.main_edges + margin_start + margin_end;
+
+        let mut item_main_position = main_position;
+        if direction.is_reverse() {
+          item_main_position = main_size - main_position - outer_main_size;
+        }
+        main_position += outer_main_size + main_gap + main_spacing;
+
+        let free_cross_space = line.cross_size - item.outer_cross_size();
+        let (cross_start, cross_end) = match item.cross_margins {
+          (None, None) => (
+            free_cross_space.max(0.0) / 2.0,
+            free_cross_space.max(0.0) / 2.0,
+          ),
+          (None, Some(end)) => (free_cross_space.max(0.0), end),
+          (Some(start), None) => (start, free_cross_space.max(0.0)),
+          (Some(start), Some(end)) => match &*item.align_self {
+            "flex-end" | "end" | "self-end" => (start + free_cross_space, end),
+            "center" => (start + free_cross_space / 2.0, end),
+            _ => (start, end),
+          },
+        };
+
+        // Move the item from where it was laid out to its final position.
+        let child = &mut self.children[item.index];
+        let d = &mut child.dimensions;
+        let (x, y) = if is_row {
+          d.margin.left = margin_start;
+          d.margin.right = margin_end;
+          d.margin.top = cross_start;
+          d.margin.bottom = cross_end;
+          (item_main_position, line.cross_position)
+        } else {
+          d.margin.left = cross_start;
+          d.margin.right = cross_end;
+          d.margin.top = margin_start;
+          d.margin.bottom = margin_end;
+          (line.cross_position, item_main_position)
+        };
+
+        let d = child.dimensions;
+        let content_x = container.x + x + d.margin.left + d.border.left + d.padding.left;
+        let content_y = container.y + y + d.margin.top + d.border.top + d.padding.top;
+        child.translate(content_x - d.content.x, content_y - d.content.y);
+      }
+    }
+
+    self.dimensions.content.height = if is_row { cross_size } else { main_size };
+  }
+
+  /// The main size of a flex item's content, used when neither `flex-basis` nor its main size
+  /// property are set: the max-content width of row items, or the width that follows from
+  /// their definite height through their aspect ratio, and the height of column items, which
+  /// are already laid out at their cross size.
+  ///
+  /// `cross_available` is the definite height of a row, and `stretched_height` the content
+  /// height of an item stretched to it.
+  ///
+  /// https://www.w3.org/TR/css-flexbox-1/#algo-main-item
+  fn flex_content_main_size(
+    &self,
+    is_row: bool,
+    cross_available: Option<f32>,
+    stretched_height: Option<f32>,
+    reference: f32,
+  ) -> f32 {
+    if !is_row {
+      return self.dimensions.content.height;
+    }
+    self
+      .ratio_width(cross_available, reference)
+      .or_else(|| {
+        stretched_height.and_then(|height| self.width_from_ratio(height.max(0.0), reference))
+      })
+      .unwrap_or_else(|| self.intrinsic_sizes().max_content)
+  }
+
+  /// Resolve a size property of a flex item, where `width`, `min-width` and `max-width` can be
+  /// intrinsic size keywords.
+  fn flex_item_size(&self, name: &str, reference: f32, edges: f32, available: f32) -> Option<f32> {
+    if name.ends_with("width") {
+      self.width_property(name, reference, edges, available)
+    } else {
+      self
+        .get_style_node_option()?
+        .size(name, reference, edges, self.viewport)
+    }
+  }
+
+  /// The intrinsic sizes of a flex container. A row is as wide as its items next to each
+  /// other, a container that wraps its lines at least as wide as its widest item, and a
+  /// column as wide as its widest item.
+  ///
+  /// https://www.w3.org/TR/css-flexbox-1/#intrinsic-sizes
+  pub(super) fn flex_intrinsic_sizes(&self) -> IntrinsicSizes {
+    let style = self.get_style_node();
+    let (_, column_gap) = gaps(style, 0.0, 0.0, self.viewport);
+    let items: Vec<IntrinsicSizes> = self
+      .children
+      .iter()
+      .filter(|child| !child.position().is_out_of_flow())
+      .map(LayoutBox::intrinsic_contributions)
+      .collect();
+    let gaps = column_gap * (items.len().max(1) - 1) as f32;
+
+    if !flex_direction(style).is_row() {
+      items
+        .into_iter()
+        .fold(IntrinsicSizes::default(), IntrinsicSizes::stacked)
+    } else if flex_wrap(style).is_some() {
+      let sizes = items
+        .into_iter()
+        .fold(IntrinsicSizes::default(), IntrinsicSizes::beside);
+      IntrinsicSizes {
+        max_content: sizes.max_content + gaps,
+        ..sizes
+      }
+    } else {
+      let sizes = items
+        .into_iter()
+        .fold(IntrinsicSizes::default(), IntrinsicSizes::sum);
+      sizes.map(|size| size + gaps)
+    }
+  }
+}
+
+/// Whether a flex item lets its content overflow rather than being a scroll container along
+/// either axis, so that `min-width: auto` resolves to its content-based minimum size.
+fn is_overflow_visible(style: &StyledNode) -> bool {
+  let (x, y) = style.overflow();
+  !x.is_scrollable() && !y.is_scrollable()
+}
+
+/// Resolve the flexible lengths of the items on one flex line.
+///
+/// https://www.w3.org/TR/css-flexbox-1/#resolve-flexible-lengths
+fn resolve_flexible_lengths(items: &mut [FlexItem], line: &[usize], main_size: f32, gap: f32) {
+  let gaps = gap * (line.len() as f32 - 1.0);
+  let hypothetical_size: f32 = line
+    .iter()
+    .map(|&i| items[i].outer_main_size(items[i].hypothetical_main_size))
+    .sum::<f32>()
+    + gaps;
+  let growing = hypothetical_size < main_size;
+
+  // Size inflexible items.
+  for &i in line {
+    let item = &mut items[i];
+    item.target_main_size = item.hypothetical_main_size;
+    item.frozen = item.flex_factor(growing) == 0.0
+      || (growing && item.base_size > item.hypothetical_main_size)
+      || (!growing && item.base_size < item.hypothetical_main_size);
+  }
+
+  let free_space = |items: &[FlexItem]| {
+    main_size
+      - gaps
+      - line
+        .iter()
+        .map(|&i| {
+          let item = &items[i];
+          let size = if item.frozen {
+            item.target_main_size
+          } else {
+            item.base_size
+          };
+          item.outer_main_size(size)
+        })
+        .sum::<f32>()
+  };
+  let initial_free_space = free_space(items);
+
+  loop {
+    let unfrozen: Vec<usize> = line.iter().cloned().filter(|&i| !items[i].frozen).collect();
+    if unfrozen.is_empty() {
+      break;
+    }
+
+    // If the sum of the flex factors is less than one, only distribute part of the free space.
+    let mut remaining_free_space = free_space(items);
+    let factors: f32 = unfrozen
+      .iter()
+      .map(|&i| items[i].flex_factor(growing))
+      .sum();
+    if factors < 1.0 {
+      let scaled = initial_free_space * factors;
+      if scaled.abs() < remaining_free_space.abs() {
+        remaining_free_space = scaled;
+      }
+    }
+
+    if growing {
+      for &i in &unfrozen {
+        let item = &mut items[i];
+        item.target_main_size = item.base_size + remaining_free_space * item.grow / factors;
+      }
+    } else {
+      let scaled_factors: f32 = unfrozen
+        .iter()
+        .map(|&i| items[i].shrink * items[i].base_size)
+        .sum();
+      for &i in &unfrozen {
+        let item = &mut items[i];
+        if scaled_factors > 0.0 {
+          let ratio = item.shrink * item.base_size / scaled_factors;
+          item.target_main_size = item.base_size - remaining_free_space.abs() * ratio;
+        } else {
+          item.target_main_size = item.base_size;
+        }
+      }
+    }
+
+    // Fix min/max violations, and freeze the items that were clamped in the direction of the
+    // total violation (or all of them if there is none).
+    let mut total_violation = 0.0;
+    let mut violations = Vec::new();
+    for &i in &unfrozen {
+      let item = &items[i];
+      let clamped = item.clamp(item.target_main_size);
+      total_violation += clamped - item.target_main_size;
+      violations.push((i, clamped - item.target_main_size, clamped));
+    }
+
+    for (i, violation, clamped) in violations {
+      let item = &mut items[i];
+      item.frozen = total_violation == 0.0
+        || (total_violation > 0.0 && violation > 0.0)
+        || (total_violation < 0.0 && violation < 0.0);
+      item.target_main_size = clamped;
+    }
+  }
+}
+
+fn flex_direction(style: &StyledNode) -> FlexDirection {
+  let keyword = style.keyword("flex-direction").or_else(|| {
+    flow_keywords(style)
+      .into_iter()
+      .find(|keyword| keyword.starts_with("row") || keyword.starts_with("column"))
+  });
+
+  match keyword.as_deref() {
+    Some("row-reverse") => FlexDirection::RowReverse,
+    Some("column") => FlexDirection::Column,
+    Some("column-reverse") => FlexDirection::ColumnReverse,
+    _ => FlexDirection::Row,
+  }
+}
+
+/// The value of `flex-wrap`: `None` for a single-line container, `Some(reversed)` otherwise.
+fn flex_wrap(style: &StyledNode) -> Option<bool> {
+  let keyword = style.keyword("flex-wrap").or_else(|| {
+    flow_keywords(style)
+      .into_iter()
+      .find(|keyword| keyword.contains("wrap"))
+  });
+
+  match keyword.as_deref() {
+    Some("wrap") => Some(false),
+    Some("wrap-reverse") => Some(true),
+    _ => None,
+  }
+}
+
+/// The keywords of the `flex-flow` shorthand.
+fn flow_keywords(style: &StyledNode) -> Vec<String> {
+  match style.value("flex-flow") {
+    Some(Value::Keyword(keyword)) => vec![keyword],
+    Some(Value::List(values)) => values
+      .into_iter()
+      .filter_map(|value| match value {
+        Value::Keyword(keyword) => Some(keyword),
+        _ => None,
+      })
+      .collect(),
+    _ => Vec::new(),
+  }
+}
+
+/// The used `flex-grow`, `flex-shrink` and `flex-basis` of an item, from the longhands or the
+/// `flex` shorthand.
+///
+/// https://www.w3.org/TR/css-flexbox-1/#flex-common
+fn flex_factors(style: &StyledNode) -> (f32, f32, Value) {
+  let auto = Value::Keyword("auto".to_string());
+  let zero = Value::Length(0.0, Unit::Px);
+
+  let (grow, shrink, basis) = match style.value("flex") {
+    Some(Value::Keyword(ref keyword)) if keyword == "none" => (0.0, 0.0, auto),
+    Some(Value::Keyword(ref keyword)) if keyword == "auto" => (1.0, 1.0, auto),
+    Some(Value::Number(grow)) => (grow, 1.0, zero),
+    Some(Value::List(values)) => match &values[..] {
+      [Value::Number(grow), Value::Number(shrink)] => (*grow, *shrink, zero),
+      [Value::Number(grow), Value::Number(shrink), basis] => (*grow, *shrink, basis.clone()),
+      [Value::Number(grow), basis] => (*grow, 1.0, basis.clone()),
+      [basis, Value::Number(grow)] => (*grow, 1.0, basis.clone()),
+      [basis, Value::Number(grow), Value::Number(shrink)] => (*grow, *shrink, basis.clone()),
+      _ => (0.0, 1.0, auto),
+    },
+    Some(basis @ Value::Length(..)) => (1.0, 1.0, basis),
+    _ => (0.0, 1.0, auto),
+  };
+
+  let number = |name: &str, default: f32| match style.value(name) {
+    Some(Value::Number(n)) => n.max(0.0),
+    _ => default,
+  };
+
+  (
+    number("flex-grow", grow),
+    number("flex-shrink", shrink),
+    style.value("flex-basis").unwrap_or(basis),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, border_box, div, layout};
+
+  fn items(count: usize) -> Vec<crate::layout::dom::Node> {
+    (0..count).map(|_| div("item", vec![])).collect()
+  }
+
+  #[test]
+  fn free_space_is_distributed_by_flex_grow() {
+    let document = body(vec![div("flex", vec![div("a", vec![]), div("b", vec![])])]);
+    let css = ".flex { display: flex; width: 400px; } \
+               .a { width: 100px; height: 10px; flex-grow: 1; } \
+               .b { width: 100px; height: 10px; flex-grow: 3; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (0.0, 0.0, 150.0, 10.0));
+      assert_eq!(border_box(root, &[0, 1]), (150.0, 0.0, 250.0, 10.0));
+    });
+  }
+
+  #[test]
+  fn overflow_is_shrunk_by_flex_shrink_and_basis() {
+    let document = body(vec![div("flex", vec![div("a", vec![]), div("b", vec![])])]);
+    let css = ".flex { display: flex; width: 300px; } \
+               .a { flex: 0 1 200px; height: 10px; } \
+               .b { flex: 0 3 200px; height: 10px; }";
+    layout(&document, css, |root| {
+      // The shrink factors are scaled by the flex basis: 100px shrink 1:3.
+      assert_eq!(border_box(root, &[0, 0]).2, 175.0);
+      assert_eq!(border_box(root, &[0, 1]).2, 125.0);
+    });
+  }
+
+  #[test]
+  fn items_wrap_onto_lines_with_gaps() {
+    let document = body(vec![div("flex", items(3))]);
+    let css = ".flex { display: flex; flex-wrap: wrap; width: 250px; gap: 5px 10px; } \
+               .item { width: 100px; height: 20px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (0.0, 0.0, 100.0, 20.0));
+      assert_eq!(border_box(root, &[0, 1]), (110.0, 0.0, 100.0, 20.0));
+      assert_eq!(border_box(root, &[0, 2]), (0.0, 25.0, 100.0, 20.0));
+      assert_eq!(border_box(root, &[0]).3, 45.0);
+    });
+  }
+
+  #[test]
+  fn justify_content_and_align_items_place_items() {
+    let document = body(vec![div("flex", items(2))]);
+    let css = ".flex { display: flex; width: 300px; height: 100px; \
+                 justify-content: space-between; align-items: center; } \
+               .item { width: 50px; height: 20px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (0.0, 40.0, 50.0, 20.0));
+      assert_eq!(border_box(root, &[0, 1]), (250.0, 40.0, 50.0, 20.0));
+    });
+  }
+
+  #[test]
+  fn items_stretch_in_a_column() {
+    let document = body(vec![div("flex", items(2))]);
+    let css = ".flex { display: flex; flex-direction: column; width: 200px; } \
+               .item { height: 30px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (0.0, 0.0, 200.0, 30.0));
+      assert_eq!(border_box(root, &[0, 1]), (0.0, 30.0, 200.0, 30.0));
+    });
+  }
+
+  #[test]
+  fn scroll_container_items_have_no_automatic_minimum_size() {
+    let document = body(vec![div(
+      "flex",
+      vec![div("a", vec![div("wide", vec![])]), div("b", vec![])],
+    )]);
+    let css = ".flex { display: flex; width: 100px; } \
+               .a { flex: 1 1 0; overflow-x: hidden; } .b { flex: 1 1 0; } \
+               .wide { width: 300px; height: 10px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]).2, 50.0);
+      assert_eq!(border_box(root, &[0, 1]).2, 50.0);
+    });
+  }
+}
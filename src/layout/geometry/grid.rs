@@ -0,0 +1,1299 @@
+//! Grid layout: the formatting context established by `display: grid | inline-grid`.
+//!
+//! https://www.w3.org/TR/css-grid-1/#layout-algorithm
+
+use super::alignment::{distribute, gaps, self_alignment_offset};
+use super::intrinsic::IntrinsicSizes;
+use super::{LayoutBox, SimpleDimensions};
+use crate::layout::css::{Unit, Value, Viewport};
+use crate::layout::style::StyledNode;
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, PartialEq)]
+enum TrackBreadth {
+  Fixed(f32),
+  Flex(f32),
+  Auto,
+  MinContent,
+  MaxContent,
+}
+
+impl TrackBreadth {
+  fn is_intrinsic(self) -> bool {
+    matches!(
+      self,
+      TrackBreadth::Auto | TrackBreadth::MinContent | TrackBreadth::MaxContent
+    )
+  }
+}
+
+#[derive(Copy, Clone)]
+struct Track {
+  min: TrackBreadth,
+  max: TrackBreadth,
+  base_size: f32,
+  growth_limit: f32,
+  position: f32,
+  // Tracks repeated with `auto-fit` collapse when no item is placed in them.
+  collapsible: bool,
+}
+
+impl Track {
+  fn new(min: TrackBreadth, max: TrackBreadth) -> Self {
+    Track {
+      min,
+      max,
+      base_size: 0.0,
+      growth_limit: 0.0,
+      position: 0.0,
+      collapsible: false,
+    }
+  }
+
+  fn flex_factor(&self) -> Option<f32> {
+    match self.max {
+      TrackBreadth::Flex(factor) => Some(factor),
+      _ => None,
+    }
+  }
+}
+
+/// The tracks a grid item spans along one axis.
+#[derive(Copy, Clone)]
+struct Span {
+  start: usize,
+  count: usize,
+}
+
+impl Span {
+  fn end(self) -> usize {
+    self.start + self.count
+  }
+}
+
+/// A grid placement property value.
+///
+/// https://www.w3.org/TR/css-grid-1/#line-placement
+enum GridLine {
+  Auto,
+  Line(i32),
+  Span(usize),
+  Named(String),
+}
+
+impl GridLine {
+  fn span(&self) -> Option<usize> {
+    match *self {
+      GridLine::Span(count) => Some(count),
+      _ => None,
+    }
+  }
+}
+
+/// The cells of a grid that are occupied by items, indexed by the axis that auto-placement
+/// fills first (the minor axis) within the axis it grows in (the major axis).
+struct Occupancy {
+  cells: Vec<Vec<bool>>,
+}
+
+impl Occupancy {
+  fn fits(&self, major: Span, minor: Span) -> bool {
+    (major.start..major.end()).all(|row| match self.cells.get(row) {
+      Some(cells) => {
+        (minor.start..minor.end()).all(|cell| !cells.get(cell).cloned().unwrap_or(false))
+      }
+      None => true,
+    })
+  }
+
+  fn occupy(&mut self, major: Span, minor: Span) {
+    if self.cells.len() < major.end() {
+      self.cells.resize(major.end(), Vec::new());
+    }
+    for row in &mut self.cells[major.start..major.end()] {
+      if row.len() < minor.end() {
+        row.resize(minor.end(), false);
+      }
+      for cell in &mut row[minor.start..minor.end()] {
+        *cell = true;
+      }
+    }
+  }
+}
+
+/// The row and column lines (start and end) of a named grid area.
+type GridArea = ((usize, usize), (usize, usize));
+
+/// The index of an item with its row and column, which are either definite or only known to
+/// span a number of tracks.
+type Placement = (usize, Result<Span, usize>, Result<Span, usize>);
+
+struct GridItem {
+  // Index of the item in the children of the container.
+  index: usize,
+  row: Span,
+  column: Span,
+}
+
+impl<'a> LayoutBox<'a> {
+  /// Lay out the grid items of a grid container within its content area.
+  ///
+  /// Sets `self.dimensions.height` to the height of the grid's rows, which
+  /// `calculate_block_height` overrides if the container has a definite height.
+  pub(super) fn layout_grid_children(&mut self, original_containing_block: &SimpleDimensions) {
+    let viewport = self.viewport;
+    let style = self.get_style_node();
+    let container = self.dimensions.content;
+    let reference = SimpleDimensions::from_dimension(container, style.direction());
+
+    let d = self.dimensions;
+    let vertical_edges = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+    let definite_height = style
+      .size(
+        "height",
+        original_containing_block.height,
+        vertical_edges,
+        viewport,
+      )
+      .map(|height| {
+        style.clamp_size(
+          "height",
+          height,
+          original_containing_block.height,
+          vertical_edges,
+          viewport,
+        )
+      });
+    let (row_gap, column_gap) = gaps(
+      style,
+      container.width,
+      definite_height.unwrap_or(0.0),
+      viewport,
+    );
+
+    // Set up the explicit grid.
+    let (areas, area_rows, area_columns) = template_areas(style);
+    let mut columns = track_list(
+      style.value("grid-template-columns"),
+      Some(container.width),
+      column_gap,
+      viewport,
+    );
+    let mut rows = track_list(
+      style.value("grid-template-rows"),
+      definite_height,
+      row_gap,
+      viewport,
+    );
+    let auto_column = implicit_track(
+      style.value("grid-auto-columns"),
+      Some(container.width),
+      viewport,
+    );
+    let auto_row = implicit_track(style.value("grid-auto-rows"), definite_height, viewport);
+    while columns.len() < area_columns {
+      columns.push(auto_column);
+    }
+    while rows.len() < area_rows {
+      rows.push(auto_row);
+    }
+
+    let column_areas: HashMap<String, (usize, usize)> = areas
+      .iter()
+      .map(|(name, area)| (name.clone(), area.1))
+      .collect();
+    let row_areas: HashMap<String, (usize, usize)> = areas
+      .iter()
+      .map(|(name, area)| (name.clone(), area.0))
+      .collect();
+
+    // Resolve the definite parts of each item's placement, in `order` order.
+    let mut placements = Vec::new();
+    for (index, child) in self.children.iter_mut().enumerate() {
+      if child.position().is_out_of_flow() {
+        child.dimensions.content.x = container.x;
+        child.dimensions.content.y = container.y;
+        continue;
+      }
+
+      let item_style = child.get_style_node_option();
+      let order = match item_style.and_then(|s| s.value("order")) {
+        Some(Value::Number(n)) => n as i32,
+        _ => 0,
+      };
+      let (row_start, row_end) = placement_lines(item_style, "row", 0);
+      let (column_start, column_end) = placement_lines(item_style, "column", 1);
+      let row = resolve_lines(&row_start, &row_end, rows.len(), &row_areas);
+      let column = resolve_lines(&column_start, &column_end, columns.len(), &column_areas);
+      placements.push((order, index, row, column));
+    }
+    placements.sort_by_key(|&(order, index, ..)| (order, index));
+
+    let flow = style.value("grid-auto-flow");
+    let has_flow_keyword = |keyword: &str| match flow {
+      Some(Value::Keyword(ref k)) => k == keyword,
+      Some(Value::List(ref values)) => values.contains(&Value::Keyword(keyword.to_string())),
+      _ => false,
+    };
+    let column_flow = has_flow_keyword("column");
+    let dense = has_flow_keyword("dense");
+
+    let items = place_items(
+      placements
+        .into_iter()
+        .map(|(_, index, row, column)| (index, row, column))
+        .collect(),
+      if column_flow {
+        rows.len()
+      } else {
+        columns.len()
+      },
+      column_flow,
+      dense,
+    );
+
+    // Add the implicit tracks, and collapse empty `auto-fit` tracks.
+    let column_count = items
+      .iter()
+      .map(|item| item.column.end())
+      .max()
+      .unwrap_or(0);
+    let row_count = items.iter().map(|item| item.row.end()).max().unwrap_or(0);
+    while columns.len() < column_count {
+      columns.push(auto_column);
+    }
+    while rows.len() < row_count {
+      rows.push(auto_row);
+    }
+    collapse_empty_tracks(&mut columns, items.iter().map(|item| item.column));
+    collapse_empty_tracks(&mut rows, items.iter().map(|item| item.row));
+
+    // Size the columns from the widths the items would like to have.
+    let column_contributions: Vec<(Span, IntrinsicSizes)> = items
+      .iter()
+      .map(|item| {
+        let child = &mut self.children[item.index];
+        child.calculate_edges(container.width);
+        let d = child.dimensions;
+        let edges = d.border.left + d.border.right + d.padding.left + d.padding.right;
+        let margins = d.margin.left + d.margin.right;
+        let available = container.width - edges - margins;
+        let sizes = match child.width_property("width", container.width, edges, available) {
+          Some(width) => IntrinsicSizes::fixed(width),
+          None => child.intrinsic_sizes(),
+        };
+        let sizes = sizes.map(|width| {
+          child.clamp_width(width, container.width, edges, available) + edges + margins
+        });
+        (item.column, sizes)
+      })
+      .collect();
+
+    let justify_content = style
+      .keyword("justify-content")
+      .unwrap_or_else(|| "normal".to_string());
+    size_tracks(
+      &mut columns,
+      Some(container.width),
+      column_gap,
+      &column_contributions,
+      justify_content == "normal" || justify_content == "stretch",
+    );
+    position_tracks(
+      &mut columns,
+      container.x,
+      column_gap,
+      Some(container.width),
+      &justify_content,
+    );
+
+    // Lay the items out in their columns, so that their heights are known.
+    let justify_items = style
+      .keyword("justify-items")
+      .unwrap_or_else(|| "normal".to_string());
+    let mut row_contributions = Vec::new();
+    for item in &items {
+      let (_, area_width) = area(&columns, item.column);
+      let child = &mut self.children[item.index];
+      let item_style = child.get_style_node_option();
+      let d = child.dimensions;
+
+      let justify_self = match item_style.and_then(|s| s.keyword("justify-self")) {
+        Some(ref keyword) if keyword != "auto" => keyword.clone(),
+        _ => justify_items.clone(),
+      };
+      let available = area_width
+        - d.margin.left
+        - d.margin.right
+        - d.border.left
+        - d.border.right
+        - d.padding.left
+        - d.padding.right;
+      let edges = d.border.left + d.border.right + d.padding.left + d.padding.right;
+      let mut width = match child.width_property("width", area_width, edges, available) {
+        Some(width) => width,
+        None
+          if (justify_self == "normal" || justify_self == "stretch")
+            && !has_auto_margins(item_style, "margin-left", "margin-right") =>
+        {
+          available.max(0.0)
+        }
+        None => child.shrink_to_fit_width(available),
+      };
+      width = child.clamp_width(width, area_width, edges, available);
+
+      child.layout_block_contents(container.x, container.y, width, &reference);
+      let d = child.dimensions;
+      row_contributions.push((item.row, IntrinsicSizes::fixed(d.margin_box().height)));
+    }
+
+    let align_content = style
+      .keyword("align-content")
+      .unwrap_or_else(|| "normal".to_string());
+    size_tracks(
+      &mut rows,
+      definite_height,
+      row_gap,
+      &row_contributions,
+      align_content == "normal" || align_content == "stretch",
+    );
+    let height = position_tracks(
+      &mut rows,
+      container.y,
+      row_gap,
+      definite_height,
+      &align_content,
+    );
+
+    // Align the items within their grid areas, and move them into place.
+    let align_items = style
+      .keyword("align-items")
+      .unwrap_or_else(|| "normal".to_string());
+    for item in &items {
+      let (x, area_width) = area(&columns, item.column);
+      let (y, area_height) = area(&rows, item.row);
+      let child = &mut self.children[item.index];
+      let item_style = child.get_style_node_option();
+
+      let align_self = match item_style.and_then(|s| s.keyword("align-self")) {
+        Some(ref keyword) if keyword != "auto" => keyword.clone(),
+        _ => align_items.clone(),
+      };
+      let justify_self = match item_style.and_then(|s| s.keyword("justify-self")) {
+        Some(ref keyword) if keyword != "auto" => keyword.clone(),
+        _ => justify_items.clone(),
+      };
+
+      let height_is_auto = item_style
+        .and_then(|s| s.size("height", area_height, 0.0, viewport))
+        .is_none();
+      let vertical_auto_margins = has_auto_margins(item_style, "margin-top", "margin-bottom");
+      // `normal` only stretches items without an aspect ratio, whose height can't follow from
+      // their width.
+      let stretch =
+        align_self == "stretch" || (align_self == "normal" && !child.has_preferred_aspect_ratio());
+      if stretch && height_is_auto && !vertical_auto_margins {
+        let d = child.dimensions;
+        let edges = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+        let height = (area_height - d.margin.top - d.margin.bottom - edges).max(0.0);
+        child.dimensions.content.height = match item_style {
+          Some(item_style) => item_style.clamp_size("height", height, area_height, edges, viewport),
+          None => height,
+        };
+      }
+
+      let margin_box = child.dimensions.margin_box();
+      let free_width = area_width - margin_box.width;
+      let free_height = area_height - margin_box.height;
+      let dx = if has_auto_margins(item_style, "margin-left", "margin-right") {
+        free_width.max(0.0) / 2.0
+      } else {
+        self_alignment_offset(&justify_self, free_width)
+      };
+      let dy = if vertical_auto_margins {
+        free_height.max(0.0) / 2.0
+      } else {
+        self_alignment_offset(&align_self, free_height)
+      };
+
+      child.translate(x + dx - margin_box.x, y + dy - margin_box.y);
+    }
+
+    self.dimensions.content.height = height;
+  }
+
+  /// The intrinsic sizes of a grid container: its columns next to each other, each as wide as
+  /// its fixed size or the widest item in it. Items are placed in order, one per column, without
+  /// looking at their grid placement properties.
+  ///
+  /// https://www.w3.org/TR/css-grid-1/#intrinsic-sizes
+  pub(super) fn grid_intrinsic_sizes(&self) -> IntrinsicSizes {
+    let viewport = self.viewport;
+    let style = self.get_style_node();
+    let (_, column_gap) = gaps(style, 0.0, 0.0, viewport);
+    let tracks = track_list(
+      style.value("grid-template-columns"),
+      None,
+      column_gap,
+      viewport,
+    );
+    let items: Vec<&LayoutBox> = self
+      .children
+      .iter()
+      .filter(|child| !child.position().is_out_of_flow())
+      .collect();
+
+    let column_count = tracks.len().max(1);
+    let mut columns = vec![IntrinsicSizes::default(); column_count];
+    for (index, item) in items.iter().enumerate() {
+      let column = &mut columns[index % column_count];
+      *column = column.stacked(item.intrinsic_contributions());
+    }
+    for (column, track) in columns.iter_mut().zip(&tracks) {
+      if let (TrackBreadth::Fixed(min), TrackBreadth::Fixed(max)) = (track.min, track.max) {
+        *column = IntrinsicSizes::fixed(min.max(max));
+      }
+    }
+
+    let gaps = column_gap * (column_count - 1) as f32;
+    columns
+      .into_iter()
+      .fold(IntrinsicSizes::default(), IntrinsicSizes::sum)
+      .map(|size| size + gaps)
+  }
+}
+
+/// Whether either of the given margins of an item is `auto`.
+fn has_auto_margins(style: Option<&StyledNode>, start: &str, end: &str) -> bool {
+  let auto = Value::Keyword("auto".to_string());
+  let zero = Value::Number(0.0);
+  match style {
+    Some(style) => {
+      style.lookup(start, "margin", &zero) == auto || style.lookup(end, "margin", &zero) == auto
+    }
+    None => false,
+  }
+}
+
+/// Position and size of the area covered by a span of tracks.
+fn area(tracks: &[Track], span: Span) -> (f32, f32) {
+  let first = tracks[span.start];
+  let last = tracks[span.end() - 1];
+  (
+    first.position,
+    last.position + last.base_size - first.position,
+  )
+}
+
+/// Parse `grid-template-areas` into the row and column lines of each named area, and the number
+/// of rows and columns it defines.
+///
+/// https://www.w3.org/TR/css-grid-1/#grid-template-areas-property
+fn template_areas(style: &StyledNode) -> (HashMap<String, GridArea>, usize, usize) {
+  let rows: Vec<String> = match style.value("grid-template-areas") {
+    Some(Value::Str(row)) => vec![row],
+    Some(Value::List(values)) => values
+      .into_iter()
+      .filter_map(|value| match value {
+        Value::Str(row) => Some(row),
+        _ => None,
+      })
+      .collect(),
+    _ => Vec::new(),
+  };
+
+  let mut areas: HashMap<String, GridArea> = HashMap::new();
+  let mut column_count = 0;
+  for (row, names) in rows.iter().enumerate() {
+    let names: Vec<&str> = names.split_whitespace().collect();
+    column_count = column_count.max(names.len());
+
+    for (column, name) in names.into_iter().enumerate() {
+      // A sequence of dots is a null cell token.
+      if name.chars().all(|c| c == '.') {
+        continue;
+      }
+
+      let area = areas
+        .entry(name.to_string())
+        .or_insert(((row, row + 1), (column, column + 1)));
+      (area.0).0 = (area.0).0.min(row);
+      (area.0).1 = (area.0).1.max(row + 1);
+      (area.1).0 = (area.1).0.min(column);
+      (area.1).1 = (area.1).1.max(column + 1);
+    }
+  }
+
+  (areas, rows.len(), column_count)
+}
+
+/// Parse `grid-template-columns` or `grid-template-rows` into the explicit tracks.
+///
+/// https://www.w3.org/TR/css-grid-1/#track-sizing
+fn track_list(
+  value: Option<Value>,
+  available: Option<f32>,
+  gap: f32,
+  viewport: Viewport,
+) -> Vec<Track> {
+  let components = match value {
+    Some(Value::List(values)) => values,
+    Some(Value::Keyword(ref keyword)) if keyword == "none" => Vec::new(),
+    Some(value) => vec![value],
+    None => Vec::new(),
+  };
+
+  let mut tracks = Vec::new();
+  for component in &components {
+    match component {
+      Value::Function(name, arguments) if name == "repeat" && arguments.len() == 2 => {
+        let repeated: Vec<Track> = match arguments[1] {
+          Value::List(ref values) => values
+            .iter()
+            .map(|v| track_size(v, available, viewport))
+            .collect(),
+          ref value => vec![track_size(value, available, viewport)],
+        };
+
+        let (count, collapsible) = match arguments[0] {
+          Value::Number(count) => (count.max(1.0) as usize, false),
+          Value::Keyword(ref keyword) => {
+            let others = components
+              .iter()
+              .filter(|c| *c != component)
+              .map(|c| fixed_size(&track_size(c, available, viewport)));
+            let count = auto_repeat_count(
+              &repeated,
+              others.sum(),
+              components.len() - 1,
+              available,
+              gap,
+            );
+            (count, keyword == "auto-fit")
+          }
+          _ => (1, false),
+        };
+
+        for _ in 0..count {
+          for track in &repeated {
+            tracks.push(Track {
+              collapsible,
+              ..*track
+            });
+          }
+        }
+      }
+      component => tracks.push(track_size(component, available, viewport)),
+    }
+  }
+  tracks
+}
+
+/// The number of repetitions for `repeat(auto-fill | auto-fit, ...)`: as many as fit in the
+/// available space, but at least one.
+///
+/// https://www.w3.org/TR/css-grid-1/#auto-repeat
+fn auto_repeat_count(
+  repeated: &[Track],
+  other_size: f32,
+  other_count: usize,
+  available: Option<f32>,
+  gap: f32,
+) -> usize {
+  let repeat_size: f32 = repeated.iter().map(fixed_size).sum::<f32>() + gap * repeated.len() as f32;
+  match available {
+    Some(available) if repeat_size > 0.0 => {
+      let space = available - other_size - gap * other_count as f32 + gap;
+      ((space / repeat_size).floor() as usize).max(1)
+    }
+    _ => 1,
+  }
+}
+
+/// The size of a track used to compute the number of automatic repetitions.
+fn fixed_size(track: &Track) -> f32 {
+  match (track.min, track.max) {
+    (_, TrackBreadth::Fixed(size)) => size,
+    (TrackBreadth::Fixed(size), _) => size,
+    _ => 0.0,
+  }
+}
+
+fn implicit_track(value: Option<Value>, available: Option<f32>, viewport: Viewport) -> Track {
+  match value {
+    Some(value) => track_size(&value, available, viewport),
+    None => Track::new(TrackBreadth::Auto, TrackBreadth::Auto),
+  }
+}
+
+fn track_size(value: &Value, available: Option<f32>, viewport: Viewport) -> Track {
+  match value {
+    Value::Function(name, arguments) if name == "minmax" && arguments.len() == 2 => {
+      // Flexible minimums are invalid, treat them as auto.
+      let min = match track_breadth(&arguments[0], available, viewport) {
+        TrackBreadth::Flex(_) => TrackBreadth::Auto,
+        min => min,
+      };
+      Track::new(min, track_breadth(&arguments[1], available, viewport))
+    }
+    Value::Function(name, arguments) if name == "fit-content" && arguments.len() == 1 => {
+      Track::new(
+        TrackBreadth::Auto,
+        track_breadth(&arguments[0], available, viewport),
+      )
+    }
+    value => match track_breadth(value, available, viewport) {
+      TrackBreadth::Flex(factor) => Track::new(TrackBreadth::Auto, TrackBreadth::Flex(factor)),
+      breadth => Track::new(breadth, breadth),
+    },
+  }
+}
+
+fn track_breadth(value: &Value, available: Option<f32>, viewport: Viewport) -> TrackBreadth {
+  match value {
+    Value::Length(factor, Unit::Fr) => TrackBreadth::Flex(*factor),
+    Value::Length(_, Unit::Percentage) => match available {
+      Some(available) => TrackBreadth::Fixed(value.to_px(available, viewport)),
+      None => TrackBreadth::Auto,
+    },
+    Value::Length(..) | Value::Number(_) => TrackBreadth::Fixed(value.to_px(0.0, viewport)),
+    Value::Keyword(ref keyword) if keyword == "min-content" => TrackBreadth::MinContent,
+    Value::Keyword(ref keyword) if keyword == "max-content" => TrackBreadth::MaxContent,
+    _ => TrackBreadth::Auto,
+  }
+}
+
+/// Read the start and end lines of an item along one axis (`row` or `column`), from the
+/// longhands, the `grid-row`/`grid-column` shorthands or the `grid-area` shorthand.
+///
+/// `area_index` is the position of the start line of the axis in `grid-area`.
+fn placement_lines(
+  style: Option<&StyledNode>,
+  axis: &str,
+  area_index: usize,
+) -> (GridLine, GridLine) {
+  let style = match style {
+    Some(style) => style,
+    None => return (GridLine::Auto, GridLine::Auto),
+  };
+
+  let shorthand = style.value(&format!("grid-{}", axis)).map(split_slash);
+  let area = style.value("grid-area").map(split_slash);
+
+  let (start, end) = match (shorthand, area) {
+    (Some(parts), _) => (parts.first().cloned(), parts.get(1).cloned()),
+    (None, Some(parts)) => {
+      // Omitted lines copy a name from the line before them: `grid-area: a` is `a / a / a / a`.
+      let mut lines: Vec<Option<Value>> = Vec::new();
+      for i in 0..4 {
+        let line = parts
+          .get(i)
+          .cloned()
+          .or_else(|| match (i, lines.get(i.saturating_sub(2))) {
+            (1, _) => match parts[0] {
+              Value::Keyword(_) => Some(parts[0].clone()),
+              _ => None,
+            },
+            (_, Some(Some(line @ Value::Keyword(_)))) => Some(line.clone()),
+            _ => None,
+          });
+        lines.push(line);
+      }
+      (lines[area_index].clone(), lines[area_index + 2].clone())
+    }
+    (None, None) => (None, None),
+  };
+
+  let start = style.value(&format!("grid-{}-start", axis)).or(start);
+  let end = style.value(&format!("grid-{}-end", axis)).or(end);
+
+  let start = start
+    .map(|value| grid_line(&value))
+    .unwrap_or(GridLine::Auto);
+  let end = match end {
+    Some(value) => grid_line(&value),
+    // A missing end line refers to the same named area as the start line.
+    None => match start {
+      GridLine::Named(ref name) => GridLine::Named(name.clone()),
+      _ => GridLine::Auto,
+    },
+  };
+  (start, end)
+}
+
+/// Split a value like `1 / span 2` on its slashes.
+fn split_slash(value: Value) -> Vec<Value> {
+  let values = match value {
+    Value::List(values) => values,
+    value => return vec![value],
+  };
+
+  values
+    .split(|value| *value == Value::Slash)
+    .map(|part| match part {
+      [value] => value.clone(),
+      part => Value::List(part.to_vec()),
+    })
+    .collect()
+}
+
+fn grid_line(value: &Value) -> GridLine {
+  match value {
+    Value::Number(line) => GridLine::Line(*line as i32),
+    Value::Keyword(ref keyword) if keyword == "auto" => GridLine::Auto,
+    Value::Keyword(ref name) => GridLine::Named(name.clone()),
+    Value::List(ref values) => {
+      let count = values
+        .iter()
+        .filter_map(|value| match value {
+          Value::Number(count) => Some(count.max(1.0) as usize),
+          _ => None,
+        })
+        .next()
+        .unwrap_or(1);
+      if values.contains(&Value::Keyword("span".to_string())) {
+        GridLine::Span(count)
+      } else {
+        GridLine::Line(count as i32)
+      }
+    }
+    _ => GridLine::Auto,
+  }
+}
+
+/// Resolve the start and end lines of an item along one axis to a span of tracks, or only the
+/// number of tracks if the item has to be auto-placed.
+///
+/// https://www.w3.org/TR/css-grid-1/#line-placement
+fn resolve_lines(
+  start: &GridLine,
+  end: &GridLine,
+  track_count: usize,
+  areas: &HashMap<String, (usize, usize)>,
+) -> Result<Span, usize> {
+  // Lines before the start of the explicit grid are not supported, they are clamped to it.
+  let line = |line: &GridLine, is_start: bool| match *line {
+    GridLine::Line(line) if line > 0 => Some(line as usize - 1),
+    GridLine::Line(line) if line < 0 => Some((track_count as i32 + 1 + line).max(0) as usize),
+    GridLine::Named(ref name) => {
+      let (name, is_start) = if name.ends_with("-start") {
+        (name.trim_end_matches("-start"), true)
+      } else if name.ends_with("-end") {
+        (name.trim_end_matches("-end"), false)
+      } else {
+        (&**name, is_start)
+      };
+      areas
+        .get(name)
+        .map(|&(area_start, area_end)| if is_start { area_start } else { area_end })
+    }
+    _ => None,
+  };
+
+  match (line(start, true), line(end, false)) {
+    (Some(start), Some(end)) if end > start => Ok(Span {
+      start,
+      count: end - start,
+    }),
+    (Some(start), Some(end)) if end < start => Ok(Span {
+      start: end,
+      count: start - end,
+    }),
+    (Some(start), _) => Ok(Span {
+      start,
+      count: end.span().unwrap_or(1),
+    }),
+    (None, Some(end)) => {
+      let start = end.saturating_sub(start.span().unwrap_or(1));
+      Ok(Span {
+        start,
+        count: (end - start).max(1),
+      })
+    }
+    (None, None) => Err(start.span().or_else(|| end.span()).unwrap_or(1)),
+  }
+}
+
+/// Place the items in the grid, using the grid item placement algorithm for the items that don't
+/// have a definite position.
+///
+/// https://www.w3.org/TR/css-grid-1/#auto-placement-algo
+fn place_items(
+  placements: Vec<Placement>,
+  explicit_minor_count: usize,
+  column_flow: bool,
+  dense: bool,
+) -> Vec<GridItem> {
+  // Work along the (major, minor) axes, which are (row, column) unless the grid flows in columns.
+  let placements: Vec<Placement> = placements
+    .into_iter()
+    .map(|(index, row, column)| {
+      if column_flow {
+        (index, column, row)
+      } else {
+        (index, row, column)
+      }
+    })
+    .collect();
+
+  let minor_count = placements
+    .iter()
+    .map(|(_, _, minor)| match *minor {
+      Ok(span) => span.end(),
+      Err(count) => count,
+    })
+    .fold(explicit_minor_count.max(1), usize::max);
+
+  let mut occupancy = Occupancy { cells: Vec::new() };
+  let mut placed: Vec<Option<(Span, Span)>> = vec![None; placements.len()];
+
+  // 1. Place the items that are not auto-placed.
+  for (i, (_, major, minor)) in placements.iter().enumerate() {
+    if let (Ok(major), Ok(minor)) = (major, minor) {
+      occupancy.occupy(*major, *minor);
+      placed[i] = Some((*major, *minor));
+    }
+  }
+
+  // 2. Place the items locked to a major track.
+  let mut row_cursors: HashMap<usize, usize> = HashMap::new();
+  for (i, (_, major, minor)) in placements.iter().enumerate() {
+    if let (Ok(major), Err(count)) = (major, minor) {
+      let cursor = row_cursors.entry(major.start).or_insert(0);
+      let mut start = if dense { 0 } else { *cursor };
+      while !occupancy.fits(
+        *major,
+        Span {
+          start,
+          count: *count,
+        },
+      ) {
+        start += 1;
+      }
+
+      let minor = Span {
+        start,
+        count: *count,
+      };
+      occupancy.occupy(*major, minor);
+      placed[i] = Some((*major, minor));
+      *cursor = minor.end();
+    }
+  }
+
+  // 3. Auto-place the remaining items.
+  let mut cursor = (0, 0);
+  for (i, (_, major, minor)) in placements.iter().enumerate() {
+    let major_count = match major {
+      Ok(_) => continue,
+      Err(count) => *count,
+    };
+    if dense {
+      cursor = (0, 0);
+    }
+
+    match minor {
+      Ok(minor) => {
+        if minor.start < cursor.1 {
+          cursor.0 += 1;
+        }
+        cursor.1 = minor.start;
+        while !occupancy.fits(
+          Span {
+            start: cursor.0,
+            count: major_count,
+          },
+          *minor,
+        ) {
+          cursor.0 += 1;
+        }
+        let major = Span {
+          start: cursor.0,
+          count: major_count,
+        };
+        occupancy.occupy(major, *minor);
+        placed[i] = Some((major, *minor));
+      }
+      Err(minor_count_of_item) => {
+        let count = (*minor_count_of_item).min(minor_count);
+        loop {
+          if cursor.1 + count > minor_count {
+            cursor = (cursor.0 + 1, 0);
+            continue;
+          }
+          let major = Span {
+            start: cursor.0,
+            count: major_count,
+          };
+          let minor = Span {
+            start: cursor.1,
+            count,
+          };
+          if occupancy.fits(major, minor) {
+            occupancy.occupy(major, minor);
+            placed[i] = Some((major, minor));
+            cursor.1 = minor.end();
+            break;
+          }
+          cursor.1 += 1;
+        }
+      }
+    }
+  }
+
+  placements
+    .iter()
+    .zip(placed)
+    .map(|((index, ..), spans)| {
+      let (major, minor) = spans.unwrap();
+      let (row, column) = if column_flow {
+        (minor, major)
+      } else {
+        (major, minor)
+      };
+      GridItem {
+        index: *index,
+        row,
+        column,
+      }
+    })
+    .collect()
+}
+
+fn collapse_empty_tracks<I: Iterator<Item = Span>>(tracks: &mut [Track], spans: I) {
+  let mut occupied = vec![false; tracks.len()];
+  for span in spans {
+    for occupied in &mut occupied[span.start..span.end()] {
+      *occupied = true;
+    }
+  }
+
+  for (track, occupied) in tracks.iter_mut().zip(occupied) {
+    if track.collapsible && !occupied {
+      track.min = TrackBreadth::Fixed(0.0);
+      track.max = TrackBreadth::Fixed(0.0);
+    }
+  }
+}
+
+/// Run the track sizing algorithm for the tracks along one axis, given the outer size of each
+/// item along that axis.
+///
+/// https://www.w3.org/TR/css-grid-1/#algo-track-sizing
+fn size_tracks(
+  tracks: &mut [Track],
+  available: Option<f32>,
+  gap: f32,
+  contributions: &[(Span, IntrinsicSizes)],
+  stretch: bool,
+) {
+  let total_gaps = gap * (tracks.len().max(1) - 1) as f32;
+
+  // Initialize each track's base size and growth limit.
+  for track in tracks.iter_mut() {
+    track.base_size = match track.min {
+      TrackBreadth::Fixed(size) => size,
+      _ => 0.0,
+    };
+    track.growth_limit = match track.max {
+      TrackBreadth::Fixed(size) => size.max(track.base_size),
+      TrackBreadth::Flex(_) => track.base_size,
+      _ => f32::INFINITY,
+    };
+  }
+
+  // Resolve intrinsic track sizes from the items that don't span flexible tracks, items
+  // spanning the fewest tracks first.
+  let mut intrinsic: Vec<&(Span, IntrinsicSizes)> = contributions
+    .iter()
+    .filter(|(span, _)| {
+      tracks[span.start..span.end()]
+        .iter()
+        .all(|track| track.flex_factor().is_none())
+    })
+    .collect();
+  intrinsic.sort_by_key(|(span, _)| span.count);
+
+  for (span, sizes) in intrinsic {
+    let spanned = &mut tracks[span.start..span.end()];
+    let gaps = gap * (span.count - 1) as f32;
+
+    // The minimums grow to the min-content contribution, unless they are all `max-content`.
+    let base_sizes: f32 = spanned.iter().map(|track| track.base_size).sum();
+    let growable = spanned
+      .iter()
+      .filter(|track| track.min.is_intrinsic())
+      .count();
+    let contribution = if spanned
+      .iter()
+      .filter(|track| track.min.is_intrinsic())
+      .all(|track| track.min == TrackBreadth::MaxContent)
+    {
+      sizes.max_content
+    } else {
+      sizes.min_content
+    };
+    let extra = contribution - gaps - base_sizes;
+    if extra > 0.0 && growable > 0 {
+      for track in spanned.iter_mut().filter(|track| track.min.is_intrinsic()) {
+        track.base_size += extra / growable as f32;
+      }
+    }
+
+    let limit = |track: &Track| {
+      if track.growth_limit.is_infinite() {
+        track.base_size
+      } else {
+        track.growth_limit
+      }
+    };
+    // The maximums grow to the max-content contribution, unless they are all `min-content`.
+    let limits: f32 = spanned.iter().map(limit).sum();
+    let growable = spanned
+      .iter()
+      .filter(|track| track.max.is_intrinsic())
+      .count();
+    let contribution = if spanned
+      .iter()
+      .filter(|track| track.max.is_intrinsic())
+      .all(|track| track.max == TrackBreadth::MinContent)
+    {
+      sizes.min_content
+    } else {
+      sizes.max_content
+    };
+    let extra = (contribution - gaps - limits).max(0.0);
+    for track in spanned.iter_mut().filter(|track| track.max.is_intrinsic()) {
+      track.growth_limit = limit(track) + extra / growable as f32;
+    }
+  }
+
+  for track in tracks.iter_mut() {
+    if track.growth_limit.is_infinite() {
+      track.growth_limit = track.base_size;
+    }
+    track.growth_limit = track.growth_limit.max(track.base_size);
+  }
+
+  // Maximize tracks: grow the base sizes up to the growth limits.
+  match available {
+    Some(available) => {
+      let mut free_space = available - total_gaps - tracks.iter().map(|t| t.base_size).sum::<f32>();
+      loop {
+        let growable = tracks
+          .iter()
+          .filter(|track| track.base_size < track.growth_limit)
+          .count();
+        if growable == 0 || free_space <= 0.01 {
+          break;
+        }
+
+        let share = free_space / growable as f32;
+        for track in tracks.iter_mut() {
+          let growth = share.min(track.growth_limit - track.base_size).max(0.0);
+          track.base_size += growth;
+          free_space -= growth;
+        }
+      }
+    }
+    None => {
+      for track in tracks.iter_mut() {
+        track.base_size = track.growth_limit;
+      }
+    }
+  }
+
+  // Expand flexible tracks.
+  if tracks.iter().any(|track| track.flex_factor().is_some()) {
+    let fraction = match available {
+      Some(available) => find_fraction_size(tracks, available - total_gaps),
+      None => {
+        let from_tracks = tracks
+          .iter()
+          .filter_map(|track| {
+            track
+              .flex_factor()
+              .map(|factor| track.base_size / factor.max(1.0))
+          })
+          .fold(0.0, f32::max);
+        let from_items = contributions
+          .iter()
+          .map(|(span, sizes)| {
+            let spanned = &tracks[span.start..span.end()];
+            let factors: f32 = spanned.iter().filter_map(Track::flex_factor).sum();
+            let fixed: f32 = spanned
+              .iter()
+              .filter(|track| track.flex_factor().is_none())
+              .map(|track| track.base_size)
+              .sum();
+            if factors > 0.0 {
+              (sizes.max_content - fixed - gap * (span.count - 1) as f32) / factors.max(1.0)
+            } else {
+              0.0
+            }
+          })
+          .fold(0.0, f32::max);
+        from_tracks.max(from_items)
+      }
+    };
+
+    for track in tracks.iter_mut() {
+      if let Some(factor) = track.flex_factor() {
+        track.base_size = track.base_size.max(fraction * factor);
+      }
+    }
+  }
+
+  // Stretch `auto` tracks into the remaining space.
+  if let (true, Some(available)) = (stretch, available) {
+    let free_space = available - total_gaps - tracks.iter().map(|t| t.base_size).sum::<f32>();
+    let auto_tracks = tracks
+      .iter()
+      .filter(|track| track.max == TrackBreadth::Auto)
+      .count();
+    if free_space > 0.0 && auto_tracks > 0 {
+      for track in tracks
+        .iter_mut()
+        .filter(|track| track.max == TrackBreadth::Auto)
+      {
+        track.base_size += free_space / auto_tracks as f32;
+      }
+    }
+  }
+}
+
+/// Find the size of one `fr` that fills `space`, treating flexible tracks whose base size is
+/// larger than their share as inflexible.
+///
+/// https://www.w3.org/TR/css-grid-1/#algo-find-fr-size
+fn find_fraction_size(tracks: &[Track], space: f32) -> f32 {
+  let mut inflexible = vec![false; tracks.len()];
+  loop {
+    let mut leftover = space;
+    let mut factors = 0.0;
+    for (track, &inflexible) in tracks.iter().zip(&inflexible) {
+      match track.flex_factor() {
+        Some(factor) if !inflexible => factors += factor,
+        _ => leftover -= track.base_size,
+      }
+    }
+
+    let fraction = leftover.max(0.0) / factors.max(1.0);
+    let mut changed = false;
+    for (track, inflexible) in tracks.iter().zip(inflexible.iter_mut()) {
+      if let Some(factor) = track.flex_factor() {
+        if !*inflexible && track.base_size > fraction * factor {
+          *inflexible = true;
+          changed = true;
+        }
+      }
+    }
+
+    if !changed {
+      return fraction;
+    }
+  }
+}
+
+/// Set the position of each track, distributing free space with `justify-content` or
+/// `align-content`. Returns the size of the content area along the axis.
+fn position_tracks(
+  tracks: &mut [Track],
+  start: f32,
+  gap: f32,
+  available: Option<f32>,
+  keyword: &str,
+) -> f32 {
+  let total =
+    tracks.iter().map(|t| t.base_size).sum::<f32>() + gap * (tracks.len().max(1) - 1) as f32;
+  let free_space = available.map(|available| available - total).unwrap_or(0.0);
+  let (offset, spacing) = distribute(keyword, free_space, tracks.len());
+
+  let mut position = start + offset;
+  for track in tracks.iter_mut() {
+    track.position = position;
+    position += track.base_size + gap + spacing;
+  }
+
+  available.unwrap_or(total)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, border_box, div, layout};
+
+  fn items(count: usize) -> Vec<crate::layout::dom::Node> {
+    (0..count).map(|_| div("item", vec![])).collect()
+  }
+
+  #[test]
+  fn flexible_tracks_share_the_free_space() {
+    let document = body(vec![div("grid", items(3))]);
+    let css = ".grid { display: grid; width: 420px; \
+                 grid-template-columns: 100px 1fr 2fr; column-gap: 10px; } \
+               .item { height: 20px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (0.0, 0.0, 100.0, 20.0));
+      assert_eq!(border_box(root, &[0, 1]), (110.0, 0.0, 100.0, 20.0));
+      assert_eq!(border_box(root, &[0, 2]), (220.0, 0.0, 200.0, 20.0));
+    });
+  }
+
+  #[test]
+  fn items_are_placed_in_rows_automatically() {
+    let document = body(vec![div("grid", items(3))]);
+    let css = ".grid { display: grid; width: 200px; \
+                 grid-template-columns: repeat(2, 1fr); gap: 5px; } \
+               .item { height: 20px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 1]), (102.5, 0.0, 97.5, 20.0));
+      assert_eq!(border_box(root, &[0, 2]), (0.0, 25.0, 97.5, 20.0));
+      assert_eq!(border_box(root, &[0]).3, 45.0);
+    });
+  }
+
+  #[test]
+  fn items_are_placed_on_explicit_lines() {
+    let document = body(vec![div("grid", vec![div("a", vec![]), div("b", vec![])])]);
+    let css = ".grid { display: grid; width: 300px; \
+                 grid-template-columns: 100px 100px 100px; grid-template-rows: 20px 30px; } \
+               .a { grid-column: 2 / 4; grid-row: 2; } \
+               .b { grid-column: 1; grid-row: 1 / 3; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (100.0, 20.0, 200.0, 30.0));
+      assert_eq!(border_box(root, &[0, 1]), (0.0, 0.0, 100.0, 50.0));
+    });
+  }
+
+  #[test]
+  fn items_are_placed_in_named_areas() {
+    let document = body(vec![div("grid", vec![div("a", vec![]), div("b", vec![])])]);
+    let css = ".grid { display: grid; width: 200px; grid-template-columns: 50px 150px; \
+                 grid-template-rows: 10px 10px; grid-template-areas: \"head head\" \"side main\"; } \
+               .a { grid-area: main; } .b { grid-area: head; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (50.0, 10.0, 150.0, 10.0));
+      assert_eq!(border_box(root, &[0, 1]), (0.0, 0.0, 200.0, 10.0));
+    });
+  }
+
+  #[test]
+  fn flexible_rows_share_the_clamped_content_height() {
+    let document = body(vec![div("grid", items(2))]);
+    // The rows share the height of the content box, less the padding with `border-box`.
+    let css = ".grid { display: grid; width: 200px; height: 100px; padding: 10px; \
+                 box-sizing: border-box; grid-template-rows: 1fr 1fr; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (10.0, 10.0, 180.0, 40.0));
+      assert_eq!(border_box(root, &[0, 1]), (10.0, 50.0, 180.0, 40.0));
+      assert_eq!(border_box(root, &[0]).3, 100.0);
+    });
+
+    let css = ".grid { display: grid; height: 200px; max-height: 60px; \
+                 grid-template-rows: 1fr 1fr; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 1]), (0.0, 30.0, 800.0, 30.0));
+      assert_eq!(border_box(root, &[0]).3, 60.0);
+    });
+  }
+}
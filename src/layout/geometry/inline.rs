@@ -0,0 +1,293 @@
+//! Inline formatting contexts, made of the inline-level children of an anonymous block.
+//!
+//! Text is not laid out yet, so only atomic inline-level boxes take up space: inline-blocks,
+//! inline flex, grid and table containers, replaced elements, inside markers and generated
+//! content, whose text is estimated like that of markers. They are placed next to each other on
+//! line boxes, and wrap onto a new line when one is full. Each line is reordered for
+//! bidirectional content, and aligned in the line box by the `text-align` of the block, where
+//! `start` is the left or right edge by its direction. The first line of a block is indented by
+//! `text-indent`. Lines don't wrap with `white-space: nowrap` or `pre`, and can be truncated with
+//! an ellipsis (see `truncation`).
+//!
+//! https://www.w3.org/TR/CSS2/visuren.html#inline-formatting
+//! https://www.w3.org/TR/css-text-3/#alignment
+
+use super::bidi;
+use super::margins::CollapsedMargins;
+use super::truncation::{ellipsis_box, line_clamp, text_overflow_ellipsis, truncate_line};
+use super::{BoxType, Dimensions, LayoutBox, Rect, SimpleDimensions};
+use crate::layout::style::{Direction, TextAlign};
+
+impl<'a> LayoutBox<'a> {
+  /// Whether the box is laid out as a single unit on a line.
+  ///
+  /// https://www.w3.org/TR/css-display-3/#atomic-inline
+  pub(super) fn is_atomic_inline(&self) -> bool {
+    match self.box_type {
+      BoxType::InlineNode(node) => node.display().is_atomic_inline() || node.image().is_some(),
+      BoxType::Marker(_) | BoxType::GeneratedText(..) => true,
+      _ => false,
+    }
+  }
+
+  /// Lay out an anonymous block holding inline-level boxes below the previous boxes in its
+  /// containing block, and fill its line boxes.
+  ///
+  /// Boxes on a line are aligned at the bottom of their margin box, which is the baseline of
+  /// boxes without text.
+  pub(super) fn layout_inline_formatting_context(
+    &mut self,
+    containing_block: Dimensions,
+    original_containing_block: &SimpleDimensions,
+  ) {
+    let container = containing_block.content;
+    self.dimensions = Dimensions::new(Rect {
+      x: container.x,
+      y: container.y + container.height,
+      width: container.width,
+      height: 0.0,
+    });
+    let content = self.dimensions.content;
+    let direction = original_containing_block.direction;
+    let style = match self.box_type {
+      BoxType::AnonymousBlock(style) => style,
+      _ => None,
+    };
+
+    // Only the first line of the block is indented, which is in the first anonymous block
+    // unless content comes before it.
+    let indent = match style {
+      Some(style) if container.height == 0.0 => style
+        .length("text-indent", content.width, self.viewport)
+        .unwrap_or(0.0),
+      _ => 0.0,
+    };
+    let (start_align, end_align) = match direction {
+      Direction::Ltr => (TextAlign::Left, TextAlign::Right),
+      Direction::Rtl => (TextAlign::Right, TextAlign::Left),
+    };
+    let text_align = match style.map(|style| style.text_align()) {
+      None | Some(TextAlign::Start) => start_align,
+      Some(TextAlign::End) => end_align,
+      Some(text_align) => text_align,
+    };
+
+    // The ellipses of truncated lines are added again by each layout.
+    self.children.retain(|child| !child.is_ellipsis());
+    let levels = bidi::atomic_inline_levels(&self.children, direction);
+    let mut atoms = Vec::new();
+    collect_atomic_inlines(&mut self.children, &mut atoms);
+
+    // Size the boxes and break them into lines.
+    let wraps = style.is_none_or(|style| style.wraps_lines());
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    let mut x = 0.0;
+    for (index, atom) in atoms.iter_mut().enumerate() {
+      atom.truncated = false;
+      atom.layout_atomic_inline(content.width, original_containing_block);
+      let width = atom.dimensions.margin_box().width;
+
+      // Start a new line if the box doesn't fit on the current one, unless it is alone.
+      let available = if lines.is_empty() {
+        content.width - indent
+      } else {
+        content.width
+      };
+      if wraps && x > 0.0 && x + width > available {
+        lines.push(line_start..index);
+        line_start = index;
+        x = 0.0;
+      }
+      x += width;
+    }
+    lines.push(line_start..atoms.len());
+
+    // The lines after the last one of a clamped block are hidden, and the last one ends with an
+    // ellipsis, like the lines that overflow a block with `text-overflow: ellipsis`.
+    let mut clamp_ellipsis = None;
+    if let Some((max_lines, ellipsis)) = style.and_then(line_clamp) {
+      if lines.len() > max_lines {
+        for line in lines.drain(max_lines..) {
+          for atom in &mut atoms[line] {
+            atom.truncated = true;
+          }
+        }
+        clamp_ellipsis = Some(ellipsis);
+      }
+    }
+    let text_overflow = style.and_then(text_overflow_ellipsis);
+    let line_count = lines.len();
+
+    // Place the boxes of each line next to each other in visual order, aligned in the line box.
+    let mut ellipses = Vec::new();
+    let mut line_top = 0.0;
+    for (line_index, line) in lines.into_iter().enumerate() {
+      let margin_boxes: Vec<Rect> = atoms[line.clone()]
+        .iter()
+        .map(|atom| atom.dimensions.margin_box())
+        .collect();
+      let order = bidi::visual_order(&levels[line.clone()]);
+      let mut line_width: f32 = margin_boxes.iter().map(|margin_box| margin_box.width).sum();
+      let mut line_height = margin_boxes
+        .iter()
+        .map(|margin_box| margin_box.height)
+        .fold(0.0, f32::max);
+
+      // The line box is narrowed by the indent on the start side of the first line.
+      let (line_left, line_box_width) = match (line_index, direction) {
+        (0, Direction::Ltr) => (indent, content.width - indent),
+        (0, Direction::Rtl) => (0.0, content.width - indent),
+        _ => (0.0, content.width),
+      };
+      let last = line_index + 1 == line_count;
+
+      // Hide the boxes at the end of a truncated line to make room for its ellipsis.
+      let ellipsis_text = match (&clamp_ellipsis, &text_overflow) {
+        (Some(text), _) if last => Some(text),
+        (_, Some(text)) if line_width > line_box_width => Some(text),
+        _ => None,
+      };
+      let ellipsis = match (style, ellipsis_text) {
+        (Some(style), Some(text)) if !text.is_empty() => {
+          Some(ellipsis_box(style, text.clone(), self.viewport))
+        }
+        _ => None,
+      };
+      let mut hidden = vec![false; line.len()];
+      let ellipsis_width = ellipsis.as_ref().map_or(0.0, |ellipsis| {
+        let margin_box = ellipsis.dimensions.margin_box();
+        line_height = line_height.max(margin_box.height);
+        margin_box.width
+      });
+      if ellipsis.is_some() {
+        let widths: Vec<f32> = order
+          .iter()
+          .map(|&index| margin_boxes[index].width)
+          .collect();
+        let available = line_box_width - ellipsis_width;
+        let truncated = truncate_line(&widths, available, direction == Direction::Rtl);
+        line_width = ellipsis_width;
+        for (&index, truncated) in order.iter().zip(truncated) {
+          hidden[index] = truncated;
+          if !truncated {
+            line_width += margin_boxes[index].width;
+          }
+        }
+      }
+
+      // Lines that overflow the line box are start-aligned, and the last line of justified text
+      // too, or one that ends with an ellipsis.
+      let free_space = line_box_width - line_width;
+      let line_align = match text_align {
+        _ if free_space < 0.0 => start_align,
+        TextAlign::Justify if last || line.len() < 2 || ellipsis.is_some() => start_align,
+        text_align => text_align,
+      };
+      let (mut x, gap) = match line_align {
+        TextAlign::Right => (line_left + free_space, 0.0),
+        TextAlign::Center => (line_left + free_space / 2.0, 0.0),
+        TextAlign::Justify => (line_left, free_space / (line.len() - 1) as f32),
+        _ => (line_left, 0.0),
+      };
+
+      // The ellipsis comes after the remaining boxes on the end side.
+      let mut ellipsis_x = x;
+      if direction == Direction::Rtl {
+        x += ellipsis_width;
+      }
+      for index in order {
+        let atom = &mut atoms[line.start + index];
+        if hidden[index] {
+          atom.truncated = true;
+          continue;
+        }
+        let margin_box = margin_boxes[index];
+        atom.translate(
+          content.x + x - margin_box.x,
+          content.y + line_top + line_height - margin_box.height - margin_box.y,
+        );
+        x += margin_box.width + gap;
+      }
+      if direction == Direction::Ltr {
+        ellipsis_x = x;
+      }
+      if let Some(ellipsis) = ellipsis {
+        ellipses.push((ellipsis, ellipsis_x, line_top + line_height));
+      }
+      line_top += line_height;
+    }
+    self.dimensions.content.height = line_top;
+
+    for (mut ellipsis, x, line_bottom) in ellipses {
+      let height = ellipsis.dimensions.margin_box().height;
+      ellipsis.translate(content.x + x, content.y + line_bottom - height);
+      self.children.push(ellipsis);
+    }
+
+    // Without any line boxes, margins collapse through the block. Until text is laid out, only
+    // whitespace is known not to generate any.
+    self.collapsed_margins = CollapsedMargins {
+      through: self.children.iter().all(|child| match child.box_type {
+        BoxType::InlineNode(node) => node.is_whitespace(),
+        _ => false,
+      }),
+      ..Default::default()
+    };
+  }
+
+  /// Lay out an atomic inline-level box with its margin box at the origin. Its width is
+  /// shrink-to-fit unless it is set, and `auto` margins are zero.
+  ///
+  /// https://www.w3.org/TR/CSS2/visudet.html#inlineblock-width
+  fn layout_atomic_inline(
+    &mut self,
+    available_width: f32,
+    original_containing_block: &SimpleDimensions,
+  ) {
+    if self.marker().is_some() {
+      self.layout_marker();
+      return;
+    }
+    if let BoxType::GeneratedText(..) = self.box_type {
+      self.layout_generated_text();
+      return;
+    }
+
+    let reference = original_containing_block.width;
+    self.calculate_edges(reference);
+    let d = self.dimensions;
+    let edges = d.border.left + d.border.right + d.padding.left + d.padding.right;
+    let available = available_width - edges - d.margin.left - d.margin.right;
+    let width = match self.replaced_size(original_containing_block) {
+      Some((width, _)) => width,
+      None => self
+        .width_property("width", reference, edges, available)
+        .or_else(|| self.ratio_width(Some(original_containing_block.height), reference))
+        .unwrap_or_else(|| self.shrink_to_fit_width(available)),
+    };
+    let width = self.clamp_width(width, reference, edges, available);
+
+    self.layout_block_contents(
+      d.margin.left + d.border.left + d.padding.left,
+      d.margin.top + d.border.top + d.padding.top,
+      width,
+      original_containing_block,
+    );
+  }
+}
+
+/// Collect the atomic inline-level boxes among some inline-level boxes and their descendants,
+/// in order. Other inline boxes are split across the lines, and don't get a size of their own.
+fn collect_atomic_inlines<'a, 'b>(
+  boxes: &'b mut [LayoutBox<'a>],
+  atoms: &mut Vec<&'b mut LayoutBox<'a>>,
+) {
+  for inline_box in boxes {
+    if inline_box.is_atomic_inline() {
+      atoms.push(inline_box);
+    } else if let BoxType::InlineNode(_) = inline_box.box_type {
+      collect_atomic_inlines(&mut inline_box.children, atoms);
+    }
+  }
+}
@@ -0,0 +1,305 @@
+//! Intrinsic sizes: the min-content and max-content widths of boxes, which size the boxes whose
+//! width doesn't follow from their containing block, like inline-blocks, absolutely positioned
+//! boxes, flex and grid items and table cells, and boxes with `width: min-content |
+//! max-content | fit-content`.
+//!
+//! Text is not laid out yet, so only atomic inlines give inline content a size. Lines can break
+//! between them, so the widest one is the min-content width, and all of them on one line is the
+//! max-content width. Percentages of the containing block are treated as `auto`, since the size
+//! of the containing block can depend on these sizes.
+//!
+//! https://www.w3.org/TR/css-sizing-3/#intrinsic-sizes
+
+use super::{BoxType, LayoutBox};
+use crate::layout::css::{Unit, Value, Viewport};
+use crate::layout::style::StyledNode;
+
+/// The min-content and max-content widths of a box, or its contributions to those of its
+/// container.
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
+pub(super) struct IntrinsicSizes {
+  pub min_content: f32,
+  pub max_content: f32,
+}
+
+impl IntrinsicSizes {
+  pub(super) fn fixed(size: f32) -> Self {
+    IntrinsicSizes {
+      min_content: size,
+      max_content: size,
+    }
+  }
+
+  /// The sizes of content placed next to each other on a line that can break between them.
+  pub(super) fn beside(self, other: Self) -> Self {
+    IntrinsicSizes {
+      min_content: self.min_content.max(other.min_content),
+      max_content: self.max_content + other.max_content,
+    }
+  }
+
+  /// The sizes of content placed next to each other without breaks, like table cells in a row.
+  pub(super) fn sum(self, other: Self) -> Self {
+    IntrinsicSizes {
+      min_content: self.min_content + other.min_content,
+      max_content: self.max_content + other.max_content,
+    }
+  }
+
+  /// The sizes of content stacked in the block direction, like blocks in normal flow.
+  pub(super) fn stacked(self, other: Self) -> Self {
+    IntrinsicSizes {
+      min_content: self.min_content.max(other.min_content),
+      max_content: self.max_content.max(other.max_content),
+    }
+  }
+
+  pub(super) fn map<F: Fn(f32) -> f32>(self, f: F) -> Self {
+    IntrinsicSizes {
+      min_content: f(self.min_content),
+      max_content: f(self.max_content),
+    }
+  }
+}
+
+impl<'a> LayoutBox<'a> {
+  /// The min-content and max-content widths of the content box. They only depend on the
+  /// styles and the contents of the box, so they are computed once per box.
+  pub(super) fn intrinsic_sizes(&self) -> IntrinsicSizes {
+    if let Some(sizes) = self.intrinsic_sizes.get() {
+      return sizes;
+    }
+    let sizes = self.compute_intrinsic_sizes();
+    self.intrinsic_sizes.set(Some(sizes));
+    sizes
+  }
+
+  /// The contributions of the box to the intrinsic sizes of its container: the width of its
+  /// margin box when its content box is as wide as its min-content or max-content width, or
+  /// as its `width` if that is set, within `min-width` and `max-width`.
+  ///
+  /// https://www.w3.org/TR/css-sizing-3/#intrinsic-contribution
+  pub(super) fn intrinsic_contributions(&self) -> IntrinsicSizes {
+    let viewport = self.viewport;
+    let sizes = self.intrinsic_sizes();
+    let style = match self.get_style_node_option() {
+      Some(style) => style,
+      None => return sizes,
+    };
+    let zero = Value::Length(0.0, Unit::Px);
+    let edge =
+      |name: &str, shorthand: &str| style.lookup(name, shorthand, &zero).to_px(0.0, viewport);
+    let border = |side: &str| style.border_width(side).to_px(0.0, viewport);
+    let edges = border("left")
+      + border("right")
+      + edge("padding-left", "padding")
+      + edge("padding-right", "padding");
+    let margins = edge("margin-left", "margin") + edge("margin-right", "margin");
+    let ratio_width = self.ratio_width(None, 0.0);
+
+    sizes.map(|size| {
+      let width = contribution_size(style, "width", sizes, edges, viewport)
+        .or(ratio_width)
+        .unwrap_or(size);
+      let min = contribution_size(style, "min-width", sizes, edges, viewport).unwrap_or(0.0);
+      let max =
+        contribution_size(style, "max-width", sizes, edges, viewport).unwrap_or(f32::INFINITY);
+      width.min(max).max(min) + edges + margins
+    })
+  }
+
+  /// The used value of `width`, `min-width` or `max-width` for the content box, where
+  /// `min-content` and `max-content` are the intrinsic sizes and `fit-content` is the
+  /// shrink-to-fit width in `available_width`. `None` for `auto`, `none` and anonymous boxes.
+  ///
+  /// https://www.w3.org/TR/css-sizing-3/#sizing-values
+  pub(super) fn width_property(
+    &self,
+    name: &str,
+    reference: f32,
+    edges: f32,
+    available_width: f32,
+  ) -> Option<f32> {
+    let style = self.get_style_node_option()?;
+    match style.keyword(name).as_deref() {
+      Some("min-content") => Some(self.intrinsic_sizes().min_content),
+      Some("max-content") => Some(self.intrinsic_sizes().max_content),
+      Some("fit-content") => Some(self.shrink_to_fit_width(available_width)),
+      _ => style.size(name, reference, edges, self.viewport),
+    }
+  }
+
+  /// Clamp the width of the content box by `min-width` and `max-width`, the minimum wins when
+  /// they conflict.
+  pub(super) fn clamp_width(
+    &self,
+    width: f32,
+    reference: f32,
+    edges: f32,
+    available_width: f32,
+  ) -> f32 {
+    let min = self.width_property("min-width", reference, edges, available_width);
+    let max = self.width_property("max-width", reference, edges, available_width);
+    width
+      .min(max.unwrap_or(f32::INFINITY))
+      .max(min.unwrap_or(0.0))
+  }
+
+  /// The shrink-to-fit width of a box with `width: auto` that does not fill its containing
+  /// block, like absolutely positioned boxes and flex items in a column: the available width,
+  /// but no narrower than the min-content width and no wider than the max-content width.
+  ///
+  /// https://www.w3.org/TR/CSS2/visudet.html#shrink-to-fit-float
+  pub(super) fn shrink_to_fit_width(&self, available_width: f32) -> f32 {
+    let sizes = self.intrinsic_sizes();
+    available_width
+      .min(sizes.max_content)
+      .max(sizes.min_content)
+  }
+
+  fn compute_intrinsic_sizes(&self) -> IntrinsicSizes {
+    if let Some(marker) = self.marker() {
+      return IntrinsicSizes::fixed(marker.size(self.viewport).0);
+    }
+    if let BoxType::GeneratedText(..) = self.box_type {
+      return IntrinsicSizes::fixed(self.generated_text_size().0);
+    }
+    if let Some(width) = self.natural_width() {
+      return IntrinsicSizes::fixed(width);
+    }
+
+    let display = self.display();
+    if display.is_flex_container() {
+      self.flex_intrinsic_sizes()
+    } else if display.is_grid_container() {
+      self.grid_intrinsic_sizes()
+    } else if display.is_table() {
+      self.table_intrinsic_sizes()
+    } else if self.is_multicol_container() {
+      self.multicol_intrinsic_sizes(self.block_intrinsic_sizes())
+    } else {
+      self.block_intrinsic_sizes()
+    }
+  }
+
+  /// The intrinsic sizes of a block container, as wide as its widest block-level child or line
+  /// of inline content.
+  fn block_intrinsic_sizes(&self) -> IntrinsicSizes {
+    // Lines that don't wrap are as wide as all of their content.
+    let style = match self.box_type {
+      BoxType::AnonymousBlock(style) => style,
+      _ => self.get_style_node_option(),
+    };
+    let wraps = style.is_none_or(|style| style.wraps_lines());
+    let mut sizes = IntrinsicSizes::default();
+    let mut line = IntrinsicSizes::default();
+    for child in &self.children {
+      if child.position().is_out_of_flow() || child.is_outside_marker() {
+        continue;
+      }
+      match child.box_type {
+        BoxType::InlineNode(_) | BoxType::Marker(_) | BoxType::GeneratedText(..) => {
+          line = inline_intrinsic_sizes(std::slice::from_ref(child), line);
+        }
+        _ => sizes = sizes.stacked(child.intrinsic_contributions()),
+      }
+    }
+    if !wraps {
+      line = IntrinsicSizes::fixed(line.max_content);
+    }
+    sizes.stacked(line)
+  }
+}
+
+/// Add the contributions of the atomic inlines among some inline-level boxes and their
+/// descendants to the sizes of a line.
+fn inline_intrinsic_sizes(boxes: &[LayoutBox], mut line: IntrinsicSizes) -> IntrinsicSizes {
+  for inline_box in boxes {
+    if inline_box.position().is_out_of_flow() {
+      continue;
+    }
+    if inline_box.is_atomic_inline() {
+      line = line.beside(inline_box.intrinsic_contributions());
+    } else if let BoxType::InlineNode(_) = inline_box.box_type {
+      line = inline_intrinsic_sizes(&inline_box.children, line);
+    }
+  }
+  line
+}
+
+/// Resolve `width`, `min-width` or `max-width` for an intrinsic size contribution, where
+/// percentages behave as `auto` and the intrinsic size keywords pick one of `sizes`.
+fn contribution_size(
+  style: &StyledNode,
+  name: &str,
+  sizes: IntrinsicSizes,
+  edges: f32,
+  viewport: Viewport,
+) -> Option<f32> {
+  match style.value(name) {
+    Some(Value::Keyword(ref keyword)) if keyword == "min-content" => Some(sizes.min_content),
+    Some(Value::Keyword(ref keyword)) if keyword == "max-content" => Some(sizes.max_content),
+    Some(Value::Length(_, Unit::Percentage)) => None,
+    _ => style.size(name, 0.0, edges, viewport),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, border_box, div, layout};
+  use super::IntrinsicSizes;
+
+  fn nested_blocks(class: &str) -> crate::layout::dom::Node {
+    div(
+      class,
+      vec![
+        div("inner", vec![div("a", vec![]), div("b", vec![])]),
+        div("wide", vec![]),
+      ],
+    )
+  }
+
+  const CSS: &str = ".inner { padding: 0 5px; } \
+                     .a { display: inline-block; width: 100px; height: 10px; } \
+                     .b { display: inline-block; width: 60px; height: 10px; } \
+                     .wide { width: 120px; margin-left: 20px; height: 10px; }";
+
+  #[test]
+  fn nested_blocks_are_as_wide_as_their_widest_child() {
+    let document = body(vec![nested_blocks("outer")]);
+    layout(&document, CSS, |root| {
+      assert_eq!(
+        root.children[0].intrinsic_sizes(),
+        IntrinsicSizes {
+          min_content: 140.0,
+          max_content: 170.0,
+        }
+      );
+    });
+  }
+
+  #[test]
+  fn min_content_and_max_content_size_nested_blocks() {
+    let document = body(vec![nested_blocks("outer")]);
+    let min = format!("{} .outer {{ width: min-content; }}", CSS);
+    layout(&document, &min, |root| {
+      assert_eq!(border_box(root, &[0]).2, 140.0);
+    });
+    let max = format!("{} .outer {{ width: max-content; }}", CSS);
+    layout(&document, &max, |root| {
+      assert_eq!(border_box(root, &[0]).2, 170.0);
+    });
+  }
+
+  #[test]
+  fn fit_content_is_clamped_by_the_intrinsic_sizes() {
+    let document = body(vec![div("narrow", vec![nested_blocks("outer")])]);
+    let css = format!(
+      "{} .narrow {{ width: 150px; }} .outer {{ width: fit-content; }}",
+      CSS
+    );
+    layout(&document, &css, |root| {
+      assert_eq!(border_box(root, &[0, 0]).2, 150.0);
+    });
+  }
+}
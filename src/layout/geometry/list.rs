@@ -0,0 +1,396 @@
+//! List items and their `::marker` boxes.
+//!
+//! Text is not laid out yet, so the size of a marker box is estimated from its text and the font
+//! size, and only the bullets of `disc`, `circle` and `square` markers are painted.
+//!
+//! https://www.w3.org/TR/css-lists-3/
+
+use super::{BoxType, LayoutBox, Rect};
+use crate::layout::css::{Value, Viewport};
+pub(super) use crate::layout::style::DEFAULT_FONT_SIZE;
+use crate::layout::style::{Direction, StyledNode, TextOrientation};
+
+/// The `::marker` box generated by a list item.
+#[derive(Debug, Clone)]
+pub struct ListMarker<'a> {
+  // The list item, which the marker takes its style from.
+  pub list_item: &'a StyledNode<'a>,
+  pub style_type: String,
+  // The text of the marker, like "1. " or a bullet followed by a space.
+  pub text: String,
+  // Whether the marker is placed outside of the principal box of the list item, rather than at
+  // the start of its first line.
+  pub outside: bool,
+}
+
+impl<'a> ListMarker<'a> {
+  fn font_size(&self, viewport: Viewport) -> f32 {
+    self
+      .list_item
+      .length("font-size", DEFAULT_FONT_SIZE, viewport)
+      .unwrap_or(DEFAULT_FONT_SIZE)
+  }
+
+  pub(super) fn size(&self, viewport: Viewport) -> (f32, f32) {
+    estimate_text_size(
+      &self.text,
+      self.font_size(viewport),
+      self.list_item,
+      viewport,
+    )
+  }
+
+  /// The rect of the bullet of a `disc`, `circle` or `square` marker, centered in the space of
+  /// its first glyph, or `None` for markers with other text.
+  pub fn bullet_rect(&self, content: Rect, viewport: Viewport) -> Option<Rect> {
+    match self.style_type.as_str() {
+      "disc" | "circle" | "square" => {}
+      _ => return None,
+    }
+    let font_size = self.font_size(viewport);
+    let size = font_size * 0.35;
+    Some(Rect {
+      x: content.x + (font_size / 2.0 - size) / 2.0,
+      y: content.y + (content.height - size) / 2.0,
+      width: size,
+      height: size,
+    })
+  }
+}
+
+impl<'a> LayoutBox<'a> {
+  /// The marker this box was generated for, if it is a `::marker` box.
+  pub fn marker(&self) -> Option<&ListMarker<'a>> {
+    match &self.box_type {
+      BoxType::Marker(marker) => Some(marker),
+      _ => None,
+    }
+  }
+
+  pub(super) fn is_outside_marker(&self) -> bool {
+    self.marker().map(|marker| marker.outside).unwrap_or(false)
+  }
+
+  /// Generate the marker box of a list item with the given ordinal. An outside marker is added
+  /// as the first child, an inside marker starts the first line.
+  pub(super) fn add_list_marker(&mut self, list_item: &'a StyledNode<'a>, ordinal: i32) {
+    let (style_type, outside) = list_style(list_item);
+    let text = match marker_text(&style_type, ordinal) {
+      Some(text) => text,
+      None => return,
+    };
+    let marker = LayoutBox::new(
+      BoxType::Marker(ListMarker {
+        list_item,
+        style_type,
+        text,
+        outside,
+      }),
+      self.viewport,
+    );
+
+    if outside {
+      self.children.insert(0, marker);
+    } else {
+      match self.children.first_mut() {
+        Some(first) if matches!(first.box_type, BoxType::AnonymousBlock(_)) => {
+          first.children.insert(0, marker)
+        }
+        _ => {
+          let mut line = LayoutBox::new(BoxType::AnonymousBlock(Some(list_item)), self.viewport);
+          line.children.push(marker);
+          self.children.insert(0, line);
+        }
+      }
+    }
+  }
+
+  /// Size a marker box that is laid out as an atomic inline, at the origin.
+  pub(super) fn layout_marker(&mut self) {
+    if let Some(marker) = self.marker() {
+      let (width, height) = marker.size(self.viewport);
+      self.dimensions.content = Rect {
+        x: 0.0,
+        y: 0.0,
+        width,
+        height,
+      };
+    }
+  }
+
+  /// Place the outside marker of a list item, if it has one, before the start edge of its border
+  /// box and at the top of its content area, where the first line starts.
+  pub(super) fn position_outside_marker(&mut self) {
+    let border_box = self.dimensions.border_box();
+    let content = self.dimensions.content;
+    if let Some(marker) = self
+      .children
+      .iter_mut()
+      .find(|child| child.is_outside_marker())
+    {
+      marker.layout_marker();
+      let width = marker.dimensions.content.width;
+      let rtl = marker
+        .marker()
+        .map(|marker| marker.list_item.direction() == Direction::Rtl)
+        .unwrap_or(false);
+      marker.dimensions.content.x = if rtl {
+        border_box.x + border_box.width
+      } else {
+        border_box.x - width
+      };
+      marker.dimensions.content.y = content.y;
+    }
+  }
+}
+
+/// Estimate the size of a line of text along and across the line, until text is laid out. The
+/// runs of text that a font face renders are shaped with it, and take the advance of their
+/// glyphs. Characters without a face are half as wide as the font size, except for wide
+/// characters like CJK ideographs. Upright glyphs in vertical writing modes take up a square.
+/// `letter-spacing` is added after every character, and `word-spacing` to every space, which is
+/// the only effect of the spacing properties until the text of the document is laid out.
+///
+/// https://www.w3.org/TR/css-fonts-4/#font-matching-algorithm
+/// https://www.w3.org/TR/css-text-3/#spacing
+pub(super) fn estimate_text_size(
+  text: &str,
+  font_size: f32,
+  style: &StyledNode,
+  viewport: Viewport,
+) -> (f32, f32) {
+  let upright =
+    style.writing_mode.is_vertical() && style.text_orientation() == TextOrientation::Upright;
+  // Percentages of `word-spacing` refer to the width of a space.
+  let letter_spacing = style
+    .length("letter-spacing", font_size, viewport)
+    .unwrap_or(0.0);
+  let word_spacing = style
+    .length("word-spacing", font_size / 2.0, viewport)
+    .unwrap_or(0.0);
+  let settings = style.font_settings(letter_spacing);
+  let fonts = style.fonts.select(style);
+
+  let advance: f32 = fonts
+    .runs(text)
+    .into_iter()
+    .map(|(range, face)| match face.filter(|_| !upright) {
+      Some(face) => face.font.shape(&text[range], font_size, &settings).width(),
+      None => text[range]
+        .chars()
+        .map(|c| {
+          if upright || is_wide(c) {
+            font_size
+          } else {
+            font_size / 2.0
+          }
+        })
+        .sum(),
+    })
+    .sum();
+  let spacing: f32 = text
+    .chars()
+    .map(|c| match c {
+      ' ' | '\u{a0}' => letter_spacing + word_spacing,
+      _ => letter_spacing,
+    })
+    .sum();
+  (advance + spacing, font_size)
+}
+
+/// Whether a character is full-width, like Hangul, CJK ideographs, kana and full-width forms.
+///
+/// https://www.unicode.org/reports/tr11/
+fn is_wide(c: char) -> bool {
+  matches!(
+    c,
+    '\u{1100}'..='\u{115f}'
+      | '\u{2e80}'..='\u{a4cf}'
+      | '\u{ac00}'..='\u{d7a3}'
+      | '\u{f900}'..='\u{faff}'
+      | '\u{fe30}'..='\u{fe4f}'
+      | '\u{ff00}'..='\u{ff60}'
+      | '\u{ffe0}'..='\u{ffe6}'
+  )
+}
+
+/// The used `list-style-type` and whether `list-style-position` is `outside`, from the longhands
+/// or the `list-style` shorthand.
+fn list_style(style: &StyledNode) -> (String, bool) {
+  let shorthand: Vec<String> = match style.value("list-style") {
+    Some(Value::Keyword(keyword)) => vec![keyword],
+    Some(Value::List(values)) => values
+      .into_iter()
+      .filter_map(|value| match value {
+        Value::Keyword(keyword) => Some(keyword),
+        _ => None,
+      })
+      .collect(),
+    _ => vec![],
+  };
+  let is_position = |keyword: &str| keyword == "inside" || keyword == "outside";
+
+  let style_type = style
+    .keyword("list-style-type")
+    .or_else(|| {
+      shorthand
+        .iter()
+        .find(|keyword| !is_position(keyword))
+        .cloned()
+    })
+    .unwrap_or_else(|| "disc".to_string());
+  let position = style.keyword("list-style-position").or_else(|| {
+    shorthand
+      .iter()
+      .find(|keyword| is_position(keyword))
+      .cloned()
+  });
+  (style_type, position.as_deref() != Some("inside"))
+}
+
+/// The text of a marker for an ordinal in a counter style, or `None` for `none`.
+fn marker_text(style_type: &str, ordinal: i32) -> Option<String> {
+  match style_type {
+    "none" => None,
+    "disc" | "circle" | "square" => Some(format!("{} ", format_counter(ordinal, style_type))),
+    _ => Some(format!("{}. ", format_counter(ordinal, style_type))),
+  }
+}
+
+/// Represent the value of a counter in a counter style. Values that a counter style can't
+/// represent fall back to `decimal`, like unknown counter styles.
+///
+/// https://www.w3.org/TR/css-counter-styles-3/#predefined-counters
+pub(super) fn format_counter(value: i32, style_type: &str) -> String {
+  match style_type {
+    "disc" => "\u{2022}".to_string(),
+    "circle" => "\u{25e6}".to_string(),
+    "square" => "\u{25aa}".to_string(),
+    "decimal-leading-zero" if (0..10).contains(&value) => format!("0{}", value),
+    "lower-alpha" | "lower-latin" => alphabetic(value, LATIN, false),
+    "upper-alpha" | "upper-latin" => alphabetic(value, LATIN, true),
+    "lower-greek" => alphabetic(value, GREEK, false),
+    "lower-roman" => roman(value).to_lowercase(),
+    "upper-roman" => roman(value),
+    _ => value.to_string(),
+  }
+}
+
+const LATIN: &str = "abcdefghijklmnopqrstuvwxyz";
+const GREEK: &str = "αβγδεζηθικλμνξοπρστυφχψω";
+
+/// Write an ordinal in an alphabetic counter style: a, b, ... z, aa, ab, ...
+///
+/// https://www.w3.org/TR/css-counter-styles-3/#alphabetic-system
+fn alphabetic(ordinal: i32, alphabet: &str, uppercase: bool) -> String {
+  if ordinal < 1 {
+    return ordinal.to_string();
+  }
+  let letters: Vec<char> = alphabet.chars().collect();
+  let base = letters.len() as i32;
+  let mut text = Vec::new();
+  let mut value = ordinal;
+  while value > 0 {
+    value -= 1;
+    text.push(letters[(value % base) as usize]);
+    value /= base;
+  }
+  let text: String = text.into_iter().rev().collect();
+  if uppercase {
+    text.to_uppercase()
+  } else {
+    text
+  }
+}
+
+/// Write an ordinal in upper-case roman numerals, which go up to 3999.
+fn roman(ordinal: i32) -> String {
+  if !(1..4000).contains(&ordinal) {
+    return ordinal.to_string();
+  }
+  let numerals = [
+    (1000, "M"),
+    (900, "CM"),
+    (500, "D"),
+    (400, "CD"),
+    (100, "C"),
+    (90, "XC"),
+    (50, "L"),
+    (40, "XL"),
+    (10, "X"),
+    (9, "IX"),
+    (5, "V"),
+    (4, "IV"),
+    (1, "I"),
+  ];
+  let mut text = String::new();
+  let mut value = ordinal;
+  for &(weight, numeral) in numerals.iter() {
+    while value >= weight {
+      text.push_str(numeral);
+      value -= weight;
+    }
+  }
+  text
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, div, layout};
+  use super::super::LayoutBox;
+  use super::ListMarker;
+  use crate::layout::css::Viewport;
+
+  /// The markers among a box and its descendants, in tree order.
+  fn markers<'l, 'a>(layout_box: &'l LayoutBox<'a>) -> Vec<&'l ListMarker<'a>> {
+    let mut found: Vec<_> = layout_box.marker().into_iter().collect();
+    for child in &layout_box.children {
+      found.extend(markers(child));
+    }
+    found
+  }
+
+  #[test]
+  fn list_style_is_inherited_by_list_items() {
+    let document = body(vec![
+      div("none", vec![div("item", vec![])]),
+      div("square", vec![div("item", vec![])]),
+    ]);
+    let css = ".item { display: list-item; height: 10px; } \
+               .none { list-style-type: none; } .square { list-style: square inside; }";
+    layout(&document, css, |root| {
+      assert!(markers(&root.children[0]).is_empty());
+      let marker = markers(&root.children[1])[0];
+      assert_eq!(marker.style_type, "square");
+      assert!(!marker.outside);
+    });
+  }
+
+  #[test]
+  fn list_style_shorthand_on_an_item_resets_inherited_longhands() {
+    let document = body(vec![div("list", vec![div("item", vec![])])]);
+    let css = ".list { list-style-type: square; list-style-position: inside; } \
+               .item { display: list-item; height: 10px; list-style: circle; }";
+    layout(&document, css, |root| {
+      let marker = markers(root)[0];
+      assert_eq!(marker.style_type, "circle");
+      assert!(marker.outside);
+    });
+  }
+
+  #[test]
+  fn markers_are_sized_by_the_inherited_font_size() {
+    let document = body(vec![div(
+      "list",
+      vec![div("item", vec![div("small", vec![])])],
+    )]);
+    let css = ".list { font-size: 24px; } .item { display: list-item; height: 10px; } \
+               .small { display: list-item; font-size: 50%; }";
+    layout(&document, css, |root| {
+      let found = markers(root);
+      let viewport = Viewport::default();
+      assert_eq!(found[0].font_size(viewport), 24.0);
+      assert_eq!(found[1].font_size(viewport), 12.0);
+    });
+  }
+}
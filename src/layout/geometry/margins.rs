@@ -0,0 +1,244 @@
+//! Collapsing of adjoining vertical margins in block layout.
+//!
+//! Siblings collapse their margins in `layout_block_children`, which leaves the margins of the
+//! first and last child inside the content area. A block in normal flow that doesn't separate
+//! them with borders or padding then takes them out of its content area and collapses them with
+//! its own margins, see `collapse_margins_with_children`.
+//!
+//! https://www.w3.org/TR/CSS2/box.html#collapsing-margins
+
+use super::LayoutBox;
+use crate::layout::style::DisplayStyle;
+
+/// A set of adjoining margins, which collapse into a single margin as wide as the largest
+/// positive margin plus the most negative margin.
+#[derive(Default, Copy, Clone, Debug)]
+pub struct MarginStrut {
+  positive: f32,
+  negative: f32,
+}
+
+impl MarginStrut {
+  pub fn new(margin: f32) -> Self {
+    MarginStrut::default().with(margin)
+  }
+
+  /// Add a margin that adjoins the margins in this set.
+  pub fn with(self, margin: f32) -> Self {
+    MarginStrut {
+      positive: self.positive.max(margin),
+      negative: self.negative.min(margin),
+    }
+  }
+
+  /// Merge two sets of adjoining margins.
+  pub fn append(self, other: MarginStrut) -> Self {
+    self.with(other.positive).with(other.negative)
+  }
+
+  /// The width of the collapsed margin.
+  pub fn value(self) -> f32 {
+    self.positive + self.negative
+  }
+}
+
+/// The top and bottom margins of a box once they have collapsed with the margins of its children.
+#[derive(Default, Copy, Clone, Debug)]
+pub struct CollapsedMargins {
+  pub top: MarginStrut,
+  pub bottom: MarginStrut,
+  // Whether the top and bottom margins adjoin, e.g. for an empty block, so that margins around
+  // the box collapse through it.
+  pub through: bool,
+}
+
+impl CollapsedMargins {
+  pub fn new(top: f32, bottom: f32) -> Self {
+    CollapsedMargins {
+      top: MarginStrut::new(top),
+      bottom: MarginStrut::new(bottom),
+      through: false,
+    }
+  }
+}
+
+impl<'a> LayoutBox<'a> {
+  /// Collapse the margins of a block in normal flow with the margins of its first and last
+  /// in-flow children, when no border or padding separates them.
+  ///
+  /// Must run after `layout_block_children`, which leaves the margins of the children that
+  /// adjoin the top and the bottom of the content area in `self.collapsed_margins`. The content
+  /// area shrinks to exclude them, and `self.collapsed_margins` is set to the margins of the
+  /// box as seen by its parent.
+  pub(super) fn collapse_margins_with_children(&mut self) {
+    let viewport = self.viewport;
+    let d = self.dimensions;
+    let own = CollapsedMargins::new(d.margin.top, d.margin.bottom);
+    if !self.collapses_margins_with_children() {
+      self.collapsed_margins = own;
+      return;
+    }
+
+    let style = self.get_style_node();
+    let children = self.collapsed_margins;
+    // A height that follows from an aspect ratio separates the margins like a set height.
+    let auto_height =
+      style.size("height", 0.0, 0.0, viewport).is_none() && self.aspect_ratio().is_none();
+    let min_height = style.size("min-height", 0.0, 0.0, viewport).unwrap_or(0.0);
+    let top_adjoins = d.border.top == 0.0 && d.padding.top == 0.0;
+    let bottom_adjoins = d.border.bottom == 0.0 && d.padding.bottom == 0.0 && auto_height;
+
+    // Without in-flow content, the top and bottom margins of the box and of all its children
+    // collapse together, and the content area keeps no height.
+    if children.through && top_adjoins && bottom_adjoins && min_height == 0.0 {
+      let margins = own.top.append(own.bottom).append(children.top);
+      self.dimensions.content.height = 0.0;
+      self.collapsed_margins = CollapsedMargins {
+        top: margins,
+        bottom: margins,
+        through: true,
+      };
+      return;
+    }
+
+    let mut margins = own;
+    if top_adjoins {
+      let offset = children.top.value();
+      margins.top = own.top.append(children.top);
+      self.dimensions.content.height -= offset;
+      // The outside marker is placed at the top of the content area already.
+      for child in &mut self.children {
+        if !child.is_outside_marker() {
+          child.translate(0.0, -offset);
+        }
+      }
+    }
+    // The margins of children without content were already taken out at the top.
+    if bottom_adjoins && !(children.through && top_adjoins) {
+      margins.bottom = own.bottom.append(children.bottom);
+      self.dimensions.content.height -= children.bottom.value();
+    }
+    self.collapsed_margins = margins;
+  }
+
+  /// Whether the margins of the box collapse with those of its children, which is not the case
+  /// for boxes that establish a new formatting context or replaced elements.
+  fn collapses_margins_with_children(&self) -> bool {
+    let style = self.get_style_node();
+    let (overflow_x, overflow_y) = style.overflow();
+    matches!(
+      style.display(),
+      DisplayStyle::Block | DisplayStyle::ListItem
+    ) && style.image().is_none()
+      && !overflow_x.is_scrollable()
+      && !overflow_y.is_scrollable()
+      && !self.is_multicol_container()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, border_box, div, layout};
+
+  #[test]
+  fn sibling_margins_collapse_to_the_largest() {
+    let document = body(vec![div("a", vec![]), div("b", vec![])]);
+    let css = ".a { height: 10px; margin-bottom: 20px; } .b { height: 10px; margin-top: 30px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0]).1, 0.0);
+      assert_eq!(border_box(root, &[1]).1, 40.0);
+    });
+  }
+
+  #[test]
+  fn negative_margins_are_subtracted() {
+    let document = body(vec![div("a", vec![]), div("b", vec![]), div("c", vec![])]);
+    let css = ".a { height: 10px; margin-bottom: 30px; } \
+               .b { height: 10px; margin-top: -10px; margin-bottom: -5px; } \
+               .c { height: 10px; margin-top: -20px; }";
+    layout(&document, css, |root| {
+      // The largest positive margin plus the most negative one.
+      assert_eq!(border_box(root, &[1]).1, 30.0);
+      // Only negative margins collapse to the most negative one.
+      assert_eq!(border_box(root, &[2]).1, 20.0);
+    });
+  }
+
+  #[test]
+  fn first_child_margin_collapses_with_parent() {
+    let document = body(vec![div("parent", vec![div("child", vec![])])]);
+    let css = ".parent { margin-top: 10px; } .child { height: 10px; margin-top: 25px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0]), (0.0, 25.0, 800.0, 10.0));
+      assert_eq!(border_box(root, &[0, 0]).1, 25.0);
+    });
+  }
+
+  #[test]
+  fn padding_separates_parent_and_child_margins() {
+    let document = body(vec![div("parent", vec![div("child", vec![])])]);
+    let css = ".parent { margin-top: 10px; padding-top: 1px; } \
+               .child { height: 10px; margin-top: 25px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0]).1, 10.0);
+      assert_eq!(border_box(root, &[0, 0]).1, 36.0);
+    });
+  }
+
+  #[test]
+  fn last_child_margin_collapses_with_parent() {
+    let document = body(vec![
+      div("parent", vec![div("child", vec![])]),
+      div("next", vec![]),
+    ]);
+    let css = ".parent { margin-bottom: 10px; } .child { height: 10px; margin-bottom: 25px; } \
+               .next { height: 10px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0]).3, 10.0);
+      assert_eq!(border_box(root, &[1]).1, 35.0);
+    });
+  }
+
+  #[test]
+  fn margins_collapse_through_empty_blocks() {
+    let document = body(vec![
+      div("a", vec![]),
+      div("empty", vec![]),
+      div("b", vec![]),
+    ]);
+    let css = ".a { height: 10px; margin-bottom: 10px; } \
+               .empty { margin-top: 20px; margin-bottom: 5px; } \
+               .b { height: 10px; margin-top: 15px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[1]).3, 0.0);
+      assert_eq!(border_box(root, &[2]).1, 30.0);
+    });
+  }
+
+  #[test]
+  fn min_height_stops_collapsing_through() {
+    let document = body(vec![
+      div("a", vec![]),
+      div("empty", vec![]),
+      div("b", vec![]),
+    ]);
+    let css = ".a { height: 10px; margin-bottom: 10px; } \
+               .empty { min-height: 5px; margin-top: 20px; margin-bottom: 5px; } \
+               .b { height: 10px; margin-top: 15px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[1]).1, 30.0);
+      assert_eq!(border_box(root, &[2]).1, 50.0);
+    });
+  }
+
+  #[test]
+  fn scroll_containers_keep_the_margins_of_their_children() {
+    let document = body(vec![div("parent", vec![div("child", vec![])])]);
+    let css = ".parent { overflow: hidden; margin-top: 10px; } \
+               .child { height: 10px; margin-top: 25px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0]), (0.0, 10.0, 800.0, 35.0));
+      assert_eq!(border_box(root, &[0, 0]).1, 35.0);
+    });
+  }
+}
@@ -1,8 +1,47 @@
 //! The Geometry Layout tree is one of the last steps in the layout pipelines
 
-use crate::layout::css::{Unit, Value};
-use crate::layout::style::DisplayStyle;
+mod alignment;
+mod aspect_ratio;
+mod bidi;
+mod border;
+mod content;
+mod decoration;
+mod flex;
+mod grid;
+mod inline;
+mod intrinsic;
+mod list;
+mod margins;
+mod multicol;
+mod overflow;
+mod pagination;
+mod position;
+mod radius;
+mod replaced;
+mod shadow;
+mod stacking;
+mod table;
+#[cfg(test)]
+pub(crate) mod testing;
+mod truncation;
+mod writing_mode;
+
+use crate::layout::css::{Unit, Value, Viewport};
 use crate::layout::style::StyledNode;
+use crate::layout::style::{Direction, DisplayStyle, OuterDisplay};
+pub use border::Polygon;
+use content::ContentState;
+pub use decoration::TextDecoration;
+use intrinsic::IntrinsicSizes;
+pub use list::ListMarker;
+use margins::{CollapsedMargins, MarginStrut};
+pub use overflow::{ScrollId, ScrollOffsets};
+pub use pagination::{layout_paged_geometry_tree, Page, PageContext};
+pub use radius::RoundedRect;
+pub use shadow::BoxShadow;
+pub use stacking::StackLevel;
+use std::cell::Cell;
+use table::generate_anonymous_table_boxes;
 
 #[derive(Default, Debug, Copy, Clone)]
 pub struct Dimensions {
@@ -25,6 +64,10 @@ impl Dimensions {
     }
   }
 
+  // The content area.
+  pub fn content_box(self) -> Rect {
+    self.content
+  }
   // The area covered by the content area plus its padding.
   pub fn padding_box(self) -> Rect {
     self.content.expanded_by(self.padding)
@@ -56,6 +99,34 @@ impl Rect {
       height: self.height + edge.top + edge.bottom,
     }
   }
+
+  // The smallest rect that contains both rects.
+  pub fn union(self, other: Rect) -> Rect {
+    let x = self.x.min(other.x);
+    let y = self.y.min(other.y);
+    Rect {
+      x,
+      y,
+      width: (self.x + self.width).max(other.x + other.width) - x,
+      height: (self.y + self.height).max(other.y + other.height) - y,
+    }
+  }
+
+  // The area covered by both rects, empty if they don't overlap.
+  pub fn intersection(self, other: Rect) -> Rect {
+    let x = self.x.max(other.x);
+    let y = self.y.max(other.y);
+    Rect {
+      x,
+      y,
+      width: ((self.x + self.width).min(other.x + other.width) - x).max(0.0),
+      height: ((self.y + self.height).min(other.y + other.height) - y).max(0.0),
+    }
+  }
+
+  pub fn contains(self, x: f32, y: f32) -> bool {
+    x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+  }
 }
 
 impl std::fmt::Debug for Rect {
@@ -71,13 +142,16 @@ impl std::fmt::Debug for Rect {
 struct SimpleDimensions {
   pub width: f32,
   pub height: f32,
+  // The inline base direction of the containing block.
+  pub direction: Direction,
 }
 
 impl SimpleDimensions {
-  pub fn from_dimension(rect: Rect) -> Self {
+  pub fn from_dimension(rect: Rect, direction: Direction) -> Self {
     SimpleDimensions {
       width: rect.width,
       height: rect.height,
+      direction,
     }
   }
 }
@@ -104,7 +178,19 @@ impl std::fmt::Debug for EdgeSizes {
 pub enum BoxType<'a> {
   BlockNode(&'a StyledNode<'a>),
   InlineNode(&'a StyledNode<'a>),
-  AnonymousBlock,
+  // A block holding a run of inline-level boxes, with the style of the box it was generated in
+  // for the properties it inherits, like `text-align`. Anonymous table boxes have none.
+  AnonymousBlock(Option<&'a StyledNode<'a>>),
+  // A table, row or cell generated to complete the table structure around other boxes.
+  AnonymousTable(DisplayStyle),
+  // The `::marker` box of a list item.
+  Marker(ListMarker<'a>),
+  // An anonymous column box of a multi-column container, created when the container is laid out.
+  Column,
+  // The text generated by the `content` of a `::before` or `::after` pseudo-element, which takes
+  // its style from the pseudo-element, or the ellipsis of a truncated line, which takes the style
+  // of its block.
+  GeneratedText(&'a StyledNode<'a>, String),
 }
 
 #[derive(Clone, Debug)]
@@ -112,14 +198,24 @@ pub struct LayoutBox<'a> {
   pub dimensions: Dimensions,
   box_type: BoxType<'a>,
   pub children: Vec<LayoutBox<'a>>,
+  collapsed_margins: CollapsedMargins,
+  intrinsic_sizes: Cell<Option<IntrinsicSizes>>,
+  // Whether the box is hidden by the truncation of its line.
+  truncated: bool,
+  // The viewport the box is laid out in, which its viewport-percentage lengths are relative to.
+  viewport: Viewport,
 }
 
 impl<'a> LayoutBox<'a> {
-  pub fn new(box_type: BoxType<'a>) -> Self {
+  pub fn new(box_type: BoxType<'a>, viewport: Viewport) -> Self {
     Self {
       dimensions: Default::default(),
       box_type,
       children: Vec::new(),
+      collapsed_margins: Default::default(),
+      intrinsic_sizes: Cell::new(None),
+      truncated: false,
+      viewport,
     }
   }
 
@@ -134,22 +230,48 @@ impl<'a> LayoutBox<'a> {
     match &self.box_type {
       BoxType::BlockNode(node) => Some(node),
       BoxType::InlineNode(node) => Some(node),
-      BoxType::AnonymousBlock => None,
+      BoxType::AnonymousBlock(_)
+      | BoxType::AnonymousTable(_)
+      | BoxType::Column
+      | BoxType::Marker(_)
+      | BoxType::GeneratedText(..) => None,
+    }
+  }
+
+  /// The viewport the box is laid out in.
+  pub fn viewport(&self) -> Viewport {
+    self.viewport
+  }
+
+  /// The display type of the box, anonymous boxes don't have a style node to look it up.
+  pub fn display(&self) -> DisplayStyle {
+    match self.box_type {
+      BoxType::BlockNode(node) | BoxType::InlineNode(node) => node.display(),
+      BoxType::AnonymousBlock(_) | BoxType::Column => DisplayStyle::Block,
+      BoxType::AnonymousTable(display) => display,
+      BoxType::Marker(_) | BoxType::GeneratedText(..) => DisplayStyle::Inline,
     }
   }
 
   fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
     match self.box_type {
-      BoxType::InlineNode(_) | BoxType::AnonymousBlock => self,
-      BoxType::BlockNode(_) => {
+      BoxType::InlineNode(_)
+      | BoxType::AnonymousBlock(_)
+      | BoxType::Marker(_)
+      | BoxType::GeneratedText(..) => self,
+      BoxType::BlockNode(_) | BoxType::AnonymousTable(_) | BoxType::Column => {
         // If we've just generated an anonymous block box, keep using it.
         // Otherwise, create a new one.
         match self.children.last() {
           Some(&LayoutBox {
-            box_type: BoxType::AnonymousBlock,
+            box_type: BoxType::AnonymousBlock(_),
             ..
           }) => {}
-          _ => self.children.push(LayoutBox::new(BoxType::AnonymousBlock)),
+          _ => {
+            let style = self.get_style_node_option();
+            let anonymous_block = LayoutBox::new(BoxType::AnonymousBlock(style), self.viewport);
+            self.children.push(anonymous_block)
+          }
         }
         self.children.last_mut().unwrap()
       }
@@ -160,8 +282,17 @@ impl<'a> LayoutBox<'a> {
   fn layout(&mut self, containing_block: Dimensions, original_containing_block: &SimpleDimensions) {
     match self.box_type {
       BoxType::BlockNode(_) => self.layout_block(containing_block, original_containing_block),
-      BoxType::InlineNode(_) => {}  // TODO
-      BoxType::AnonymousBlock => {} // TODO
+      BoxType::AnonymousTable(DisplayStyle::Table) => {
+        self.layout_anonymous_table(containing_block, original_containing_block)
+      }
+      BoxType::AnonymousBlock(_) => {
+        self.layout_inline_formatting_context(containing_block, original_containing_block)
+      }
+      // Inline-level boxes are laid out by the inline formatting context they are in, and
+      // markers by their list item.
+      BoxType::InlineNode(_) | BoxType::Marker(_) | BoxType::GeneratedText(..) => {}
+      BoxType::AnonymousTable(_) => {} // Laid out by the table.
+      BoxType::Column => {}            // Laid out by the multi-column container.
     }
   }
 
@@ -178,46 +309,105 @@ impl<'a> LayoutBox<'a> {
     self.calculate_block_position(containing_block, original_containing_block);
 
     // Recursively lay out the children of this box.
-    self.layout_block_children(original_containing_block);
+    self.layout_children(original_containing_block);
+    self.collapse_margins_with_children();
 
     // Parent height can depend on child height, so `calculate_height`
     // must be called *after* the children are laid out.
     self.calculate_block_height(original_containing_block);
   }
 
-  /// Calculate the width of a block-level non-replaced element in normal flow.
+  /// Calculate the width of a block-level element in normal flow.
   ///
   /// http://www.w3.org/TR/CSS2/visudet.html#blockwidth
+  /// http://www.w3.org/TR/CSS2/visudet.html#block-replaced-width
   ///
   /// Sets the horizontal margin/padding/border dimensions, and the `width`.
   fn calculate_block_width(
     &mut self,
     containing_block: Dimensions,
     original_containing_block: &SimpleDimensions,
+  ) {
+    // The width of a replaced element follows from its content, only the margins are resolved
+    // like for other blocks.
+    let replaced_width = self
+      .replaced_size(original_containing_block)
+      .map(|(width, _)| width);
+    self.resolve_block_width(containing_block, original_containing_block, replaced_width);
+
+    // If the tentative width violates `min-width` or `max-width`, the rules are applied again
+    // with the clamped width as the specified width.
+    //
+    // https://www.w3.org/TR/CSS2/visudet.html#min-max-widths
+    let d = self.dimensions;
+    let edges = d.border.left + d.border.right + d.padding.left + d.padding.right;
+    let width = d.content.width;
+    let reference = original_containing_block.width;
+    let zero = Value::Length(0.0, Unit::Px);
+    let margin = |name| {
+      self
+        .get_style_node()
+        .lookup(name, "margin", &zero)
+        .to_px(reference, self.viewport)
+    };
+    let available = reference - edges - margin("margin-left") - margin("margin-right");
+    let clamped = self.clamp_width(width, reference, edges, available);
+    if clamped != width {
+      self.resolve_block_width(containing_block, original_containing_block, Some(clamped));
+    }
+  }
+
+  /// Resolve the horizontal dimensions of a block in normal flow, using `used_width` instead
+  /// of the specified width if it is given.
+  fn resolve_block_width(
+    &mut self,
+    containing_block: Dimensions,
+    original_containing_block: &SimpleDimensions,
+    used_width: Option<f32>,
   ) {
     use Unit::Px;
     use Value::*;
+    let viewport = self.viewport;
 
     let style = self.get_style_node();
 
     let reference_containing_width = original_containing_block.width;
 
-    // `width` has initial value `auto`.
-    let auto = Value::Keyword("auto".to_string());
-    let mut width = style.value("width").unwrap_or(auto.clone());
-
     // margin, border, and padding have initial value 0.
     let zero = Value::Length(0.0, Unit::Px);
 
     let mut margin_left = style.lookup("margin-left", "margin", &zero);
     let mut margin_right = style.lookup("margin-right", "margin", &zero);
 
-    let border_left = style.lookup("border-left-width", "border-width", &zero);
-    let border_right = style.lookup("border-right-width", "border-width", &zero);
+    let border_left = style.border_width("left");
+    let border_right = style.border_width("right");
 
     let padding_left = style.lookup("padding-left", "padding", &zero);
     let padding_right = style.lookup("padding-right", "padding", &zero);
 
+    // `width` has initial value `auto`, and includes the padding and border with
+    // `box-sizing: border-box`.
+    let auto = Value::Keyword("auto".to_string());
+    let edges: f32 = [&border_left, &border_right, &padding_left, &padding_right]
+      .iter()
+      .map(|v| v.to_px(reference_containing_width, viewport))
+      .sum();
+    let margins = margin_left.to_px(reference_containing_width, viewport)
+      + margin_right.to_px(reference_containing_width, viewport);
+    let available = reference_containing_width - edges - margins;
+    let specified_width = used_width
+      .or_else(|| self.width_property("width", reference_containing_width, edges, available))
+      .or_else(|| {
+        self.ratio_width(
+          Some(original_containing_block.height),
+          reference_containing_width,
+        )
+      });
+    let mut width = match specified_width {
+      Some(width) => Length(width, Px),
+      None => auto.clone(),
+    };
+
     let total: f32 = [
       &margin_left,
       &margin_right,
@@ -228,7 +418,7 @@ impl<'a> LayoutBox<'a> {
       &width,
     ]
     .iter()
-    .map(|v| v.to_px(reference_containing_width))
+    .map(|v| v.to_px(reference_containing_width, viewport))
     .sum();
 
     // If width is not auto and the total is wider than the container, treat auto margins as 0.
@@ -246,11 +436,20 @@ impl<'a> LayoutBox<'a> {
     // and afterward all values should be absolute lengths in px.
     let underflow = original_containing_block.width - total;
 
+    // If the values are overconstrained, the margin at the end of the containing block is
+    // calculated: `margin-right`, or `margin-left` when it is right-to-left.
+    let rtl = original_containing_block.direction == Direction::Rtl;
+
     match (width == auto, margin_left == auto, margin_right == auto) {
-      // If the values are overconstrained, calculate margin_right.
+      (false, false, false) if rtl => {
+        margin_left = Length(
+          margin_left.to_px(reference_containing_width, viewport) + underflow,
+          Px,
+        );
+      }
       (false, false, false) => {
         margin_right = Length(
-          margin_right.to_px(reference_containing_width) + underflow,
+          margin_right.to_px(reference_containing_width, viewport) + underflow,
           Px,
         );
       }
@@ -275,11 +474,17 @@ impl<'a> LayoutBox<'a> {
         if underflow >= 0.0 {
           // Expand width to fill the underflow.
           width = Length(underflow, Px);
+        } else if rtl {
+          // Width can't be negative. Adjust the end margin instead.
+          width = Length(0.0, Px);
+          margin_left = Length(
+            margin_left.to_px(reference_containing_width, viewport) + underflow,
+            Px,
+          );
         } else {
-          // Width can't be negative. Adjust the right margin instead.
           width = Length(0.0, Px);
           margin_right = Length(
-            margin_right.to_px(reference_containing_width) + underflow,
+            margin_right.to_px(reference_containing_width, viewport) + underflow,
             Px,
           );
         }
@@ -293,16 +498,16 @@ impl<'a> LayoutBox<'a> {
     }
 
     let d = &mut self.dimensions;
-    d.content.width = width.to_px(reference_containing_width);
+    d.content.width = width.to_px(reference_containing_width, viewport);
 
-    d.padding.left = padding_left.to_px(reference_containing_width);
-    d.padding.right = padding_right.to_px(reference_containing_width);
+    d.padding.left = padding_left.to_px(reference_containing_width, viewport);
+    d.padding.right = padding_right.to_px(reference_containing_width, viewport);
 
-    d.border.left = border_left.to_px(reference_containing_width);
-    d.border.right = border_right.to_px(reference_containing_width);
+    d.border.left = border_left.to_px(reference_containing_width, viewport);
+    d.border.right = border_right.to_px(reference_containing_width, viewport);
 
-    d.margin.left = margin_left.to_px(reference_containing_width);
-    d.margin.right = margin_right.to_px(reference_containing_width);
+    d.margin.left = margin_left.to_px(reference_containing_width, viewport);
+    d.margin.right = margin_right.to_px(reference_containing_width, viewport);
   }
 
   /// Finish calculating the block's edge sizes, and position it within its containing block.
@@ -317,6 +522,7 @@ impl<'a> LayoutBox<'a> {
   ) {
     use Unit::Px;
     use Value::*;
+    let viewport = self.viewport;
     let style = self.get_style_node();
     let d = &mut self.dimensions;
 
@@ -328,24 +534,24 @@ impl<'a> LayoutBox<'a> {
     // If margin-top or margin-bottom is `auto`, the used value is zero.
     d.margin.top = style
       .lookup("margin-top", "margin", &zero)
-      .to_px(reference_containing_height);
+      .to_px(reference_containing_height, viewport);
     d.margin.bottom = style
       .lookup("margin-bottom", "margin", &zero)
-      .to_px(reference_containing_height);
+      .to_px(reference_containing_height, viewport);
 
     d.border.top = style
-      .lookup("border-top-width", "border-width", &zero)
-      .to_px(reference_containing_height);
+      .border_width("top")
+      .to_px(reference_containing_height, viewport);
     d.border.bottom = style
-      .lookup("border-bottom-width", "border-width", &zero)
-      .to_px(reference_containing_height);
+      .border_width("bottom")
+      .to_px(reference_containing_height, viewport);
 
     d.padding.top = style
       .lookup("padding-top", "padding", &zero)
-      .to_px(reference_containing_height);
+      .to_px(reference_containing_height, viewport);
     d.padding.bottom = style
       .lookup("padding-bottom", "padding", &zero)
-      .to_px(reference_containing_height);
+      .to_px(reference_containing_height, viewport);
 
     d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
 
@@ -357,51 +563,297 @@ impl<'a> LayoutBox<'a> {
       + d.padding.top;
   }
 
+  /// The inline base direction of the box, which anonymous boxes take from their containing
+  /// block.
+  fn direction(&self, containing_block: &SimpleDimensions) -> Direction {
+    match self.get_style_node_option() {
+      Some(style) => style.direction(),
+      None => containing_block.direction,
+    }
+  }
+
+  /// Lay out the children of this box in the formatting context it establishes.
+  fn layout_children(&mut self, original_containing_block: &SimpleDimensions) {
+    let display = self.display();
+    if display.is_flex_container() {
+      self.layout_flex_children(original_containing_block);
+    } else if display.is_grid_container() {
+      self.layout_grid_children(original_containing_block);
+    } else if display.is_table() {
+      self.layout_table_children(original_containing_block);
+    } else if self.is_multicol_container() {
+      self.layout_multicol_children(original_containing_block);
+    } else {
+      self.layout_block_children(original_containing_block);
+    }
+  }
+
+  /// Lay out the contents of a block whose edges and used width were already resolved by the
+  /// formatting context it participates in, e.g. a flex item.
+  ///
+  /// Places the content box at `(x, y)` and sets its `height`.
+  fn layout_block_contents(
+    &mut self,
+    x: f32,
+    y: f32,
+    width: f32,
+    original_containing_block: &SimpleDimensions,
+  ) {
+    let d = &mut self.dimensions;
+    d.content.x = x;
+    d.content.y = y;
+    d.content.width = width;
+
+    self.layout_children(original_containing_block);
+    self.calculate_block_height(original_containing_block);
+  }
+
+  /// Resolve the margin, border and padding on all sides, with `auto` margins treated as zero.
+  ///
+  /// Percentages refer to the width of the containing block, also for vertical edges.
+  fn calculate_edges(&mut self, reference_width: f32) {
+    let viewport = self.viewport;
+    let style = match self.get_style_node_option() {
+      Some(style) => style,
+      None => {
+        self.dimensions = Dimensions::new(self.dimensions.content);
+        return;
+      }
+    };
+    let zero = Value::Length(0.0, Unit::Px);
+    let edge = |name: &str, fallback_name: &str| {
+      style
+        .lookup(name, fallback_name, &zero)
+        .to_px(reference_width, viewport)
+    };
+
+    let d = &mut self.dimensions;
+    d.margin.left = edge("margin-left", "margin");
+    d.margin.right = edge("margin-right", "margin");
+    d.margin.top = edge("margin-top", "margin");
+    d.margin.bottom = edge("margin-bottom", "margin");
+
+    d.border.left = style.border_width("left").to_px(reference_width, viewport);
+    d.border.right = style.border_width("right").to_px(reference_width, viewport);
+    d.border.top = style.border_width("top").to_px(reference_width, viewport);
+    d.border.bottom = style
+      .border_width("bottom")
+      .to_px(reference_width, viewport);
+
+    d.padding.left = edge("padding-left", "padding");
+    d.padding.right = edge("padding-right", "padding");
+    d.padding.top = edge("padding-top", "padding");
+    d.padding.bottom = edge("padding-bottom", "padding");
+  }
+
   /// Lay out the block's children within its content area.
   ///
-  /// Sets `self.dimensions.height` to the total content height.
-  fn layout_block_children(&mut self, _: &SimpleDimensions) {
+  /// Sets `self.dimensions.height` to the total content height. Adjoining margins of siblings
+  /// collapse, the margins that adjoin the top and bottom of the content area are left in
+  /// `self.collapsed_margins` for `collapse_margins_with_children`.
+  fn layout_block_children(&mut self, original_containing_block: &SimpleDimensions) {
+    let direction = self.direction(original_containing_block);
     let d = &mut self.dimensions;
-    let original_container = SimpleDimensions::from_dimension(d.content.clone());
+    d.content.height = 0.0;
+    let original_container = SimpleDimensions::from_dimension(d.content, direction);
+
+    // The margins adjoining the top of the content area, until a child with content ends them.
+    let mut leading = None;
+    let mut margins = MarginStrut::default();
     for child in &mut self.children {
+      // Out-of-flow boxes are laid out once their containing block is known, remember their
+      // static position until then.
+      if child.position().is_out_of_flow() {
+        child.dimensions.content.x = d.content.x;
+        child.dimensions.content.y = d.content.y + d.content.height;
+        continue;
+      }
+      if child.is_outside_marker() {
+        continue;
+      }
+
       child.layout(*d, &original_container);
 
+      // Place the child below the previous one, separated by the collapsed margins between them.
+      let child_margins = child.collapsed_margins;
+      margins = margins.append(child_margins.top);
+      let y = d.content.y + d.content.height + margins.value();
+      let offset = y - child.dimensions.border_box().y;
+      if offset != 0.0 {
+        child.translate(0.0, offset);
+      }
+
+      if child_margins.through {
+        margins = margins.append(child_margins.bottom);
+        continue;
+      }
+      if leading.is_none() {
+        leading = Some(margins);
+      }
+
       // Increment the height so each child is laid out below the previous one.
-      d.content.height = d.content.height + child.dimensions.margin_box().height;
+      d.content.height += margins.value() + child.dimensions.border_box().height;
+      margins = child_margins.bottom;
     }
+    d.content.height += margins.value();
+
+    self.collapsed_margins = CollapsedMargins {
+      top: leading.unwrap_or(margins),
+      bottom: margins,
+      through: leading.is_none(),
+    };
+    self.position_outside_marker();
   }
 
-  /// Height of a block-level non-replaced element in normal flow with overflow visible.
+  /// Height of a block-level element in normal flow with overflow visible.
+  ///
+  /// https://www.w3.org/TR/CSS2/visudet.html#min-max-heights
   fn calculate_block_height(&mut self, original_container: &SimpleDimensions) {
-    // If the height is set to an explicit length, use that exact length.
-    // Otherwise, just keep the value set by `layout_block_children`.
-    if let Some(value) = self.get_style_node().value("height") {
-      self.dimensions.content.height = value.to_px(original_container.height);
+    let viewport = self.viewport;
+    let style = match self.get_style_node_option() {
+      Some(style) => style,
+      None => return,
+    };
+    if let Some((_, height)) = self.replaced_size(original_container) {
+      self.dimensions.content.height = height;
+      return;
     }
+    let ratio_height = self.ratio_height(self.dimensions.content.width, original_container.width);
+    let d = &mut self.dimensions;
+    let reference = original_container.height;
+    let edges = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+
+    // If the height is set to an explicit length, use that exact length, or follow it from the
+    // width with an aspect ratio. Otherwise, just keep the value set by `layout_block_children`.
+    let display = style.display();
+    let height = match (
+      style.size("height", reference, edges, viewport),
+      ratio_height,
+    ) {
+      // The height of a table or a cell is a minimum, it grows to fit its contents.
+      (Some(height), _) if display.is_table() || display == DisplayStyle::TableCell => {
+        d.content.height.max(height)
+      }
+      (Some(height), _) => height,
+      (None, Some(height)) if aspect_ratio::grows_to_fit_content(style) => {
+        d.content.height.max(height)
+      }
+      (None, Some(height)) => height,
+      (None, None) => d.content.height,
+    };
+    d.content.height = style.clamp_size("height", height, reference, edges, viewport);
   }
 }
 
-// Build the tree of LayoutBoxes, but don't perform any layout calculations yet.
-pub fn build_geometry_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
+// Build the tree of LayoutBoxes to be laid out in a viewport, but don't perform any layout
+// calculations yet.
+pub fn build_geometry_tree<'a>(
+  style_node: &'a StyledNode<'a>,
+  viewport: Viewport,
+) -> LayoutBox<'a> {
+  let display = style_node.display();
+  let mut state = ContentState::new();
+  state.update_counters(style_node, display);
+  build_layout_box(style_node, display, viewport, &mut state)
+}
+
+fn build_layout_box<'a>(
+  style_node: &'a StyledNode<'a>,
+  display: DisplayStyle,
+  viewport: Viewport,
+  state: &mut ContentState,
+) -> LayoutBox<'a> {
   // Create the root box.
-  let mut root = LayoutBox::new(match style_node.display() {
-    DisplayStyle::Block => BoxType::BlockNode(style_node),
-    DisplayStyle::Inline => BoxType::InlineNode(style_node),
+  let box_type = match display {
     DisplayStyle::None => panic!("Root node has display: none."),
-  });
+    display if display.outer() == Some(OuterDisplay::Inline) => BoxType::InlineNode(style_node),
+    _ => BoxType::BlockNode(style_node),
+  };
+  let mut root = LayoutBox::new(box_type, viewport);
+
+  // Create the descendant boxes. A pseudo-element starts with its generated text, which comes
+  // before the boxes of its own pseudo-elements.
+  state.enter_element();
+  if style_node.pseudo_element.is_some() {
+    if let Some(text) = state.generated_text(style_node) {
+      let text_box = LayoutBox::new(BoxType::GeneratedText(style_node, text), viewport);
+      root.get_inline_container().children.push(text_box);
+    }
+  }
+  build_child_boxes(&mut root, display, style_node, state);
+  state.leave_element();
+
+  let children = std::mem::take(&mut root.children);
+  root.children = generate_anonymous_table_boxes(display, children);
+  return root;
+}
+
+// Add the boxes of the children of `style_node` to `root`, which is the box of `style_node` or,
+// with `display: contents`, of one of its ancestors.
+fn build_child_boxes<'a>(
+  root: &mut LayoutBox<'a>,
+  display: DisplayStyle,
+  style_node: &'a StyledNode<'a>,
+  state: &mut ContentState,
+) {
+  let viewport = root.viewport;
+  let children = style_node
+    .before
+    .as_deref()
+    .into_iter()
+    .chain(&style_node.children)
+    .chain(style_node.after.as_deref());
+  for child in children {
+    // Pseudo-elements without any content don't generate boxes.
+    if child.pseudo_element.is_some() && !content::generates_box(child) {
+      continue;
+    }
+    let mut child_display = child.display();
+
+    // Children of a flex or grid container are flex or grid items, which are blockified.
+    // Whitespace between them is not rendered.
+    if display.is_flex_container() || display.is_grid_container() {
+      if child.is_whitespace() {
+        continue;
+      }
+      child_display = child_display.blockified();
+    }
+
+    // Whitespace between the parts of a table is not rendered either.
+    if (display.is_table() || display.is_table_row_group() || display == DisplayStyle::TableRow)
+      && child.is_whitespace()
+    {
+      continue;
+    }
+
+    if child_display != DisplayStyle::None {
+      state.update_counters(child, child_display);
+    }
 
-  // Create the descendant boxes.
-  for child in &style_node.children {
-    match child.display() {
-      DisplayStyle::Block => root.children.push(build_geometry_tree(child)),
-      DisplayStyle::Inline => root
+    match child_display {
+      // Skip nodes with `display: none;`, and replaced elements with `display: contents;`.
+      DisplayStyle::None => {}
+      DisplayStyle::Contents if child.image().is_some() => {}
+      // The children take the place of a box with `display: contents;`.
+      DisplayStyle::Contents => {
+        state.enter_element();
+        build_child_boxes(root, display, child, state);
+        state.leave_element();
+      }
+      child_display if child_display.outer() == Some(OuterDisplay::Inline) => root
         .get_inline_container()
         .children
-        .push(build_geometry_tree(child)),
-      DisplayStyle::None => {} // Skip nodes with `display: none;`
+        .push(build_layout_box(child, child_display, viewport, state)),
+      _ => {
+        let ordinal = state.counter("list-item");
+        let mut child_box = build_layout_box(child, child_display, viewport, state);
+        if child_display == DisplayStyle::ListItem {
+          child_box.add_list_marker(child, ordinal);
+        }
+        root.children.push(child_box);
+      }
     }
   }
-  return root;
 }
 
 pub fn layout_geometry_tree<'a>(
@@ -410,14 +862,44 @@ pub fn layout_geometry_tree<'a>(
 ) -> LayoutBox<'a> {
   let layout_start = std::time::Instant::now();
 
+  // Vertical writing modes are laid out with the axes of the viewport swapped.
+  let writing_mode = node.writing_mode;
+  let physical_viewport = containing_block.content;
+  if writing_mode.is_vertical() {
+    containing_block.content = containing_block.content.transposed();
+  }
+
   // The layout algorithm expects the container height to start at 0.
-  let original_container = SimpleDimensions::from_dimension(containing_block.content.clone());
+  // The initial containing block has the direction of the root element.
+  let original_container =
+    SimpleDimensions::from_dimension(containing_block.content, node.direction());
   containing_block.content.height = 0.0;
 
-  let mut root_box = build_geometry_tree(node);
+  // Viewport-percentage lengths are relative to the physical size of the viewport.
+  let viewport = Viewport {
+    width: physical_viewport.width,
+    height: physical_viewport.height,
+  };
+  let mut root_box = build_geometry_tree(node, viewport);
   root_box.layout(containing_block, &original_container);
   root_box.dimensions.content.height = original_container.height;
 
+  // Margins can't collapse through the viewport, the root box starts below its collapsed margin.
+  let offset = root_box.collapsed_margins.top.value() - root_box.dimensions.margin.top;
+  root_box.translate(0.0, offset);
+
+  // The initial containing block has the dimensions of the viewport.
+  let viewport = Rect {
+    height: original_container.height,
+    ..containing_block.content
+  };
+  let viewport = (viewport, original_container.direction);
+  root_box.layout_positioned_descendants(viewport, viewport);
+
+  if writing_mode.is_vertical() {
+    root_box.map_to_physical(writing_mode, physical_viewport);
+  }
+
   println!(
     "Layout step took {} microseconds",
     layout_start.elapsed().as_micros()
@@ -0,0 +1,524 @@
+//! Multi-column layout, where the content of a block container flows through columns.
+//!
+//! The children of a multi-column container are laid out in a single column of the used column
+//! width first. That column is then broken into anonymous column boxes, which are placed next to
+//! each other and hold the children that fell into them. Boxes are not split: columns only break
+//! between the children of the container. Children with `column-span: all` interrupt the columns
+//! and span the whole container.
+//!
+//! https://www.w3.org/TR/css-multicol-1/
+
+use super::intrinsic::IntrinsicSizes;
+use super::list::DEFAULT_FONT_SIZE;
+use super::margins::CollapsedMargins;
+use super::{BoxType, LayoutBox, Rect, SimpleDimensions};
+use crate::layout::css::{Unit, Value, Viewport};
+use crate::layout::style::{Direction, InnerDisplay, OuterDisplay, StyledNode};
+
+/// The used `column-rule-width` when it is `medium` or only the rule style is given.
+const MEDIUM_RULE_WIDTH: f32 = 3.0;
+
+impl<'a> LayoutBox<'a> {
+  /// Whether the box is a block container whose content flows through columns, because it sets
+  /// `column-count` or `column-width`.
+  ///
+  /// https://www.w3.org/TR/css-multicol-1/#multi-column-container
+  pub(super) fn is_multicol_container(&self) -> bool {
+    let style = match self.get_style_node_option() {
+      Some(style) => style,
+      None => return false,
+    };
+    let display = style.display();
+    let block_container = match display.inner() {
+      InnerDisplay::Flow => display.outer() == Some(OuterDisplay::Block),
+      InnerDisplay::FlowRoot => true,
+      _ => false,
+    };
+    let (count, width) = column_properties(style);
+    block_container && (count.is_some() || width.is_some())
+  }
+
+  /// Whether the box is an anonymous column box of a multi-column container.
+  pub fn is_column(&self) -> bool {
+    matches!(self.box_type, BoxType::Column)
+  }
+
+  /// Lay out the children of a multi-column container in columns, each run of children between
+  /// spanning children in balanced columns.
+  ///
+  /// Sets `self.dimensions.height` to the total content height. The container establishes a
+  /// new block formatting context, so the margins of its children don't collapse with its own.
+  pub(super) fn layout_multicol_children(&mut self, original_containing_block: &SimpleDimensions) {
+    let viewport = self.viewport;
+    let style = self.get_style_node();
+    let content = self.dimensions.content;
+    let gap = column_gap(style, content.width, viewport);
+    let (count, column_width) = used_columns(style, content.width, gap);
+    let direction = style.direction();
+
+    // The height columns are filled to when `column-fill: auto`, and that balanced columns
+    // may not exceed.
+    let d = self.dimensions;
+    let vertical_edges = d.padding.top + d.padding.bottom + d.border.top + d.border.bottom;
+    let max_height = style.size(
+      "height",
+      original_containing_block.height,
+      vertical_edges,
+      viewport,
+    );
+    let balance = style.keyword("column-fill").as_deref() != Some("auto") || max_height.is_none();
+
+    let reference = SimpleDimensions::from_dimension(content, direction);
+    let layout = ColumnLayout {
+      area: content,
+      count,
+      column_width,
+      gap,
+      direction,
+      max_height: if balance { None } else { max_height },
+      viewport: self.viewport,
+    };
+
+    let mut children = Vec::new();
+    let mut run = Vec::new();
+    let mut height = 0.0;
+    for child in std::mem::take(&mut self.children) {
+      if child.is_outside_marker() {
+        children.push(child);
+        continue;
+      }
+      if !child.is_column_spanner() {
+        run.push(child);
+        continue;
+      }
+
+      // A spanning child ends the columns before it, and is laid out across all of them.
+      let y = content.y + height;
+      height += layout_columns(
+        std::mem::take(&mut run),
+        &mut children,
+        &layout,
+        y,
+        &reference,
+      );
+      let mut flow = flow_container(
+        vec![child],
+        Rect {
+          y: content.y + height,
+          ..content
+        },
+        self.viewport,
+      );
+      flow.layout_block_children(&reference);
+      height += flow.dimensions.content.height;
+      children.append(&mut flow.children);
+    }
+    let y = content.y + height;
+    height += layout_columns(run, &mut children, &layout, y, &reference);
+
+    self.children = children;
+    self.dimensions.content.height = height;
+    self.collapsed_margins = CollapsedMargins::default();
+    self.position_outside_marker();
+  }
+
+  /// Whether the box spans all columns of its multi-column container with `column-span: all`.
+  fn is_column_spanner(&self) -> bool {
+    match self.get_style_node_option() {
+      Some(style) => {
+        style.keyword("column-span").as_deref() == Some("all")
+          && style.display().outer() == Some(OuterDisplay::Block)
+          && !style.position().is_out_of_flow()
+      }
+      None => false,
+    }
+  }
+
+  /// The intrinsic sizes of a multi-column container whose content has the intrinsic sizes
+  /// `column` when laid out in one column. With a `column-width`, the container can shrink to
+  /// one column of that width, and is as wide as its columns at that width otherwise.
+  ///
+  /// https://www.w3.org/TR/css-multicol-1/#intrinsic-sizing
+  pub(super) fn multicol_intrinsic_sizes(&self, column: IntrinsicSizes) -> IntrinsicSizes {
+    let style = self.get_style_node();
+    let gap = column_gap(style, 0.0, self.viewport);
+    let (count, width) = column_properties(style);
+    let count = count.unwrap_or(1) as f32;
+    let columns = |size: f32| size * count + gap * (count - 1.0);
+    match width {
+      Some(width) => IntrinsicSizes {
+        min_content: column.min_content.min(width),
+        max_content: columns(column.max_content.max(width)),
+      },
+      None => column.map(columns),
+    }
+  }
+
+  /// The rects of the rules painted between adjacent columns of a multi-column container, in
+  /// the middle of the gap between them.
+  ///
+  /// https://www.w3.org/TR/css-multicol-1/#column-gaps-and-rules
+  pub fn column_rule_rects(&self) -> Vec<Rect> {
+    let style = match self.get_style_node_option() {
+      Some(style) => style,
+      None => return Vec::new(),
+    };
+    let width = match column_rule_width(style) {
+      Some(width) => width,
+      None => return Vec::new(),
+    };
+
+    // Columns are next to each other along the lines, which run down the page in vertical
+    // writing modes.
+    let vertical = style.writing_mode.is_vertical();
+    let line_relative = |rect: Rect| {
+      if vertical {
+        Rect {
+          x: rect.y,
+          y: rect.x,
+          width: rect.height,
+          height: rect.width,
+        }
+      } else {
+        rect
+      }
+    };
+
+    let columns: Vec<Rect> = self
+      .children
+      .iter()
+      .filter(|child| child.is_column())
+      .map(|column| line_relative(column.dimensions.content))
+      .collect();
+    columns
+      .windows(2)
+      .filter(|pair| pair[0].y == pair[1].y)
+      .map(|pair| {
+        let (left, right) = if pair[0].x < pair[1].x {
+          (pair[0], pair[1])
+        } else {
+          (pair[1], pair[0])
+        };
+        let middle = (left.x + left.width + right.x) / 2.0;
+        line_relative(Rect {
+          x: middle - width / 2.0,
+          y: left.y,
+          width,
+          height: left.height.max(right.height),
+        })
+      })
+      .collect()
+  }
+}
+
+/// The geometry of the rows of columns in a multi-column container.
+struct ColumnLayout {
+  // The content area of the container, only its `x` and `width` are used.
+  area: Rect,
+  count: usize,
+  column_width: f32,
+  gap: f32,
+  direction: Direction,
+  // The height of the columns if they are filled in turn rather than balanced.
+  max_height: Option<f32>,
+  viewport: Viewport,
+}
+
+/// Lay out a run of children in a row of columns starting at `y`, and add the column boxes
+/// holding them to `boxes`. Returns the height of the row.
+fn layout_columns<'a>(
+  run: Vec<LayoutBox<'a>>,
+  boxes: &mut Vec<LayoutBox<'a>>,
+  layout: &ColumnLayout,
+  y: f32,
+  reference: &SimpleDimensions,
+) -> f32 {
+  if run.is_empty() {
+    return 0.0;
+  }
+  let area = Rect { y, ..layout.area };
+
+  // Lay out the children in a single column first, and find where they start and end.
+  let mut flow = flow_container(
+    run,
+    Rect {
+      width: layout.column_width,
+      ..area
+    },
+    layout.viewport,
+  );
+  flow.layout_block_children(reference);
+  let extents: Vec<(f32, f32)> = flow
+    .children
+    .iter()
+    .map(|child| {
+      let border_box = child.dimensions.border_box();
+      let top = border_box.y - area.y;
+      if child.position().is_out_of_flow() {
+        (top, top)
+      } else {
+        (top, top + border_box.height)
+      }
+    })
+    .collect();
+  let total_height = flow.dimensions.content.height;
+
+  let height = match layout.max_height {
+    Some(height) => height,
+    None => balanced_height(&extents, total_height, layout.count),
+  };
+  let starts = fill_columns(&extents, height);
+
+  // Move the children into their columns, the last column takes what is left.
+  let first_column = boxes.len();
+  let mut children = flow.children.into_iter();
+  let mut row_height: f32 = 0.0;
+  for (index, &start) in starts.iter().enumerate() {
+    let end = starts.get(index + 1).cloned().unwrap_or(extents.len());
+    let column_top = if index == 0 { 0.0 } else { extents[start].0 };
+    let column_bottom = if end == extents.len() {
+      total_height
+    } else {
+      extents[end - 1].1
+    };
+    let offset = index as f32 * (layout.column_width + layout.gap);
+    let x = match layout.direction {
+      Direction::Ltr => area.x + offset,
+      Direction::Rtl => area.x + area.width - layout.column_width - offset,
+    };
+
+    let mut column = LayoutBox::new(BoxType::Column, layout.viewport);
+    column.dimensions.content = Rect {
+      x,
+      y: area.y,
+      width: layout.column_width,
+      height,
+    };
+    for _ in start..end {
+      let mut child = children.next().unwrap();
+      child.translate(x - area.x, -column_top);
+      column.children.push(child);
+    }
+    row_height = row_height.max(column_bottom - column_top);
+    boxes.push(column);
+  }
+
+  // Columns filled in turn are as tall as the container, balanced ones as their content.
+  let row_height = layout.max_height.unwrap_or(row_height);
+  for column in &mut boxes[first_column..] {
+    column.dimensions.content.height = row_height;
+  }
+  row_height
+}
+
+/// An anonymous container to lay out children in normal flow in an area.
+fn flow_container<'a>(
+  children: Vec<LayoutBox<'a>>,
+  area: Rect,
+  viewport: Viewport,
+) -> LayoutBox<'a> {
+  let mut flow = LayoutBox::new(BoxType::Column, viewport);
+  flow.dimensions.content = Rect {
+    height: 0.0,
+    ..area
+  };
+  flow.children = children;
+  flow
+}
+
+/// Break children with the given extents into columns at most `height` tall, between children.
+/// A child that is taller than a column gets a column of its own. Returns the index of the first
+/// child in each column.
+fn fill_columns(extents: &[(f32, f32)], height: f32) -> Vec<usize> {
+  let mut starts = vec![0];
+  let mut column_top = 0.0;
+  for (index, &(top, bottom)) in extents.iter().enumerate().skip(1) {
+    let first = *starts.last().unwrap();
+    if bottom - column_top > height + 0.01 && top > extents[first].0 {
+      starts.push(index);
+      column_top = top;
+    }
+  }
+  starts
+}
+
+/// The smallest column height that fits the children into `count` columns, which balances the
+/// content over them.
+///
+/// https://www.w3.org/TR/css-multicol-1/#cf
+fn balanced_height(extents: &[(f32, f32)], total_height: f32, count: usize) -> f32 {
+  // Columns break between children, so their heights are the distance from the top of a child
+  // to the bottom of a later one.
+  let mut tops = vec![0.0];
+  tops.extend(extents.iter().skip(1).map(|&(top, _)| top));
+  let mut candidates: Vec<f32> = tops
+    .iter()
+    .flat_map(|top| extents.iter().map(move |&(_, bottom)| bottom - top))
+    .filter(|height| *height >= 0.0)
+    .collect();
+  candidates.push(total_height);
+  candidates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+  candidates
+    .into_iter()
+    .find(|height| fill_columns(extents, *height).len() <= count)
+    .unwrap_or(total_height)
+}
+
+/// The specified `column-count` and `column-width`, from the longhands or the `columns`
+/// shorthand. `auto` is `None`.
+fn column_properties(style: &StyledNode) -> (Option<usize>, Option<f32>) {
+  let shorthand = match style.value("columns") {
+    Some(Value::List(values)) => values,
+    Some(value) => vec![value],
+    None => Vec::new(),
+  };
+  let count = |value: &Value| match value {
+    Value::Number(count) if *count >= 1.0 => Some(*count as usize),
+    _ => None,
+  };
+  let width = |value: &Value| match value {
+    Value::Length(width, Unit::Px) => Some(width.max(1.0)),
+    _ => None,
+  };
+
+  let column_count = match style.value("column-count") {
+    Some(value) => count(&value),
+    None => shorthand.iter().find_map(count),
+  };
+  let column_width = match style.value("column-width") {
+    Some(value) => width(&value),
+    None => shorthand.iter().find_map(width),
+  };
+  (column_count, column_width)
+}
+
+/// The number and width of the columns in a container with content width `available`.
+///
+/// https://www.w3.org/TR/css-multicol-1/#pseudo-algorithm
+fn used_columns(style: &StyledNode, available: f32, gap: f32) -> (usize, f32) {
+  let count = match column_properties(style) {
+    (Some(count), None) => count,
+    (count, Some(width)) => {
+      let fitting = (((available + gap) / (width + gap)).floor() as usize).max(1);
+      count.map(|count| count.min(fitting)).unwrap_or(fitting)
+    }
+    (None, None) => 1,
+  };
+  let width = ((available - (count - 1) as f32 * gap) / count as f32).max(0.0);
+  (count, width)
+}
+
+/// The used `column-gap`, from the longhand or the `gap` shorthand. `normal` is 1em.
+fn column_gap(style: &StyledNode, available: f32, viewport: Viewport) -> f32 {
+  let gap = style
+    .value("column-gap")
+    .or_else(|| match style.value("gap") {
+      Some(Value::List(values)) => values.last().cloned(),
+      value => value,
+    });
+  match gap {
+    None | Some(Value::Keyword(_)) => DEFAULT_FONT_SIZE,
+    Some(gap) => gap.to_px(available, viewport),
+  }
+}
+
+/// The width of the column rule, or `None` if no rule is painted, from the longhands or the
+/// `column-rule` shorthand.
+fn column_rule_width(style: &StyledNode) -> Option<f32> {
+  let shorthand = match style.value("column-rule") {
+    Some(Value::List(values)) => values,
+    Some(value) => vec![value],
+    None => Vec::new(),
+  };
+  let rule_style = style.keyword("column-rule-style").or_else(|| {
+    shorthand.iter().find_map(|value| match value {
+      Value::Keyword(keyword) if keyword != "medium" => Some(keyword.clone()),
+      _ => None,
+    })
+  });
+  match rule_style.as_deref() {
+    None | Some("none") | Some("hidden") => return None,
+    _ => {}
+  }
+
+  let width = style.value("column-rule-width").or_else(|| {
+    shorthand
+      .iter()
+      .find(|value| matches!(value, Value::Length(..)))
+      .cloned()
+  });
+  match width {
+    Some(Value::Length(width, Unit::Px)) => Some(width),
+    _ => Some(MEDIUM_RULE_WIDTH),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, border_box, div, layout};
+
+  fn items(count: usize) -> Vec<crate::layout::dom::Node> {
+    (0..count).map(|_| div("item", vec![])).collect()
+  }
+
+  #[test]
+  fn children_are_balanced_over_the_columns() {
+    let document = body(vec![div("columns", items(4))]);
+    let css = ".columns { column-count: 2; column-gap: 10px; width: 410px; } \
+               .item { height: 50px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (0.0, 0.0, 200.0, 100.0));
+      assert_eq!(border_box(root, &[0, 1]), (210.0, 0.0, 200.0, 100.0));
+      assert_eq!(border_box(root, &[0, 0, 1]), (0.0, 50.0, 200.0, 50.0));
+      assert_eq!(border_box(root, &[0, 1, 0]), (210.0, 0.0, 200.0, 50.0));
+      assert_eq!(border_box(root, &[0]).3, 100.0);
+    });
+  }
+
+  #[test]
+  fn columns_are_filled_in_turn_with_column_fill_auto() {
+    let document = body(vec![div("columns", items(3))]);
+    let css = ".columns { column-count: 2; column-gap: 10px; width: 410px; height: 120px; \
+                 column-fill: auto; } \
+               .item { height: 50px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0, 1]), (0.0, 50.0, 200.0, 50.0));
+      assert_eq!(border_box(root, &[0, 1, 0]), (210.0, 0.0, 200.0, 50.0));
+      assert_eq!(border_box(root, &[0, 1]).3, 120.0);
+    });
+  }
+
+  #[test]
+  fn spanners_interrupt_the_columns() {
+    let mut children = vec![div("item", vec![]), div("spanner", vec![])];
+    children.extend(items(2));
+    let document = body(vec![div("columns", children)]);
+    let css = ".columns { column-count: 2; column-gap: 10px; width: 410px; } \
+               .item { height: 50px; } \
+               .spanner { column-span: all; height: 30px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (0.0, 0.0, 200.0, 50.0));
+      assert_eq!(border_box(root, &[0, 1]), (0.0, 50.0, 410.0, 30.0));
+      assert_eq!(border_box(root, &[0, 2, 0]), (0.0, 80.0, 200.0, 50.0));
+      assert_eq!(border_box(root, &[0, 3, 0]), (210.0, 80.0, 200.0, 50.0));
+      assert_eq!(border_box(root, &[0]).3, 130.0);
+    });
+  }
+
+  #[test]
+  fn rules_are_drawn_between_columns() {
+    let document = body(vec![div("columns", items(2))]);
+    let css = ".columns { column-count: 2; column-gap: 10px; width: 410px; \
+                 column-rule: 2px solid black; } \
+               .item { height: 50px; }";
+    layout(&document, css, |root| {
+      let rules = root.children[0].column_rule_rects();
+      assert_eq!(rules.len(), 1);
+      assert_eq!(
+        (rules[0].x, rules[0].width, rules[0].height),
+        (204.0, 2.0, 50.0)
+      );
+    });
+  }
+}
@@ -0,0 +1,260 @@
+//! Overflow: clipping the content of a box to its padding box, and scroll containers.
+//!
+//! Scrolling doesn't change the layout, the scroll offsets are applied when the display list is
+//! built. The root box scrolls the viewport.
+//!
+//! https://www.w3.org/TR/css-overflow-3/
+
+use super::{LayoutBox, Rect, RoundedRect};
+use crate::layout::style::{OverflowStyle, WritingMode};
+use std::collections::HashMap;
+
+/// Identifies a box by the indices of the children on the path to it from the root box, which
+/// stays the same when the same document is laid out again. The root box has an empty path.
+pub type ScrollId = Vec<usize>;
+
+/// The scroll offsets of the scroll containers in a document, which the viewer keeps across
+/// layouts.
+pub type ScrollOffsets = HashMap<ScrollId, (f32, f32)>;
+
+/// The extent of a clip rect along an axis that doesn't clip.
+const UNBOUNDED: f32 = 1.0e9;
+
+impl<'a> LayoutBox<'a> {
+  /// The used `overflow-x` and `overflow-y`, anonymous boxes never clip.
+  pub fn overflow(&self) -> (OverflowStyle, OverflowStyle) {
+    match self.get_style_node_option() {
+      Some(node) => node.overflow(),
+      None => (OverflowStyle::Visible, OverflowStyle::Visible),
+    }
+  }
+
+  /// Whether the box is a scroll container, which clips its content and lets it scroll.
+  pub fn is_scroll_container(&self) -> bool {
+    let (x, y) = self.overflow();
+    x.is_scrollable() || y.is_scrollable()
+  }
+
+  /// The rect the descendants of the box are clipped to, or `None` if the box doesn't clip.
+  /// It is unbounded along an axis with visible overflow.
+  ///
+  /// https://www.w3.org/TR/css-overflow-3/#overflow-clip-edge
+  pub fn overflow_clip_rect(&self) -> Option<Rect> {
+    let (x, y) = self.overflow();
+    if !x.clips() && !y.clips() {
+      return None;
+    }
+
+    let mut clip = self.dimensions.padding_box();
+    if !x.clips() {
+      clip.x = -UNBOUNDED;
+      clip.width = 2.0 * UNBOUNDED;
+    }
+    if !y.clips() {
+      clip.y = -UNBOUNDED;
+      clip.height = 2.0 * UNBOUNDED;
+    }
+    Some(clip)
+  }
+
+  /// The rounded rect the descendants of the box are clipped to when it clips along both axes
+  /// and has rounded corners, the padding box with the inner curves of the border.
+  ///
+  /// https://www.w3.org/TR/css-backgrounds-3/#corner-clipping
+  pub fn overflow_clip_rounded_rect(&self) -> Option<RoundedRect> {
+    let (x, y) = self.overflow();
+    let clip = self.rounded_padding_box();
+    if x.clips() && y.clips() && clip.is_rounded() {
+      Some(clip)
+    } else {
+      None
+    }
+  }
+
+  /// The scrollable overflow area of the box: its padding box, together with the border boxes
+  /// of its descendants and whatever overflows them unless they clip it.
+  ///
+  /// https://www.w3.org/TR/css-overflow-3/#scrollable
+  pub fn scrollable_overflow(&self) -> Rect {
+    self
+      .children
+      .iter()
+      .filter_map(LayoutBox::overflow_extent)
+      .fold(self.dimensions.padding_box(), Rect::union)
+  }
+
+  /// The area covered by the border box and the visible overflow of a box, if it has any.
+  fn overflow_extent(&self) -> Option<Rect> {
+    if self.is_truncated() {
+      return None;
+    }
+    // Boxes that aren't laid out yet, like text, don't have a size.
+    let border_box = self.dimensions.border_box();
+    let own = if border_box.width > 0.0 || border_box.height > 0.0 {
+      Some(border_box)
+    } else {
+      None
+    };
+
+    let descendants = self
+      .children
+      .iter()
+      .filter_map(LayoutBox::overflow_extent)
+      .reduce(Rect::union);
+    let descendants = match (descendants, self.overflow_clip_rect()) {
+      (Some(area), Some(clip)) => Some(area.intersection(clip)),
+      (area, _) => area,
+    };
+
+    match (own, descendants) {
+      (Some(own), Some(descendants)) => Some(own.union(descendants)),
+      (own, descendants) => own.or(descendants),
+    }
+  }
+
+  /// The largest scroll offset along each axis, which scrolls the end of the scrollable overflow
+  /// area into view.
+  pub fn max_scroll_offset(&self) -> (f32, f32) {
+    let area = self.scrollable_overflow();
+    let padding_box = self.dimensions.padding_box();
+    (
+      (area.x + area.width - padding_box.x - padding_box.width).max(0.0),
+      (area.y + area.height - padding_box.y - padding_box.height).max(0.0),
+    )
+  }
+
+  /// The smallest scroll offset along each axis. Blocks stack from the right in `vertical-rl`,
+  /// so content overflows to the left, which negative offsets scroll into view.
+  pub fn min_scroll_offset(&self) -> (f32, f32) {
+    let right_to_left = self
+      .get_style_node_option()
+      .map(|style| style.writing_mode == WritingMode::VerticalRl)
+      .unwrap_or(false);
+    if !right_to_left {
+      return (0.0, 0.0);
+    }
+    let area = self.scrollable_overflow();
+    ((area.x - self.dimensions.padding_box().x).min(0.0), 0.0)
+  }
+
+  /// Whether the user can scroll the box along each axis. The root box scrolls the viewport,
+  /// which the user can scroll unless the root clips its overflow.
+  fn user_scrollable_axes(&self, is_viewport: bool) -> (bool, bool) {
+    let (x, y) = self.overflow();
+    if is_viewport {
+      (
+        !x.clips() || x.is_user_scrollable(),
+        !y.clips() || y.is_user_scrollable(),
+      )
+    } else {
+      (x.is_user_scrollable(), y.is_user_scrollable())
+    }
+  }
+
+  /// Scroll the innermost scroll container at a point in the viewport by `(dx, dy)`. What it
+  /// can't scroll is passed on to the scroll containers around it, up to the viewport. Scroll
+  /// containers with `overflow: hidden` along an axis are passed over along it.
+  ///
+  /// Must be called on the root box. Returns whether any scroll offset changed.
+  pub fn scroll_by(
+    &self,
+    offsets: &mut ScrollOffsets,
+    position: (f32, f32),
+    delta: (f32, f32),
+  ) -> bool {
+    let root_offset = offsets.get(&Vec::new()).cloned().unwrap_or_default();
+    let mut chain = vec![(Vec::new(), self)];
+    self.scroll_containers_at(
+      (position.0 + root_offset.0, position.1 + root_offset.1),
+      offsets,
+      &mut Vec::new(),
+      &mut chain,
+    );
+
+    let (mut dx, mut dy) = delta;
+    let mut changed = false;
+    for (id, container) in chain.into_iter().rev() {
+      let (scrolls_x, scrolls_y) = container.user_scrollable_axes(id.is_empty());
+      let (min_x, min_y) = container.min_scroll_offset();
+      let (max_x, max_y) = container.max_scroll_offset();
+      let offset = offsets.entry(id).or_default();
+      let x = if scrolls_x {
+        (offset.0 + dx).min(max_x).max(min_x)
+      } else {
+        offset.0
+      };
+      let y = if scrolls_y {
+        (offset.1 + dy).min(max_y).max(min_y)
+      } else {
+        offset.1
+      };
+      dx -= x - offset.0;
+      dy -= y - offset.1;
+      changed |= x != offset.0 || y != offset.1;
+      *offset = (x, y);
+    }
+    changed
+  }
+
+  /// Collect the scroll containers among the descendants that contain a point in the
+  /// coordinates of this box's content, outermost first. Those that only scroll by script are
+  /// collected too, as their scroll offset moves the content under the point.
+  fn scroll_containers_at<'b>(
+    &'b self,
+    (x, y): (f32, f32),
+    offsets: &ScrollOffsets,
+    path: &mut ScrollId,
+    chain: &mut Vec<(ScrollId, &'b LayoutBox<'a>)>,
+  ) {
+    for (index, child) in self.children.iter().enumerate() {
+      path.push(index);
+      let mut position = (x, y);
+      if child.is_scroll_container() {
+        // Content outside of the scroll container is clipped, so it can't be hit.
+        if !child.dimensions.padding_box().contains(x, y) {
+          path.pop();
+          continue;
+        }
+        let offset = offsets.get(path).cloned().unwrap_or_default();
+        position = (x + offset.0, y + offset.1);
+        chain.push((path.clone(), child));
+      }
+      child.scroll_containers_at(position, offsets, path, chain);
+      path.pop();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, div, layout};
+  use super::ScrollOffsets;
+
+  #[test]
+  fn the_user_scrolls_auto_and_scroll_containers() {
+    let document = body(vec![div("scroller", vec![div("tall", vec![])])]);
+    let css = ".scroller { height: 100px; overflow: auto; } .tall { height: 300px; }";
+    layout(&document, css, |root| {
+      let mut offsets = ScrollOffsets::new();
+      assert!(root.scroll_by(&mut offsets, (10.0, 10.0), (0.0, 50.0)));
+      assert_eq!(offsets.get(&vec![0]), Some(&(0.0, 50.0)));
+    });
+  }
+
+  #[test]
+  fn the_user_scrolls_past_hidden_overflow() {
+    let document = body(vec![
+      div("scroller", vec![div("tall", vec![])]),
+      div("tall", vec![]),
+      div("tall", vec![]),
+    ]);
+    let css = ".scroller { height: 100px; overflow-y: hidden; } .tall { height: 300px; }";
+    layout(&document, css, |root| {
+      let mut offsets = ScrollOffsets::new();
+      assert!(root.scroll_by(&mut offsets, (10.0, 10.0), (0.0, 50.0)));
+      // The scroller only scrolls by script, so the viewport scrolls instead.
+      assert_eq!(offsets.get(&vec![0]), Some(&(0.0, 0.0)));
+      assert_eq!(offsets.get(&Vec::new()), Some(&(0.0, 50.0)));
+    });
+  }
+}
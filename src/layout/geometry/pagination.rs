@@ -0,0 +1,460 @@
+//! Paged media: a document laid out across pages of the size and margins of its `@page` rules.
+//!
+//! The document is laid out once in the page area, as one column as tall as its content, which
+//! is then fragmented into slices that fit the page area. Pages break between the block-level
+//! boxes of a block container or the rows of a table, and between the lines of an inline
+//! formatting context. Forced breaks come first, then the last break on the page that no
+//! `break-*`, `orphans` or `widows` avoids. The content after a break starts at the top of the
+//! next page, without the margins that adjoined an unforced break. Content that can't be broken,
+//! like boxes side by side, scroll containers and atomic inlines, is sliced where a page ends
+//! when it is taller than the page area.
+//!
+//! Only `@page` rules without a page selector apply, so every page has the same size and
+//! margins.
+//!
+//! https://www.w3.org/TR/css-page-3/
+//! https://www.w3.org/TR/css-break-3/
+
+use super::{layout_geometry_tree, BoxType, Dimensions, EdgeSizes, LayoutBox, Rect};
+use crate::layout::css::{Stylesheet, Unit, Value, Viewport};
+use crate::layout::style::{box_shorthand_side, Direction, StyledNode};
+use std::collections::HashMap;
+
+/// The size of pages when `@page` doesn't set one, A4 at 96px to the inch.
+const DEFAULT_PAGE_SIZE: (f32, f32) = (793.7, 1122.5);
+
+/// The number of lines that `orphans` and `widows` keep together when they aren't set.
+const DEFAULT_ORPHANS_AND_WIDOWS: usize = 2;
+
+/// The pages a document is laid out on: their size and margins, from the `@page` rules of its
+/// stylesheet.
+#[derive(Debug, Copy, Clone)]
+pub struct PageContext {
+  pub size: (f32, f32),
+  margin: EdgeSizes,
+}
+
+impl PageContext {
+  /// The pages of a stylesheet, with later rules overriding earlier ones. Page margins default
+  /// to zero, and percentages refer to the width of the page for the left and right margins, and
+  /// its height for the top and bottom ones.
+  ///
+  /// https://www.w3.org/TR/css-page-3/#page-size-prop
+  /// https://www.w3.org/TR/css-page-3/#margin-dimension
+  pub fn new(stylesheet: &Stylesheet) -> Self {
+    let mut declarations = HashMap::new();
+    for rule in stylesheet
+      .page_rules
+      .iter()
+      .filter(|rule| rule.selector.is_empty())
+    {
+      for declaration in &rule.declarations {
+        declarations.insert(declaration.name.as_str(), declaration.value.clone());
+      }
+    }
+
+    let size = declarations
+      .get("size")
+      .and_then(page_size)
+      .unwrap_or(DEFAULT_PAGE_SIZE);
+    let viewport = Viewport {
+      width: size.0,
+      height: size.1,
+    };
+    let side = |name: &str, reference: f32| {
+      declarations
+        .get(name)
+        .cloned()
+        .or_else(|| {
+          let margin = declarations.get("margin")?.clone();
+          Some(box_shorthand_side(name, margin))
+        })
+        .map_or(0.0, |value| value.to_px(reference, viewport))
+    };
+    let margin = EdgeSizes {
+      left: side("margin-left", size.0),
+      right: side("margin-right", size.0),
+      top: side("margin-top", size.1),
+      bottom: side("margin-bottom", size.1),
+    };
+    PageContext { size, margin }
+  }
+
+  /// The page area, the part of a page inside its margins that the content is laid out in.
+  pub fn area(&self) -> Rect {
+    Rect {
+      x: self.margin.left,
+      y: self.margin.top,
+      width: (self.size.0 - self.margin.left - self.margin.right).max(0.0),
+      height: (self.size.1 - self.margin.top - self.margin.bottom).max(0.0),
+    }
+  }
+}
+
+/// A page of a paged layout, which shows a slice of the content in its page area.
+#[derive(Debug, Copy, Clone)]
+pub struct Page {
+  pub area: Rect,
+  // Where the slice of the content starts and ends along the block axis of the layout. Blank
+  // pages, inserted for a break to a left or right page, have an empty slice.
+  pub start: f32,
+  pub end: f32,
+}
+
+impl Page {
+  /// The part of the page that shows the slice of the content: the page area, cut off after the
+  /// height of the slice.
+  pub fn content_rect(&self) -> Rect {
+    Rect {
+      height: self.end - self.start,
+      ..self.area
+    }
+  }
+}
+
+/// A forced page break, and the side of the spread that the next page has to be on.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ForcedBreak {
+  Page,
+  Left,
+  Right,
+}
+
+/// A place where the content can break between pages, at the start of the content after it.
+struct BreakPoint {
+  position: f32,
+  forced: Option<ForcedBreak>,
+  avoided: bool,
+}
+
+/// Lay out a document for paged media, with the page area as the initial containing block, and
+/// fragment it into pages.
+pub fn layout_paged_geometry_tree<'a>(
+  node: &'a StyledNode<'a>,
+  context: &PageContext,
+) -> (LayoutBox<'a>, Vec<Page>) {
+  let area = context.area();
+  let root_box = layout_geometry_tree(
+    node,
+    Dimensions::new(Rect {
+      x: 0.0,
+      y: 0.0,
+      ..area
+    }),
+  );
+  let pages = root_box.paginate(area);
+  (root_box, pages)
+}
+
+impl<'a> LayoutBox<'a> {
+  /// Fragment the laid out content of the root box into pages with a page area. Every page ends
+  /// at its first forced break, or else at the last break that fits and isn't avoided, or at the
+  /// last one that fits, or where the page area ends. The first page is a right page in
+  /// left-to-right documents, and a left page otherwise.
+  ///
+  /// https://www.w3.org/TR/css-break-3/#breaking-rules
+  fn paginate(&self, area: Rect) -> Vec<Page> {
+    let mut break_points = Vec::new();
+    self.collect_break_points(false, &mut break_points);
+    break_points.sort_by(|a, b| a.position.total_cmp(&b.position));
+
+    let overflow = self.scrollable_overflow();
+    let content_end = overflow.y + overflow.height;
+    let first_page_is_right = self
+      .get_style_node_option()
+      .is_none_or(|style| style.direction() == Direction::Ltr);
+
+    let mut pages = Vec::new();
+    let mut start = 0.0;
+    loop {
+      let limit = start + area.height;
+      let fitting: Vec<&BreakPoint> = break_points
+        .iter()
+        .filter(|point| point.position > start && point.position <= limit)
+        .collect();
+      let (end, forced) = match fitting.iter().find(|point| point.forced.is_some()) {
+        Some(point) => (point.position, point.forced),
+        None if content_end <= limit || area.height <= 0.0 => {
+          pages.push(Page {
+            area,
+            start,
+            end: content_end.max(start),
+          });
+          return pages;
+        }
+        None => {
+          let end = fitting
+            .iter()
+            .rev()
+            .find(|point| !point.avoided)
+            .or(fitting.last())
+            .map_or(limit, |point| point.position);
+          (end, None)
+        }
+      };
+      pages.push(Page { area, start, end });
+
+      // A break to a left or right page leaves a blank page when the next one is on the other
+      // side.
+      let next_is_right = (pages.len() % 2 == 0) == first_page_is_right;
+      let blank = match forced {
+        Some(ForcedBreak::Left) => next_is_right,
+        Some(ForcedBreak::Right) => !next_is_right,
+        _ => false,
+      };
+      if blank {
+        pages.push(Page {
+          area,
+          start: end,
+          end,
+        });
+      }
+      start = end;
+    }
+  }
+
+  /// Collect the break points inside the box: between its children when they are stacked in the
+  /// block axis, and between its lines. Breaks are avoided inside boxes with `break-inside:
+  /// avoid`.
+  fn collect_break_points(&self, avoided: bool, break_points: &mut Vec<BreakPoint>) {
+    let avoided = avoided || self.avoids_break("break-inside", "page-break-inside");
+    if self.overflow_clip_rect().is_some() || self.is_multicol_container() {
+      return;
+    }
+    if let BoxType::AnonymousBlock(style) = self.box_type {
+      self.collect_line_break_points(style, avoided, break_points);
+      return;
+    }
+
+    let children: Vec<&LayoutBox> = self.in_flow_children().collect();
+    let stacked = children.windows(2).all(|pair| {
+      pair[1].dimensions.border_box().y
+        >= pair[0].dimensions.border_box().y + pair[0].dimensions.border_box().height
+    });
+    if !stacked {
+      return;
+    }
+    for pair in children.windows(2) {
+      let (previous, next) = (pair[0], pair[1]);
+      // Margins are kept after a forced break, and truncated after an unforced one.
+      let forced = previous.forced_break_after().or(next.forced_break_before());
+      let position = match forced {
+        Some(_) => next.dimensions.margin_box().y,
+        None => next.dimensions.border_box().y,
+      };
+      break_points.push(BreakPoint {
+        position,
+        forced,
+        avoided: avoided
+          || previous.avoids_break("break-after", "page-break-after")
+          || next.avoids_break("break-before", "page-break-before"),
+      });
+    }
+    for child in children {
+      child.collect_break_points(avoided, break_points);
+    }
+  }
+
+  /// Collect the break points between the lines of an inline formatting context, from the
+  /// block container it was generated in. Breaks that leave fewer lines than `orphans` before
+  /// them or `widows` after them are avoided.
+  ///
+  /// https://www.w3.org/TR/css-break-3/#widows-orphans
+  fn collect_line_break_points(
+    &self,
+    style: Option<&StyledNode>,
+    avoided: bool,
+    break_points: &mut Vec<BreakPoint>,
+  ) {
+    // Lines start where the next box is below all boxes of the line before it.
+    let mut lines: Vec<(f32, f32)> = Vec::new();
+    for child in self.in_flow_children() {
+      let rect = child.dimensions.margin_box();
+      if rect.height <= 0.0 {
+        continue;
+      }
+      match lines.last_mut() {
+        Some((_, bottom)) if rect.y < *bottom => *bottom = bottom.max(rect.y + rect.height),
+        _ => lines.push((rect.y, rect.y + rect.height)),
+      }
+    }
+
+    let lines_kept = |name: &str| match style.and_then(|style| style.value(name)) {
+      Some(Value::Number(lines)) if lines >= 1.0 => lines as usize,
+      _ => DEFAULT_ORPHANS_AND_WIDOWS,
+    };
+    let (orphans, widows) = (lines_kept("orphans"), lines_kept("widows"));
+    for (index, (top, _)) in lines.iter().enumerate().skip(1) {
+      break_points.push(BreakPoint {
+        position: *top,
+        forced: None,
+        avoided: avoided || index < orphans || lines.len() - index < widows,
+      });
+    }
+  }
+
+  /// The children that take part in the flow of the box, which leaves out positioned boxes and
+  /// the ones hidden by truncation.
+  fn in_flow_children(&self) -> impl Iterator<Item = &LayoutBox<'a>> {
+    self
+      .children
+      .iter()
+      .filter(|child| !child.position().is_out_of_flow() && !child.is_truncated())
+  }
+
+  /// The forced break before the box, from `break-before` on the box or the first of its
+  /// children, which it propagates to.
+  ///
+  /// https://www.w3.org/TR/css-break-3/#break-propagation
+  fn forced_break_before(&self) -> Option<ForcedBreak> {
+    self
+      .forced_break("break-before", "page-break-before")
+      .or_else(|| self.in_flow_children().next()?.forced_break_before())
+  }
+
+  /// The forced break after the box, from `break-after` on the box or the last of its children.
+  fn forced_break_after(&self) -> Option<ForcedBreak> {
+    self
+      .forced_break("break-after", "page-break-after")
+      .or_else(|| self.in_flow_children().last()?.forced_break_after())
+  }
+
+  /// The forced page break that a `break-before` or `break-after` property, or its legacy
+  /// `page-break-*` alias, sets on the box.
+  ///
+  /// https://www.w3.org/TR/css-break-3/#break-between
+  fn forced_break(&self, name: &str, legacy_name: &str) -> Option<ForcedBreak> {
+    let style = self.get_style_node_option()?;
+    let keyword = style.keyword(name).or_else(|| style.keyword(legacy_name))?;
+    // Recto and verso pages are right and left pages in left-to-right documents.
+    let rtl = style.direction() == Direction::Rtl;
+    match keyword.as_str() {
+      "page" | "always" => Some(ForcedBreak::Page),
+      "left" => Some(ForcedBreak::Left),
+      "right" => Some(ForcedBreak::Right),
+      "recto" if rtl => Some(ForcedBreak::Left),
+      "recto" => Some(ForcedBreak::Right),
+      "verso" if rtl => Some(ForcedBreak::Right),
+      "verso" => Some(ForcedBreak::Left),
+      _ => None,
+    }
+  }
+
+  /// Whether a `break-*` property, or its legacy `page-break-*` alias, avoids page breaks.
+  fn avoids_break(&self, name: &str, legacy_name: &str) -> bool {
+    let style = match self.get_style_node_option() {
+      Some(style) => style,
+      None => return false,
+    };
+    matches!(
+      style
+        .keyword(name)
+        .or_else(|| style.keyword(legacy_name))
+        .as_deref(),
+      Some("avoid") | Some("avoid-page")
+    )
+  }
+}
+
+/// The size of a page from the `size` property: one or two lengths, or a page size like `A4`
+/// and an orientation, either of which can be left out.
+///
+/// https://www.w3.org/TR/css-page-3/#page-size-prop
+fn page_size(value: &Value) -> Option<(f32, f32)> {
+  let values = match value {
+    Value::List(values) => values.clone(),
+    value => vec![value.clone()],
+  };
+  match values.as_slice() {
+    [Value::Length(width, Unit::Px)] => return Some((*width, *width)),
+    [Value::Length(width, Unit::Px), Value::Length(height, Unit::Px)] => {
+      return Some((*width, *height))
+    }
+    _ => {}
+  }
+
+  let mut size = None;
+  let mut landscape = None;
+  for value in values {
+    let keyword = match value {
+      Value::Keyword(keyword) => keyword.to_ascii_lowercase(),
+      _ => return None,
+    };
+    match keyword.as_str() {
+      "auto" => {}
+      "portrait" => landscape = Some(false),
+      "landscape" => landscape = Some(true),
+      name => size = Some(named_page_size(name)?),
+    }
+  }
+  let (width, height) = size.unwrap_or(DEFAULT_PAGE_SIZE);
+  Some(match landscape {
+    Some(true) => (width.max(height), width.min(height)),
+    Some(false) => (width.min(height), width.max(height)),
+    None => (width, height),
+  })
+}
+
+/// The portrait size of a named page size, in px.
+///
+/// https://www.w3.org/TR/css-page-3/#typedef-page-size-page-size
+fn named_page_size(name: &str) -> Option<(f32, f32)> {
+  let (width, height, px_per_unit) = match name {
+    "a5" => (148.0, 210.0, 96.0 / 25.4),
+    "a4" => (210.0, 297.0, 96.0 / 25.4),
+    "a3" => (297.0, 420.0, 96.0 / 25.4),
+    "b5" => (176.0, 250.0, 96.0 / 25.4),
+    "b4" => (250.0, 353.0, 96.0 / 25.4),
+    "jis-b5" => (182.0, 257.0, 96.0 / 25.4),
+    "jis-b4" => (257.0, 364.0, 96.0 / 25.4),
+    "letter" => (8.5, 11.0, 96.0),
+    "legal" => (8.5, 14.0, 96.0),
+    "ledger" => (11.0, 17.0, 96.0),
+    _ => return None,
+  };
+  Some((width * px_per_unit, height * px_per_unit))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, div, pages};
+
+  const PAGE: &str = "@page { size: 200px 100px; } .block { height: 40px; }";
+
+  fn blocks(count: usize) -> Vec<crate::layout::dom::Node> {
+    (0..count).map(|_| div("block", vec![])).collect()
+  }
+
+  #[test]
+  fn pages_break_between_blocks_that_fit() {
+    let document = body(blocks(3));
+    assert_eq!(pages(&document, PAGE), vec![(0.0, 80.0), (80.0, 120.0)]);
+  }
+
+  #[test]
+  fn forced_breaks_start_a_new_page() {
+    let document = body(vec![div("block", vec![]), div("block next", vec![])]);
+    let css = format!("{} .next {{ break-before: page; }}", PAGE);
+    assert_eq!(pages(&document, &css), vec![(0.0, 40.0), (40.0, 100.0)]);
+  }
+
+  #[test]
+  fn breaks_inside_boxes_with_break_inside_avoid_are_avoided() {
+    let document = body(vec![div("block", vec![]), div("avoid", blocks(2))]);
+    let css = format!("{} .avoid {{ break-inside: avoid; }}", PAGE);
+    assert_eq!(pages(&document, &css), vec![(0.0, 40.0), (40.0, 120.0)]);
+  }
+
+  #[test]
+  fn line_breaks_keep_orphans_and_widows() {
+    let lines = (0..5).map(|_| div("line", vec![])).collect();
+    let document = body(vec![div("lines", lines)]);
+    let css = "@page { size: 200px 100px; } .lines { width: 100px; } \
+               .line { display: inline-block; width: 100px; height: 30px; }";
+    let first_page = |rules: &str| pages(&document, &format!("{} {}", css, rules))[0];
+    assert_eq!(first_page(""), (0.0, 90.0));
+    assert_eq!(first_page(".lines { widows: 3; }"), (0.0, 60.0));
+    // When every break that fits is avoided, the page ends at the last one.
+    assert_eq!(first_page(".lines { widows: 3; orphans: 3; }"), (0.0, 90.0));
+  }
+}
@@ -0,0 +1,558 @@
+//! Positioned layout: relative, absolute, fixed and sticky boxes.
+//!
+//! Positioning runs as a second pass over the geometry tree, once normal flow layout is done.
+//! Out-of-flow boxes are skipped by normal flow (which only records their static position), and
+//! are laid out here against the padding box of their nearest positioned ancestor.
+//!
+//! https://www.w3.org/TR/CSS2/visuren.html#positioning-scheme
+
+use super::aspect_ratio;
+use super::{LayoutBox, Rect, SimpleDimensions};
+use crate::layout::css::{Unit, Value};
+use crate::layout::style::{Direction, PositionStyle};
+
+impl<'a> LayoutBox<'a> {
+  /// The value of the `position` property, anonymous boxes are never positioned.
+  pub fn position(&self) -> PositionStyle {
+    match self.get_style_node_option() {
+      Some(node) => node.position(),
+      None => PositionStyle::Static,
+    }
+  }
+
+  /// Resolve one of the `top`, `right`, `bottom` or `left` offsets, `None` when it is `auto`.
+  fn offset(&self, name: &str, reference: f32) -> Option<f32> {
+    self.get_style_node().length(name, reference, self.viewport)
+  }
+
+  /// Move this box and all of its descendants.
+  pub(super) fn translate(&mut self, dx: f32, dy: f32) {
+    self.dimensions.content.x += dx;
+    self.dimensions.content.y += dy;
+
+    for child in &mut self.children {
+      child.translate(dx, dy);
+    }
+  }
+
+  /// Lay out all positioned descendants of this box.
+  ///
+  /// `containing_block` is the padding box of the nearest positioned ancestor and `viewport`
+  /// is the containing block of fixed boxes, each with the direction of its box. Sticky boxes
+  /// stay in flow, they are shifted when they are painted, see `sticky_offset`.
+  pub(super) fn layout_positioned_descendants(
+    &mut self,
+    containing_block: (Rect, Direction),
+    viewport: (Rect, Direction),
+  ) {
+    let parent_content = self.dimensions.content;
+
+    for child in &mut self.children {
+      let position = child.position();
+      match position {
+        PositionStyle::Static => {}
+        PositionStyle::Relative => child.apply_relative_offset(parent_content),
+        PositionStyle::Sticky => {}
+        PositionStyle::Absolute => child.layout_absolute(containing_block),
+        PositionStyle::Fixed => child.layout_absolute(viewport),
+      }
+      let child_containing_block = match position {
+        PositionStyle::Static => containing_block,
+        _ => (
+          child.dimensions.padding_box(),
+          child.get_style_node().direction(),
+        ),
+      };
+
+      child.layout_positioned_descendants(child_containing_block, viewport);
+    }
+  }
+
+  /// Shift a relatively positioned box from its position in normal flow.
+  ///
+  /// https://www.w3.org/TR/CSS2/visuren.html#relative-positioning
+  fn apply_relative_offset(&mut self, containing_block: Rect) {
+    let dx = match (
+      self.offset("left", containing_block.width),
+      self.offset("right", containing_block.width),
+    ) {
+      // If neither is auto, `left` wins.
+      (Some(left), _) => left,
+      (None, Some(right)) => -right,
+      (None, None) => 0.0,
+    };
+
+    let dy = match (
+      self.offset("top", containing_block.height),
+      self.offset("bottom", containing_block.height),
+    ) {
+      // If neither is auto, `top` wins.
+      (Some(top), _) => top,
+      (None, Some(bottom)) => -bottom,
+      (None, None) => 0.0,
+    };
+
+    self.translate(dx, dy);
+  }
+
+  /// How far a sticky box is shifted so that it stays within the scrollport of its nearest
+  /// scroll container inset by its offsets, without leaving its containing block. The scrollport
+  /// moves with the scroll offset, so this is resolved when the box is painted, in the physical
+  /// coordinates of the laid out box.
+  ///
+  /// https://www.w3.org/TR/css-position-3/#stickypos-insets
+  pub fn sticky_offset(&self, scrollport: Rect, containing_block: Rect) -> (f32, f32) {
+    let border_box = self.dimensions.border_box();
+    let margin_box = self.dimensions.margin_box();
+
+    let mut dx = 0.0;
+    if let Some(right) = self.physical_offset("right", scrollport.width) {
+      let limit = scrollport.x + scrollport.width - right;
+      dx = (limit - (border_box.x + border_box.width)).min(0.0);
+    }
+    // If both are set, `left` wins.
+    if let Some(left) = self.physical_offset("left", scrollport.width) {
+      let limit = scrollport.x + left;
+      if border_box.x < limit {
+        dx = limit - border_box.x;
+      }
+    }
+
+    let mut dy = 0.0;
+    if let Some(bottom) = self.physical_offset("bottom", scrollport.height) {
+      let limit = scrollport.y + scrollport.height - bottom;
+      dy = (limit - (border_box.y + border_box.height)).min(0.0);
+    }
+    // If both are set, `top` wins.
+    if let Some(top) = self.physical_offset("top", scrollport.height) {
+      let limit = scrollport.y + top;
+      if border_box.y < limit {
+        dy = limit - border_box.y;
+      }
+    }
+
+    // The margin box may never be pushed out of the containing block.
+    let dx = dx
+      .min(containing_block.x + containing_block.width - (margin_box.x + margin_box.width))
+      .max(containing_block.x - margin_box.x);
+    let dy = dy
+      .min(containing_block.y + containing_block.height - (margin_box.y + margin_box.height))
+      .max(containing_block.y - margin_box.y);
+    (dx, dy)
+  }
+
+  /// Resolve one of the physical `top`, `right`, `bottom` or `left` offsets, once the box is
+  /// mapped to the page, `None` when it is `auto`.
+  fn physical_offset(&self, name: &str, reference: f32) -> Option<f32> {
+    match self.get_style_node_option()?.values.get(name) {
+      None => None,
+      Some(Value::Keyword(keyword)) if keyword == "auto" => None,
+      Some(value) => Some(value.to_px(reference, self.viewport)),
+    }
+  }
+
+  /// Lay out an absolutely positioned box and its in-flow descendants.
+  ///
+  /// Normal flow leaves the static position of the box in the origin of its content rect.
+  fn layout_absolute(&mut self, (containing_block, direction): (Rect, Direction)) {
+    let static_position = (
+      self.dimensions.content.x - containing_block.x,
+      self.dimensions.content.y - containing_block.y,
+    );
+
+    let style = self.get_style_node();
+    let containing_size = SimpleDimensions::from_dimension(containing_block, direction);
+    let replaced_size = self.replaced_size(&containing_size);
+    let mut left = self.calculate_absolute_width(
+      containing_block,
+      static_position.0,
+      replaced_size.map(|(width, _)| width),
+    );
+
+    // A width outside of `min-width` and `max-width` is resolved again as the specified width.
+    //
+    // https://www.w3.org/TR/CSS2/visudet.html#min-max-widths
+    let d = self.dimensions;
+    let edges = d.border.left + d.border.right + d.padding.left + d.padding.right;
+    let width = d.content.width;
+    let available = containing_block.width - edges - d.margin.left - d.margin.right;
+    let clamped = self.clamp_width(width, containing_block.width, edges, available);
+    if clamped != width {
+      left = self.calculate_absolute_width(containing_block, static_position.0, Some(clamped));
+    }
+    self.calculate_absolute_vertical_edges(containing_block);
+
+    let d = &mut self.dimensions;
+    d.content.x = containing_block.x + left + d.margin.left + d.border.left + d.padding.left;
+    d.content.y = containing_block.y + d.margin.top + d.border.top + d.padding.top;
+
+    // Children are laid out at the top of the containing block, and moved once the final
+    // vertical position is known.
+    self.layout_children(&containing_size);
+
+    let mut top = self.calculate_absolute_height(
+      containing_block,
+      static_position.1,
+      replaced_size.map(|(_, height)| height),
+    );
+
+    // Same for the height with `min-height` and `max-height`.
+    let d = self.dimensions;
+    let edges = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+    let height = d.content.height;
+    let clamped = style.clamp_size(
+      "height",
+      height,
+      containing_block.height,
+      edges,
+      self.viewport,
+    );
+    if clamped != height {
+      top = self.calculate_absolute_height(containing_block, static_position.1, Some(clamped));
+    }
+    self.translate(0.0, top);
+  }
+
+  /// Width of an absolutely positioned, non-replaced element.
+  ///
+  /// https://www.w3.org/TR/CSS2/visudet.html#abs-non-replaced-width
+  ///
+  /// Sets the horizontal margin/padding/border dimensions and the `width`, and returns the used
+  /// value of `left`. `used_width` replaces the specified width if it is given.
+  fn calculate_absolute_width(
+    &mut self,
+    containing_block: Rect,
+    static_left: f32,
+    used_width: Option<f32>,
+  ) -> f32 {
+    let viewport = self.viewport;
+    let style = self.get_style_node();
+    let reference = containing_block.width;
+
+    let auto = Value::Keyword("auto".to_string());
+    let zero = Value::Length(0.0, Unit::Px);
+
+    let left = self.offset("left", reference);
+    let right = self.offset("right", reference);
+
+    let margin_left = style.lookup("margin-left", "margin", &zero);
+    let margin_right = style.lookup("margin-right", "margin", &zero);
+
+    let d = &mut self.dimensions;
+    d.border.left = style.border_width("left").to_px(reference, viewport);
+    d.border.right = style.border_width("right").to_px(reference, viewport);
+    d.padding.left = style
+      .lookup("padding-left", "padding", &zero)
+      .to_px(reference, viewport);
+    d.padding.right = style
+      .lookup("padding-right", "padding", &zero)
+      .to_px(reference, viewport);
+
+    let edges = d.border.left + d.border.right + d.padding.left + d.padding.right;
+    let available = reference
+      - left.unwrap_or(static_left)
+      - right.unwrap_or(0.0)
+      - margin_left.to_px(reference, viewport)
+      - margin_right.to_px(reference, viewport)
+      - edges;
+    let width = used_width
+      .or_else(|| self.width_property("width", reference, edges, available))
+      .or_else(|| self.ratio_width(Some(containing_block.height), reference));
+
+    // Auto margins are treated as zero, unless all of left, width and right are known.
+    let mut used_margin_left = margin_left.to_px(reference, viewport);
+    let mut used_margin_right = margin_right.to_px(reference, viewport);
+
+    let (used_left, used_width) = match (left, width, right) {
+      (Some(left), Some(width), Some(right)) => {
+        let remaining = reference - left - width - right - edges;
+
+        match (margin_left == auto, margin_right == auto) {
+          (true, true) => {
+            if remaining < 0.0 {
+              used_margin_left = 0.0;
+              used_margin_right = remaining;
+            } else {
+              used_margin_left = remaining / 2.0;
+              used_margin_right = remaining / 2.0;
+            }
+          }
+          (true, false) => used_margin_left = remaining - used_margin_right,
+          (false, true) => used_margin_right = remaining - used_margin_left,
+          // Over-constrained, ignore the value for `right`.
+          (false, false) => {}
+        }
+
+        (left, width)
+      }
+
+      (left, width, right) => {
+        let margins = used_margin_left + used_margin_right;
+
+        match (left, width, right) {
+          (None, None, None) => {
+            let width = self.shrink_to_fit_width(reference - static_left - margins - edges);
+            (static_left, width)
+          }
+          (None, None, Some(right)) => {
+            let width = self.shrink_to_fit_width(reference - right - margins - edges);
+            (reference - right - margins - edges - width, width)
+          }
+          (Some(left), None, None) => {
+            let width = self.shrink_to_fit_width(reference - left - margins - edges);
+            (left, width)
+          }
+          (None, Some(width), None) => (static_left, width),
+          (None, Some(width), Some(right)) => (reference - right - margins - edges - width, width),
+          (Some(left), None, Some(right)) => {
+            (left, (reference - left - right - margins - edges).max(0.0))
+          }
+          (Some(left), Some(width), None) => (left, width),
+          (Some(_), Some(_), Some(_)) => unreachable!(),
+        }
+      }
+    };
+
+    let d = &mut self.dimensions;
+    d.content.width = used_width;
+    d.margin.left = used_margin_left;
+    d.margin.right = used_margin_right;
+
+    used_left
+  }
+
+  /// Vertical padding and border of an absolutely positioned box, margins are resolved with
+  /// the height in `calculate_absolute_height`.
+  fn calculate_absolute_vertical_edges(&mut self, containing_block: Rect) {
+    let viewport = self.viewport;
+    let style = self.get_style_node();
+    let zero = Value::Length(0.0, Unit::Px);
+
+    // Percentages of vertical edges refer to the containing block's width.
+    let reference = containing_block.width;
+
+    let d = &mut self.dimensions;
+    d.border.top = style.border_width("top").to_px(reference, viewport);
+    d.border.bottom = style.border_width("bottom").to_px(reference, viewport);
+    d.padding.top = style
+      .lookup("padding-top", "padding", &zero)
+      .to_px(reference, viewport);
+    d.padding.bottom = style
+      .lookup("padding-bottom", "padding", &zero)
+      .to_px(reference, viewport);
+    d.margin.top = 0.0;
+    d.margin.bottom = 0.0;
+  }
+
+  /// Height of an absolutely positioned, non-replaced element.
+  ///
+  /// https://www.w3.org/TR/CSS2/visudet.html#abs-non-replaced-height
+  ///
+  /// Must be called after the children are laid out. Sets the vertical margins and the `height`,
+  /// and returns the used value of `top` plus the top margin. `used_height` replaces the
+  /// specified height if it is given.
+  fn calculate_absolute_height(
+    &mut self,
+    containing_block: Rect,
+    static_top: f32,
+    used_height: Option<f32>,
+  ) -> f32 {
+    let viewport = self.viewport;
+    let style = self.get_style_node();
+    let reference = containing_block.height;
+
+    let auto = Value::Keyword("auto".to_string());
+    let zero = Value::Length(0.0, Unit::Px);
+
+    let top = self.offset("top", reference);
+    let bottom = self.offset("bottom", reference);
+
+    let margin_top = style.lookup("margin-top", "margin", &zero);
+    let margin_bottom = style.lookup("margin-bottom", "margin", &zero);
+    let ratio_height = self.ratio_height(self.dimensions.content.width, containing_block.width);
+
+    let d = &mut self.dimensions;
+    let content_height = d.content.height;
+    let edges = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+    let height = used_height
+      .or_else(|| style.size("height", reference, edges, viewport))
+      .or_else(|| match ratio_height {
+        Some(height) if aspect_ratio::grows_to_fit_content(style) => {
+          Some(height.max(content_height))
+        }
+        height => height,
+      });
+
+    let mut used_margin_top = margin_top.to_px(containing_block.width, viewport);
+    let mut used_margin_bottom = margin_bottom.to_px(containing_block.width, viewport);
+
+    let (used_top, used_height) = match (top, height, bottom) {
+      (Some(top), Some(height), Some(bottom)) => {
+        let remaining = reference - top - height - bottom - edges;
+
+        match (margin_top == auto, margin_bottom == auto) {
+          (true, true) => {
+            used_margin_top = remaining / 2.0;
+            used_margin_bottom = remaining / 2.0;
+          }
+          (true, false) => used_margin_top = remaining - used_margin_bottom,
+          (false, true) => used_margin_bottom = remaining - used_margin_top,
+          // Over-constrained, ignore the value for `bottom`.
+          (false, false) => {}
+        }
+
+        (top, height)
+      }
+
+      (top, height, bottom) => {
+        let margins = used_margin_top + used_margin_bottom;
+
+        match (top, height, bottom) {
+          (None, None, None) => (static_top, content_height),
+          (None, None, Some(bottom)) => (
+            reference - bottom - margins - edges - content_height,
+            content_height,
+          ),
+          (Some(top), None, None) => (top, content_height),
+          (None, Some(height), None) => (static_top, height),
+          (None, Some(height), Some(bottom)) => {
+            (reference - bottom - margins - edges - height, height)
+          }
+          (Some(top), None, Some(bottom)) => {
+            (top, (reference - top - bottom - margins - edges).max(0.0))
+          }
+          (Some(top), Some(height), None) => (top, height),
+          (Some(_), Some(_), Some(_)) => unreachable!(),
+        }
+      }
+    };
+
+    d.content.height = used_height;
+    d.margin.top = used_margin_top;
+    d.margin.bottom = used_margin_bottom;
+
+    used_top + used_margin_top
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, border_box, div, layout};
+  use super::super::Rect;
+
+  #[test]
+  fn relative_boxes_are_offset_from_their_place_in_flow() {
+    let document = body(vec![div("a", vec![]), div("b", vec![])]);
+    let css = ".a { position: relative; left: 10px; top: -5px; height: 20px; } \
+               .b { height: 20px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0]), (10.0, -5.0, 800.0, 20.0));
+      // The following box is placed as if the first one wasn't moved.
+      assert_eq!(border_box(root, &[1]), (0.0, 20.0, 800.0, 20.0));
+    });
+  }
+
+  #[test]
+  fn absolute_boxes_are_placed_in_the_padding_box_of_the_nearest_positioned_ancestor() {
+    let document = body(vec![div(
+      "outer",
+      vec![div(
+        "static",
+        vec![div("top-left", vec![]), div("bottom-right", vec![])],
+      )],
+    )]);
+    let css = ".outer { position: relative; width: 300px; height: 200px; padding: 20px; \
+                 border-width: 5px; border-style: solid; } \
+               .static { margin: 7px; } \
+               .top-left { position: absolute; top: 0; left: 0; width: 50px; height: 40px; } \
+               .bottom-right { position: absolute; right: 10%; bottom: 0; \
+                 width: 50px; height: 40px; }";
+    layout(&document, css, |root| {
+      // The padding box of `.outer` is 340px by 240px, inside its 5px border.
+      assert_eq!(border_box(root, &[0, 0, 0]), (5.0, 5.0, 50.0, 40.0));
+      assert_eq!(border_box(root, &[0, 0, 1]), (261.0, 205.0, 50.0, 40.0));
+    });
+  }
+
+  #[test]
+  fn absolute_boxes_with_auto_offsets_stay_at_their_static_position() {
+    let document = body(vec![div(
+      "outer",
+      vec![
+        div("before", vec![]),
+        div("absolute", vec![]),
+        div("after", vec![]),
+      ],
+    )]);
+    let css = ".outer { position: relative; padding: 10px; } \
+               .before { height: 30px; } \
+               .absolute { position: absolute; width: 20px; height: 20px; } \
+               .after { height: 10px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 1]), (10.0, 40.0, 20.0, 20.0));
+      // The absolute box is out of flow, so the next box takes its place.
+      assert_eq!(border_box(root, &[0, 2]), (10.0, 40.0, 780.0, 10.0));
+      assert_eq!(border_box(root, &[0]).3, 60.0);
+    });
+  }
+
+  #[test]
+  fn fixed_boxes_are_placed_in_the_viewport() {
+    let document = body(vec![div("outer", vec![div("fixed", vec![])])]);
+    let css = ".outer { position: relative; top: 100px; left: 100px; padding: 10px; } \
+               .fixed { position: fixed; right: 0; bottom: 0; width: 100px; height: 50px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (700.0, 550.0, 100.0, 50.0));
+    });
+  }
+
+  #[test]
+  fn viewport_percentages_are_relative_to_the_viewport() {
+    let document = body(vec![div("outer", vec![div("fixed", vec![])])]);
+    let css = ".outer { width: 100px; } \
+               .fixed { position: fixed; right: 5vw; bottom: 0; width: 50vw; height: 10vmin; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (360.0, 540.0, 400.0, 60.0));
+    });
+  }
+
+  #[test]
+  fn sticky_boxes_stay_in_the_scrollport_within_their_containing_block() {
+    let document = body(vec![div(
+      "container",
+      vec![
+        div("spacer", vec![]),
+        div("sticky", vec![]),
+        div("rest", vec![]),
+      ],
+    )]);
+    let css = ".spacer { height: 100px; } \
+               .sticky { position: sticky; top: 10px; height: 50px; } \
+               .rest { height: 300px; }";
+    layout(&document, css, |root| {
+      let container = &root.children[0];
+      let sticky = &container.children[1];
+      let containing_block = container.dimensions.content_box();
+      let scrolled_by = |y| Rect {
+        x: 0.0,
+        y,
+        width: 800.0,
+        height: 600.0,
+      };
+      // In flow until the scrollport reaches it, then stuck 10px below the top of the
+      // scrollport, until the end of the containing block pushes it back.
+      assert_eq!(
+        sticky.sticky_offset(scrolled_by(0.0), containing_block),
+        (0.0, 0.0)
+      );
+      assert_eq!(
+        sticky.sticky_offset(scrolled_by(200.0), containing_block),
+        (0.0, 110.0)
+      );
+      assert_eq!(
+        sticky.sticky_offset(scrolled_by(420.0), containing_block),
+        (0.0, 300.0)
+      );
+    });
+  }
+}
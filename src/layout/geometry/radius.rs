@@ -0,0 +1,226 @@
+//! Rounded corners: the radii of `border-radius` and its longhands, which round the edges of a
+//! box, its background and the clip of its overflow.
+//!
+//! The radii are physical properties, so they are resolved against the boxes once they are laid
+//! out and mapped to the page.
+//!
+//! https://www.w3.org/TR/css-backgrounds-3/#corners
+
+use super::{EdgeSizes, LayoutBox, Rect};
+use crate::layout::css::{Value, Viewport};
+use crate::layout::style::StyledNode;
+
+/// The corners of a box, clockwise from the top left like the values of `border-radius`.
+const CORNERS: [&str; 4] = ["top-left", "top-right", "bottom-right", "bottom-left"];
+
+/// A rect with rounded corners, with the horizontal and vertical radius of each corner clockwise
+/// from the top left.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RoundedRect {
+  pub rect: Rect,
+  pub radii: [(f32, f32); 4],
+}
+
+impl RoundedRect {
+  /// Whether any corner of the rect is rounded.
+  pub fn is_rounded(&self) -> bool {
+    self.radii.iter().any(|&(x, y)| x > 0.0 && y > 0.0)
+  }
+
+  /// The rounded rect grown by a distance on every side, or shrunk by a negative one, like the
+  /// shape of a box shadow with a spread. Radii grow by the distance too, but less so when they
+  /// are smaller than it, so that square corners stay square.
+  ///
+  /// https://www.w3.org/TR/css-backgrounds-3/#shadow-shape
+  pub(super) fn spread_by(self, distance: f32) -> RoundedRect {
+    let spread = |radius: f32| {
+      if radius <= 0.0 {
+        0.0
+      } else if distance >= 0.0 && radius < distance {
+        radius + distance * (1.0 + (radius / distance - 1.0).powi(3))
+      } else {
+        (radius + distance).max(0.0)
+      }
+    };
+    RoundedRect {
+      rect: Rect {
+        x: self.rect.x - distance,
+        y: self.rect.y - distance,
+        width: (self.rect.width + 2.0 * distance).max(0.0),
+        height: (self.rect.height + 2.0 * distance).max(0.0),
+      },
+      radii: self.radii.map(|(x, y)| (spread(x), spread(y))),
+    }
+  }
+
+  /// The rounded rect inside some edges, with the radii of each corner reduced by the edges it
+  /// meets, like the padding edge of a box inside its border.
+  ///
+  /// https://www.w3.org/TR/css-backgrounds-3/#corner-shaping
+  pub(super) fn inset_by(self, edges: EdgeSizes) -> RoundedRect {
+    let shrink = |(x, y): (f32, f32), dx: f32, dy: f32| {
+      let (x, y) = ((x - dx).max(0.0), (y - dy).max(0.0));
+      // A corner without a curve along one axis is square.
+      if x > 0.0 && y > 0.0 {
+        (x, y)
+      } else {
+        (0.0, 0.0)
+      }
+    };
+    let [top_left, top_right, bottom_right, bottom_left] = self.radii;
+    RoundedRect {
+      rect: Rect {
+        x: self.rect.x + edges.left,
+        y: self.rect.y + edges.top,
+        width: (self.rect.width - edges.left - edges.right).max(0.0),
+        height: (self.rect.height - edges.top - edges.bottom).max(0.0),
+      },
+      radii: [
+        shrink(top_left, edges.left, edges.top),
+        shrink(top_right, edges.right, edges.top),
+        shrink(bottom_right, edges.right, edges.bottom),
+        shrink(bottom_left, edges.left, edges.bottom),
+      ],
+    }
+  }
+}
+
+impl<'a> LayoutBox<'a> {
+  /// The border box with its corners rounded by `border-radius`. Percentages refer to the width
+  /// of the border box for horizontal radii and to its height for vertical ones, and all radii
+  /// are reduced in proportion when the radii of two corners don't fit the side between them.
+  ///
+  /// https://www.w3.org/TR/css-backgrounds-3/#corner-overlap
+  pub fn rounded_border_box(&self) -> RoundedRect {
+    let rect = self.dimensions.border_box();
+    let mut radii = [(0.0, 0.0); 4];
+    if let Some(style) = self.get_style_node_option() {
+      for (index, radius) in radii.iter_mut().enumerate() {
+        *radius = corner_radius(style, index, rect, self.viewport);
+      }
+    }
+
+    let [top_left, top_right, bottom_right, bottom_left] = radii;
+    let fit = |length: f32, a: f32, b: f32| if a + b > 0.0 { length / (a + b) } else { 1.0 };
+    let scale = fit(rect.width, top_left.0, top_right.0)
+      .min(fit(rect.height, top_right.1, bottom_right.1))
+      .min(fit(rect.width, bottom_right.0, bottom_left.0))
+      .min(fit(rect.height, bottom_left.1, top_left.1));
+    if scale < 1.0 {
+      for radius in radii.iter_mut() {
+        *radius = (radius.0 * scale, radius.1 * scale);
+      }
+    }
+    RoundedRect { rect, radii }
+  }
+
+  /// The padding box with the inner curves of the border.
+  pub fn rounded_padding_box(&self) -> RoundedRect {
+    self.rounded_border_box().inset_by(self.dimensions.border)
+  }
+}
+
+/// The horizontal and vertical radius of a corner, from its `border-<corner>-radius` longhand or
+/// the `border-radius` shorthand. A corner with a zero radius along either axis is square.
+fn corner_radius(style: &StyledNode, index: usize, rect: Rect, viewport: Viewport) -> (f32, f32) {
+  let name = format!("border-{}-radius", CORNERS[index]);
+  let radius = match style.value(&name) {
+    Some(Value::List(values)) => match values.as_slice() {
+      [horizontal, vertical] => Some((horizontal.clone(), vertical.clone())),
+      _ => None,
+    },
+    Some(value) => Some((value.clone(), value)),
+    None => style
+      .value("border-radius")
+      .and_then(|value| shorthand_radius(value, index)),
+  };
+  let (horizontal, vertical) = match radius {
+    Some((horizontal, vertical)) => (
+      horizontal.to_px(rect.width, viewport).max(0.0),
+      vertical.to_px(rect.height, viewport).max(0.0),
+    ),
+    None => return (0.0, 0.0),
+  };
+  if horizontal > 0.0 && vertical > 0.0 {
+    (horizontal, vertical)
+  } else {
+    (0.0, 0.0)
+  }
+}
+
+/// The radii of a corner in a `border-radius` shorthand like `10px 5% / 20px`, where the
+/// vertical radii follow the horizontal ones after a slash, or are the same without one.
+///
+/// https://www.w3.org/TR/css-backgrounds-3/#border-radius
+fn shorthand_radius(value: Value, index: usize) -> Option<(Value, Value)> {
+  let values = match value {
+    Value::List(values) => values,
+    value => vec![value],
+  };
+  let mut parts = values.split(|value| *value == Value::Slash);
+  let horizontal = parts.next()?;
+  let vertical = parts.next().unwrap_or(horizontal);
+
+  // Missing corners copy the opposite corner, like the sides of the box shorthands.
+  let corner = |values: &[Value]| {
+    let position = match values.len() {
+      1 => 0,
+      2 => index % 2,
+      3 if index == 3 => 1,
+      _ => index,
+    };
+    values.get(position).cloned()
+  };
+  Some((corner(horizontal)?, corner(vertical)?))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, div, layout};
+  use super::RoundedRect;
+
+  fn rounded(css: &str, padding_box: bool) -> RoundedRect {
+    let document = body(vec![div("box", vec![])]);
+    layout(&document, css, |root| {
+      if padding_box {
+        root.children[0].rounded_padding_box()
+      } else {
+        root.children[0].rounded_border_box()
+      }
+    })
+  }
+
+  #[test]
+  fn elliptical_radii_are_resolved_against_the_border_box() {
+    let css = ".box { width: 200px; height: 100px; border-radius: 10% / 20px 30px; }";
+    assert_eq!(
+      rounded(css, false).radii,
+      [(20.0, 20.0), (20.0, 30.0), (20.0, 20.0), (20.0, 30.0)]
+    );
+
+    let css = ".box { width: 200px; height: 100px; border-top-left-radius: 50px 25%; \
+                 border-top-right-radius: 10px 0; }";
+    let radii = rounded(css, false).radii;
+    assert_eq!(radii[0], (50.0, 25.0));
+    // A corner without a curve along one axis is square.
+    assert_eq!(radii[1], (0.0, 0.0));
+  }
+
+  #[test]
+  fn overlapping_radii_are_scaled_down_together() {
+    // The top and bottom radii add up to 160px on sides that are 100px long.
+    let css = ".box { width: 100px; height: 200px; border-radius: 80px / 40px; }";
+    let scale = 100.0 / 160.0;
+    assert_eq!(rounded(css, false).radii, [(80.0 * scale, 40.0 * scale); 4]);
+  }
+
+  #[test]
+  fn inner_radii_are_reduced_by_the_border() {
+    let css = ".box { width: 100px; height: 100px; border: 5px solid black; \
+                 border-radius: 20px 3px; }";
+    let inner = rounded(css, true);
+    assert_eq!((inner.rect.x, inner.rect.width), (5.0, 100.0));
+    assert_eq!(inner.radii[0], (15.0, 15.0));
+    assert_eq!(inner.radii[1], (0.0, 0.0));
+  }
+}
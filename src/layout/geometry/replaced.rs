@@ -0,0 +1,387 @@
+//! Sizing of replaced elements like `<img>`, whose content is an image with an intrinsic size
+//! and aspect ratio, and placement of the image in their content box.
+//!
+//! https://www.w3.org/TR/CSS2/visudet.html#inline-replaced-width
+//! https://www.w3.org/TR/css-images-3/#the-object-fit
+
+use super::{LayoutBox, Rect, SimpleDimensions};
+use crate::layout::css::{Unit, Value, Viewport};
+use crate::layout::style::StyledNode;
+
+impl<'a> LayoutBox<'a> {
+  /// The intrinsic size of the content of a replaced element, or `None` for other boxes.
+  pub fn intrinsic_size(&self) -> Option<(f32, f32)> {
+    self
+      .get_style_node_option()
+      .and_then(|style| style.image())
+      .map(|image| image.size())
+  }
+
+  /// The used width and height of the content box of a replaced element, or `None` for other
+  /// boxes. An `auto` width or height follows from the other one and the aspect ratio, or from
+  /// the intrinsic size when both are `auto`, and the result respects the `min-` and `max-`
+  /// sizes.
+  ///
+  /// https://www.w3.org/TR/CSS2/visudet.html#min-max-widths
+  pub(super) fn replaced_size(&self, containing_block: &SimpleDimensions) -> Option<(f32, f32)> {
+    let viewport = self.viewport;
+    let (intrinsic_width, intrinsic_height, ratio) = self.natural_size()?;
+    let style = self.get_style_node();
+    let reference = containing_block.width;
+    let (horizontal_edges, vertical_edges) = edges(style, reference, viewport);
+    let vertical = style.writing_mode.is_vertical();
+
+    // The `width` and `height` attributes are hints for the size, below the styles.
+    let attribute = |name| {
+      style
+        .attribute(name)
+        .and_then(|value| value.trim_end_matches("px").parse::<f32>().ok())
+    };
+    let (width_attribute, height_attribute) = if vertical {
+      ("height", "width")
+    } else {
+      ("width", "height")
+    };
+    let width = style
+      .size("width", reference, horizontal_edges, viewport)
+      .or_else(|| attribute(width_attribute));
+    let height = style
+      .size("height", containing_block.height, vertical_edges, viewport)
+      .or_else(|| attribute(height_attribute));
+
+    let clamp_width =
+      |width| style.clamp_size("width", width, reference, horizontal_edges, viewport);
+    let clamp_height = |height| {
+      style.clamp_size(
+        "height",
+        height,
+        containing_block.height,
+        vertical_edges,
+        viewport,
+      )
+    };
+
+    Some(match (width, height) {
+      (Some(width), Some(height)) => (clamp_width(width), clamp_height(height)),
+      (Some(width), None) => {
+        let width = clamp_width(width);
+        (width, clamp_height(width / ratio))
+      }
+      (None, Some(height)) => {
+        let height = clamp_height(height);
+        (clamp_width(height * ratio), height)
+      }
+      (None, None) => {
+        let min_width = style.size("min-width", reference, horizontal_edges, viewport);
+        let max_width = style.size("max-width", reference, horizontal_edges, viewport);
+        let min_height = style.size(
+          "min-height",
+          containing_block.height,
+          vertical_edges,
+          viewport,
+        );
+        let max_height = style.size(
+          "max-height",
+          containing_block.height,
+          vertical_edges,
+          viewport,
+        );
+        constrain_size(
+          (intrinsic_width, intrinsic_height),
+          (min_width.unwrap_or(0.0), max_width.unwrap_or(f32::INFINITY)),
+          (
+            min_height.unwrap_or(0.0),
+            max_height.unwrap_or(f32::INFINITY),
+          ),
+        )
+      }
+    })
+  }
+
+  /// The width of the content box of a replaced element sized by its content alone, or `None`
+  /// for other boxes: the intrinsic width, or the width that follows from a definite height and
+  /// the aspect ratio. The `width` and `height` attributes stand in for the intrinsic size.
+  pub(super) fn natural_width(&self) -> Option<f32> {
+    let viewport = self.viewport;
+    let (intrinsic_width, _, ratio) = self.natural_size()?;
+    let style = self.get_style_node();
+    let (width_attribute, height_attribute) = if style.writing_mode.is_vertical() {
+      ("height", "width")
+    } else {
+      ("width", "height")
+    };
+    let attribute = |name| {
+      style
+        .attribute(name)
+        .and_then(|value| value.trim_end_matches("px").parse::<f32>().ok())
+    };
+    if let Some(width) = attribute(width_attribute) {
+      return Some(width);
+    }
+
+    let (_, vertical_edges) = edges(style, 0.0, viewport);
+    let height = match style.value("height") {
+      Some(Value::Length(_, Unit::Percentage)) => None,
+      _ => style.size("height", 0.0, vertical_edges, viewport),
+    };
+    Some(match height.or_else(|| attribute(height_attribute)) {
+      Some(height) => height * ratio,
+      None => intrinsic_width,
+    })
+  }
+
+  /// The intrinsic width and height of a replaced element and the ratio between them, along and
+  /// across the lines, which are the physical height and width in vertical writing modes.
+  ///
+  /// An `aspect-ratio` replaces the natural ratio unless it is `auto`, and the height follows
+  /// from the intrinsic width through it. `auto` with a ratio only applies to images without a
+  /// natural ratio.
+  fn natural_size(&self) -> Option<(f32, f32, f32)> {
+    let (width, height) = self.intrinsic_size()?;
+    let (width, height) = if self.get_style_node().writing_mode.is_vertical() {
+      (height, width)
+    } else {
+      (width, height)
+    };
+    Some(match self.aspect_ratio() {
+      Some((ratio, false)) => (width, width / ratio, ratio),
+      _ if height > 0.0 => (width, height, width / height),
+      Some((ratio, true)) => (width, width / ratio, ratio),
+      None => (width, height, 1.0),
+    })
+  }
+
+  /// The rect the image of a replaced element is painted in, sized by `object-fit` and placed
+  /// in the content box by `object-position`. Parts outside of the content box are clipped.
+  ///
+  /// https://www.w3.org/TR/css-images-3/#the-object-fit
+  pub fn object_rect(&self) -> Option<Rect> {
+    let (intrinsic_width, intrinsic_height) = self.intrinsic_size()?;
+    let style = self.get_style_node();
+    let content = self.dimensions.content;
+    if intrinsic_width == 0.0 || intrinsic_height == 0.0 {
+      return Some(content);
+    }
+
+    let contain = (content.width / intrinsic_width).min(content.height / intrinsic_height);
+    let cover = (content.width / intrinsic_width).max(content.height / intrinsic_height);
+    let scale = match style.keyword("object-fit").as_deref() {
+      Some("contain") => contain,
+      Some("cover") => cover,
+      Some("none") => 1.0,
+      Some("scale-down") => contain.min(1.0),
+      _ => return Some(content),
+    };
+    let width = intrinsic_width * scale;
+    let height = intrinsic_height * scale;
+
+    let (x, y) = object_position(
+      style,
+      content.width - width,
+      content.height - height,
+      self.viewport,
+    );
+    Some(Rect {
+      x: content.x + x,
+      y: content.y + y,
+      width,
+      height,
+    })
+  }
+}
+
+/// The horizontal and vertical padding and border of a box, which are needed to size replaced
+/// elements before its vertical edges are resolved.
+pub(super) fn edges(style: &StyledNode, reference: f32, viewport: Viewport) -> (f32, f32) {
+  let zero = Value::Length(0.0, Unit::Px);
+  let edge = |name: &str, shorthand: &str| {
+    style
+      .lookup(name, shorthand, &zero)
+      .to_px(reference, viewport)
+  };
+  let border = |side: &str| style.border_width(side).to_px(reference, viewport);
+  (
+    edge("padding-left", "padding")
+      + edge("padding-right", "padding")
+      + border("left")
+      + border("right"),
+    edge("padding-top", "padding")
+      + edge("padding-bottom", "padding")
+      + border("top")
+      + border("bottom"),
+  )
+}
+
+/// Fit an intrinsic size between a minimum and maximum width and height, keeping its aspect
+/// ratio where possible.
+///
+/// https://www.w3.org/TR/CSS2/visudet.html#min-max-widths
+fn constrain_size(
+  (width, height): (f32, f32),
+  (min_width, max_width): (f32, f32),
+  (min_height, max_height): (f32, f32),
+) -> (f32, f32) {
+  let max_width = max_width.max(min_width);
+  let max_height = max_height.max(min_height);
+  if width == 0.0 || height == 0.0 {
+    return (
+      width.max(min_width).min(max_width),
+      height.max(min_height).min(max_height),
+    );
+  }
+
+  let too_wide = width > max_width;
+  let too_narrow = width < min_width;
+  let too_tall = height > max_height;
+  let too_short = height < min_height;
+  match (too_wide, too_narrow, too_tall, too_short) {
+    (true, _, true, _) if max_width / width <= max_height / height => {
+      (max_width, min_height.max(max_width * height / width))
+    }
+    (true, _, true, _) => (min_width.max(max_height * width / height), max_height),
+    (_, true, _, true) if min_width / width <= min_height / height => {
+      (max_width.min(min_height * width / height), min_height)
+    }
+    (_, true, _, true) => (min_width, max_height.min(min_width * height / width)),
+    (_, true, true, _) => (min_width, max_height),
+    (true, _, _, true) => (max_width, min_height),
+    (true, _, _, _) => (max_width, min_height.max(max_width * height / width)),
+    (_, true, _, _) => (min_width, max_height.min(min_width * height / width)),
+    (_, _, true, _) => (min_width.max(max_height * width / height), max_height),
+    (_, _, _, true) => (max_width.min(min_height * width / height), min_height),
+    _ => (width, height),
+  }
+}
+
+/// The offset of the image in the content box for `object-position`, given the space left
+/// around it along each axis. Defaults to the center.
+///
+/// https://www.w3.org/TR/css-images-3/#the-object-position
+fn object_position(
+  style: &StyledNode,
+  free_width: f32,
+  free_height: f32,
+  viewport: Viewport,
+) -> (f32, f32) {
+  let center = Value::Keyword("center".to_string());
+  let is_vertical = |value: &Value| match value {
+    Value::Keyword(keyword) => keyword == "top" || keyword == "bottom",
+    _ => false,
+  };
+  let is_horizontal = |value: &Value| match value {
+    Value::Keyword(keyword) => keyword == "left" || keyword == "right",
+    _ => false,
+  };
+
+  let (x, y) = match style.value("object-position") {
+    Some(Value::List(values)) if values.len() >= 2 => {
+      let (first, second) = (values[0].clone(), values[1].clone());
+      // Keywords can be given in either order, e.g. `top left`.
+      if is_vertical(&first) || is_horizontal(&second) {
+        (second, first)
+      } else {
+        (first, second)
+      }
+    }
+    Some(value) if is_vertical(&value) => (center, value),
+    Some(value) => (value, center),
+    None => (center.clone(), center),
+  };
+  (
+    position_offset(&x, free_width, viewport),
+    position_offset(&y, free_height, viewport),
+  )
+}
+
+/// The offset for one component of a position, with percentages and keywords relative to the
+/// free space.
+fn position_offset(value: &Value, free_space: f32, viewport: Viewport) -> f32 {
+  match value {
+    Value::Keyword(keyword) => match keyword.as_str() {
+      "left" | "top" => 0.0,
+      "right" | "bottom" => free_space,
+      _ => free_space / 2.0,
+    },
+    value => value.to_px(free_space, viewport),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, border_box, img, layout};
+
+  fn content_size(css: &str) -> (f32, f32) {
+    let document = body(vec![img("image", 200, 100)]);
+    let css = format!("img {{ display: block; }} {}", css);
+    layout(&document, &css, |root| {
+      let content = root.children[0].dimensions.content_box();
+      (content.width, content.height)
+    })
+  }
+
+  #[test]
+  fn replaced_elements_are_sized_by_their_intrinsic_size_and_ratio() {
+    assert_eq!(content_size(""), (200.0, 100.0));
+    assert_eq!(content_size(".image { width: 100px; }"), (100.0, 50.0));
+    assert_eq!(content_size(".image { height: 20px; }"), (40.0, 20.0));
+    assert_eq!(
+      content_size(".image { width: 30px; height: 30px; }"),
+      (30.0, 30.0)
+    );
+    // The limits keep the aspect ratio when both sizes are `auto`.
+    assert_eq!(content_size(".image { max-width: 50px; }"), (50.0, 25.0));
+    assert_eq!(
+      content_size(".image { min-height: 150px; }"),
+      (300.0, 150.0)
+    );
+  }
+
+  /// The rect the image is painted in, relative to the content box of a 100px square.
+  fn object_rect(css: &str) -> (f32, f32, f32, f32) {
+    let document = body(vec![img("image", 200, 100)]);
+    let css = format!(
+      "img {{ display: block; margin: 10px; width: 100px; height: 100px; }} .image {{ {} }}",
+      css
+    );
+    layout(&document, &css, |root| {
+      let (x, y, _, _) = border_box(root, &[0]);
+      let rect = root.children[0].object_rect().unwrap();
+      (rect.x - x, rect.y - y, rect.width, rect.height)
+    })
+  }
+
+  #[test]
+  fn object_fit_sizes_the_image_in_the_content_box() {
+    assert_eq!(object_rect(""), (0.0, 0.0, 100.0, 100.0));
+    assert_eq!(
+      object_rect("object-fit: contain;"),
+      (0.0, 25.0, 100.0, 50.0)
+    );
+    assert_eq!(
+      object_rect("object-fit: cover;"),
+      (-50.0, 0.0, 200.0, 100.0)
+    );
+    assert_eq!(object_rect("object-fit: none;"), (-50.0, 0.0, 200.0, 100.0));
+    assert_eq!(
+      object_rect("object-fit: scale-down;"),
+      (0.0, 25.0, 100.0, 50.0)
+    );
+  }
+
+  #[test]
+  fn object_position_places_the_image_in_the_free_space() {
+    let contain = "object-fit: contain;";
+    assert_eq!(
+      object_rect(&format!("{} object-position: top left;", contain)),
+      (0.0, 0.0, 100.0, 50.0)
+    );
+    assert_eq!(
+      object_rect(&format!("{} object-position: 0 100%;", contain)),
+      (0.0, 50.0, 100.0, 50.0)
+    );
+    assert_eq!(
+      object_rect("object-fit: none; object-position: right 10px;"),
+      (-100.0, 10.0, 200.0, 100.0)
+    );
+  }
+}
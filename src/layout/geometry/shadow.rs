@@ -0,0 +1,93 @@
+//! Box shadows: the shadows that `box-shadow` casts outside the border box of a box, or inside
+//! its padding box for `inset` shadows, in the shape of the box with its rounded corners.
+//!
+//! https://www.w3.org/TR/css-backgrounds-3/#box-shadow
+
+use super::{LayoutBox, Rect, RoundedRect};
+use crate::gfx::colors::Color;
+use crate::layout::css::Value;
+
+/// A shadow of a box, resolved against the box.
+#[derive(Debug, Copy, Clone)]
+pub struct BoxShadow {
+  pub color: Color,
+  // The shape that casts the shadow: the edge of the box, moved by the offset of the shadow and
+  // grown by its spread, or shrunk by it for an inset shadow.
+  pub shape: RoundedRect,
+  // The standard deviation of the Gaussian blur of the shadow, half its blur radius.
+  pub sigma: f32,
+  // The edge of the box that the shadow is painted outside of, or inside of for an inset
+  // shadow: the border edge or the padding edge.
+  pub edge: RoundedRect,
+  pub inset: bool,
+}
+
+impl BoxShadow {
+  /// The area that the shadow paints: its shape with the reach of its blur, which fades out
+  /// within three standard deviations, or the padding box for an inset shadow.
+  pub fn bounds(&self) -> Rect {
+    if self.inset {
+      self.edge.rect
+    } else {
+      self.shape.spread_by(3.0 * self.sigma + 1.0).rect
+    }
+  }
+}
+
+impl<'a> LayoutBox<'a> {
+  /// The shadows of the `box-shadow` of the box, from the top one to the bottom one. Shadows
+  /// without a color take the `color` of the box.
+  ///
+  /// https://www.w3.org/TR/css-backgrounds-3/#box-shadow
+  pub fn box_shadows(&self) -> Vec<BoxShadow> {
+    let style = match self.get_style_node_option() {
+      Some(style) => style,
+      None => return Vec::new(),
+    };
+    let current_color = match style.value("color") {
+      Some(Value::ColorValue(color)) => color,
+      _ => Color::black(),
+    };
+
+    style
+      .comma_separated("box-shadow")
+      .iter()
+      .filter_map(|values| {
+        let mut lengths = Vec::new();
+        let mut color = current_color;
+        let mut inset = false;
+        for value in values {
+          match value {
+            Value::Keyword(keyword) if keyword == "inset" => inset = true,
+            Value::ColorValue(value) => color = *value,
+            Value::Length(..) | Value::Number(_) => lengths.push(value.to_px(0.0, self.viewport)),
+            // `none`, or a value that isn't valid.
+            _ => return None,
+          }
+        }
+        let (offset_x, offset_y, blur, spread) = match lengths.as_slice() {
+          [x, y] => (*x, *y, 0.0, 0.0),
+          [x, y, blur] => (*x, *y, *blur, 0.0),
+          [x, y, blur, spread] => (*x, *y, *blur, *spread),
+          _ => return None,
+        };
+
+        let edge = if inset {
+          self.rounded_padding_box()
+        } else {
+          self.rounded_border_box()
+        };
+        let mut shape = edge.spread_by(if inset { -spread } else { spread });
+        shape.rect.x += offset_x;
+        shape.rect.y += offset_y;
+        Some(BoxShadow {
+          color,
+          shape,
+          sigma: blur.max(0.0) / 2.0,
+          edge,
+          inset,
+        })
+      })
+      .collect()
+  }
+}
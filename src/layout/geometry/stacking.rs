@@ -0,0 +1,115 @@
+//! Stacking contexts: the boxes that are painted together with their descendants, in the order
+//! of their `z-index` among the other stacking contexts of their parent context, and the
+//! positioned boxes that are painted after the rest of the content of their context.
+//!
+//! Floats and transforms aren't laid out, but `transform` and the other properties that create
+//! a stacking context still do.
+//!
+//! https://www.w3.org/TR/CSS22/zindex.html
+//! https://www.w3.org/TR/css-position-4/#painting-order
+
+use super::{BoxType, LayoutBox};
+use crate::layout::style::PositionStyle;
+
+/// How a box takes part in the painting order of its stacking context.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StackLevel {
+  // The box creates a stacking context, painted in the layer of its `z-index`.
+  Context(i32),
+  // The box is positioned without a `z-index`, so it is painted in the layer of `z-index: 0` as
+  // if it created a stacking context, but its positioned descendants and the stacking contexts
+  // in it belong to the parent context.
+  Positioned,
+  // The box is painted with the in-flow content of its stacking context.
+  InFlow,
+}
+
+impl<'a> LayoutBox<'a> {
+  /// Where the box is painted in its stacking context, given the box it is a child of. A
+  /// `z-index` applies to positioned boxes, and to flex and grid items.
+  ///
+  /// https://www.w3.org/TR/css-position-4/#stacking-context
+  pub fn stack_level(&self, parent: Option<&LayoutBox>) -> StackLevel {
+    let style = match self.get_style_node_option() {
+      Some(style) => style,
+      None => return StackLevel::InFlow,
+    };
+    let position = style.position();
+    let is_item = parent
+      .and_then(LayoutBox::get_style_node_option)
+      .is_some_and(|parent| {
+        let display = parent.display();
+        display.is_flex_container() || display.is_grid_container()
+      });
+    let z_index = match style.z_index() {
+      Some(z_index) if position != PositionStyle::Static || is_item => Some(z_index),
+      _ => None,
+    };
+
+    match z_index {
+      Some(z_index) => StackLevel::Context(z_index),
+      None if style.creates_stacking_context() => StackLevel::Context(0),
+      None if matches!(position, PositionStyle::Fixed | PositionStyle::Sticky) => {
+        StackLevel::Context(0)
+      }
+      None if position != PositionStyle::Static => StackLevel::Positioned,
+      None => StackLevel::InFlow,
+    }
+  }
+
+  /// Whether the box is inline-level, so its background is painted with the inline content of
+  /// its stacking context rather than with the backgrounds of the blocks.
+  pub fn is_inline_level(&self) -> bool {
+    matches!(
+      self.box_type,
+      BoxType::InlineNode(_) | BoxType::Marker(_) | BoxType::GeneratedText(..)
+    )
+  }
+
+  /// Whether the box is an inline-level box that is painted as a unit, like an `inline-block`,
+  /// as if it created a stacking context.
+  pub fn is_painted_atomically(&self) -> bool {
+    matches!(self.box_type, BoxType::InlineNode(_)) && self.is_atomic_inline()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, div, layout};
+  use super::StackLevel;
+
+  fn stack_levels(css: &str) -> Vec<StackLevel> {
+    let document = body(vec![div("item", vec![]), div("other", vec![])]);
+    layout(&document, css, |root| {
+      root
+        .children
+        .iter()
+        .map(|child| child.stack_level(Some(root)))
+        .collect()
+    })
+  }
+
+  #[test]
+  fn transforms_create_stacking_contexts() {
+    let levels = stack_levels(".item { transform: rotate(45deg); }");
+    assert_eq!(levels, vec![StackLevel::Context(0), StackLevel::InFlow]);
+
+    let levels = stack_levels(".item { transform: rotate(0.25turn) translate(10px, 2em); }");
+    assert_eq!(levels[0], StackLevel::Context(0));
+  }
+
+  #[test]
+  fn transforms_with_unknown_units_are_dropped() {
+    let levels = stack_levels(".item { transform: rotate(45furlongs); }");
+    assert_eq!(levels[0], StackLevel::InFlow);
+  }
+
+  #[test]
+  fn z_index_applies_to_positioned_boxes_and_flex_items() {
+    let levels = stack_levels(".item { position: relative; z-index: 2; } .other { z-index: 3; }");
+    assert_eq!(levels, vec![StackLevel::Context(2), StackLevel::InFlow]);
+
+    let levels = stack_levels("body { display: flex; } .item { z-index: -1; }");
+    assert_eq!(levels[0], StackLevel::Context(-1));
+  }
+}
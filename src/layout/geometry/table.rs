@@ -0,0 +1,942 @@
+//! Table layout: the formatting context established by `display: table | inline-table`.
+//!
+//! https://www.w3.org/TR/CSS2/tables.html
+
+use super::intrinsic::IntrinsicSizes;
+use super::{BoxType, Dimensions, EdgeSizes, LayoutBox, Rect, SimpleDimensions};
+use crate::layout::css::{Value, Viewport};
+use crate::layout::style::{DisplayStyle, StyledNode};
+
+/// Where a row is in the box tree: the index of its row group in the children of the table, if
+/// it has one, and its index in the children of its parent.
+type RowPath = (Option<usize>, usize);
+
+/// A cell placed in the grid of table slots.
+struct TableCell {
+  // Index of the cell in the children of its row.
+  index: usize,
+  row: usize,
+  column: usize,
+  row_span: usize,
+  column_span: usize,
+}
+
+#[derive(Copy, Clone, Default)]
+struct Column {
+  min: f32,
+  max: f32,
+  width: f32,
+  position: f32,
+  // The width set on the column or on a cell in it.
+  specified: Option<f32>,
+}
+
+/// Complete the table structure around table-internal boxes: boxes inside tables and rows that
+/// are not rows or cells are wrapped in anonymous rows and cells, and table-internal boxes with
+/// any other parent are wrapped in an anonymous table. Column groups drop anything that is not
+/// a column.
+///
+/// https://www.w3.org/TR/CSS2/tables.html#anonymous-boxes
+pub fn generate_anonymous_table_boxes<'a>(
+  parent: DisplayStyle,
+  children: Vec<LayoutBox<'a>>,
+) -> Vec<LayoutBox<'a>> {
+  if parent.is_table() {
+    wrap_runs(children, DisplayStyle::TableRow, |d| {
+      !d.is_proper_table_child()
+    })
+  } else if parent.is_table_row_group() {
+    wrap_runs(children, DisplayStyle::TableRow, |d| {
+      d != DisplayStyle::TableRow
+    })
+  } else if parent == DisplayStyle::TableColumnGroup {
+    // Column groups only contain columns, anything else is not rendered.
+    children
+      .into_iter()
+      .filter(|c| c.display() == DisplayStyle::TableColumn)
+      .collect()
+  } else if parent == DisplayStyle::TableRow {
+    wrap_runs(children, DisplayStyle::TableCell, |d| {
+      d != DisplayStyle::TableCell
+    })
+  } else {
+    let children = wrap_runs(children, DisplayStyle::TableRow, |d| {
+      d == DisplayStyle::TableCell
+    });
+    wrap_runs(children, DisplayStyle::Table, |d| d.is_proper_table_child())
+  }
+}
+
+/// Wrap each run of consecutive children that need a wrapper in an anonymous box of `wrapper`.
+fn wrap_runs<'a>(
+  children: Vec<LayoutBox<'a>>,
+  wrapper: DisplayStyle,
+  needs_wrapper: impl Fn(DisplayStyle) -> bool,
+) -> Vec<LayoutBox<'a>> {
+  let mut result = Vec::with_capacity(children.len());
+  let mut run = Vec::new();
+  for child in children {
+    if needs_wrapper(child.display()) {
+      run.push(child);
+      continue;
+    }
+    if !run.is_empty() {
+      result.push(anonymous_table_box(wrapper, std::mem::take(&mut run)));
+    }
+    result.push(child);
+  }
+  if !run.is_empty() {
+    result.push(anonymous_table_box(wrapper, run));
+  }
+  result
+}
+
+/// An anonymous table box wrapping a run of children, laid out in the same viewport as them.
+fn anonymous_table_box<'a>(display: DisplayStyle, children: Vec<LayoutBox<'a>>) -> LayoutBox<'a> {
+  let mut wrapper = LayoutBox::new(BoxType::AnonymousTable(display), children[0].viewport);
+  wrapper.children = generate_anonymous_table_boxes(display, children);
+  wrapper
+}
+
+impl<'a> LayoutBox<'a> {
+  /// Lay out an anonymous table, which has no margins, borders or padding and is placed below
+  /// the previous boxes in its container.
+  pub(super) fn layout_anonymous_table(
+    &mut self,
+    containing_block: Dimensions,
+    original_containing_block: &SimpleDimensions,
+  ) {
+    let container = containing_block.content;
+    self.dimensions = Dimensions::new(Rect {
+      x: container.x,
+      y: container.y + container.height,
+      width: container.width,
+      height: 0.0,
+    });
+    self.collapsed_margins = Default::default();
+    self.layout_children(original_containing_block);
+  }
+
+  /// Lay out the captions, rows and cells of a table within its content area.
+  ///
+  /// The table box stands in for both the table wrapper box and the table grid box, so captions
+  /// are part of its content area. Sets `self.dimensions.height` to the height of the captions
+  /// and rows, and shrinks the width of tables with `width: auto` to fit their columns.
+  pub(super) fn layout_table_children(&mut self, original_containing_block: &SimpleDimensions) {
+    let style = self.get_style_node_option();
+    let collapse = style.and_then(|s| s.keyword("border-collapse")).as_deref() == Some("collapse");
+    let (spacing_x, spacing_y) = match (collapse, style) {
+      (false, Some(style)) => border_spacing(style, self.viewport),
+      _ => (0.0, 0.0),
+    };
+
+    // Header groups are placed first and footer groups last, whatever their order in the tree.
+    let mut headers = Vec::new();
+    let mut bodies = Vec::new();
+    let mut footers = Vec::new();
+    let mut captions = Vec::new();
+    let mut column_boxes = Vec::new();
+    for (i, child) in self.children.iter().enumerate() {
+      let rows = (0..child.children.len()).map(|row| (Some(i), row));
+      match child.display() {
+        DisplayStyle::TableHeaderGroup => headers.extend(rows),
+        DisplayStyle::TableFooterGroup => footers.extend(rows),
+        DisplayStyle::TableRowGroup => bodies.extend(rows),
+        DisplayStyle::TableRow => bodies.push((None, i)),
+        DisplayStyle::TableCaption => captions.push(i),
+        DisplayStyle::TableColumn | DisplayStyle::TableColumnGroup => column_boxes.push(i),
+        _ => {}
+      }
+    }
+    let rows: Vec<RowPath> = headers.into_iter().chain(bodies).chain(footers).collect();
+
+    let (cells, slots) = self.place_cells(&rows);
+    let column_count = slots.iter().map(Vec::len).max().unwrap_or(0);
+
+    // Resolve the edges of the cells, which take part in the column widths.
+    let reference_width = self.dimensions.content.width;
+    for cell in &cells {
+      let row = rows[cell.row];
+      self.table_row(row).children[cell.index].calculate_edges(reference_width);
+    }
+    if collapse {
+      self.collapse_borders(&rows, &cells, &slots);
+    }
+
+    let column_widths = self.column_widths(&column_boxes, column_count, reference_width);
+    let mut columns = self.table_columns(&rows, &cells, &column_widths, spacing_x);
+    for column in &mut columns {
+      column.position += self.dimensions.content.x;
+    }
+    let table_width = self.dimensions.content.width;
+    let reference = SimpleDimensions::from_dimension(
+      self.dimensions.content,
+      self.direction(original_containing_block),
+    );
+
+    // Top captions, then the rows, then bottom captions.
+    let mut y = self.dimensions.content.y;
+    for &index in &captions {
+      if caption_side(&self.children[index]) != "bottom" {
+        y = self.layout_caption(index, y, &reference);
+      }
+    }
+
+    let row_heights = self.table_row_heights(
+      &rows,
+      &cells,
+      &columns,
+      spacing_y,
+      y,
+      original_containing_block,
+    );
+    let mut row_positions = Vec::with_capacity(rows.len());
+    for height in &row_heights {
+      y += spacing_y;
+      row_positions.push(y);
+      y += height;
+    }
+    if !rows.is_empty() {
+      y += spacing_y;
+    }
+    self.position_cells(&rows, &cells, &row_positions, &row_heights);
+
+    // Rows, row groups and columns cover the cells they contain.
+    let grid_x = columns
+      .first()
+      .map_or(self.dimensions.content.x, |c| c.position);
+    let grid_width = columns
+      .last()
+      .map_or(0.0, |c| c.position + c.width - grid_x);
+    let grid_y = row_positions.first().cloned().unwrap_or(y);
+    let grid_height = match rows.len() {
+      0 => 0.0,
+      n => row_positions[n - 1] + row_heights[n - 1] - grid_y,
+    };
+    for (r, &path) in rows.iter().enumerate() {
+      self.table_row(path).dimensions = Dimensions::new(Rect {
+        x: grid_x,
+        y: row_positions[r],
+        width: grid_width,
+        height: row_heights[r],
+      });
+    }
+    for child in &mut self.children {
+      if child.display().is_table_row_group() {
+        let bounds = child
+          .children
+          .iter()
+          .map(|row| row.dimensions.content)
+          .reduce(Rect::union);
+        child.dimensions = Dimensions::new(bounds.unwrap_or(Rect {
+          x: grid_x,
+          y: grid_y,
+          width: grid_width,
+          height: 0.0,
+        }));
+      }
+    }
+    self.position_column_boxes(&column_boxes, &columns, grid_y, grid_height);
+
+    for &index in &captions {
+      if caption_side(&self.children[index]) == "bottom" {
+        y = self.layout_caption(index, y, &reference);
+      }
+    }
+
+    let d = &mut self.dimensions;
+    d.content.width = table_width;
+    d.content.height = y - d.content.y;
+  }
+
+  fn table_row(&mut self, (group, index): RowPath) -> &mut LayoutBox<'a> {
+    match group {
+      Some(group) => &mut self.children[group].children[index],
+      None => &mut self.children[index],
+    }
+  }
+
+  /// Assign each cell to the slots it covers, going through the rows in order.
+  ///
+  /// https://html.spec.whatwg.org/multipage/tables.html#forming-a-table
+  fn place_cells(&mut self, rows: &[RowPath]) -> (Vec<TableCell>, Vec<Vec<Option<usize>>>) {
+    let mut cells = Vec::new();
+    let mut slots: Vec<Vec<Option<usize>>> = vec![Vec::new(); rows.len()];
+
+    for (r, &path) in rows.iter().enumerate() {
+      let mut column = 0;
+      for (index, cell) in self.table_row(path).children.iter().enumerate() {
+        let node = cell.get_style_node_option();
+        let column_span = span_attribute(node, "colspan").unwrap_or(1).clamp(1, 1000);
+        // A rowspan of zero spans the remaining rows.
+        let row_span = match span_attribute(node, "rowspan").unwrap_or(1) {
+          0 => rows.len() - r,
+          span => span.min(rows.len() - r),
+        };
+
+        while let Some(Some(_)) = slots[r].get(column) {
+          column += 1;
+        }
+        for row in &mut slots[r..r + row_span] {
+          if row.len() < column + column_span {
+            row.resize(column + column_span, None);
+          }
+          for slot in &mut row[column..column + column_span] {
+            *slot = Some(cells.len());
+          }
+        }
+
+        cells.push(TableCell {
+          index,
+          row: r,
+          column,
+          row_span,
+          column_span,
+        });
+        column += column_span;
+      }
+    }
+
+    (cells, slots)
+  }
+
+  /// Resolve the borders in the collapsing border model, where adjacent cells share a border
+  /// that is as wide as the widest of the borders meeting there. Each cell gets half of it, and
+  /// the table gets half of the borders along its outer edges.
+  ///
+  /// https://www.w3.org/TR/CSS2/tables.html#collapsing-borders
+  fn collapse_borders(
+    &mut self,
+    rows: &[RowPath],
+    cells: &[TableCell],
+    slots: &[Vec<Option<usize>>],
+  ) {
+    let borders: Vec<EdgeSizes> = cells
+      .iter()
+      .map(|cell| {
+        self.table_row(rows[cell.row]).children[cell.index]
+          .dimensions
+          .border
+      })
+      .collect();
+    let outer = self.dimensions.border;
+    let mut table_border = outer;
+    let slot = |row: usize, column: usize| slots.get(row).and_then(|r| r.get(column)).cloned();
+
+    for (i, cell) in cells.iter().enumerate() {
+      let rows_covered = cell.row..cell.row + cell.row_span;
+      let columns_covered = cell.column..cell.column + cell.column_span;
+      let mut border = borders[i];
+
+      for column in columns_covered.clone() {
+        match cell.row.checked_sub(1).and_then(|row| slot(row, column)) {
+          Some(Some(above)) => border.top = border.top.max(borders[above].bottom),
+          _ => {
+            border.top = border.top.max(outer.top);
+            table_border.top = table_border.top.max(border.top);
+          }
+        }
+        match slot(cell.row + cell.row_span, column) {
+          Some(Some(below)) => border.bottom = border.bottom.max(borders[below].top),
+          _ => {
+            border.bottom = border.bottom.max(outer.bottom);
+            table_border.bottom = table_border.bottom.max(border.bottom);
+          }
+        }
+      }
+      for row in rows_covered {
+        match cell
+          .column
+          .checked_sub(1)
+          .and_then(|column| slot(row, column))
+        {
+          Some(Some(before)) => border.left = border.left.max(borders[before].right),
+          _ => {
+            border.left = border.left.max(outer.left);
+            table_border.left = table_border.left.max(border.left);
+          }
+        }
+        match slot(row, cell.column + cell.column_span) {
+          Some(Some(after)) => border.right = border.right.max(borders[after].left),
+          _ => {
+            border.right = border.right.max(outer.right);
+            table_border.right = table_border.right.max(border.right);
+          }
+        }
+      }
+
+      let d = &mut self.table_row(rows[cell.row]).children[cell.index].dimensions;
+      d.border = EdgeSizes {
+        left: border.left / 2.0,
+        right: border.right / 2.0,
+        top: border.top / 2.0,
+        bottom: border.bottom / 2.0,
+      };
+    }
+
+    // The table has no padding, and its border box stays where it is.
+    let d = &mut self.dimensions;
+    let border_box = d.border_box();
+    d.padding = EdgeSizes::default();
+    d.border = EdgeSizes {
+      left: table_border.left / 2.0,
+      right: table_border.right / 2.0,
+      top: table_border.top / 2.0,
+      bottom: table_border.bottom / 2.0,
+    };
+    d.content.x = border_box.x + d.border.left;
+    d.content.y = border_box.y + d.border.top;
+    d.content.width = (border_box.width - d.border.left - d.border.right).max(0.0);
+  }
+
+  /// The `width` of each column set by column and column group boxes, if any.
+  fn column_widths(
+    &self,
+    column_boxes: &[usize],
+    column_count: usize,
+    reference_width: f32,
+  ) -> Vec<Option<f32>> {
+    let viewport = self.viewport;
+    let mut widths = Vec::new();
+    for &index in column_boxes {
+      let column_box = &self.children[index];
+      let node = column_box.get_style_node_option();
+      let width = node.and_then(|s| s.length("width", reference_width, viewport));
+
+      let columns: Vec<&LayoutBox> = column_box
+        .children
+        .iter()
+        .filter(|c| c.display() == DisplayStyle::TableColumn)
+        .collect();
+      if column_box.display() == DisplayStyle::TableColumnGroup && !columns.is_empty() {
+        for column in columns {
+          let node = column.get_style_node_option();
+          let span = span_attribute(node, "span").unwrap_or(1).clamp(1, 1000);
+          let column_width = node.and_then(|s| s.length("width", reference_width, viewport));
+          widths.extend(vec![column_width.or(width); span]);
+        }
+      } else {
+        let span = span_attribute(node, "span").unwrap_or(1).clamp(1, 1000);
+        widths.extend(vec![width; span]);
+      }
+    }
+
+    widths.resize(column_count, None);
+    widths
+  }
+
+  /// Size and position the columns, relative to the start of the content area.
+  ///
+  /// Tables with `table-layout: fixed` and a definite width only look at the columns and the
+  /// first row, the automatic table layout looks at all cells. Updates the width of the table
+  /// to fit its columns if needed.
+  ///
+  /// https://www.w3.org/TR/CSS2/tables.html#width-layout
+  fn table_columns(
+    &mut self,
+    rows: &[RowPath],
+    cells: &[TableCell],
+    column_widths: &[Option<f32>],
+    spacing: f32,
+  ) -> Vec<Column> {
+    let style = self.get_style_node_option();
+    let available = self.dimensions.content.width;
+    let definite_width = style.and_then(|s| s.length("width", available, self.viewport));
+    let fixed = style.and_then(|s| s.keyword("table-layout")).as_deref() == Some("fixed");
+    let total_spacing = spacing * (column_widths.len() + 1) as f32;
+
+    let mut columns = vec![Column::default(); column_widths.len()];
+    let table_width = if fixed && definite_width.is_some() {
+      let mut widths = column_widths.to_vec();
+      for cell in cells.iter().filter(|cell| cell.row == 0) {
+        let cell_box = &self.table_row(rows[cell.row]).children[cell.index];
+        let width = cell_box.cell_width(available);
+        if let Some(width) = width {
+          let outer = width + horizontal_edges(&cell_box.dimensions);
+          for column in &mut widths[cell.column..cell.column + cell.column_span] {
+            column.get_or_insert(outer / cell.column_span as f32);
+          }
+        }
+      }
+
+      // Columns without a width share the remaining space.
+      let assigned: f32 = widths.iter().flatten().sum();
+      let unassigned = widths.iter().filter(|w| w.is_none()).count();
+      let remaining = (available - total_spacing - assigned).max(0.0);
+      for (column, width) in columns.iter_mut().zip(&widths) {
+        column.width = width.unwrap_or(remaining / unassigned as f32);
+      }
+      available.max(assigned + total_spacing)
+    } else {
+      for (column, width) in columns.iter_mut().zip(column_widths) {
+        column.specified = *width;
+      }
+
+      let mut contributions = Vec::with_capacity(cells.len());
+      for cell in cells {
+        let cell_box = &self.table_row(rows[cell.row]).children[cell.index];
+        let edges = horizontal_edges(&cell_box.dimensions);
+        let width = cell_box.cell_width(available);
+        let sizes = cell_box.intrinsic_sizes();
+        contributions.push((
+          sizes.min_content + edges,
+          sizes.max_content + edges,
+          width.map(|w| w + edges),
+        ));
+
+        // A width on a cell spanning one column sets the width of that column.
+        if let (1, Some(width)) = (cell.column_span, width) {
+          let column = &mut columns[cell.column];
+          column.specified = Some(column.specified.unwrap_or(0.0).max(width + edges));
+        }
+      }
+
+      // A column with a width is as wide as that, unless its cells need more.
+      for (cell, &(min, max, width)) in cells.iter().zip(&contributions) {
+        if cell.column_span == 1 {
+          let column = &mut columns[cell.column];
+          column.min = column.min.max(width.map_or(min, |w| w.max(min)));
+          column.max = column.max.max(width.map_or(max, |w| w.max(min)));
+        }
+      }
+      for column in &mut columns {
+        if let Some(width) = column.specified {
+          column.min = column.min.max(width);
+          column.max = column.max.min(width).max(column.min);
+        }
+      }
+
+      // Cells spanning several columns only add what the columns are missing, preferably to
+      // columns without a width.
+      for (cell, &(min, max, width)) in cells.iter().zip(&contributions) {
+        if cell.column_span == 1 {
+          continue;
+        }
+        let spanned = &mut columns[cell.column..cell.column + cell.column_span];
+        let inner_spacing = spacing * (cell.column_span - 1) as f32;
+        let min = width.map_or(min, |w| w.max(min)) - inner_spacing;
+        let max = width.map_or(max, |w| w.max(min)) - inner_spacing;
+        let min_deficit = min - spanned.iter().map(|c| c.min).sum::<f32>();
+        let max_deficit = max - spanned.iter().map(|c| c.max).sum::<f32>();
+
+        let flexible = spanned.iter().any(|c| c.specified.is_none());
+        let growing = |c: &&mut Column| !flexible || c.specified.is_none();
+        let count = spanned.iter_mut().filter(growing).count() as f32;
+        for column in spanned.iter_mut().filter(growing) {
+          column.min += min_deficit.max(0.0) / count;
+          column.max += max_deficit.max(0.0) / count;
+          column.max = column.max.max(column.min);
+        }
+      }
+
+      let min_total: f32 = columns.iter().map(|c| c.min).sum();
+      let max_total: f32 = columns.iter().map(|c| c.max).sum();
+      let table_width = match definite_width {
+        Some(_) => available.max(min_total + total_spacing),
+        None => (min_total + total_spacing).max(available.min(max_total + total_spacing)),
+      };
+
+      // Grow the columns from their minimum towards their maximum width, and past it in
+      // proportion to their maximum width if there is still space left, preferably the columns
+      // without a width.
+      let width = table_width - total_spacing;
+      let flexible = columns.iter().any(|c| c.specified.is_none());
+      let growing = |c: &Column| !flexible || c.specified.is_none();
+      let growing_max: f32 = columns.iter().filter(|c| growing(c)).map(|c| c.max).sum();
+      let growing_count = columns.iter().filter(|c| growing(c)).count() as f32;
+      for column in &mut columns {
+        column.width = if width >= max_total {
+          let share = match (growing(column), growing_max) {
+            (false, _) => 0.0,
+            (true, total) if total > 0.0 => column.max / total,
+            (true, _) => 1.0 / growing_count,
+          };
+          column.max + (width - max_total) * share
+        } else if max_total > min_total {
+          column.min + (column.max - column.min) * (width - min_total) / (max_total - min_total)
+        } else {
+          column.min
+        };
+      }
+      table_width
+    };
+
+    let mut position = spacing;
+    for column in &mut columns {
+      column.position = position;
+      position += column.width + spacing;
+    }
+
+    self.resize_table(table_width, definite_width.is_none());
+    columns
+  }
+
+  /// Change the width of the table's content area, moving it to keep auto margins filling the
+  /// rest of the containing block.
+  fn resize_table(&mut self, width: f32, auto_width: bool) {
+    let auto = Value::Keyword("auto".to_string());
+    let zero = Value::Number(0.0);
+    let auto_margin = |name: &str| match self.get_style_node_option() {
+      Some(style) => auto_width && style.lookup(name, "margin", &zero) == auto,
+      None => false,
+    };
+    let shift = match (auto_margin("margin-left"), auto_margin("margin-right")) {
+      (true, true) => 0.5,
+      (true, false) => 1.0,
+      _ => 0.0,
+    };
+
+    let d = &mut self.dimensions;
+    let difference = d.content.width - width;
+    d.content.width = width;
+    d.content.x += difference * shift;
+    d.margin.left += difference * shift;
+    d.margin.right += difference * (1.0 - shift);
+  }
+
+  /// The width of the content box of a cell set by its `width`, if any.
+  fn cell_width(&self, reference_width: f32) -> Option<f32> {
+    let d = self.dimensions;
+    let edges = d.border.left + d.border.right + d.padding.left + d.padding.right;
+    self.width_property("width", reference_width, edges, reference_width - edges)
+  }
+
+  /// The intrinsic sizes of a table: its columns next to each other, each as wide as its widest
+  /// cell, and at least as wide as its captions. A cell spanning several columns adds an equal
+  /// share to each, and cells spanning several rows don't move the cells of the next rows.
+  ///
+  /// https://www.w3.org/TR/CSS2/tables.html#auto-table-layout
+  pub(super) fn table_intrinsic_sizes(&self) -> IntrinsicSizes {
+    let style = self.get_style_node_option();
+    let collapse = style.and_then(|s| s.keyword("border-collapse")).as_deref() == Some("collapse");
+    let spacing = match (collapse, style) {
+      (false, Some(style)) => border_spacing(style, self.viewport).0,
+      _ => 0.0,
+    };
+
+    let mut columns: Vec<IntrinsicSizes> = Vec::new();
+    let mut captions = IntrinsicSizes::default();
+    for child in &self.children {
+      let rows: Vec<&LayoutBox> = match child.display() {
+        display if display.is_table_row_group() => child.children.iter().collect(),
+        DisplayStyle::TableRow => vec![child],
+        DisplayStyle::TableCaption => {
+          captions = captions.stacked(child.intrinsic_contributions());
+          continue;
+        }
+        _ => continue,
+      };
+      for row in rows {
+        let mut column = 0;
+        for cell in &row.children {
+          let node = cell.get_style_node_option();
+          let span = span_attribute(node, "colspan").unwrap_or(1).clamp(1, 1000);
+          if columns.len() < column + span {
+            columns.resize(column + span, IntrinsicSizes::default());
+          }
+          let inner_spacing = spacing * (span - 1) as f32;
+          let share = cell
+            .intrinsic_contributions()
+            .map(|size| (size - inner_spacing) / span as f32);
+          for sizes in &mut columns[column..column + span] {
+            *sizes = sizes.stacked(share);
+          }
+          column += span;
+        }
+      }
+    }
+
+    let total_spacing = spacing * (columns.len() + 1) as f32;
+    columns
+      .into_iter()
+      .fold(IntrinsicSizes::default(), IntrinsicSizes::sum)
+      .map(|size| size + total_spacing)
+      .stacked(captions)
+  }
+
+  /// Lay out the cells at the width of their columns and find the height of each row.
+  ///
+  /// A row is as tall as its tallest cell, or its `height` if that is larger. Cells spanning
+  /// several rows and a `height` on the table make the rows grow evenly.
+  ///
+  /// https://www.w3.org/TR/CSS2/tables.html#height-layout
+  fn table_row_heights(
+    &mut self,
+    rows: &[RowPath],
+    cells: &[TableCell],
+    columns: &[Column],
+    spacing: f32,
+    y: f32,
+    original_containing_block: &SimpleDimensions,
+  ) -> Vec<f32> {
+    let viewport = self.viewport;
+    let reference = SimpleDimensions::from_dimension(
+      self.dimensions.content,
+      self.direction(original_containing_block),
+    );
+    let mut heights: Vec<f32> = rows
+      .iter()
+      .map(|&path| {
+        self
+          .table_row(path)
+          .get_style_node_option()
+          .and_then(|s| s.length("height", original_containing_block.height, viewport))
+          .unwrap_or(0.0)
+      })
+      .collect();
+
+    let mut cell_heights = Vec::with_capacity(cells.len());
+    for cell in cells {
+      let first = columns[cell.column];
+      let last = columns[cell.column + cell.column_span - 1];
+      let cell_box = &mut self.table_row(rows[cell.row]).children[cell.index];
+      let d = cell_box.dimensions;
+      let width = last.position + last.width - first.position - horizontal_edges(&d);
+      cell_box.layout_block_contents(
+        first.position + d.margin.left + d.border.left + d.padding.left,
+        y + d.margin.top + d.border.top + d.padding.top,
+        width.max(0.0),
+        &reference,
+      );
+      cell_heights.push(cell_box.dimensions.margin_box().height);
+    }
+
+    for (cell, &height) in cells.iter().zip(&cell_heights) {
+      if cell.row_span == 1 {
+        heights[cell.row] = heights[cell.row].max(height);
+      }
+    }
+    for (cell, &height) in cells.iter().zip(&cell_heights) {
+      let spanned = &mut heights[cell.row..cell.row + cell.row_span];
+      let inner_spacing = spacing * (cell.row_span - 1) as f32;
+      let deficit = height - inner_spacing - spanned.iter().sum::<f32>();
+      if cell.row_span > 1 && deficit > 0.0 {
+        for row in spanned.iter_mut() {
+          *row += deficit / cell.row_span as f32;
+        }
+      }
+    }
+
+    let d = self.dimensions;
+    let edges = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+    let table_height = self
+      .get_style_node_option()
+      .and_then(|s| s.size("height", original_containing_block.height, edges, viewport));
+    if let (Some(table_height), false) = (table_height, rows.is_empty()) {
+      let caption_height = y - self.dimensions.content.y;
+      let rows_height = heights.iter().sum::<f32>() + spacing * (rows.len() + 1) as f32;
+      let extra = table_height - caption_height - rows_height;
+      if extra > 0.0 {
+        for height in &mut heights {
+          *height += extra / rows.len() as f32;
+        }
+      }
+    }
+
+    heights
+  }
+
+  /// Move the cells to their rows and stretch them to the height of the rows they span, then
+  /// align their contents with `vertical-align`.
+  fn position_cells(
+    &mut self,
+    rows: &[RowPath],
+    cells: &[TableCell],
+    row_positions: &[f32],
+    row_heights: &[f32],
+  ) {
+    for cell in cells {
+      let last = cell.row + cell.row_span - 1;
+      let span_height = row_positions[last] + row_heights[last] - row_positions[cell.row];
+      let cell_box = &mut self.table_row(rows[cell.row]).children[cell.index];
+      let d = cell_box.dimensions;
+
+      // Cells were laid out where the first row would start.
+      cell_box.translate(0.0, row_positions[cell.row] - d.margin_box().y);
+
+      let height = span_height - (d.margin_box().height - d.content.height);
+      let free_space = (height - d.content.height).max(0.0);
+      let offset = match cell_box
+        .get_style_node_option()
+        .and_then(|s| s.keyword("vertical-align"))
+        .as_deref()
+      {
+        Some("middle") => free_space / 2.0,
+        Some("bottom") => free_space,
+        _ => 0.0,
+      };
+      cell_box.dimensions.content.height = height.max(d.content.height);
+      for child in &mut cell_box.children {
+        child.translate(0.0, offset);
+      }
+    }
+  }
+
+  /// Lay out a caption at the width of the table, returns the position below it.
+  fn layout_caption(&mut self, index: usize, y: f32, reference: &SimpleDimensions) -> f32 {
+    let x = self.dimensions.content.x;
+    let width = self.dimensions.content.width;
+    let caption = &mut self.children[index];
+    caption.calculate_edges(width);
+    let d = caption.dimensions;
+    caption.layout_block_contents(
+      x + d.margin.left + d.border.left + d.padding.left,
+      y + d.margin.top + d.border.top + d.padding.top,
+      (width - horizontal_edges(&d)).max(0.0),
+      reference,
+    );
+    y + caption.dimensions.margin_box().height
+  }
+
+  /// Make column and column group boxes cover the slots of their columns.
+  fn position_column_boxes(
+    &mut self,
+    column_boxes: &[usize],
+    columns: &[Column],
+    y: f32,
+    height: f32,
+  ) {
+    let rect = |start: usize, span: usize| {
+      let spanned = &columns[start.min(columns.len())..(start + span).min(columns.len())];
+      match (spanned.first(), spanned.last()) {
+        (Some(first), Some(last)) => Rect {
+          x: first.position,
+          y,
+          width: last.position + last.width - first.position,
+          height,
+        },
+        _ => Rect {
+          y,
+          ..Rect::default()
+        },
+      }
+    };
+
+    let mut next = 0;
+    for &index in column_boxes {
+      let column_box = &mut self.children[index];
+      let start = next;
+      let has_columns = column_box
+        .children
+        .iter()
+        .any(|c| c.display() == DisplayStyle::TableColumn);
+      if column_box.display() == DisplayStyle::TableColumnGroup && has_columns {
+        for column in &mut column_box.children {
+          let span = span_attribute(column.get_style_node_option(), "span").unwrap_or(1);
+          column.dimensions = Dimensions::new(rect(next, span.clamp(1, 1000)));
+          next += span.clamp(1, 1000);
+        }
+      } else {
+        next += span_attribute(column_box.get_style_node_option(), "span")
+          .unwrap_or(1)
+          .clamp(1, 1000);
+      }
+      column_box.dimensions = Dimensions::new(rect(start, next - start));
+    }
+  }
+}
+
+/// The horizontal and vertical `border-spacing`.
+fn border_spacing(style: &StyledNode, viewport: Viewport) -> (f32, f32) {
+  match style.value("border-spacing") {
+    Some(Value::List(values)) => (
+      values[0].to_px(0.0, viewport),
+      values[values.len() - 1].to_px(0.0, viewport),
+    ),
+    Some(value) => (value.to_px(0.0, viewport), value.to_px(0.0, viewport)),
+    None => (0.0, 0.0),
+  }
+}
+
+fn caption_side(caption: &LayoutBox) -> String {
+  caption
+    .get_style_node_option()
+    .and_then(|s| s.keyword("caption-side"))
+    .unwrap_or_else(|| "top".to_string())
+}
+
+/// A non-negative integer attribute like `colspan`, `rowspan` or `span`.
+fn span_attribute(node: Option<&StyledNode>, name: &str) -> Option<usize> {
+  node
+    .and_then(|node| node.attribute(name))
+    .and_then(|value| value.trim().parse().ok())
+}
+
+fn horizontal_edges(d: &Dimensions) -> f32 {
+  d.margin.left + d.margin.right + d.border.left + d.border.right + d.padding.left + d.padding.right
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, border_box, div, layout};
+
+  fn row(cells: usize) -> crate::layout::dom::Node {
+    div("row", (0..cells).map(|_| div("cell", vec![])).collect())
+  }
+
+  #[test]
+  fn cells_share_the_width_of_a_fixed_table() {
+    let document = body(vec![div("table", vec![row(2), row(2)])]);
+    let css = ".table { display: table; table-layout: fixed; width: 300px; } \
+               .row { display: table-row; } .cell { display: table-cell; height: 20px; }";
+    layout(&document, css, |root| {
+      let cell = |r, c| border_box(root, &[0, r, c]);
+      assert_eq!(cell(0, 0), (0.0, 0.0, 150.0, 20.0));
+      assert_eq!(cell(0, 1), (150.0, 0.0, 150.0, 20.0));
+      assert_eq!(cell(1, 0), (0.0, 20.0, 150.0, 20.0));
+    });
+  }
+
+  #[test]
+  fn border_spacing_separates_cells() {
+    let document = body(vec![div("table", vec![row(2)])]);
+    let css = ".table { display: table; table-layout: fixed; width: 310px; \
+                 border-spacing: 10px 5px; } \
+               .row { display: table-row; } .cell { display: table-cell; height: 20px; }";
+    layout(&document, css, |root| {
+      let cell = |c| border_box(root, &[0, 0, c]);
+      assert_eq!(cell(0), (10.0, 5.0, 140.0, 20.0));
+      assert_eq!(cell(1), (160.0, 5.0, 140.0, 20.0));
+      assert_eq!(border_box(root, &[0]).3, 30.0);
+    });
+  }
+
+  #[test]
+  fn cells_outside_a_table_get_anonymous_table_boxes() {
+    let document = body(vec![div("cell", vec![]), div("cell", vec![])]);
+    let css = ".cell { display: table-cell; width: 50px; height: 20px; }";
+    layout(&document, css, |root| {
+      // A table wraps a row with both cells.
+      assert_eq!(root.children.len(), 1);
+      let row = &root.children[0].children[0];
+      assert_eq!(row.children.len(), 2);
+      assert_eq!(border_box(row, &[1]).0, 50.0);
+    });
+  }
+
+  #[test]
+  fn the_tallest_cell_sets_the_height_of_a_row() {
+    let document = body(vec![div(
+      "table",
+      vec![div("row", vec![div("short", vec![]), div("tall", vec![])])],
+    )]);
+    let css = ".table { display: table; width: 200px; } .row { display: table-row; } \
+               .short, .tall { display: table-cell; } \
+               .short { height: 10px; } .tall { height: 40px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0, 0]).3, 40.0);
+      assert_eq!(border_box(root, &[0]).3, 40.0);
+    });
+  }
+
+  #[test]
+  fn anonymous_cells_have_the_direction_of_their_table() {
+    let document = body(vec![div("table", vec![div("item", vec![])])]);
+    let css = ".table { display: table; table-layout: fixed; width: 200px; direction: rtl; } \
+               .item { display: inline-block; width: 50px; height: 10px; }";
+    layout(&document, css, |root| {
+      // The item starts the line of the anonymous cell from its right edge.
+      assert_eq!(border_box(root, &[0, 0, 0, 0, 0]), (150.0, 0.0, 50.0, 10.0));
+    });
+  }
+}
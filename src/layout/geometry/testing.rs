@@ -0,0 +1,74 @@
+//! Helpers for the tests of the layout algorithms: documents of `div`s with classes, styled by
+//! a stylesheet and laid out in a viewport.
+
+use super::{layout_geometry_tree, layout_paged_geometry_tree, Dimensions, LayoutBox};
+use super::{PageContext, Rect};
+use crate::layout::css;
+use crate::layout::dom::{elem, Node, NodeType};
+use crate::layout::image::Image;
+use crate::layout::style::create_styletree;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The rules every test stylesheet starts with, so that only the rules under test need to be
+/// written out.
+const BASE_STYLESHEET: &str = "body { display: block; margin: 0; } div { display: block; }";
+
+/// A `div` with a class.
+pub fn div(class: &str, children: Vec<Node>) -> Node {
+  let mut attributes = HashMap::new();
+  attributes.insert("class".to_string(), class.to_string());
+  elem("div".to_string(), attributes, children)
+}
+
+/// An `img` with a class, showing a blank image of some size.
+pub fn img(class: &str, width: u32, height: u32) -> Node {
+  let mut attributes = HashMap::new();
+  attributes.insert("class".to_string(), class.to_string());
+  let mut node = elem("img".to_string(), attributes, vec![]);
+  if let NodeType::Element(data) = &mut node.node_type {
+    data.image = Some(Rc::new(Image {
+      width,
+      height,
+      pixels: vec![0; (width * height * 4) as usize],
+    }));
+  }
+  node
+}
+
+/// The `body` of a document with some children.
+pub fn body(children: Vec<Node>) -> Node {
+  elem("body".to_string(), HashMap::new(), children)
+}
+
+/// Lay out a document in an 800 by 600 viewport and check the layout tree.
+pub fn layout<R>(document: &Node, stylesheet: &str, check: impl FnOnce(&LayoutBox) -> R) -> R {
+  let stylesheet = css::parse(format!("{} {}", BASE_STYLESHEET, stylesheet));
+  let style_tree = create_styletree(document, &stylesheet);
+  let viewport = Dimensions::new(Rect {
+    x: 0.0,
+    y: 0.0,
+    width: 800.0,
+    height: 600.0,
+  });
+  check(&layout_geometry_tree(&style_tree, viewport))
+}
+
+/// Lay out a document on the pages of the `@page` rules of its stylesheet, and return where the
+/// slice of the content on each page starts and ends.
+pub fn pages(document: &Node, stylesheet: &str) -> Vec<(f32, f32)> {
+  let stylesheet = css::parse(format!("{} {}", BASE_STYLESHEET, stylesheet));
+  let style_tree = create_styletree(document, &stylesheet);
+  let (_, pages) = layout_paged_geometry_tree(&style_tree, &PageContext::new(&stylesheet));
+  pages.iter().map(|page| (page.start, page.end)).collect()
+}
+
+/// The border box of the descendant of a box at the path of child indices, as `(x, y, width,
+/// height)`.
+pub fn border_box(root: &LayoutBox, path: &[usize]) -> (f32, f32, f32, f32) {
+  let layout_box = path
+    .iter()
+    .fold(root, |layout_box, &index| &layout_box.children[index]);
+  let rect = layout_box.dimensions.border_box();
+  (rect.x, rect.y, rect.width, rect.height)
+}
@@ -0,0 +1,120 @@
+//! Truncating inline content with an ellipsis: the lines that overflow a block with
+//! `text-overflow: ellipsis`, and the last line of a block clamped by `line-clamp`.
+//!
+//! The atomic inlines that the ellipsis would overlap are hidden entirely, but the first one on a
+//! line always stays, and the ellipsis is placed right after the remaining content, on the end
+//! side. The lines after a clamped one are hidden and don't take up space in the block.
+//!
+//! Text is not laid out yet, so lines only hold atomic inlines and are never cut inside one, and
+//! the ellipsis is a box of generated text that takes up space at the end of the line but, like
+//! other text, isn't painted.
+//!
+//! https://www.w3.org/TR/css-overflow-3/#text-overflow
+//! https://www.w3.org/TR/css-overflow-4/#line-clamp
+
+use super::{BoxType, LayoutBox};
+use crate::layout::css::{Value, Viewport};
+use crate::layout::style::StyledNode;
+
+/// The ellipsis of a clamped line, and of `text-overflow: ellipsis`.
+const ELLIPSIS: &str = "\u{2026}";
+
+impl<'a> LayoutBox<'a> {
+  /// Whether the box is hidden because its line was truncated, and isn't painted.
+  pub fn is_truncated(&self) -> bool {
+    self.truncated
+  }
+
+  /// Whether the box is the ellipsis added to a truncated line, which is generated text without
+  /// a pseudo-element.
+  pub(super) fn is_ellipsis(&self) -> bool {
+    match self.box_type {
+      BoxType::GeneratedText(style, _) => style.pseudo_element.is_none(),
+      _ => false,
+    }
+  }
+}
+
+/// The ellipsis box of a line in a block with some style, laid out at the origin.
+pub(super) fn ellipsis_box<'a>(
+  block: &'a StyledNode<'a>,
+  text: String,
+  viewport: Viewport,
+) -> LayoutBox<'a> {
+  let mut ellipsis = LayoutBox::new(BoxType::GeneratedText(block, text), viewport);
+  ellipsis.layout_generated_text();
+  ellipsis
+}
+
+/// The text that replaces the end of the lines that overflow a block, from `text-overflow`. Only
+/// blocks that clip their overflow along the lines truncate them.
+///
+/// https://www.w3.org/TR/css-overflow-3/#text-overflow
+pub(super) fn text_overflow_ellipsis(block: &StyledNode) -> Option<String> {
+  let (overflow_x, overflow_y) = block.overflow();
+  let inline_overflow = if block.writing_mode.is_vertical() {
+    overflow_y
+  } else {
+    overflow_x
+  };
+  if !inline_overflow.clips() {
+    return None;
+  }
+  match block.value("text-overflow")? {
+    Value::Keyword(ref keyword) if keyword == "ellipsis" => Some(ELLIPSIS.to_string()),
+    Value::Str(string) => Some(string),
+    _ => None,
+  }
+}
+
+/// The number of lines a block is clamped to by `line-clamp`, or by the legacy
+/// `-webkit-line-clamp` on a `-webkit-box` with `-webkit-box-orient: vertical`, and its ellipsis.
+///
+/// https://www.w3.org/TR/css-overflow-4/#propdef-line-clamp
+/// https://www.w3.org/TR/css-overflow-4/#webkit-line-clamp
+pub(super) fn line_clamp(block: &StyledNode) -> Option<(usize, String)> {
+  let webkit_box = matches!(
+    block.keyword("display").as_deref(),
+    Some("-webkit-box") | Some("-webkit-inline-box")
+  ) && block.keyword("-webkit-box-orient").as_deref() == Some("vertical");
+  let value = match block.value("line-clamp") {
+    Some(value) => value,
+    None if webkit_box => block.value("-webkit-line-clamp")?,
+    None => return None,
+  };
+  let values = match value {
+    Value::List(values) => values,
+    value => vec![value],
+  };
+
+  // The number of lines can be followed by the `block-ellipsis`, `auto` or a string.
+  let lines = match values.first() {
+    Some(Value::Number(lines)) if *lines >= 1.0 => *lines as usize,
+    _ => return None,
+  };
+  let ellipsis = match values.get(1) {
+    Some(Value::Str(string)) => string.clone(),
+    Some(Value::Keyword(ref keyword)) if keyword == "none" => String::new(),
+    _ => ELLIPSIS.to_string(),
+  };
+  Some((lines, ellipsis))
+}
+
+/// Whether each of the boxes of a line, with their widths in visual order, is hidden to make
+/// room for an ellipsis in `available` width. Boxes are kept from the start side until one
+/// doesn't fit, but the first one is always kept.
+pub(super) fn truncate_line(widths: &[f32], available: f32, rtl: bool) -> Vec<bool> {
+  let mut hidden = vec![false; widths.len()];
+  let mut order: Vec<usize> = (0..widths.len()).collect();
+  if rtl {
+    order.reverse();
+  }
+  let mut width = 0.0;
+  let mut truncated = false;
+  for (position, index) in order.into_iter().enumerate() {
+    truncated = truncated || (position > 0 && width + widths[index] > available);
+    hidden[index] = truncated;
+    width += widths[index];
+  }
+  hidden
+}
@@ -0,0 +1,109 @@
+//! Vertical writing modes.
+//!
+//! Layout happens in a line-relative space where lines run along x and blocks stack along y,
+//! with the physical properties of vertical writing modes mapped into it by the style tree (see
+//! `WritingMode`). Once the document is laid out, the boxes are mapped back to the page: lines
+//! run from top to bottom, and blocks stack from right to left in `vertical-rl` or from left to
+//! right in `vertical-lr`.
+//!
+//! Orthogonal flows are not supported, descendants with another writing mode are laid out in the
+//! principal writing mode of the document.
+//!
+//! https://www.w3.org/TR/css-writing-modes-3/#vertical-layout
+
+use super::{Dimensions, EdgeSizes, LayoutBox, Rect};
+use crate::layout::style::WritingMode;
+
+impl Rect {
+  /// The rect with its axes swapped, around the same origin.
+  pub(super) fn transposed(self) -> Rect {
+    Rect {
+      x: self.x,
+      y: self.y,
+      width: self.height,
+      height: self.width,
+    }
+  }
+}
+
+impl<'a> LayoutBox<'a> {
+  /// Map the boxes from the line-relative space of a vertical writing mode to the page, in a
+  /// viewport with the physical rect `viewport`, which starts at the same origin in both.
+  pub(super) fn map_to_physical(&mut self, writing_mode: WritingMode, viewport: Rect) {
+    self.dimensions = self.dimensions.to_physical(writing_mode, viewport);
+    for child in &mut self.children {
+      child.map_to_physical(writing_mode, viewport);
+    }
+  }
+}
+
+impl Dimensions {
+  fn to_physical(self, writing_mode: WritingMode, viewport: Rect) -> Dimensions {
+    let content = self.content;
+    let inline_offset = content.x - viewport.x;
+    let block_offset = content.y - viewport.y;
+    let x = match writing_mode {
+      WritingMode::VerticalRl => viewport.x + viewport.width - block_offset - content.height,
+      _ => viewport.x + block_offset,
+    };
+    Dimensions {
+      content: Rect {
+        x,
+        y: viewport.y + inline_offset,
+        width: content.height,
+        height: content.width,
+      },
+      padding: self.padding.to_physical(writing_mode),
+      border: self.border.to_physical(writing_mode),
+      margin: self.margin.to_physical(writing_mode),
+    }
+  }
+}
+
+impl EdgeSizes {
+  /// The physical edges of line-relative ones, where line-left is the top and the block start
+  /// is the right in `vertical-rl` and the left in `vertical-lr`.
+  fn to_physical(self, writing_mode: WritingMode) -> EdgeSizes {
+    let (left, right) = match writing_mode {
+      WritingMode::VerticalRl => (self.bottom, self.top),
+      _ => (self.top, self.bottom),
+    };
+    EdgeSizes {
+      left,
+      right,
+      top: self.left,
+      bottom: self.right,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{body, border_box, div, layout};
+
+  #[test]
+  fn vertical_blocks_stack_across_the_page() {
+    let document = body(vec![div("a", vec![]), div("b", vec![])]);
+    let css = "body { writing-mode: vertical-rl; } \
+               .a { width: 100px; margin-top: 10px; } .b { width: 50px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0]), (700.0, 10.0, 100.0, 590.0));
+      assert_eq!(border_box(root, &[1]), (650.0, 0.0, 50.0, 600.0));
+    });
+
+    let css = "body { writing-mode: vertical-lr; } .a, .b { width: 100px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[1]), (100.0, 0.0, 100.0, 600.0));
+    });
+  }
+
+  #[test]
+  fn other_writing_modes_below_the_root_are_ignored() {
+    let document = body(vec![div("a", vec![div("b", vec![])])]);
+    let css = "body { writing-mode: vertical-rl; } \
+               .a { writing-mode: horizontal-tb; } .b { width: 100px; }";
+    layout(&document, css, |root| {
+      assert_eq!(border_box(root, &[0, 0]), (700.0, 0.0, 100.0, 600.0));
+    });
+  }
+}
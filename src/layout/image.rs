@@ -0,0 +1,107 @@
+//! Loading of the images that replace the content of `<img>` elements.
+
+use super::dom::{Node, NodeType};
+use std::path::Path;
+use std::rc::Rc;
+
+/// A decoded image, as rows of RGBA pixels from the top left.
+pub struct Image {
+  pub width: u32,
+  pub height: u32,
+  pub pixels: Vec<u8>,
+}
+
+impl Image {
+  /// Decode the image in a PNG, JPEG, GIF (only the first frame) or WebP file.
+  pub fn open(path: &Path) -> Result<Self, ::image::ImageError> {
+    let image = ::image::open(path)?.into_rgba8();
+    Ok(Image {
+      width: image.width(),
+      height: image.height(),
+      pixels: image.into_raw(),
+    })
+  }
+
+  // The intrinsic size of the image in px.
+  pub fn size(&self) -> (f32, f32) {
+    (self.width as f32, self.height as f32)
+  }
+}
+
+impl std::fmt::Debug for Image {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "Image {{ width: {}, height: {} }}",
+      self.width, self.height
+    )
+  }
+}
+
+/// Load the image of every `<img>` element in a document, from a `src` relative to the
+/// directory of the document. Images that fail to load are left out, so the element renders
+/// as an empty box.
+pub fn load_images(node: &mut Node, base_path: &Path) {
+  if let NodeType::Element(ref mut data) = node.node_type {
+    if data.tag_name == "img" {
+      if let Some(src) = data.attributes.get("src") {
+        let path = base_path.join(src);
+        match Image::open(&path) {
+          Ok(image) => data.image = Some(Rc::new(image)),
+          Err(error) => eprintln!("Failed to load image {}: {}", path.display(), error),
+        }
+      }
+    }
+  }
+
+  for child in &mut node.children {
+    load_images(child, base_path);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::load_images;
+  use crate::layout::dom::{elem, Node, NodeType};
+  use std::collections::HashMap;
+
+  fn img(src: &str) -> Node {
+    let mut attributes = HashMap::new();
+    attributes.insert("src".to_string(), src.to_string());
+    elem("img".to_string(), attributes, vec![])
+  }
+
+  fn image_size(node: &Node) -> Option<(f32, f32)> {
+    match &node.node_type {
+      NodeType::Element(data) => data.image.as_ref().map(|image| image.size()),
+      _ => None,
+    }
+  }
+
+  #[test]
+  fn images_are_loaded_relative_to_the_document() {
+    let directory = std::env::temp_dir().join(format!("noord-images-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let pixels = vec![255; 3 * 2 * 4];
+    ::image::save_buffer(
+      directory.join("image.png"),
+      &pixels,
+      3,
+      2,
+      ::image::ColorType::Rgba8,
+    )
+    .unwrap();
+
+    let mut document = elem(
+      "body".to_string(),
+      HashMap::new(),
+      vec![img("image.png"), img("missing.png")],
+    );
+    load_images(&mut document, &directory);
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(image_size(&document.children[0]), Some((3.0, 2.0)));
+    // Images that fail to load are left out.
+    assert_eq!(image_size(&document.children[1]), None);
+  }
+}
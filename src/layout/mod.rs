@@ -1,7 +1,9 @@
 pub mod css;
 pub mod dom;
+pub mod font;
 pub mod geometry;
 pub mod html;
+pub mod image;
 pub mod style;
 
 // pub fn layout_pipeline<'a>() -> &'a geometry::LayoutBox<'a> {
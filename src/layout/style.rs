@@ -1,45 +1,1221 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 
-use super::css::Value;
-use super::dom::{Node, NodeType};
+use super::css::{PseudoElement, Unit, Value, Viewport};
+use super::dom::{ElementData, Node, NodeType};
+use super::font::{FontCollection, FontSettings};
+use super::image::Image;
+use std::rc::Rc;
 
 pub type StylePropertyMap = HashMap<String, Value>;
 
+/// The font size of the root, in px.
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+/// The properties that inherit their value from the parent when they are not specified.
+const INHERITED_PROPERTIES: &[&str] = &[
+  "color",
+  "font-size",
+  "list-style",
+  "list-style-type",
+  "list-style-position",
+  "quotes",
+  "direction",
+  "writing-mode",
+  "text-orientation",
+  "text-align",
+  "text-indent",
+  "letter-spacing",
+  "word-spacing",
+  "text-transform",
+  "white-space",
+  "font-family",
+  "font-weight",
+  "font-style",
+  "font-stretch",
+  "font-kerning",
+  "font-variant-ligatures",
+  "font-feature-settings",
+  "font-variation-settings",
+  "orphans",
+  "widows",
+];
+
 #[derive(Clone, Debug)]
 pub struct StyledNode<'a> {
   pub values: StylePropertyMap,
+  // The node that is styled, or the element that generates a pseudo-element.
   pub node: &'a Node,
   pub children: Vec<StyledNode<'a>>,
+  pub pseudo_element: Option<PseudoElement>,
+  // The `::before` and `::after` pseudo-elements of an element, when rules apply to them.
+  pub before: Option<Box<StyledNode<'a>>>,
+  pub after: Option<Box<StyledNode<'a>>>,
+  // The principal writing mode of the document, which the whole document is laid out in. A
+  // `writing-mode` on other elements is ignored, there are no orthogonal flows.
+  pub writing_mode: WritingMode,
+  // The font faces of the `@font-face` rules of the stylesheet, which text is measured with.
+  pub fonts: Rc<FontCollection>,
 }
 
+/// The display types of a box, the combinations of an outer and an inner display type that
+/// are supported, plus the internal table types, `contents` and `none`.
+///
+/// https://www.w3.org/TR/css-display-3/#the-display-properties
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum DisplayStyle {
   Inline,
   Block,
+  InlineBlock,
+  FlowRoot,
+  ListItem,
+  Flex,
+  InlineFlex,
+  Grid,
+  InlineGrid,
+  Table,
+  InlineTable,
+  TableRowGroup,
+  TableHeaderGroup,
+  TableFooterGroup,
+  TableRow,
+  TableCell,
+  TableColumnGroup,
+  TableColumn,
+  TableCaption,
+  Contents,
+  None,
+}
+
+/// How a box participates in the formatting context of its parent.
+///
+/// https://www.w3.org/TR/css-display-3/#outer-role
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OuterDisplay {
+  Block,
+  Inline,
+}
+
+/// The formatting context a box establishes for its children, if any.
+///
+/// https://www.w3.org/TR/css-display-3/#inner-model
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InnerDisplay {
+  // Lays out the children in the formatting context the box participates in, if that is a
+  // block or inline formatting context.
+  Flow,
+  FlowRoot,
+  Flex,
+  Grid,
+  Table,
+}
+
+impl DisplayStyle {
+  /// Parse the keywords of a `display` value, both the single keywords and the multi-keyword
+  /// syntax like `inline flow-root`.
+  ///
+  /// https://www.w3.org/TR/css-display-3/#display-value-summary
+  fn from_keywords(keywords: &[&str]) -> Option<Self> {
+    let display = match keywords {
+      [] => return None,
+      ["none"] => DisplayStyle::None,
+      ["contents"] => DisplayStyle::Contents,
+      ["inline-block"] => DisplayStyle::InlineBlock,
+      ["inline-flex"] => DisplayStyle::InlineFlex,
+      ["inline-grid"] => DisplayStyle::InlineGrid,
+      ["inline-table"] => DisplayStyle::InlineTable,
+      ["table-row-group"] => DisplayStyle::TableRowGroup,
+      ["table-header-group"] => DisplayStyle::TableHeaderGroup,
+      ["table-footer-group"] => DisplayStyle::TableFooterGroup,
+      ["table-row"] => DisplayStyle::TableRow,
+      ["table-cell"] => DisplayStyle::TableCell,
+      ["table-column-group"] => DisplayStyle::TableColumnGroup,
+      ["table-column"] => DisplayStyle::TableColumn,
+      ["table-caption"] => DisplayStyle::TableCaption,
+      // The legacy boxes that `-webkit-line-clamp` applies to, laid out like `flow-root`.
+      ["-webkit-box"] => DisplayStyle::FlowRoot,
+      ["-webkit-inline-box"] => DisplayStyle::InlineBlock,
+      keywords => {
+        let mut outer = None;
+        let mut inner = None;
+        let mut list_item = false;
+        for keyword in keywords {
+          match *keyword {
+            "block" if outer.is_none() => outer = Some(OuterDisplay::Block),
+            "inline" if outer.is_none() => outer = Some(OuterDisplay::Inline),
+            "flow" if inner.is_none() => inner = Some(InnerDisplay::Flow),
+            "flow-root" if inner.is_none() => inner = Some(InnerDisplay::FlowRoot),
+            "flex" if inner.is_none() => inner = Some(InnerDisplay::Flex),
+            "grid" if inner.is_none() => inner = Some(InnerDisplay::Grid),
+            "table" if inner.is_none() => inner = Some(InnerDisplay::Table),
+            "list-item" if !list_item => list_item = true,
+            _ => return None,
+          }
+        }
+
+        // A missing outer type is `block`, and a missing inner type is `flow`. Only flow
+        // layout supports list items.
+        let outer = outer.unwrap_or(OuterDisplay::Block);
+        match (outer, inner.unwrap_or(InnerDisplay::Flow), list_item) {
+          (OuterDisplay::Block, InnerDisplay::Flow, true) => DisplayStyle::ListItem,
+          (OuterDisplay::Block, InnerDisplay::Flow, false) => DisplayStyle::Block,
+          (OuterDisplay::Inline, InnerDisplay::Flow, false) => DisplayStyle::Inline,
+          (OuterDisplay::Block, InnerDisplay::FlowRoot, false) => DisplayStyle::FlowRoot,
+          (OuterDisplay::Inline, InnerDisplay::FlowRoot, false) => DisplayStyle::InlineBlock,
+          (OuterDisplay::Block, InnerDisplay::Flex, false) => DisplayStyle::Flex,
+          (OuterDisplay::Inline, InnerDisplay::Flex, false) => DisplayStyle::InlineFlex,
+          (OuterDisplay::Block, InnerDisplay::Grid, false) => DisplayStyle::Grid,
+          (OuterDisplay::Inline, InnerDisplay::Grid, false) => DisplayStyle::InlineGrid,
+          (OuterDisplay::Block, InnerDisplay::Table, false) => DisplayStyle::Table,
+          (OuterDisplay::Inline, InnerDisplay::Table, false) => DisplayStyle::InlineTable,
+          _ => return None,
+        }
+      }
+    };
+    Some(display)
+  }
+
+  /// The outer display type, `None` for boxes that don't take part in flow layout like the
+  /// internal table boxes.
+  pub fn outer(self) -> Option<OuterDisplay> {
+    match self {
+      DisplayStyle::Inline
+      | DisplayStyle::InlineBlock
+      | DisplayStyle::InlineFlex
+      | DisplayStyle::InlineGrid
+      | DisplayStyle::InlineTable => Some(OuterDisplay::Inline),
+      DisplayStyle::Block
+      | DisplayStyle::FlowRoot
+      | DisplayStyle::ListItem
+      | DisplayStyle::Flex
+      | DisplayStyle::Grid
+      | DisplayStyle::Table => Some(OuterDisplay::Block),
+      _ => None,
+    }
+  }
+
+  /// The inner display type. Table cells and captions establish a new block formatting
+  /// context, the other internal table boxes are laid out by the table.
+  pub fn inner(self) -> InnerDisplay {
+    match self {
+      DisplayStyle::FlowRoot
+      | DisplayStyle::InlineBlock
+      | DisplayStyle::TableCell
+      | DisplayStyle::TableCaption => InnerDisplay::FlowRoot,
+      DisplayStyle::Flex | DisplayStyle::InlineFlex => InnerDisplay::Flex,
+      DisplayStyle::Grid | DisplayStyle::InlineGrid => InnerDisplay::Grid,
+      DisplayStyle::Table
+      | DisplayStyle::InlineTable
+      | DisplayStyle::TableRowGroup
+      | DisplayStyle::TableHeaderGroup
+      | DisplayStyle::TableFooterGroup
+      | DisplayStyle::TableRow
+      | DisplayStyle::TableColumnGroup
+      | DisplayStyle::TableColumn => InnerDisplay::Table,
+      _ => InnerDisplay::Flow,
+    }
+  }
+
+  /// Whether the box is inline-level and laid out as a single unit, like an inline-block,
+  /// rather than split across lines.
+  ///
+  /// https://www.w3.org/TR/css-display-3/#atomic-inline
+  pub fn is_atomic_inline(self) -> bool {
+    self.outer() == Some(OuterDisplay::Inline) && self.inner() != InnerDisplay::Flow
+  }
+
+  /// Whether the box establishes a flex formatting context for its children.
+  pub fn is_flex_container(self) -> bool {
+    self == DisplayStyle::Flex || self == DisplayStyle::InlineFlex
+  }
+
+  /// Whether the box establishes a grid formatting context for its children.
+  pub fn is_grid_container(self) -> bool {
+    self == DisplayStyle::Grid || self == DisplayStyle::InlineGrid
+  }
+
+  /// Whether the box establishes a table formatting context for its children.
+  pub fn is_table(self) -> bool {
+    self == DisplayStyle::Table || self == DisplayStyle::InlineTable
+  }
+
+  /// Whether the box is a row group, e.g. `display: table-header-group`.
+  pub fn is_table_row_group(self) -> bool {
+    matches!(
+      self,
+      DisplayStyle::TableRowGroup | DisplayStyle::TableHeaderGroup | DisplayStyle::TableFooterGroup
+    )
+  }
+
+  /// Whether the box can be a direct child of a table box.
+  ///
+  /// https://www.w3.org/TR/CSS2/tables.html#anonymous-boxes
+  pub fn is_proper_table_child(self) -> bool {
+    match self {
+      DisplayStyle::TableRow
+      | DisplayStyle::TableColumnGroup
+      | DisplayStyle::TableColumn
+      | DisplayStyle::TableCaption => true,
+      display => display.is_table_row_group(),
+    }
+  }
+
+  /// The block-level equivalent of an inline-level display type.
+  ///
+  /// https://www.w3.org/TR/css-display-3/#blockify
+  pub fn blockified(self) -> Self {
+    match self {
+      DisplayStyle::Inline => DisplayStyle::Block,
+      DisplayStyle::InlineBlock => DisplayStyle::FlowRoot,
+      DisplayStyle::InlineFlex => DisplayStyle::Flex,
+      DisplayStyle::InlineGrid => DisplayStyle::Grid,
+      DisplayStyle::InlineTable => DisplayStyle::Table,
+      display => display,
+    }
+  }
+}
+
+/// The inline base direction of a box, used for its text and for the placement of its children.
+///
+/// https://www.w3.org/TR/css-writing-modes-3/#direction
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+  Ltr,
+  Rtl,
+}
+
+/// The direction lines are laid out in, and the direction blocks and lines stack in.
+///
+/// Layout happens in a line-relative coordinate space, where x runs along the lines and y
+/// across them, as in `horizontal-tb`. In vertical writing modes the physical properties are
+/// mapped into that space: `width` is the inline size and `margin-left` the line-left margin,
+/// which are `height` and `margin-top` on the page.
+///
+/// https://www.w3.org/TR/css-writing-modes-3/#block-flow
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WritingMode {
+  HorizontalTb,
+  VerticalRl,
+  VerticalLr,
+}
+
+impl WritingMode {
+  fn from_keyword(keyword: &str) -> Option<Self> {
+    match keyword {
+      "horizontal-tb" => Some(WritingMode::HorizontalTb),
+      "vertical-rl" => Some(WritingMode::VerticalRl),
+      "vertical-lr" => Some(WritingMode::VerticalLr),
+      _ => None,
+    }
+  }
+
+  pub fn is_vertical(self) -> bool {
+    self != WritingMode::HorizontalTb
+  }
+
+  /// The physical property that a property of the line-relative space is read from.
+  ///
+  /// https://www.w3.org/TR/css-writing-modes-3/#logical-to-physical
+  fn physical_property(self, name: &str) -> Cow<'_, str> {
+    if !self.is_vertical() {
+      return Cow::Borrowed(name);
+    }
+    match name {
+      "width" => return Cow::Borrowed("height"),
+      "height" => return Cow::Borrowed("width"),
+      "min-width" => return Cow::Borrowed("min-height"),
+      "min-height" => return Cow::Borrowed("min-width"),
+      "max-width" => return Cow::Borrowed("max-height"),
+      "max-height" => return Cow::Borrowed("max-width"),
+      _ => {}
+    }
+
+    // Line-left is the top, and the block flow goes from the right or left edge.
+    let (top, bottom) = match self {
+      WritingMode::VerticalRl => ("right", "left"),
+      _ => ("left", "right"),
+    };
+    let side = |side: &str| match side {
+      "left" => Some("top"),
+      "right" => Some("bottom"),
+      "top" => Some(top),
+      "bottom" => Some(bottom),
+      _ => None,
+    };
+    let mut parts: Vec<&str> = name.split('-').collect();
+    match parts.as_slice() {
+      [offset] => side(offset)
+        .map(Cow::Borrowed)
+        .unwrap_or(Cow::Borrowed(name)),
+      ["margin", _] | ["padding", _] | ["border", _, "width"] => match side(parts[1]) {
+        Some(physical) => {
+          parts[1] = physical;
+          Cow::Owned(parts.join("-"))
+        }
+        None => Cow::Borrowed(name),
+      },
+      _ => Cow::Borrowed(name),
+    }
+  }
+}
+
+/// The orientation of the characters on a line in vertical writing modes.
+///
+/// https://www.w3.org/TR/css-writing-modes-3/#text-orientation
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextOrientation {
+  // Characters of horizontal scripts are turned sideways, those of vertical scripts like CJK
+  // stay upright.
+  Mixed,
+  Upright,
+  Sideways,
+}
+
+/// How an inline box takes part in the bidirectional reordering of its paragraph.
+///
+/// https://www.w3.org/TR/css-writing-modes-3/#unicode-bidi
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UnicodeBidi {
+  Normal,
+  Embed,
+  Isolate,
+  BidiOverride,
+  IsolateOverride,
+  Plaintext,
+}
+
+/// How the content of a line box is aligned along the line, where `start` and `end` depend on
+/// the direction of the block.
+///
+/// https://www.w3.org/TR/css-text-3/#text-align-property
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextAlign {
+  Start,
+  End,
+  Left,
+  Right,
+  Center,
+  // Stretches every line but the last to fill the line box.
+  Justify,
+}
+
+/// The case transformation applied to text.
+///
+/// https://www.w3.org/TR/css-text-3/#text-transform-property
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextTransform {
+  None,
+  Uppercase,
+  Lowercase,
+  // Uppercases the first letter of each word.
+  Capitalize,
+}
+
+/// The slope of text, or of a font face.
+///
+/// https://www.w3.org/TR/css-fonts-4/#font-style-prop
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FontStyle {
+  Normal,
+  Italic,
+  Oblique,
+}
+
+impl FontStyle {
+  pub fn from_keyword(keyword: &str) -> Option<Self> {
+    match keyword {
+      "normal" => Some(FontStyle::Normal),
+      "italic" => Some(FontStyle::Italic),
+      "oblique" => Some(FontStyle::Oblique),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PositionStyle {
+  Static,
+  Relative,
+  Absolute,
+  Fixed,
+  Sticky,
+}
+
+impl PositionStyle {
+  /// Absolutely positioned boxes (including fixed ones) are taken out of normal flow.
+  pub fn is_out_of_flow(self) -> bool {
+    self == PositionStyle::Absolute || self == PositionStyle::Fixed
+  }
+}
+
+/// https://www.w3.org/TR/css-backgrounds-3/#border-style
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BorderStyle {
   None,
+  Hidden,
+  Solid,
+  Dashed,
+  Dotted,
+  Double,
+  Groove,
+  Ridge,
+  Inset,
+  Outset,
 }
 
-impl StyledNode<'_> {
-  // Return the specified value of a property if it exists, otherwise `None`.
+impl BorderStyle {
+  pub fn from_keyword(keyword: &str) -> Option<Self> {
+    match keyword {
+      "none" => Some(BorderStyle::None),
+      "hidden" => Some(BorderStyle::Hidden),
+      "solid" => Some(BorderStyle::Solid),
+      "dashed" => Some(BorderStyle::Dashed),
+      "dotted" => Some(BorderStyle::Dotted),
+      "double" => Some(BorderStyle::Double),
+      "groove" => Some(BorderStyle::Groove),
+      "ridge" => Some(BorderStyle::Ridge),
+      "inset" => Some(BorderStyle::Inset),
+      "outset" => Some(BorderStyle::Outset),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OverflowStyle {
+  Visible,
+  Hidden,
+  Clip,
+  Scroll,
+  Auto,
+}
+
+impl OverflowStyle {
+  fn from_keyword(keyword: &str) -> Option<Self> {
+    match keyword {
+      "visible" => Some(OverflowStyle::Visible),
+      "hidden" => Some(OverflowStyle::Hidden),
+      "clip" => Some(OverflowStyle::Clip),
+      "scroll" => Some(OverflowStyle::Scroll),
+      "auto" => Some(OverflowStyle::Auto),
+      _ => None,
+    }
+  }
+
+  /// Whether content that overflows the box along this axis is clipped.
+  pub fn clips(self) -> bool {
+    self != OverflowStyle::Visible
+  }
+
+  /// Whether the box can be scrolled along this axis, by the user or by script.
+  pub fn is_scrollable(self) -> bool {
+    matches!(
+      self,
+      OverflowStyle::Hidden | OverflowStyle::Scroll | OverflowStyle::Auto
+    )
+  }
+
+  /// Whether the user can scroll the box along this axis, `hidden` only scrolls by script.
+  pub fn is_user_scrollable(self) -> bool {
+    matches!(self, OverflowStyle::Scroll | OverflowStyle::Auto)
+  }
+}
+
+impl<'a> StyledNode<'a> {
+  // Return the specified value of a property if it exists, otherwise `None`. Sizes and sides are
+  // those of the line-relative space layout happens in, see `WritingMode`.
   pub fn value(&self, name: &str) -> Option<Value> {
-    self.values.get(name).map(|v| v.clone())
+    let name = self.writing_mode.physical_property(name);
+    self.values.get(name.as_ref()).map(|v| v.clone())
   }
 
-  // The value of the `display` property (defaults to inline).
+  // The value of the `display` property (defaults to inline, also for invalid values).
   pub fn display(&self) -> DisplayStyle {
-    match self.value("display") {
+    let keywords = match self.value("display") {
+      Some(Value::Keyword(keyword)) => vec![keyword],
+      Some(Value::List(values)) => values
+        .into_iter()
+        .map(|value| match value {
+          Value::Keyword(keyword) => keyword,
+          _ => String::new(),
+        })
+        .collect(),
+      _ => vec![],
+    };
+    let keywords: Vec<&str> = keywords.iter().map(String::as_str).collect();
+    let display = DisplayStyle::from_keywords(&keywords).unwrap_or(DisplayStyle::Inline);
+
+    // Absolutely positioned boxes are blockified.
+    // https://www.w3.org/TR/CSS2/visuren.html#dis-pos-flo
+    if self.position().is_out_of_flow() {
+      return display.blockified();
+    }
+    display
+  }
+
+  // The element this node styles, `None` for text and pseudo-elements.
+  pub fn element(&self) -> Option<&'a ElementData> {
+    match self.node.node_type {
+      NodeType::Element(ref data) if self.pseudo_element.is_none() => Some(data),
+      _ => None,
+    }
+  }
+
+  // The value of an attribute, if this is an element that has it.
+  pub fn attribute(&self, name: &str) -> Option<&'a String> {
+    self.element().and_then(|data| data.attributes.get(name))
+  }
+
+  // The image that replaces the content of the element, if it is a replaced element.
+  pub fn image(&self) -> Option<&'a Rc<Image>> {
+    self.element().and_then(|data| data.image.as_ref())
+  }
+
+  // Whether this is a text node containing only whitespace.
+  pub fn is_whitespace(&self) -> bool {
+    match self.node.node_type {
+      NodeType::Text(ref text) => text.trim().is_empty(),
+      _ => false,
+    }
+  }
+
+  // The value of the `position` property (defaults to static).
+  pub fn position(&self) -> PositionStyle {
+    match self.value("position") {
       Some(Value::Keyword(s)) => match &*s {
-        "block" => DisplayStyle::Block,
-        "none" => DisplayStyle::None,
-        _ => DisplayStyle::Inline,
+        "relative" => PositionStyle::Relative,
+        "absolute" => PositionStyle::Absolute,
+        "fixed" => PositionStyle::Fixed,
+        "sticky" => PositionStyle::Sticky,
+        _ => PositionStyle::Static,
+      },
+      _ => PositionStyle::Static,
+    }
+  }
+
+  // The value of the `direction` property (defaults to ltr), which is inherited.
+  pub fn direction(&self) -> Direction {
+    match self.keyword("direction").as_deref() {
+      Some("rtl") => Direction::Rtl,
+      _ => Direction::Ltr,
+    }
+  }
+
+  // The value of the `writing-mode` property (defaults to horizontal-tb), which is inherited.
+  // Layout uses the principal writing mode in `self.writing_mode` instead.
+  pub fn specified_writing_mode(&self) -> WritingMode {
+    self
+      .keyword("writing-mode")
+      .and_then(|keyword| WritingMode::from_keyword(&keyword))
+      .unwrap_or(WritingMode::HorizontalTb)
+  }
+
+  // The value of the `text-orientation` property (defaults to mixed), which is inherited. Text
+  // is not laid out yet, so it only changes how the generated text of markers and pseudo-elements
+  // is measured in vertical writing modes.
+  pub fn text_orientation(&self) -> TextOrientation {
+    match self.keyword("text-orientation").as_deref() {
+      Some("upright") => TextOrientation::Upright,
+      Some("sideways") | Some("sideways-right") => TextOrientation::Sideways,
+      _ => TextOrientation::Mixed,
+    }
+  }
+
+  // The value of the `text-align` property (defaults to start), which is inherited.
+  pub fn text_align(&self) -> TextAlign {
+    match self.keyword("text-align").as_deref() {
+      Some("end") => TextAlign::End,
+      Some("left") => TextAlign::Left,
+      Some("right") => TextAlign::Right,
+      Some("center") => TextAlign::Center,
+      Some("justify") => TextAlign::Justify,
+      _ => TextAlign::Start,
+    }
+  }
+
+  // The value of the `text-transform` property (defaults to none), which is inherited.
+  pub fn text_transform(&self) -> TextTransform {
+    match self.keyword("text-transform").as_deref() {
+      Some("uppercase") => TextTransform::Uppercase,
+      Some("lowercase") => TextTransform::Lowercase,
+      Some("capitalize") => TextTransform::Capitalize,
+      _ => TextTransform::None,
+    }
+  }
+
+  // The families of the `font-family` property in order of preference, which is inherited.
+  // Unquoted family names can be several keywords.
+  pub fn font_families(&self) -> Vec<String> {
+    self
+      .comma_separated("font-family")
+      .iter()
+      .filter_map(|family| match family.as_slice() {
+        [Value::Str(name)] => Some(name.clone()),
+        keywords => keywords
+          .iter()
+          .map(|value| match value {
+            Value::Keyword(keyword) => Some(keyword.as_str()),
+            _ => None,
+          })
+          .collect::<Option<Vec<&str>>>()
+          .filter(|keywords| !keywords.is_empty())
+          .map(|keywords| keywords.join(" ")),
+      })
+      .collect()
+  }
+
+  // The weight of the `font-weight` property (defaults to 400), which is inherited. `bolder`
+  // and `lighter` are resolved against a normal weight.
+  pub fn font_weight(&self) -> f32 {
+    match self.value("font-weight") {
+      Some(Value::Keyword(ref keyword)) if keyword == "bolder" => 700.0,
+      Some(Value::Keyword(ref keyword)) if keyword == "lighter" => 100.0,
+      Some(value) => font_weight_value(&value).unwrap_or(400.0),
+      None => 400.0,
+    }
+  }
+
+  // The value of the `font-style` property (defaults to normal), which is inherited.
+  pub fn font_style(&self) -> FontStyle {
+    let keyword = match self.value("font-style") {
+      Some(Value::List(values)) => match values.first() {
+        Some(Value::Keyword(keyword)) => Some(keyword.clone()),
+        _ => None,
       },
-      _ => DisplayStyle::Inline,
+      Some(Value::Keyword(keyword)) => Some(keyword),
+      _ => None,
+    };
+    keyword
+      .and_then(|keyword| FontStyle::from_keyword(&keyword))
+      .unwrap_or(FontStyle::Normal)
+  }
+
+  // The percentage of the `font-stretch` property (defaults to 100%), which is inherited.
+  pub fn font_stretch(&self) -> f32 {
+    self
+      .value("font-stretch")
+      .and_then(|value| font_stretch_value(&value))
+      .unwrap_or(100.0)
+  }
+
+  // The OpenType features and variation axes that text is shaped with, from `font-kerning`,
+  // `font-variant-ligatures`, `font-feature-settings` and `font-variation-settings`, in order of
+  // precedence. Optional ligatures are disabled when letters are spaced apart.
+  // https://www.w3.org/TR/css-fonts-4/#feature-precedence
+  pub fn font_settings(&self, letter_spacing: f32) -> FontSettings {
+    let mut features = Vec::new();
+    match self.keyword("font-kerning").as_deref() {
+      Some("normal") => features.push((*b"kern", 1)),
+      Some("none") => features.push((*b"kern", 0)),
+      _ => {}
+    }
+
+    let ligatures = match self.value("font-variant-ligatures") {
+      Some(Value::List(values)) => values,
+      Some(value) => vec![value],
+      None => vec![],
+    };
+    for value in ligatures {
+      let keyword = match value {
+        Value::Keyword(keyword) => keyword,
+        _ => continue,
+      };
+      let (tags, enabled): (&[&[u8; 4]], bool) = match keyword.as_str() {
+        "none" => (&[b"liga", b"clig", b"dlig", b"hlig", b"calt"], false),
+        "common-ligatures" => (&[b"liga", b"clig"], true),
+        "no-common-ligatures" => (&[b"liga", b"clig"], false),
+        "discretionary-ligatures" => (&[b"dlig"], true),
+        "no-discretionary-ligatures" => (&[b"dlig"], false),
+        "historical-ligatures" => (&[b"hlig"], true),
+        "no-historical-ligatures" => (&[b"hlig"], false),
+        "contextual" => (&[b"calt"], true),
+        "no-contextual" => (&[b"calt"], false),
+        _ => continue,
+      };
+      features.extend(tags.iter().map(|tag| (**tag, enabled as u32)));
+    }
+
+    if letter_spacing != 0.0 {
+      for tag in [b"liga", b"clig", b"dlig", b"hlig"] {
+        features.push((*tag, 0));
+      }
+    }
+
+    // Features are a comma-separated list of a tag with an optional value or `on` or `off`.
+    for setting in self.comma_separated("font-feature-settings") {
+      let value = match setting.get(1) {
+        None => 1,
+        Some(Value::Number(value)) if *value >= 0.0 => *value as u32,
+        Some(Value::Keyword(keyword)) if keyword == "on" => 1,
+        Some(Value::Keyword(keyword)) if keyword == "off" => 0,
+        _ => continue,
+      };
+      if let Some(tag) = setting.first().and_then(opentype_tag) {
+        features.push((tag, value));
+      }
+    }
+
+    let variations = self
+      .comma_separated("font-variation-settings")
+      .iter()
+      .filter_map(|setting| match setting.as_slice() {
+        [tag, Value::Number(value)] => Some((opentype_tag(tag)?, *value)),
+        _ => None,
+      })
+      .collect();
+
+    FontSettings {
+      features,
+      variations,
     }
   }
 
+  // The items of a comma-separated property, each as its list of values.
+  pub fn comma_separated(&self, name: &str) -> Vec<Vec<Value>> {
+    let values = match self.value(name) {
+      Some(Value::List(values)) => values,
+      Some(value) => vec![value],
+      None => return vec![],
+    };
+    values
+      .split(|value| *value == Value::Comma)
+      .map(|item| item.to_vec())
+      .collect()
+  }
+
+  // Whether lines wrap by the `white-space` property (defaults to normal), which is inherited.
+  // With `nowrap` and `pre` inline content stays on one line.
+  pub fn wraps_lines(&self) -> bool {
+    !matches!(
+      self.keyword("white-space").as_deref(),
+      Some("nowrap") | Some("pre")
+    )
+  }
+
+  // The value of the `unicode-bidi` property (defaults to normal).
+  pub fn unicode_bidi(&self) -> UnicodeBidi {
+    match self.keyword("unicode-bidi").as_deref() {
+      Some("embed") => UnicodeBidi::Embed,
+      Some("isolate") => UnicodeBidi::Isolate,
+      Some("bidi-override") => UnicodeBidi::BidiOverride,
+      Some("isolate-override") => UnicodeBidi::IsolateOverride,
+      Some("plaintext") => UnicodeBidi::Plaintext,
+      _ => UnicodeBidi::Normal,
+    }
+  }
+
+  // The `z-index` of the element, or `None` for `auto`.
+  // https://www.w3.org/TR/CSS22/visuren.html#z-index
+  pub fn z_index(&self) -> Option<i32> {
+    match self.value("z-index") {
+      Some(Value::Number(z_index)) => Some(z_index as i32),
+      _ => None,
+    }
+  }
+
+  // Whether a property other than `position` and `z-index` makes the element a stacking
+  // context: an `opacity` below 1, a `transform` or a `filter`, a `mix-blend-mode`, or
+  // `isolation: isolate`.
+  // https://www.w3.org/TR/css-position-4/#stacking-context
+  pub fn creates_stacking_context(&self) -> bool {
+    let set = |name: &str, initial: &str| match self.value(name) {
+      None => false,
+      Some(Value::Keyword(keyword)) => keyword != initial,
+      Some(_) => true,
+    };
+    self.opacity() < 1.0
+      || set("transform", "none")
+      || set("filter", "none")
+      || set("mix-blend-mode", "normal")
+      || set("isolation", "auto")
+  }
+
+  // The `opacity` of the element as a fraction, a number or a percentage clamped to 0 to 1.
+  // https://www.w3.org/TR/css-color-4/#transparency
+  pub fn opacity(&self) -> f32 {
+    let opacity = match self.value("opacity") {
+      Some(Value::Number(opacity)) => opacity,
+      Some(Value::Length(percentage, Unit::Percentage)) => percentage / 100.0,
+      _ => 1.0,
+    };
+    opacity.clamp(0.0, 1.0)
+  }
+
+  // The used `overflow-x` and `overflow-y`, from the longhands or the `overflow` shorthand.
+  // https://www.w3.org/TR/css-overflow-3/#overflow-properties
+  pub fn overflow(&self) -> (OverflowStyle, OverflowStyle) {
+    let keyword = |value: &Value| match value {
+      Value::Keyword(keyword) => OverflowStyle::from_keyword(keyword),
+      _ => None,
+    };
+    let (shorthand_x, shorthand_y) = match self.value("overflow") {
+      Some(Value::List(values)) => (keyword(&values[0]), keyword(&values[values.len() - 1])),
+      Some(value) => (keyword(&value), keyword(&value)),
+      None => (None, None),
+    };
+    let x = self
+      .value("overflow-x")
+      .and_then(|v| keyword(&v))
+      .or(shorthand_x);
+    let y = self
+      .value("overflow-y")
+      .and_then(|v| keyword(&v))
+      .or(shorthand_y);
+    let x = x.unwrap_or(OverflowStyle::Visible);
+    let y = y.unwrap_or(OverflowStyle::Visible);
+
+    // A box can't scroll along one axis only, `visible` and `clip` become `auto` and `hidden`
+    // when the other axis scrolls.
+    let adjust = |value: OverflowStyle, other: OverflowStyle| match value {
+      OverflowStyle::Visible if other.is_scrollable() => OverflowStyle::Auto,
+      OverflowStyle::Clip if other.is_scrollable() => OverflowStyle::Hidden,
+      value => value,
+    };
+    (adjust(x, y), adjust(y, x))
+  }
+
   pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
+    self.value(name).unwrap_or_else(|| {
+      self
+        .value(fallback_name)
+        .map(|value| box_shorthand_side(&self.writing_mode.physical_property(name), value))
+        .unwrap_or_else(|| default.clone())
+    })
+  }
+
+  // The width of a side of the border, in the line-relative space like `lookup`. Keywords like
+  // `thin` are given in px, and a side whose style is `none` or `hidden` has no border.
+  // https://www.w3.org/TR/css-backgrounds-3/#border-width
+  pub fn border_width(&self, side: &str) -> Value {
+    let name = format!("border-{}-width", side);
+    let name = self.writing_mode.physical_property(&name);
+    let side = &name["border-".len()..name.len() - "-width".len()];
+    if matches!(
+      self.border_style(side),
+      BorderStyle::None | BorderStyle::Hidden
+    ) {
+      return Value::Length(0.0, Unit::Px);
+    }
+    match self.border_value(side, "width") {
+      Some(Value::Keyword(keyword)) => match keyword.as_str() {
+        "thin" => Value::Length(1.0, Unit::Px),
+        "thick" => Value::Length(5.0, Unit::Px),
+        _ => Value::Length(3.0, Unit::Px),
+      },
+      Some(width) => width,
+      None => Value::Length(3.0, Unit::Px),
+    }
+  }
+
+  // The style of a physical side of the border.
+  // https://www.w3.org/TR/css-backgrounds-3/#border-style
+  pub fn border_style(&self, side: &str) -> BorderStyle {
+    match self.border_value(side, "style") {
+      Some(Value::Keyword(keyword)) => {
+        BorderStyle::from_keyword(&keyword).unwrap_or(BorderStyle::None)
+      }
+      _ => BorderStyle::None,
+    }
+  }
+
+  // The `width`, `style` or `color` of a physical side of the border, as the border is painted
+  // on the page: the longhand like `border-top-style`, or else the value that the
+  // `border-top`, `border-style` or `border` shorthand sets, in that order.
+  // https://www.w3.org/TR/css-backgrounds-3/#border-shorthands
+  pub fn border_value(&self, side: &str, property: &str) -> Option<Value> {
+    let longhand = format!("border-{}-{}", side, property);
+    let shorthand = |name: &str| {
+      self
+        .values
+        .get(name)
+        .map(|value| border_shorthand_value(value, property))
+    };
     self
-      .value(name)
-      .unwrap_or_else(|| self.value(fallback_name).unwrap_or_else(|| default.clone()))
+      .values
+      .get(&longhand)
+      .cloned()
+      .or_else(|| shorthand(&format!("border-{}", side)))
+      .or_else(|| {
+        self
+          .values
+          .get(&format!("border-{}", property))
+          .map(|value| box_shorthand_side(&longhand, value.clone()))
+      })
+      .or_else(|| shorthand("border"))
+  }
+
+  // The keyword value of a property, if it is set to a keyword.
+  pub fn keyword(&self, name: &str) -> Option<String> {
+    match self.value(name) {
+      Some(Value::Keyword(keyword)) => Some(keyword),
+      _ => None,
+    }
+  }
+
+  // The value of a length property in px, or `None` if it is not set or `auto`.
+  pub fn length(&self, name: &str, reference: f32, viewport: Viewport) -> Option<f32> {
+    match self.value(name) {
+      None => None,
+      Some(Value::Keyword(ref keyword)) if keyword == "auto" => None,
+      Some(value) => Some(value.to_px(reference, viewport)),
+    }
+  }
+
+  // The size of the content box set by a sizing property like `width` or `max-height`, or `None`
+  // if it is not set, `auto` or `none`. With `box-sizing: border-box` the value includes the
+  // padding and border along the same axis, which are given as `edges`.
+  pub fn size(&self, name: &str, reference: f32, edges: f32, viewport: Viewport) -> Option<f32> {
+    let size = match self.value(name) {
+      None | Some(Value::Keyword(_)) => return None,
+      Some(value) => value.to_px(reference, viewport),
+    };
+    match self.keyword("box-sizing").as_deref() {
+      Some("border-box") => Some((size - edges).max(0.0)),
+      _ => Some(size),
+    }
+  }
+
+  // Clamp the size of the content box by `min-<name>` and `max-<name>`, the minimum wins when
+  // they conflict.
+  pub fn clamp_size(
+    &self,
+    name: &str,
+    size: f32,
+    reference: f32,
+    edges: f32,
+    viewport: Viewport,
+  ) -> f32 {
+    let min = self.size(&format!("min-{}", name), reference, edges, viewport);
+    let max = self.size(&format!("max-{}", name), reference, edges, viewport);
+    size
+      .min(max.unwrap_or(f32::INFINITY))
+      .max(min.unwrap_or(0.0))
+  }
+}
+
+/// The OpenType tag of a string of four ASCII characters, like `"liga"`.
+fn opentype_tag(value: &Value) -> Option<[u8; 4]> {
+  match value {
+    Value::Str(tag) if tag.len() == 4 && tag.is_ascii() => {
+      let bytes = tag.as_bytes();
+      Some([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+    _ => None,
+  }
+}
+
+/// The weight of an absolute `font-weight` value, a number from 1 to 1000 or `normal` and `bold`.
+///
+/// https://www.w3.org/TR/css-fonts-4/#font-weight-prop
+pub fn font_weight_value(value: &Value) -> Option<f32> {
+  match value {
+    Value::Number(weight) if (1.0..=1000.0).contains(weight) => Some(*weight),
+    Value::Keyword(keyword) if keyword == "normal" => Some(400.0),
+    Value::Keyword(keyword) if keyword == "bold" => Some(700.0),
+    _ => None,
+  }
+}
+
+/// The percentage of a `font-stretch` value, a percentage or a keyword from `ultra-condensed` to
+/// `ultra-expanded`.
+///
+/// https://www.w3.org/TR/css-fonts-4/#font-stretch-prop
+pub fn font_stretch_value(value: &Value) -> Option<f32> {
+  let keyword = match value {
+    Value::Length(percentage, Unit::Percentage) if *percentage >= 0.0 => return Some(*percentage),
+    Value::Keyword(keyword) => keyword,
+    _ => return None,
+  };
+  match keyword.as_str() {
+    "ultra-condensed" => Some(50.0),
+    "extra-condensed" => Some(62.5),
+    "condensed" => Some(75.0),
+    "semi-condensed" => Some(87.5),
+    "normal" => Some(100.0),
+    "semi-expanded" => Some(112.5),
+    "expanded" => Some(125.0),
+    "extra-expanded" => Some(150.0),
+    "ultra-expanded" => Some(200.0),
+    _ => None,
+  }
+}
+
+/// Pick the value for one side out of a shorthand like `margin: 10px auto`.
+///
+/// https://www.w3.org/TR/CSS2/box.html#margin-properties
+pub fn box_shorthand_side(name: &str, value: Value) -> Value {
+  let values = match value {
+    Value::List(values) => values,
+    value => return value,
+  };
+
+  // Sides are listed as top, right, bottom, left, with missing sides copied from the opposite one.
+  let index = match values.len() {
+    1 => 0,
+    2 if name.contains("-top") || name.contains("-bottom") => 0,
+    2 => 1,
+    3 if name.contains("-top") => 0,
+    3 if name.contains("-bottom") => 2,
+    3 => 1,
+    _ if name.contains("-top") => 0,
+    _ if name.contains("-right") => 1,
+    _ if name.contains("-bottom") => 2,
+    _ => 3,
+  };
+
+  values[index].clone()
+}
+
+/// The `width`, `style` or `color` that a `border` or `border-<side>` shorthand sets, which is
+/// the initial value when the shorthand leaves it out.
+///
+/// https://www.w3.org/TR/css-backgrounds-3/#propdef-border
+fn border_shorthand_value(value: &Value, property: &str) -> Value {
+  let values = match value {
+    Value::List(values) => values.clone(),
+    value => vec![value.clone()],
+  };
+  let sets = |value: &Value| match value {
+    Value::Length(..) | Value::Number(_) => property == "width",
+    Value::Keyword(keyword) if ["thin", "medium", "thick"].contains(&keyword.as_str()) => {
+      property == "width"
+    }
+    Value::Keyword(keyword) if BorderStyle::from_keyword(keyword).is_some() => property == "style",
+    Value::Keyword(_) | Value::ColorValue(_) => property == "color",
+    _ => false,
+  };
+  values.into_iter().find(sets).unwrap_or_else(|| {
+    let initial = match property {
+      "width" => "medium",
+      "style" => "none",
+      _ => "currentcolor",
+    };
+    Value::Keyword(initial.to_string())
+  })
+}
+
+/// Add the styles that HTML attributes and elements imply for the bidirectional algorithm, unless
+/// a rule sets them.
+///
+/// https://html.spec.whatwg.org/multipage/rendering.html#bidi-rendering
+fn add_bidi_hints(node: &Node, data: &ElementData, values: &mut StylePropertyMap) {
+  let keyword = |keyword: &str| Value::Keyword(keyword.to_string());
+  let direction = match data
+    .attributes
+    .get("dir")
+    .map(|dir| dir.to_lowercase())
+    .as_deref()
+  {
+    Some("ltr") => Some("ltr"),
+    Some("rtl") => Some("rtl"),
+    // The direction of the first strong character in the text of the element.
+    Some("auto") => match unicode_bidi::get_base_direction(text_content(node).as_str()) {
+      unicode_bidi::Direction::Rtl => Some("rtl"),
+      _ => Some("ltr"),
+    },
+    _ => None,
+  };
+  if let Some(direction) = direction {
+    values
+      .entry("direction".to_string())
+      .or_insert_with(|| keyword(direction));
+  }
+
+  let unicode_bidi = match data.tag_name.as_str() {
+    "bdo" => Some("isolate-override"),
+    "bdi" => Some("isolate"),
+    _ if direction.is_some() => Some("isolate"),
+    _ => None,
+  };
+  if let Some(unicode_bidi) = unicode_bidi {
+    values
+      .entry("unicode-bidi".to_string())
+      .or_insert_with(|| keyword(unicode_bidi));
+  }
+}
+
+/// The text of a node and its descendants, in document order.
+fn text_content(node: &Node) -> String {
+  match node.node_type {
+    NodeType::Text(ref text) => text.clone(),
+    _ => node.children.iter().map(text_content).collect(),
+  }
+}
+
+/// Give a node the values of the inherited properties of its parent that are not specified.
+/// A shorthand that is specified on the node sets its longhands instead of the parent.
+///
+/// The font size is computed to an absolute length, so that relative sizes are resolved
+/// against the font size of the parent rather than compounding down the tree, and so are the
+/// lengths relative to the font size, see `font_relative_to_px`.
+fn inherit(values: &mut StylePropertyMap, parent: &StylePropertyMap) {
+  for name in INHERITED_PROPERTIES {
+    let shorthand = match *name {
+      "list-style-type" | "list-style-position" => Some("list-style"),
+      _ => None,
+    };
+    if shorthand.is_some_and(|shorthand| values.contains_key(shorthand)) {
+      continue;
+    }
+    if let Some(value) = parent.get(*name) {
+      values
+        .entry(name.to_string())
+        .or_insert_with(|| value.clone());
+    }
+  }
+
+  let parent_font_size = match parent.get("font-size") {
+    Some(Value::Length(size, Unit::Px)) => *size,
+    _ => DEFAULT_FONT_SIZE,
+  };
+  if let Some(value) = values.get("font-size") {
+    let size = match font_relative_to_px(value, parent_font_size) {
+      Value::Length(size, Unit::Percentage) => {
+        Value::Length(size / 100.0 * parent_font_size, Unit::Px)
+      }
+      size => size,
+    };
+    values.insert("font-size".to_string(), size);
+  }
+  let font_size = match values.get("font-size") {
+    Some(Value::Length(size, Unit::Px)) => *size,
+    _ => parent_font_size,
+  };
+  for value in values.values_mut() {
+    *value = font_relative_to_px(value, font_size);
+  }
+}
+
+/// A value with its lengths relative to the font size in px: `em` against the font size, and
+/// `ex` and `ch` as half of it, their size when the font doesn't give one. `rem` is relative
+/// to the font size of the `html` element, which isn't styled and keeps the initial size.
+///
+/// https://www.w3.org/TR/css-values-4/#font-relative-lengths
+fn font_relative_to_px(value: &Value, font_size: f32) -> Value {
+  match value {
+    Value::Length(length, unit) => {
+      let scale = match unit {
+        Unit::Em => font_size,
+        Unit::Ex | Unit::Ch => font_size / 2.0,
+        Unit::Rem => DEFAULT_FONT_SIZE,
+        _ => return value.clone(),
+      };
+      Value::Length(length * scale, Unit::Px)
+    }
+    Value::List(values) => Value::List(
+      values
+        .iter()
+        .map(|value| font_relative_to_px(value, font_size))
+        .collect(),
+    ),
+    Value::Function(name, arguments) => Value::Function(
+      name.clone(),
+      arguments
+        .iter()
+        .map(|value| font_relative_to_px(value, font_size))
+        .collect(),
+    ),
+    value => value.clone(),
   }
 }
 
@@ -48,17 +1224,118 @@ pub fn create_styletree<'a>(root: &'a Node, stylesheet: &super::css::Stylesheet)
   if let NodeType::Document() = root.node_type {
     root = &root.children.first().unwrap().children.last().unwrap();
   }
+  let fonts = Rc::new(FontCollection::new(stylesheet));
+  let mut styled_root = style_node(root, stylesheet, &HashMap::new(), &fonts);
 
+  // The principal writing mode is that of the root of the style tree, the body. Only that one
+  // is laid out, elements with another writing mode don't establish orthogonal flows.
+  // https://www.w3.org/TR/css-writing-modes-3/#principal-flow
+  let writing_mode = styled_root.specified_writing_mode();
+  set_writing_mode(&mut styled_root, writing_mode, writing_mode);
+  styled_root
+}
+
+// Give a node and its descendants the principal writing mode, and warn about the elements that
+// set a different one, which is ignored.
+fn set_writing_mode(node: &mut StyledNode, writing_mode: WritingMode, inherited: WritingMode) {
+  let specified = node.specified_writing_mode();
+  if specified != inherited {
+    eprintln!(
+      "Ignoring writing-mode {:?} on {}, orthogonal flows are not supported",
+      specified,
+      node.node.debug_identifier()
+    );
+  }
+  node.writing_mode = writing_mode;
+  let pseudo_elements = node.before.iter_mut().chain(node.after.iter_mut());
+  for pseudo_element in pseudo_elements {
+    pseudo_element.writing_mode = writing_mode;
+  }
+  for child in &mut node.children {
+    set_writing_mode(child, writing_mode, specified);
+  }
+}
+
+fn style_node<'a>(
+  root: &'a Node,
+  stylesheet: &super::css::Stylesheet,
+  parent: &StylePropertyMap,
+  fonts: &Rc<FontCollection>,
+) -> StyledNode<'a> {
+  let mut values = match root.node_type {
+    NodeType::Element(ref data) => {
+      let mut values = stylesheet.specified_values_for_element(data);
+      add_bidi_hints(root, data, &mut values);
+      values
+    }
+    _ => HashMap::new(),
+  };
+  inherit(&mut values, parent);
+
+  // Replaced elements don't have `::before` and `::after` pseudo-elements.
+  let pseudo_element = |pseudo_element| match root.node_type {
+    NodeType::Element(ref data) if data.image.is_none() => stylesheet
+      .specified_values_for_pseudo_element(data, pseudo_element)
+      .map(|mut pseudo_values| {
+        inherit(&mut pseudo_values, &values);
+        Box::new(StyledNode {
+          values: pseudo_values,
+          node: root,
+          children: Vec::new(),
+          pseudo_element: Some(pseudo_element),
+          before: None,
+          after: None,
+          writing_mode: WritingMode::HorizontalTb,
+          fonts: fonts.clone(),
+        })
+      }),
+    _ => None,
+  };
+
+  let before = pseudo_element(PseudoElement::Before);
+  let after = pseudo_element(PseudoElement::After);
   StyledNode {
     node: root,
-    values: match root.node_type {
-      NodeType::Element(ref data) => stylesheet.specified_values_for_element(data),
-      _ => HashMap::new(),
-    },
     children: root
       .children
       .iter()
-      .map(|child| create_styletree(&child, stylesheet))
+      .map(|child| style_node(&child, stylesheet, &values, fonts))
       .collect(),
+    values,
+    pseudo_element: None,
+    before,
+    after,
+    writing_mode: WritingMode::HorizontalTb,
+    fonts: fonts.clone(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::layout::css;
+  use crate::layout::dom::elem;
+
+  #[test]
+  fn font_relative_lengths_are_computed_against_the_font_size() {
+    let mut class = HashMap::new();
+    class.insert("class".to_string(), "child".to_string());
+    let document = elem(
+      "body".to_string(),
+      HashMap::new(),
+      vec![elem("div".to_string(), class, Vec::new())],
+    );
+    let stylesheet = css::parse(
+      "body { font-size: 20px; } \
+       .child { font-size: 1.5em; text-indent: 2em; letter-spacing: 1rem; word-spacing: 2ex; }"
+        .to_string(),
+    );
+    let root = create_styletree(&document, &stylesheet);
+    let child = &root.children[0];
+    let px = |name: &str| child.value(name);
+    assert_eq!(px("font-size"), Some(Value::Length(30.0, Unit::Px)));
+    assert_eq!(px("text-indent"), Some(Value::Length(60.0, Unit::Px)));
+    assert_eq!(px("letter-spacing"), Some(Value::Length(16.0, Unit::Px)));
+    assert_eq!(px("word-spacing"), Some(Value::Length(30.0, Unit::Px)));
   }
 }
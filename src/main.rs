@@ -11,10 +11,23 @@ use glium::Surface;
 use std::fs::File;
 use std::io::Read;
 
+// How far the content scrolls for each line of a mouse wheel, in px.
+const SCROLL_LINE_HEIGHT: f32 = 40.0;
+
+// The size of the viewport that documents are laid out in when they are rendered into a file.
+const OUTPUT_SIZE: (u32, u32) = (1024, 768);
+
 fn main() {
     let mut opts = getopts::Options::new();
     opts.optopt("h", "html", "HTML document", "FILENAME");
     opts.optopt("c", "css", "CSS stylesheet", "FILENAME");
+    opts.optopt("p", "page", "Show a page of the document laid out for print", "NUMBER");
+    opts.optopt(
+        "o",
+        "output",
+        "Render the document into a PNG file on the CPU instead of showing it in a window",
+        "FILENAME",
+    );
 
     let matches = opts.parse(std::env::args().skip(1)).unwrap();
     let html_path = matches
@@ -23,48 +36,101 @@ fn main() {
     let css_path = matches
         .opt_str("c")
         .unwrap_or(String::from("support/style.css"));
+    // Pages are numbered from 1.
+    let page_number = match matches.opt_str("p") {
+        None => None,
+        Some(number) => match number.parse::<usize>() {
+            Ok(number) if number >= 1 => Some(number),
+            _ => {
+                eprintln!("Invalid page number {:?}, pages are numbered from 1", number);
+                std::process::exit(1);
+            }
+        },
+    };
 
-    // 1. The **winit::EventsLoop** for handling events.
-    let mut events_loop = glutin::EventsLoop::new();
-    // 2. Parameters for building the Window.
-    let wb = glutin::WindowBuilder::new().with_title("Noord");
-    // 3. Parameters for building the OpenGL context.
-    let cb = glutin::ContextBuilder::new();
-    // 4. Build the Display with the given window and OpenGL context parameters and register the
-    //    window with the events_loop.
-    let display = glium::Display::new(wb, cb, &events_loop).unwrap();
+    let rcdom = layout::html::parse_html_doc(html_path.clone());
+    let mut dom = layout::dom::serialize_rc_dom(rcdom);
 
-    let rcdom = layout::html::parse_html_doc(html_path);
-    let dom = layout::dom::serialize_rc_dom(rcdom);
+    // Images are loaded relative to the directory of the document.
+    let base_path = std::path::Path::new(&html_path)
+        .parent()
+        .unwrap_or(std::path::Path::new(""));
+    layout::image::load_images(&mut dom, base_path);
 
     let mut css_buffer = String::new();
-    File::open(css_path)
+    File::open(&css_path)
         .unwrap()
         .read_to_string(&mut css_buffer)
         .unwrap();
 
-    let stylesheet = layout::css::parse(css_buffer);
+    let mut stylesheet = layout::css::parse(css_buffer);
+
+    // Fonts are loaded relative to the directory of the stylesheet.
+    let css_base_path = std::path::Path::new(&css_path)
+        .parent()
+        .unwrap_or(std::path::Path::new(""));
+    layout::font::load_font_faces(&mut stylesheet, css_base_path);
+
     let style_tree = layout::style::create_styletree(&dom, &stylesheet);
+    let page_context = layout::geometry::PageContext::new(&stylesheet);
+
+    if let Some(output_path) = matches.opt_str("o") {
+        if let Err(error) = render_to_file(&style_tree, &page_context, page_number, &output_path) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // 1. The **winit::EventsLoop** for handling events.
+    let mut events_loop = glutin::EventsLoop::new();
+    // 2. Parameters for building the Window.
+    let wb = glutin::WindowBuilder::new().with_title("Noord");
+    // 3. Parameters for building the OpenGL context, multisampled to anti-alias the edges of
+    //    shapes.
+    let cb = glutin::ContextBuilder::new().with_multisampling(4);
+    // 4. Build the Display with the given window and OpenGL context parameters and register the
+    //    window with the events_loop.
+    let display = glium::Display::new(wb, cb, &events_loop).unwrap();
 
     let mut closed = false;
     let mut should_redraw = true;
 
+    // Scroll offsets are kept across layouts, and mouse wheel events scroll the box that was
+    // under the cursor in the last layout.
+    let mut scroll_offsets = layout::geometry::ScrollOffsets::new();
+    let mut cursor_position = (0.0, 0.0);
+    let mut geom_tree = None;
+
     while !closed {
         if should_redraw {
             let mut target = display.draw();
             target.clear_color(1.0, 1.0, 1.0, 1.0);
 
-            let display_dimensions = display.get_framebuffer_dimensions();
-            let window_dimensions = layout::geometry::Dimensions::new(layout::geometry::Rect {
-                x: 0.0,
-                y: 0.0,
-                width: display_dimensions.0 as f32,
-                height: display_dimensions.1 as f32,
-            });
-            let geom_tree = layout::geometry::layout_geometry_tree(&style_tree, window_dimensions);
+            if let Some(page_number) = page_number {
+                // Pages have the size of the `@page` rules, and don't scroll.
+                let (tree, pages) =
+                    layout::geometry::layout_paged_geometry_tree(&style_tree, &page_context);
+                let lists = gfx::display_list::build_page_display_lists(&tree, &pages);
+                if let Some(list) = lists.get(page_number - 1) {
+                    gfx::render_list(list, display.clone(), &mut target);
+                }
+            } else {
+                let display_dimensions = display.get_framebuffer_dimensions();
+                let viewport = layout::geometry::Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: display_dimensions.0 as f32,
+                    height: display_dimensions.1 as f32,
+                };
+                let window_dimensions = layout::geometry::Dimensions::new(viewport);
+                let tree = layout::geometry::layout_geometry_tree(&style_tree, window_dimensions);
 
-            let list = gfx::display_list::build_display_list(&geom_tree);
-            gfx::render_list(&list, display.clone(), &mut target);
+                let list =
+                    gfx::display_list::build_display_list(&tree, viewport, &scroll_offsets);
+                gfx::render_list(&list, display.clone(), &mut target);
+                geom_tree = Some(tree);
+            }
 
             should_redraw = false;
 
@@ -76,9 +142,68 @@ fn main() {
             glutin::Event::WindowEvent { event, .. } => match event {
                 glutin::WindowEvent::CloseRequested => closed = true,
                 glutin::WindowEvent::Refresh => should_redraw = true,
+                glutin::WindowEvent::CursorMoved { position, .. } => {
+                    let hidpi_factor = display.gl_window().window().get_hidpi_factor();
+                    let position = position.to_physical(hidpi_factor);
+                    cursor_position = (position.x as f32, position.y as f32);
+                }
+                glutin::WindowEvent::MouseWheel { delta, .. } => {
+                    let delta = match delta {
+                        glutin::MouseScrollDelta::LineDelta(x, y) => {
+                            (-x * SCROLL_LINE_HEIGHT, -y * SCROLL_LINE_HEIGHT)
+                        }
+                        glutin::MouseScrollDelta::PixelDelta(position) => {
+                            (-position.x as f32, -position.y as f32)
+                        }
+                    };
+                    if let Some(tree) = &geom_tree {
+                        if tree.scroll_by(&mut scroll_offsets, cursor_position, delta) {
+                            should_redraw = true;
+                        }
+                    }
+                }
                 _ => (),
             },
             _ => (),
         });
     }
 }
+
+/// Lay out the document in a viewport of `OUTPUT_SIZE`, or on pages for print, and rasterize it,
+/// or one of its pages, into a PNG file. Fails if there is no such page, or the file can't be
+/// written.
+fn render_to_file(
+    style_tree: &layout::style::StyledNode,
+    page_context: &layout::geometry::PageContext,
+    page_number: Option<usize>,
+    path: &str,
+) -> Result<(), String> {
+    let (list, (width, height)) = if let Some(page_number) = page_number {
+        let (tree, pages) = layout::geometry::layout_paged_geometry_tree(style_tree, page_context);
+        let mut lists = gfx::display_list::build_page_display_lists(&tree, &pages);
+        if page_number > lists.len() {
+            return Err(format!("The document has no page {}", page_number));
+        }
+        let (width, height) = page_context.size;
+        (lists.swap_remove(page_number - 1), (width.ceil() as u32, height.ceil() as u32))
+    } else {
+        let viewport = layout::geometry::Rect {
+            x: 0.0,
+            y: 0.0,
+            width: OUTPUT_SIZE.0 as f32,
+            height: OUTPUT_SIZE.1 as f32,
+        };
+        let dimensions = layout::geometry::Dimensions::new(viewport);
+        let tree = layout::geometry::layout_geometry_tree(style_tree, dimensions);
+        let scroll_offsets = layout::geometry::ScrollOffsets::new();
+        let list = gfx::display_list::build_display_list(&tree, viewport, &scroll_offsets);
+        (list, OUTPUT_SIZE)
+    };
+
+    let white = gfx::colors::Color::new_alpha(255, 255, 255, 255);
+    let mut canvas = gfx::raster::Canvas::new(width, height, white);
+    gfx::raster::render_list(&list, &mut canvas);
+    let pixels = canvas.to_rgba8();
+    image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)
+        .map_err(|error| format!("Failed to write {}: {}", path, error))
+}